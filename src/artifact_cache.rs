@@ -0,0 +1,482 @@
+use axum::body::Bytes;
+use futures::Stream;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::io::SeekFrom;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+use tokio::sync::watch;
+
+use crate::hawkbit::{HawkbitError, HawkbitResult};
+
+const READ_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Progress of an artifact being fetched into the cache
+#[derive(Debug, Clone)]
+enum FillState {
+    /// Number of bytes written so far
+    Filling(u64),
+    Done,
+    Failed(String),
+}
+
+#[derive(Debug)]
+struct Entry {
+    size: u64,
+    last_used: SystemTime,
+}
+
+#[derive(Debug, Default)]
+struct Index {
+    entries: HashMap<String, Entry>,
+    /// Space promised to fills in progress
+    reserved: u64,
+}
+
+impl Index {
+    fn used(&self) -> u64 {
+        self.entries.values().map(|entry| entry.size).sum::<u64>() + self.reserved
+    }
+}
+
+/// Open handle on cached content, possibly still being fetched
+pub struct CachedArtifact {
+    file: std::fs::File,
+    pub size: u64,
+    progress: Option<watch::Receiver<FillState>>,
+}
+
+impl CachedArtifact {
+    /// Streams `start..end` of the artifact. Bytes that are still being
+    /// fetched are sent as they arrive, except for the last one, which waits
+    /// for the hash check; a failed fetch ends the stream with an error so
+    /// the client sees a broken download instead of bad data.
+    pub fn stream(self, start: u64, end: u64) -> impl Stream<Item = std::io::Result<Bytes>> {
+        let progress = self.progress;
+        let last = self.size.saturating_sub(1);
+        let file = tokio::fs::File::from_std(self.file);
+        let state = (file, progress, start, false);
+        futures::stream::unfold(
+            state,
+            move |(mut file, mut progress, pos, seeked)| async move {
+                if pos >= end {
+                    return None;
+                }
+                loop {
+                    let state = progress.as_ref().map(|receiver| receiver.borrow().clone());
+                    let available = match state {
+                        None | Some(FillState::Done) => end,
+                        Some(FillState::Filling(written)) => written.min(end).min(last),
+                        Some(FillState::Failed(e)) => {
+                            let error = std::io::Error::other(e);
+                            return Some((Err(error), (file, progress, end, seeked)));
+                        }
+                    };
+                    if available > pos {
+                        let len = (available - pos).min(READ_CHUNK_SIZE as u64) as usize;
+                        let mut buf = vec![0u8; len];
+                        let result = async {
+                            if !seeked {
+                                file.seek(SeekFrom::Start(pos)).await?;
+                            }
+                            file.read_exact(&mut buf).await
+                        }
+                        .await;
+                        return match result {
+                            Ok(_) => Some((
+                                Ok(Bytes::from(buf)),
+                                (file, progress, pos + len as u64, true),
+                            )),
+                            Err(e) => Some((Err(e), (file, progress, end, true))),
+                        };
+                    }
+                    let changed = match progress.as_mut() {
+                        Some(receiver) => receiver.changed().await.is_ok(),
+                        None => false,
+                    };
+                    if !changed {
+                        let error = std::io::Error::other("artifact fetch was aborted");
+                        return Some((Err(error), (file, progress, end, seeked)));
+                    }
+                }
+            },
+        )
+    }
+}
+
+/// Artifacts on local disk, named by their SHA-256. The least recently used
+/// artifacts are removed once the cache would exceed `max_size`.
+#[derive(Debug)]
+pub struct ArtifactCache {
+    dir: PathBuf,
+    max_size: u64,
+    index: Mutex<Index>,
+    /// Size and progress of the artifacts being fetched
+    fills: Mutex<HashMap<String, (u64, watch::Receiver<FillState>)>>,
+}
+
+fn is_sha256(name: &str) -> bool {
+    name.len() == 64 && name.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+impl ArtifactCache {
+    /// Opens the cache in `dir`, picking up artifacts of earlier runs and
+    /// removing their unfinished downloads.
+    pub fn open(dir: &Path, max_size: u64) -> HawkbitResult<Self> {
+        std::fs::create_dir_all(dir)?;
+        let mut index = Index::default();
+        for entry in std::fs::read_dir(dir)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().to_string();
+            if name.ends_with(".part") {
+                std::fs::remove_file(entry.path())?;
+                continue;
+            }
+            if !is_sha256(&name) {
+                continue;
+            }
+            let meta = entry.metadata()?;
+            index.entries.insert(
+                name.to_lowercase(),
+                Entry {
+                    size: meta.len(),
+                    last_used: meta.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+                },
+            );
+        }
+        tracing::info!(
+            "Artifact cache {} holds {} artifacts, {} bytes",
+            dir.display(),
+            index.entries.len(),
+            index.used()
+        );
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            max_size,
+            index: Mutex::new(index),
+            fills: Mutex::new(HashMap::new()),
+        })
+    }
+
+    fn path(&self, sha256: &str) -> PathBuf {
+        self.dir.join(sha256)
+    }
+
+    fn part_path(&self, sha256: &str) -> PathBuf {
+        self.dir.join(format!("{}.part", sha256))
+    }
+
+    /// Opens a cached artifact or one that is being fetched.
+    pub fn get(&self, sha256: &str) -> HawkbitResult<Option<CachedArtifact>> {
+        let sha256 = sha256.to_lowercase();
+        // The fill lock is held while a finished fill is renamed, and the
+        // index lock while an entry is evicted, so the files cannot vanish
+        // between the lookup and the open.
+        let fills = self.fills.lock().expect("fills lock poisoned");
+        if let Some((size, progress)) = fills.get(&sha256) {
+            return Ok(Some(CachedArtifact {
+                file: std::fs::File::open(self.part_path(&sha256))?,
+                size: *size,
+                progress: Some(progress.clone()),
+            }));
+        }
+        let mut index = self.index.lock().expect("index lock poisoned");
+        drop(fills);
+        let Some(entry) = index.entries.get_mut(&sha256) else {
+            return Ok(None);
+        };
+        let path = self.path(&sha256);
+        let file = match std::fs::File::open(&path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                index.entries.remove(&sha256);
+                return Ok(None);
+            }
+            Err(e) => return Err(e.into()),
+        };
+        entry.last_used = SystemTime::now();
+        // Keeps the LRU order across restarts, best effort
+        let _ = file.set_modified(entry.last_used);
+        Ok(Some(CachedArtifact {
+            size: entry.size,
+            file,
+            progress: None,
+        }))
+    }
+
+    /// Starts fetching an artifact with `request` unless that already
+    /// happens, and returns a handle streaming it while it arrives. Returns
+    /// `None` if the artifact is larger than the whole cache.
+    pub fn fill(
+        self: &Arc<Self>,
+        sha256: &str,
+        size: u64,
+        request: reqwest::RequestBuilder,
+    ) -> HawkbitResult<Option<CachedArtifact>> {
+        let sha256 = sha256.to_lowercase();
+        if size > self.max_size {
+            return Ok(None);
+        }
+        let mut fills = self.fills.lock().expect("fills lock poisoned");
+        if fills.contains_key(&sha256) {
+            drop(fills);
+            return self.get(&sha256);
+        }
+        {
+            let index = self.index.lock().expect("index lock poisoned");
+            if index.entries.contains_key(&sha256) {
+                drop(index);
+                drop(fills);
+                return self.get(&sha256);
+            }
+        }
+        self.make_room(size)?;
+
+        let part = self.part_path(&sha256);
+        let file = std::fs::File::create(&part)?;
+        let reader = std::fs::File::open(&part)?;
+        let (sender, receiver) = watch::channel(FillState::Filling(0));
+        fills.insert(sha256.clone(), (size, receiver.clone()));
+        drop(fills);
+
+        tracing::info!(
+            "Fetching artifact {} ({} bytes) into the cache",
+            sha256,
+            size
+        );
+        let cache = self.clone();
+        tokio::spawn(async move {
+            let result = cache
+                .download(
+                    &sha256,
+                    size,
+                    request,
+                    tokio::fs::File::from_std(file),
+                    &sender,
+                )
+                .await;
+            cache.finish_fill(&sha256, size, result, &sender);
+        });
+
+        Ok(Some(CachedArtifact {
+            file: reader,
+            size,
+            progress: Some(receiver),
+        }))
+    }
+
+    /// Evicts least recently used artifacts until `size` more bytes fit and
+    /// reserves them.
+    fn make_room(&self, size: u64) -> HawkbitResult<()> {
+        let mut index = self.index.lock().expect("index lock poisoned");
+        while index.used() + size > self.max_size {
+            let Some(oldest) = index
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(sha256, _)| sha256.clone())
+            else {
+                // Only fills in progress left, let the cache run over
+                break;
+            };
+            match std::fs::remove_file(self.path(&oldest)) {
+                Ok(()) => {}
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e.into()),
+            }
+            let entry = index.entries.remove(&oldest).expect("entry was found");
+            tracing::info!("Evicted artifact {} ({} bytes)", oldest, entry.size);
+        }
+        index.reserved += size;
+        Ok(())
+    }
+
+    async fn download(
+        &self,
+        sha256: &str,
+        size: u64,
+        request: reqwest::RequestBuilder,
+        mut file: tokio::fs::File,
+        progress: &watch::Sender<FillState>,
+    ) -> HawkbitResult<()> {
+        let mut res = request.send().await?;
+        let status = res.status();
+        if status != reqwest::StatusCode::OK {
+            let body = res.text().await.unwrap_or_default();
+            return Err(HawkbitError::http(status, body));
+        }
+
+        let mut hasher = Sha256::new();
+        let mut written = 0;
+        while let Some(chunk) = res.chunk().await? {
+            written += chunk.len() as u64;
+            if written > size {
+                return Err(HawkbitError::new(format!(
+                    "Artifact {} is larger than the expected {} bytes",
+                    sha256, size
+                )));
+            }
+            hasher.update(&chunk);
+            file.write_all(&chunk).await?;
+            // Readers must only see bytes that reached the file
+            file.flush().await?;
+            progress.send_replace(FillState::Filling(written));
+        }
+        file.sync_all().await?;
+
+        if written != size {
+            return Err(HawkbitError::new(format!(
+                "Incomplete download of artifact {}: got {} of {} bytes",
+                sha256, written, size
+            )));
+        }
+        let actual = hex::encode(hasher.finalize());
+        if actual != sha256 {
+            return Err(HawkbitError::new(format!(
+                "sha256 mismatch for artifact {}: got {}",
+                sha256, actual
+            )));
+        }
+        Ok(())
+    }
+
+    fn finish_fill(
+        &self,
+        sha256: &str,
+        size: u64,
+        result: HawkbitResult<()>,
+        progress: &watch::Sender<FillState>,
+    ) {
+        let mut fills = self.fills.lock().expect("fills lock poisoned");
+        let mut index = self.index.lock().expect("index lock poisoned");
+        index.reserved -= size;
+        let part = self.part_path(sha256);
+        let result = result.and_then(|()| Ok(std::fs::rename(&part, self.path(sha256))?));
+        match result {
+            Ok(()) => {
+                index.entries.insert(
+                    sha256.to_string(),
+                    Entry {
+                        size,
+                        last_used: SystemTime::now(),
+                    },
+                );
+                tracing::info!("Cached artifact {}", sha256);
+                progress.send_replace(FillState::Done);
+            }
+            Err(e) => {
+                tracing::warn!("Fetching artifact {} failed: {}", sha256, e);
+                let _ = std::fs::remove_file(&part);
+                progress.send_replace(FillState::Failed(e.to_string()));
+            }
+        }
+        fills.remove(sha256);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    /// Fresh directory holding an artifact of `size` bytes per name, the
+    /// first one used least recently.
+    fn cache_dir(test: &str, artifacts: &[(&str, u64)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "hawkbit-artifact-cache-{}-{}",
+            test,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        for (age, (name, size)) in artifacts.iter().enumerate() {
+            let file = std::fs::File::create(dir.join(name)).unwrap();
+            file.set_len(*size).unwrap();
+            file.set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(age as u64 + 1))
+                .unwrap();
+        }
+        dir
+    }
+
+    fn sha(n: u8) -> String {
+        format!("{:064x}", n)
+    }
+
+    fn cached(cache: &ArtifactCache) -> Vec<String> {
+        let index = cache.index.lock().unwrap();
+        let mut names: Vec<String> = index.entries.keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn open_skips_unfinished_and_foreign_files() {
+        let dir = cache_dir(
+            "open",
+            &[
+                (&sha(1), 10),
+                (&format!("{}.part", sha(2)), 5),
+                ("notes.txt", 3),
+            ],
+        );
+        let cache = ArtifactCache::open(&dir, 100).unwrap();
+        assert_eq!(cached(&cache), [sha(1)]);
+        assert!(!dir.join(format!("{}.part", sha(2))).exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn make_room_evicts_least_recently_used() {
+        let dir = cache_dir("evict", &[(&sha(1), 10), (&sha(2), 10), (&sha(3), 10)]);
+        let cache = ArtifactCache::open(&dir, 30).unwrap();
+        cache.get(&sha(1)).unwrap().unwrap();
+
+        cache.make_room(10).unwrap();
+        assert_eq!(cached(&cache), [sha(1), sha(3)]);
+        assert!(!dir.join(sha(2)).exists());
+        assert_eq!(cache.index.lock().unwrap().reserved, 10);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn reservations_count_as_used() {
+        let dir = cache_dir("reserve", &[(&sha(1), 10), (&sha(2), 10)]);
+        let cache = ArtifactCache::open(&dir, 30).unwrap();
+
+        cache.make_room(10).unwrap();
+        assert_eq!(cached(&cache), [sha(1), sha(2)]);
+        cache.make_room(10).unwrap();
+        assert_eq!(cached(&cache), [sha(2)]);
+        // Only reservations left, the cache runs over rather than fail
+        cache.make_room(20).unwrap();
+        assert!(cached(&cache).is_empty());
+        assert_eq!(cache.index.lock().unwrap().reserved, 40);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn failed_fill_releases_its_reservation() {
+        use futures::StreamExt;
+
+        let dir = cache_dir("failed-fill", &[]);
+        let cache = Arc::new(ArtifactCache::open(&dir, 100).unwrap());
+        // Nothing listens on port 1, so the fetch fails
+        let request = || reqwest::Client::new().get("http://127.0.0.1:1/");
+
+        // Larger than the whole cache, passed through without a reservation
+        assert!(cache.fill(&sha(1), 200, request()).unwrap().is_none());
+        assert_eq!(cache.index.lock().unwrap().reserved, 0);
+
+        let artifact = cache.fill(&sha(1), 50, request()).unwrap().unwrap();
+        assert_eq!(cache.index.lock().unwrap().reserved, 50);
+        let mut stream = Box::pin(artifact.stream(0, 50));
+        assert!(stream.next().await.unwrap().is_err());
+
+        assert_eq!(cache.index.lock().unwrap().reserved, 0);
+        assert!(cached(&cache).is_empty());
+        assert!(!dir.join(format!("{}.part", sha(1))).exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,598 @@
+use md5::Md5;
+use reqwest::{RequestBuilder, Response, StatusCode, header};
+use serde::Serialize;
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use std::path::{Component, Path, PathBuf};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+use crate::hawkbit::{
+    Artifact, ArtifactHashes, DistributionSet, HawkbitError, HawkbitMgmtClient, HawkbitResult,
+    SoftwareModule,
+};
+
+/// Computes sha1, md5 and sha256 of a local file in a single pass.
+pub async fn compute_hashes(path: &Path) -> HawkbitResult<ArtifactHashes> {
+    let mut file = tokio::fs::File::open(path).await?;
+    let mut sha1 = Sha1::new();
+    let mut md5 = Md5::new();
+    let mut sha256 = Sha256::new();
+    let mut buf = vec![0u8; 64 * 1024];
+
+    loop {
+        let read = file.read(&mut buf).await?;
+        if read == 0 {
+            break;
+        }
+        sha1.update(&buf[..read]);
+        md5.update(&buf[..read]);
+        sha256.update(&buf[..read]);
+    }
+
+    Ok(ArtifactHashes {
+        sha1: Some(hex::encode(sha1.finalize())),
+        md5: Some(hex::encode(md5.finalize())),
+        sha256: Some(hex::encode(sha256.finalize())),
+    })
+}
+
+/// Checks every hash hawkBit knows for the artifact against the local file.
+pub async fn verify_file(path: &Path, expected: &ArtifactHashes) -> HawkbitResult<()> {
+    let actual = compute_hashes(path).await?;
+    let checks = [
+        ("sha256", &expected.sha256, &actual.sha256),
+        ("sha1", &expected.sha1, &actual.sha1),
+        ("md5", &expected.md5, &actual.md5),
+    ];
+
+    let mut verified = false;
+    for (name, expected, actual) in checks {
+        if let (Some(expected), Some(actual)) = (expected, actual) {
+            if !expected.eq_ignore_ascii_case(actual) {
+                return Err(HawkbitError::new(format!(
+                    "{} mismatch for {:?}: expected {}, got {}",
+                    name, path, expected, actual
+                )));
+            }
+            verified = true;
+        }
+    }
+
+    if !verified {
+        return Err(HawkbitError::new(format!(
+            "No hashes available to verify {:?}",
+            path
+        )));
+    }
+    Ok(())
+}
+
+/// Returns `name` if it is a single plain file name, so a name supplied by
+/// the server can't point outside the directory it is joined to.
+pub fn safe_file_name(name: &str) -> HawkbitResult<&str> {
+    let mut components = Path::new(name).components();
+    match (components.next(), components.next()) {
+        (Some(Component::Normal(_)), None) if !name.contains(['/', '\\']) => Ok(name),
+        _ => Err(HawkbitError::new(format!(
+            "Refusing unsafe file name {:?}",
+            name
+        ))),
+    }
+}
+
+/// Streams an artifact to `dest` through `<dest>.part`, resuming a partial
+/// download of the same artifact (named by `identity`) with an HTTP Range
+/// request. `send` sends the finished request, e.g. with the client's
+/// authentication; it is called again for the whole file if the server
+/// answers with a range other than the one requested. The file is verified
+/// against `hashes` before it is moved into place. Returns the size of the
+/// downloaded file.
+pub(crate) async fn download_resumable(
+    request: RequestBuilder,
+    send: impl AsyncFn(RequestBuilder) -> HawkbitResult<Response>,
+    dest: &Path,
+    identity: &str,
+    size: u64,
+    hashes: &ArtifactHashes,
+) -> HawkbitResult<u64> {
+    let partial = partial_download_path(dest);
+    let mut offset = resumable_offset(&partial, identity, size).await?;
+
+    if offset < size || size == 0 {
+        let request = request.header(header::ACCEPT, "application/octet-stream");
+        let whole = request.try_clone();
+        let mut res = if offset > 0 {
+            send(request.header(header::RANGE, format!("bytes={}-", offset))).await?
+        } else {
+            send(request).await?
+        };
+        if res.status() == StatusCode::PARTIAL_CONTENT
+            && content_range_start(&res) != Some(offset)
+            && let Some(whole) = whole
+        {
+            // Appending any other range would corrupt the file
+            res = send(whole).await?;
+        }
+
+        let status = res.status();
+        let mut file = match status {
+            StatusCode::PARTIAL_CONTENT if content_range_start(&res) == Some(offset) => {
+                tokio::fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&partial)
+                    .await?
+            }
+            StatusCode::OK => {
+                // Server ignored the range, so the body is the whole file
+                offset = 0;
+                tokio::fs::File::create(&partial).await?
+            }
+            _ => {
+                let body = res.text().await.unwrap_or_default();
+                return Err(HawkbitError::http(status, body));
+            }
+        };
+
+        while let Some(chunk) = res.chunk().await? {
+            file.write_all(&chunk).await?;
+            offset += chunk.len() as u64;
+        }
+        file.flush().await?;
+    }
+
+    if offset != size {
+        return Err(HawkbitError::new(format!(
+            "Incomplete download of {:?}: got {} of {} bytes",
+            dest, offset, size
+        )));
+    }
+    if let Err(e) = verify_file(&partial, hashes).await {
+        // A corrupt partial file would otherwise be resumed forever
+        tokio::fs::remove_file(&partial).await?;
+        tokio::fs::remove_file(partial_marker_path(&partial)).await?;
+        return Err(e);
+    }
+
+    finish_partial(&partial, dest).await?;
+    Ok(offset)
+}
+
+/// Start of the range in a `Content-Range: bytes <start>-<end>/<size>` header
+fn content_range_start(res: &Response) -> Option<u64> {
+    let value = res.headers().get(header::CONTENT_RANGE)?.to_str().ok()?;
+    let (start, _) = value.strip_prefix("bytes ")?.split_once('-')?;
+    start.parse().ok()
+}
+
+fn partial_download_path(dest: &Path) -> PathBuf {
+    let mut name = dest.file_name().unwrap_or_default().to_os_string();
+    name.push(".part");
+    dest.with_file_name(name)
+}
+
+/// File next to a `.part` download naming the artifact it belongs to
+fn partial_marker_path(partial: &Path) -> PathBuf {
+    let mut name = partial.file_name().unwrap_or_default().to_os_string();
+    name.push(".id");
+    partial.with_file_name(name)
+}
+
+/// Returns how many bytes of `partial` can be resumed for the artifact
+/// identified by `identity`. A leftover of any other artifact is discarded,
+/// and the marker is (re)written for the download about to start.
+async fn resumable_offset(partial: &Path, identity: &str, size: u64) -> HawkbitResult<u64> {
+    let marker = partial_marker_path(partial);
+    let same_artifact = tokio::fs::read_to_string(&marker)
+        .await
+        .is_ok_and(|existing| existing == identity);
+    let offset = match tokio::fs::metadata(partial).await {
+        Ok(meta) if same_artifact && meta.len() <= size => meta.len(),
+        Ok(_) => {
+            tokio::fs::remove_file(partial).await?;
+            0
+        }
+        Err(_) => 0,
+    };
+    if !same_artifact {
+        tokio::fs::write(&marker, identity).await?;
+    }
+    Ok(offset)
+}
+
+/// Moves a finished `.part` download into place and drops its marker.
+async fn finish_partial(partial: &Path, dest: &Path) -> HawkbitResult<()> {
+    tokio::fs::rename(partial, dest).await?;
+    match tokio::fs::remove_file(partial_marker_path(partial)).await {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ModuleArtifacts {
+    pub module: SoftwareModule,
+    pub artifacts: Vec<Artifact>,
+}
+
+pub async fn list_distribution_set_artifacts(
+    client: &HawkbitMgmtClient,
+    distribution_set: &DistributionSet,
+) -> HawkbitResult<Vec<ModuleArtifacts>> {
+    let mut modules = Vec::new();
+    for module in &distribution_set.modules {
+        let artifacts = client.get_artifacts(module.id).await?;
+        modules.push(ModuleArtifacts {
+            module: module.clone(),
+            artifacts,
+        });
+    }
+    Ok(modules)
+}
+
+/// Directory of a module's artifacts, `<base>/<name>-<version>`. Name and
+/// version come from the server and are rejected if they leave `base`.
+fn module_dir(base: &Path, module: &SoftwareModule) -> HawkbitResult<PathBuf> {
+    let name = format!("{}-{}", module.name, module.version);
+    Ok(base.join(safe_file_name(&name)?))
+}
+
+/// Downloads all artifacts of a distribution set into `<dir>/<module>-<version>/`
+/// and verifies them. Files that already exist and verify are not downloaded again.
+pub async fn download_distribution_set(
+    client: &HawkbitMgmtClient,
+    distribution_set: &DistributionSet,
+    dir: &Path,
+) -> HawkbitResult<Vec<PathBuf>> {
+    let mut downloaded = Vec::new();
+    for entry in list_distribution_set_artifacts(client, distribution_set).await? {
+        let target_dir = module_dir(dir, &entry.module)?;
+        tokio::fs::create_dir_all(&target_dir).await?;
+
+        for artifact in &entry.artifacts {
+            let dest = target_dir.join(safe_file_name(&artifact.provided_filename)?);
+            if dest.exists() && verify_file(&dest, &artifact.hashes).await.is_ok() {
+                downloaded.push(dest);
+                continue;
+            }
+
+            client
+                .download_artifact(entry.module.id, artifact, &dest)
+                .await?;
+            downloaded.push(dest);
+        }
+    }
+    Ok(downloaded)
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+pub enum AuditStatus {
+    Match,
+    Mismatch,
+    Missing,
+    /// The module or file name from the server is not a safe local path
+    Rejected,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ArtifactAudit {
+    pub module: String,
+    pub filename: String,
+    pub size: u64,
+    pub local_path: PathBuf,
+    pub status: AuditStatus,
+    pub detail: Option<String>,
+}
+
+/// Compares the artifacts stored on hawkBit with the files in a local build
+/// output directory. Files are looked up by their provided filename, either
+/// directly in `dir` or in the `<module>-<version>` layout used by downloads.
+pub async fn audit_distribution_set(
+    client: &HawkbitMgmtClient,
+    distribution_set: &DistributionSet,
+    dir: &Path,
+) -> HawkbitResult<Vec<ArtifactAudit>> {
+    let mut audits = Vec::new();
+    for entry in list_distribution_set_artifacts(client, distribution_set).await? {
+        let module_dir = module_dir(dir, &entry.module);
+        for artifact in &entry.artifacts {
+            let candidates = match (&module_dir, safe_file_name(&artifact.provided_filename)) {
+                (Ok(module_dir), Ok(name)) => Ok([dir.join(name), module_dir.join(name)]),
+                (Err(e), _) => Err(e.to_string()),
+                (_, Err(e)) => Err(e.to_string()),
+            };
+
+            let (local_path, status, detail) = match candidates {
+                Err(e) => (dir.to_path_buf(), AuditStatus::Rejected, Some(e)),
+                Ok(candidates) => match candidates.iter().find(|p| p.is_file()) {
+                    None => (candidates[0].clone(), AuditStatus::Missing, None),
+                    Some(path) => {
+                        let size = tokio::fs::metadata(path).await?.len();
+                        if size != artifact.size {
+                            (
+                                path.clone(),
+                                AuditStatus::Mismatch,
+                                Some(format!("size {} != {}", size, artifact.size)),
+                            )
+                        } else {
+                            match verify_file(path, &artifact.hashes).await {
+                                Ok(()) => (path.clone(), AuditStatus::Match, None),
+                                Err(e) => {
+                                    (path.clone(), AuditStatus::Mismatch, Some(e.to_string()))
+                                }
+                            }
+                        }
+                    }
+                },
+            };
+
+            audits.push(ArtifactAudit {
+                module: format!("{} {}", entry.module.name, entry.module.version),
+                filename: artifact.provided_filename.clone(),
+                size: artifact.size,
+                local_path,
+                status,
+                detail,
+            });
+        }
+    }
+    Ok(audits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hawkbit::SoftwareModuleLinks;
+    use axum::Router;
+    use axum::body::Body;
+    use axum::extract::{Request, State};
+    use std::sync::{Arc, Mutex};
+
+    /// Range headers of the requests the test server received
+    type Ranges = Arc<Mutex<Vec<Option<String>>>>;
+
+    fn content() -> Vec<u8> {
+        (0..1000u32).map(|i| (i % 251) as u8).collect()
+    }
+
+    fn hashes(data: &[u8]) -> ArtifactHashes {
+        ArtifactHashes {
+            sha1: None,
+            md5: None,
+            sha256: Some(hex::encode(Sha256::digest(data))),
+        }
+    }
+
+    /// Serves `content()` at `/ok`, which honours ranges, at `/ignore`, which
+    /// always sends the whole file, and at `/wrong`, which answers ranges
+    /// with a 206 starting at 0.
+    async fn serve() -> (String, Ranges) {
+        async fn artifact(
+            State(ranges): State<Ranges>,
+            request: Request,
+        ) -> axum::response::Response {
+            let range = request
+                .headers()
+                .get(header::RANGE)
+                .map(|value| value.to_str().unwrap().to_string());
+            ranges.lock().unwrap().push(range.clone());
+            let content = content();
+            let start = match (request.uri().path(), range) {
+                ("/ok", Some(range)) => Some(
+                    range
+                        .strip_prefix("bytes=")
+                        .and_then(|range| range.strip_suffix('-'))
+                        .unwrap()
+                        .parse::<usize>()
+                        .unwrap(),
+                ),
+                ("/wrong", Some(_)) => Some(0),
+                _ => None,
+            };
+            let Some(start) = start else {
+                return axum::response::Response::new(Body::from(content));
+            };
+            axum::http::Response::builder()
+                .status(StatusCode::PARTIAL_CONTENT)
+                .header(
+                    header::CONTENT_RANGE,
+                    format!("bytes {}-{}/{}", start, content.len() - 1, content.len()),
+                )
+                .body(Body::from(content[start..].to_vec()))
+                .unwrap()
+        }
+
+        let ranges = Ranges::default();
+        let app = Router::new().fallback(artifact).with_state(ranges.clone());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        (base, ranges)
+    }
+
+    fn temp_dir(test: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("hawkbit-artifacts-{}-{}", test, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Leaves `data` as a partial download of `identity`
+    fn leave_partial(dest: &Path, identity: &str, data: &[u8]) {
+        let partial = partial_download_path(dest);
+        std::fs::write(&partial, data).unwrap();
+        std::fs::write(partial_marker_path(&partial), identity).unwrap();
+    }
+
+    async fn download(url: &str, dest: &Path, identity: &str) -> HawkbitResult<u64> {
+        let content = content();
+        download_resumable(
+            reqwest::Client::new().get(url),
+            async |request| Ok(request.send().await?),
+            dest,
+            identity,
+            content.len() as u64,
+            &hashes(&content),
+        )
+        .await
+    }
+
+    fn assert_downloaded(dest: &Path) {
+        assert_eq!(std::fs::read(dest).unwrap(), content());
+        let partial = partial_download_path(dest);
+        assert!(!partial.exists());
+        assert!(!partial_marker_path(&partial).exists());
+    }
+
+    fn module(name: &str, version: &str) -> SoftwareModule {
+        SoftwareModule {
+            links: SoftwareModuleLinks { self_link: None },
+            created_at: 0,
+            created_by: String::new(),
+            deleted: false,
+            encrypted: false,
+            id: 1,
+            last_modified_at: 0,
+            last_modified_by: String::new(),
+            name: name.to_string(),
+            module_type: "os".to_string(),
+            type_name: "OS".to_string(),
+            version: version.to_string(),
+            description: None,
+            vendor: None,
+        }
+    }
+
+    #[test]
+    fn module_dir_stays_inside_base() {
+        let base = Path::new("/downloads");
+        assert_eq!(
+            module_dir(base, &module("rootfs", "1.2")).unwrap(),
+            base.join("rootfs-1.2")
+        );
+        assert!(module_dir(base, &module("../../etc", "1")).is_err());
+        assert!(module_dir(base, &module("rootfs", "1/../../x")).is_err());
+        assert!(module_dir(base, &module("/etc/cron.d", "")).is_err());
+        assert!(module_dir(base, &module("a\\b", "1")).is_err());
+    }
+
+    #[test]
+    fn safe_file_name_accepts_only_plain_names() {
+        assert_eq!(safe_file_name("fw.bin").unwrap(), "fw.bin");
+        assert_eq!(safe_file_name("..fw").unwrap(), "..fw");
+        for name in [
+            "",
+            ".",
+            "..",
+            "../fw.bin",
+            "dir/fw.bin",
+            "/fw.bin",
+            "fw.bin/",
+        ] {
+            assert!(safe_file_name(name).is_err(), "{:?}", name);
+        }
+    }
+
+    #[tokio::test]
+    async fn resumes_a_partial_download_of_the_same_artifact() {
+        let (base, ranges) = serve().await;
+        let dir = temp_dir("resume");
+        let dest = dir.join("fw.bin");
+        leave_partial(&dest, "fw", &content()[..400]);
+
+        assert_eq!(
+            download(&format!("{}/ok", base), &dest, "fw")
+                .await
+                .unwrap(),
+            1000
+        );
+        assert_downloaded(&dest);
+        assert_eq!(*ranges.lock().unwrap(), [Some("bytes=400-".to_string())]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn partial_download_of_another_artifact_is_discarded() {
+        let (base, ranges) = serve().await;
+        let dir = temp_dir("marker");
+        let dest = dir.join("fw.bin");
+        leave_partial(&dest, "other", b"not this firmware");
+
+        download(&format!("{}/ok", base), &dest, "fw")
+            .await
+            .unwrap();
+        assert_downloaded(&dest);
+        assert_eq!(*ranges.lock().unwrap(), [None]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn unexpected_range_falls_back_to_the_whole_file() {
+        let (base, ranges) = serve().await;
+        let dir = temp_dir("range");
+        for path in ["wrong", "ignore"] {
+            let dest = dir.join(path);
+            leave_partial(&dest, "fw", &content()[..400]);
+
+            download(&format!("{}/{}", base, path), &dest, "fw")
+                .await
+                .unwrap();
+            assert_downloaded(&dest);
+        }
+        let requested = [
+            Some("bytes=400-".to_string()),
+            None,
+            Some("bytes=400-".to_string()),
+        ];
+        assert_eq!(*ranges.lock().unwrap(), requested);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn corrupt_partial_download_is_removed() {
+        let (base, _ranges) = serve().await;
+        let dir = temp_dir("corrupt");
+        let dest = dir.join("fw.bin");
+        leave_partial(&dest, "fw", &[0u8; 400]);
+
+        let url = format!("{}/ok", base);
+        let err = download(&url, &dest, "fw").await.unwrap_err();
+        assert!(err.to_string().contains("sha256 mismatch"), "{}", err);
+        assert!(!dest.exists());
+        let partial = partial_download_path(&dest);
+        assert!(!partial.exists());
+        assert!(!partial_marker_path(&partial).exists());
+
+        // The next attempt starts over
+        download(&url, &dest, "fw").await.unwrap();
+        assert_downloaded(&dest);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn verify_file_checks_every_known_hash() {
+        let dir = temp_dir("verify");
+        let path = dir.join("fw.bin");
+        std::fs::write(&path, content()).unwrap();
+        let actual = compute_hashes(&path).await.unwrap();
+
+        verify_file(&path, &actual).await.unwrap();
+        let upper = ArtifactHashes {
+            sha256: actual.sha256.as_ref().map(|hash| hash.to_uppercase()),
+            ..actual.clone()
+        };
+        verify_file(&path, &upper).await.unwrap();
+        let wrong_md5 = ArtifactHashes {
+            md5: Some("0".repeat(32)),
+            ..actual.clone()
+        };
+        assert!(verify_file(&path, &wrong_md5).await.is_err());
+        let none = ArtifactHashes {
+            sha1: None,
+            md5: None,
+            sha256: None,
+        };
+        assert!(verify_file(&path, &none).await.is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,269 @@
+use async_trait::async_trait;
+use reqwest::header::{HeaderName, HeaderValue};
+use reqwest::{Client, RequestBuilder, StatusCode};
+use serde::Deserialize;
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+use crate::hawkbit::{HawkbitError, HawkbitResult};
+use crate::secrets::Secret;
+
+/// Adds credentials to management API requests.
+#[async_trait]
+pub trait AuthStrategy: Send + Sync + fmt::Debug {
+    async fn authenticate(&self, request: RequestBuilder) -> HawkbitResult<RequestBuilder>;
+
+    /// Drops cached credentials after the server answered 401. Returns true if
+    /// retrying the request with fresh credentials may succeed.
+    async fn invalidate(&self) -> bool {
+        false
+    }
+}
+
+pub struct BasicAuth {
+    username: String,
+    password: String,
+}
+
+impl BasicAuth {
+    pub fn new(username: &str, password: &str) -> Self {
+        Self {
+            username: username.to_string(),
+            password: password.to_string(),
+        }
+    }
+}
+
+impl fmt::Debug for BasicAuth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BasicAuth")
+            .field("username", &self.username)
+            .finish_non_exhaustive()
+    }
+}
+
+#[async_trait]
+impl AuthStrategy for BasicAuth {
+    async fn authenticate(&self, request: RequestBuilder) -> HawkbitResult<RequestBuilder> {
+        Ok(request.basic_auth(&self.username, Some(&self.password)))
+    }
+}
+
+/// A fixed bearer token, e.g. a personal access token of the SSO
+pub struct BearerAuth {
+    token: String,
+}
+
+impl BearerAuth {
+    pub fn new(token: &str) -> Self {
+        Self {
+            token: token.to_string(),
+        }
+    }
+}
+
+impl fmt::Debug for BearerAuth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BearerAuth").finish_non_exhaustive()
+    }
+}
+
+#[async_trait]
+impl AuthStrategy for BearerAuth {
+    async fn authenticate(&self, request: RequestBuilder) -> HawkbitResult<RequestBuilder> {
+        Ok(request.bearer_auth(&self.token))
+    }
+}
+
+/// Sends a fixed header, e.g. `Authorization: GatewayToken <token>` or the
+/// header expected by an authenticating reverse proxy.
+pub struct HeaderAuth {
+    name: HeaderName,
+    value: HeaderValue,
+}
+
+impl HeaderAuth {
+    pub fn new(name: &str, value: &str) -> HawkbitResult<Self> {
+        let name = HeaderName::from_bytes(name.as_bytes())
+            .map_err(|e| HawkbitError::new(format!("Invalid auth header name: {}", e)))?;
+        let mut value = HeaderValue::from_str(value)
+            .map_err(|e| HawkbitError::new(format!("Invalid auth header value: {}", e)))?;
+        value.set_sensitive(true);
+        Ok(Self { name, value })
+    }
+}
+
+impl fmt::Debug for HeaderAuth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HeaderAuth")
+            .field("name", &self.name)
+            .finish_non_exhaustive()
+    }
+}
+
+#[async_trait]
+impl AuthStrategy for HeaderAuth {
+    async fn authenticate(&self, request: RequestBuilder) -> HawkbitResult<RequestBuilder> {
+        Ok(request.header(self.name.clone(), self.value.clone()))
+    }
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    /// Lifetime in seconds
+    expires_in: Option<u64>,
+}
+
+struct CachedToken {
+    token: String,
+    expires_at: Instant,
+}
+
+/// OAuth2 client credentials grant. The access token is cached and fetched
+/// again shortly before it expires or when the server rejects it.
+pub struct OAuth2ClientCredentials {
+    http: Client,
+    token_url: String,
+    client_id: String,
+    client_secret: String,
+    scope: Option<String>,
+    cached: Mutex<Option<CachedToken>>,
+}
+
+// Tokens are refreshed this long before they expire
+const EXPIRY_MARGIN: Duration = Duration::from_secs(30);
+// Used when the token endpoint does not report a lifetime
+const DEFAULT_LIFETIME: Duration = Duration::from_secs(300);
+
+impl OAuth2ClientCredentials {
+    pub fn new(
+        http: Client,
+        token_url: &str,
+        client_id: &str,
+        client_secret: &str,
+        scope: Option<&str>,
+    ) -> Self {
+        Self {
+            http,
+            token_url: token_url.to_string(),
+            client_id: client_id.to_string(),
+            client_secret: client_secret.to_string(),
+            scope: scope.map(str::to_string),
+            cached: Mutex::new(None),
+        }
+    }
+
+    async fn fetch_token(&self) -> HawkbitResult<CachedToken> {
+        let mut form = vec![("grant_type", "client_credentials")];
+        if let Some(scope) = &self.scope {
+            form.push(("scope", scope.as_str()));
+        }
+        let res = self
+            .http
+            .post(&self.token_url)
+            .basic_auth(&self.client_id, Some(&self.client_secret))
+            .form(&form)
+            .send()
+            .await?;
+
+        let status = res.status();
+        if status != StatusCode::OK {
+            let body = res.text().await.unwrap_or_default();
+            return Err(HawkbitError::new(format!(
+                "Token request failed with HTTP {}: {}",
+                status.as_u16(),
+                body
+            )));
+        }
+
+        let response: TokenResponse = res.json().await?;
+        let lifetime = response
+            .expires_in
+            .map(Duration::from_secs)
+            .unwrap_or(DEFAULT_LIFETIME);
+        Ok(CachedToken {
+            token: response.access_token,
+            expires_at: Instant::now() + lifetime.saturating_sub(EXPIRY_MARGIN),
+        })
+    }
+}
+
+impl fmt::Debug for OAuth2ClientCredentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OAuth2ClientCredentials")
+            .field("token_url", &self.token_url)
+            .field("client_id", &self.client_id)
+            .field("scope", &self.scope)
+            .finish_non_exhaustive()
+    }
+}
+
+#[async_trait]
+impl AuthStrategy for OAuth2ClientCredentials {
+    async fn authenticate(&self, request: RequestBuilder) -> HawkbitResult<RequestBuilder> {
+        let mut cached = self.cached.lock().await;
+        let valid = cached
+            .as_ref()
+            .is_some_and(|token| token.expires_at > Instant::now());
+        if !valid {
+            *cached = Some(self.fetch_token().await?);
+        }
+        let token = &cached.as_ref().expect("token was just fetched").token;
+        Ok(request.bearer_auth(token))
+    }
+
+    async fn invalidate(&self) -> bool {
+        self.cached.lock().await.take();
+        true
+    }
+}
+
+/// How the client authenticates, selected with `HAWKBIT_AUTH`
+#[derive(Debug, Clone)]
+pub enum AuthConfig {
+    Basic {
+        username: String,
+        password: Secret,
+    },
+    Bearer {
+        token: Secret,
+    },
+    OAuth2 {
+        token_url: String,
+        client_id: String,
+        client_secret: Secret,
+        scope: Option<String>,
+    },
+    Header {
+        name: String,
+        value: Secret,
+    },
+}
+
+impl AuthConfig {
+    /// Builds the strategy; `http` is used for token requests.
+    pub fn strategy(&self, http: &Client) -> HawkbitResult<Arc<dyn AuthStrategy>> {
+        Ok(match self {
+            AuthConfig::Basic { username, password } => {
+                Arc::new(BasicAuth::new(username, password.expose()))
+            }
+            AuthConfig::Bearer { token } => Arc::new(BearerAuth::new(token.expose())),
+            AuthConfig::OAuth2 {
+                token_url,
+                client_id,
+                client_secret,
+                scope,
+            } => Arc::new(OAuth2ClientCredentials::new(
+                http.clone(),
+                token_url,
+                client_id,
+                client_secret.expose(),
+                scope.as_deref(),
+            )),
+            AuthConfig::Header { name, value } => Arc::new(HeaderAuth::new(name, value.expose())?),
+        })
+    }
+}
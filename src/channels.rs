@@ -0,0 +1,195 @@
+use futures::stream::{self, StreamExt};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+
+use crate::hawkbit::{AssignmentRequest, DistributionSet, HawkbitMgmtClient, HawkbitResult};
+
+pub const UPDATE_CHANNEL_ATTRIBUTE: &str = "update_channel";
+pub const BUILD_CHANNEL_ATTRIBUTE: &str = "build_channel";
+
+/// Name of the distribution set that devices on `channel` should run.
+pub fn distribution_set_name(channel: &str) -> String {
+    channel.to_owned() + " EMMC"
+}
+
+/// Keeps the newest distribution set for every name, as hawkBit allows
+/// several sets (versions) with the same name.
+pub fn latest_by_name(sets: Vec<DistributionSet>) -> HashMap<String, DistributionSet> {
+    let mut lookup: HashMap<String, DistributionSet> = HashMap::new();
+    for set in sets {
+        match lookup.get(&set.name) {
+            Some(existing) if existing.created_at >= set.created_at => {}
+            _ => {
+                lookup.insert(set.name.clone(), set);
+            }
+        }
+    }
+    lookup
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ChannelMismatch {
+    pub controller_id: String,
+    pub update_channel: Option<String>,
+    pub build_channel: Option<String>,
+}
+
+/// Mismatching targets sharing the same pair of channels
+#[derive(Debug, Clone, Serialize)]
+pub struct MismatchGroup {
+    pub update_channel: String,
+    pub build_channel: String,
+    pub targets: Vec<ChannelMismatch>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct ChannelAudit {
+    pub checked: usize,
+    /// Targets whose attributes could not be fetched
+    pub failed: Vec<String>,
+    pub mismatches: Vec<MismatchGroup>,
+}
+
+impl ChannelAudit {
+    pub fn mismatch_count(&self) -> usize {
+        self.mismatches
+            .iter()
+            .map(|group| group.targets.len())
+            .sum()
+    }
+
+    pub fn mismatched_targets(&self) -> impl Iterator<Item = &ChannelMismatch> {
+        self.mismatches
+            .iter()
+            .flat_map(|group| group.targets.iter())
+    }
+}
+
+fn label(channel: &Option<String>) -> String {
+    channel.clone().unwrap_or_else(|| "<unset>".to_string())
+}
+
+/// Compares `update_channel` and `build_channel` of all targets matching the
+/// filter, fetching at most `concurrency` attribute sets at a time.
+pub async fn audit_channels(
+    client: &HawkbitMgmtClient,
+    filter_query: Option<&str>,
+    concurrency: usize,
+) -> HawkbitResult<ChannelAudit> {
+    let targets = client.get_targets(filter_query).await?;
+
+    let results: Vec<_> = stream::iter(targets.iter())
+        .map(|target| async move {
+            let attributes = client
+                .get_target_attributes(&target.controller_id, None)
+                .await;
+            (target.controller_id.clone(), attributes)
+        })
+        .buffer_unordered(concurrency.max(1))
+        .collect()
+        .await;
+
+    let mut audit = ChannelAudit {
+        checked: results.len(),
+        ..Default::default()
+    };
+    let mut groups: BTreeMap<(String, String), Vec<ChannelMismatch>> = BTreeMap::new();
+    for (controller_id, attributes) in results {
+        let attributes = match attributes {
+            Ok(attributes) => attributes,
+            Err(e) => {
+                tracing::warn!("Failed to get attributes of {:?}: {}", controller_id, e);
+                audit.failed.push(controller_id);
+                continue;
+            }
+        };
+
+        let update_channel = attributes.get(UPDATE_CHANNEL_ATTRIBUTE).cloned();
+        let build_channel = attributes.get(BUILD_CHANNEL_ATTRIBUTE).cloned();
+        if update_channel == build_channel {
+            continue;
+        }
+
+        groups
+            .entry((label(&update_channel), label(&build_channel)))
+            .or_default()
+            .push(ChannelMismatch {
+                controller_id,
+                update_channel,
+                build_channel,
+            });
+    }
+
+    audit.mismatches = groups
+        .into_iter()
+        .map(|((update_channel, build_channel), mut targets)| {
+            targets.sort_by(|a, b| a.controller_id.cmp(&b.controller_id));
+            MismatchGroup {
+                update_channel,
+                build_channel,
+                targets,
+            }
+        })
+        .collect();
+    Ok(audit)
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct FixResult {
+    pub assigned: Vec<String>,
+    /// Targets for whose update channel no distribution set exists
+    pub no_distribution_set: Vec<String>,
+    pub failed: Vec<(String, String)>,
+}
+
+/// Assigns every mismatching target the newest distribution set of its
+/// update channel.
+pub async fn fix_mismatches(
+    client: &HawkbitMgmtClient,
+    audit: &ChannelAudit,
+    request: &AssignmentRequest,
+) -> HawkbitResult<FixResult> {
+    let lookup = latest_by_name(client.get_distribution_sets(None).await?);
+    let mut result = FixResult::default();
+
+    for mismatch in audit.mismatched_targets() {
+        let Some(set) = mismatch
+            .update_channel
+            .as_deref()
+            .and_then(|channel| lookup.get(&distribution_set_name(channel)))
+        else {
+            result
+                .no_distribution_set
+                .push(mismatch.controller_id.clone());
+            continue;
+        };
+
+        match client
+            .assign_distribution(&mismatch.controller_id, &set.id, request)
+            .await
+        {
+            Ok(_) => result.assigned.push(mismatch.controller_id.clone()),
+            Err(e) => result
+                .failed
+                .push((mismatch.controller_id.clone(), e.to_string())),
+        }
+    }
+    Ok(result)
+}
+
+/// Tags all mismatching targets so they can be found in the hawkBit UI.
+pub async fn tag_mismatches(
+    client: &HawkbitMgmtClient,
+    audit: &ChannelAudit,
+    tag_name: &str,
+) -> HawkbitResult<usize> {
+    let tag = client.ensure_target_tag(tag_name).await?;
+    let ids: Vec<&str> = audit
+        .mismatched_targets()
+        .map(|mismatch| mismatch.controller_id.as_str())
+        .collect();
+    for batch in ids.chunks(100) {
+        client.assign_target_tag(tag.id, batch).await?;
+    }
+    Ok(ids.len())
+}
@@ -0,0 +1,283 @@
+use chrono::Utc;
+use regex::Regex;
+use serde::Serialize;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+
+use crate::hawkbit::{HawkbitMgmtClient, HawkbitResult, MgmtTarget};
+use crate::undo::{self, TargetSnapshot, UndoJournal};
+
+/// Rules deciding which targets are deleted by the cleanup.
+///
+/// A target is deleted if it is on the deny list, or if its controller ID
+/// matches one of `id_patterns` and it has not polled for `min_age`. Targets
+/// on the allow list are never deleted.
+#[derive(Debug, Clone)]
+pub struct CleanupPolicy {
+    pub id_patterns: Vec<Regex>,
+    pub min_age: Duration,
+    /// Also delete matching targets that never polled
+    pub include_never_seen: bool,
+    pub allow_list: HashSet<String>,
+    pub deny_list: HashSet<String>,
+    /// Circuit breaker: delete nothing if more targets would be deleted
+    pub max_deletions: usize,
+    /// Directory receiving the full record of every target before deletion
+    pub export_dir: PathBuf,
+    /// Undo journal receiving a snapshot of every target before deletion
+    pub journal: PathBuf,
+    pub dry_run: bool,
+}
+
+impl Default for CleanupPolicy {
+    fn default() -> Self {
+        Self {
+            // Factory machines that never got a serial number assigned
+            id_patterns: vec![Regex::new("-999").unwrap()],
+            min_age: Duration::from_secs(3 * 24 * 3600),
+            include_never_seen: false,
+            allow_list: HashSet::new(),
+            deny_list: HashSet::new(),
+            max_deletions: 50,
+            export_dir: PathBuf::from("cleanup-exports"),
+            journal: PathBuf::from("undo-journal.jsonl"),
+            dry_run: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CleanupReason {
+    DenyListed,
+    /// Matched `pattern` and was last seen `idle_secs` ago (None: never seen)
+    Inactive {
+        pattern: String,
+        idle_secs: Option<u64>,
+    },
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CleanupCandidate {
+    pub controller_id: String,
+    pub reason: CleanupReason,
+}
+
+impl CleanupPolicy {
+    fn evaluate(&self, target: &MgmtTarget, now_ms: i64) -> Option<CleanupReason> {
+        if self.allow_list.contains(&target.controller_id) {
+            return None;
+        }
+        if self.deny_list.contains(&target.controller_id) {
+            return Some(CleanupReason::DenyListed);
+        }
+
+        let pattern = self
+            .id_patterns
+            .iter()
+            .find(|pattern| pattern.is_match(&target.controller_id))?;
+
+        let idle = target
+            .last_controller_request_at
+            .map(|last_seen| Duration::from_millis(now_ms.saturating_sub(last_seen).max(0) as u64));
+        let eligible = match idle {
+            Some(idle) => idle >= self.min_age,
+            None => self.include_never_seen,
+        };
+
+        eligible.then(|| CleanupReason::Inactive {
+            pattern: pattern.as_str().to_string(),
+            idle_secs: idle.map(|idle| idle.as_secs()),
+        })
+    }
+
+    /// Selects the targets this policy would delete.
+    pub fn plan(&self, targets: &[MgmtTarget]) -> Vec<CleanupCandidate> {
+        let now = Utc::now().timestamp_millis();
+        targets
+            .iter()
+            .filter_map(|target| {
+                self.evaluate(target, now).map(|reason| CleanupCandidate {
+                    controller_id: target.controller_id.clone(),
+                    reason,
+                })
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct CleanupReport {
+    pub candidates: Vec<CleanupCandidate>,
+    pub deleted: Vec<String>,
+    pub exports: Vec<PathBuf>,
+    pub failed: Vec<(String, String)>,
+    /// Set when the circuit breaker prevented any deletion
+    pub aborted: Option<String>,
+    pub dry_run: bool,
+}
+
+/// Writes the snapshot of a target to `<dir>/<controllerId>-<ts>.json`.
+/// The snapshot includes the security token, so the file is only readable
+/// by the current user.
+pub async fn export_target(snapshot: &TargetSnapshot, dir: &Path) -> HawkbitResult<PathBuf> {
+    tokio::fs::create_dir_all(dir).await?;
+    let path = dir.join(format!(
+        "{}-{}.json",
+        snapshot.controller_id,
+        Utc::now().format("%Y%m%dT%H%M%SZ")
+    ));
+    let mut options = tokio::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+    options
+        .open(&path)
+        .await?
+        .write_all(&serde_json::to_vec_pretty(snapshot)?)
+        .await?;
+    Ok(path)
+}
+
+/// Plans and runs the cleanup. Every target is exported and recorded in the
+/// undo journal before it is deleted; a target whose export or journal entry
+/// fails is not deleted.
+pub async fn run_cleanup(
+    client: &HawkbitMgmtClient,
+    policy: &CleanupPolicy,
+    targets: &[MgmtTarget],
+) -> CleanupReport {
+    let mut report = CleanupReport {
+        candidates: policy.plan(targets),
+        dry_run: policy.dry_run,
+        ..Default::default()
+    };
+
+    if report.candidates.len() > policy.max_deletions {
+        report.aborted = Some(format!(
+            "{} targets selected for deletion, more than the limit of {}",
+            report.candidates.len(),
+            policy.max_deletions
+        ));
+        return report;
+    }
+    if policy.dry_run {
+        return report;
+    }
+
+    let journal = UndoJournal::new(&policy.journal);
+    for candidate in &report.candidates {
+        let controller_id = &candidate.controller_id;
+        // One snapshot feeds both the export and the undo journal
+        let snapshot = match undo::capture_target(client, controller_id).await {
+            Ok(snapshot) => snapshot,
+            Err(e) => {
+                report.failed.push((controller_id.clone(), e.to_string()));
+                continue;
+            }
+        };
+        match export_target(&snapshot, &policy.export_dir).await {
+            Ok(path) => report.exports.push(path),
+            Err(e) => {
+                report
+                    .failed
+                    .push((controller_id.clone(), format!("export failed: {}", e)));
+                continue;
+            }
+        }
+
+        match undo::delete_captured(client, &journal, &snapshot).await {
+            Ok(()) => report.deleted.push(controller_id.clone()),
+            Err(e) => report.failed.push((controller_id.clone(), e.to_string())),
+        }
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const NOW: i64 = 1_000 * 24 * 3600 * 1000;
+    const DAY: i64 = 24 * 3600 * 1000;
+
+    fn target(controller_id: &str, status: &str, days_idle: Option<i64>) -> MgmtTarget {
+        serde_json::from_value(json!({
+            "_links": {},
+            "controllerId": controller_id,
+            "updateStatus": status,
+            "lastControllerRequestAt": days_idle.map(|days| NOW - days * DAY),
+        }))
+        .unwrap()
+    }
+
+    fn inactive(days: Option<u64>) -> Option<CleanupReason> {
+        Some(CleanupReason::Inactive {
+            pattern: "-999".to_string(),
+            idle_secs: days.map(|days| days * 24 * 3600),
+        })
+    }
+
+    #[test]
+    fn evaluate_table() {
+        let policy = CleanupPolicy {
+            allow_list: ["keep-999".to_string(), "both".to_string()].into(),
+            deny_list: ["broken".to_string(), "both".to_string()].into(),
+            ..Default::default()
+        };
+        let never_seen = CleanupPolicy {
+            include_never_seen: true,
+            ..policy.clone()
+        };
+
+        // Policy, controller ID, update status, days since the last poll,
+        // expected reason
+        let cases = [
+            (&policy, "dev-999", "registered", Some(3), inactive(Some(3))),
+            (&policy, "dev-999", "in_sync", Some(30), inactive(Some(30))),
+            (&policy, "dev-999", "error", Some(2), None),
+            (&policy, "dev-999", "registered", None, None),
+            (&never_seen, "dev-999", "registered", None, inactive(None)),
+            (&policy, "dev-001", "error", Some(30), None),
+            (&policy, "keep-999", "error", Some(30), None),
+            // The allow list wins over the deny list
+            (&policy, "both", "error", Some(30), None),
+            // Deny-listed targets are always deleted, whatever their
+            // status, age or ID
+            (
+                &policy,
+                "broken",
+                "in_sync",
+                Some(0),
+                Some(CleanupReason::DenyListed),
+            ),
+            (
+                &policy,
+                "broken",
+                "error",
+                None,
+                Some(CleanupReason::DenyListed),
+            ),
+        ];
+        for (policy, controller_id, status, days_idle, expected) in cases {
+            let target = target(controller_id, status, days_idle);
+            assert_eq!(
+                policy.evaluate(&target, NOW),
+                expected,
+                "{} {} {:?}",
+                controller_id,
+                status,
+                days_idle
+            );
+        }
+    }
+
+    #[test]
+    fn polls_in_the_future_count_as_active() {
+        let target = target("dev-999", "registered", Some(-1));
+        assert_eq!(CleanupPolicy::default().evaluate(&target, NOW), None);
+    }
+}
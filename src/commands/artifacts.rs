@@ -0,0 +1,105 @@
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use clap::Subcommand;
+use hawkbit_data_proxy_rs::artifacts::{self, AuditStatus};
+use hawkbit_data_proxy_rs::hawkbit::{HawkbitMgmtClient, HawkbitResult};
+
+#[derive(Subcommand)]
+pub enum ArtifactsCommand {
+    /// List the artifacts of every module in a distribution set
+    List { distribution_set: u64 },
+    /// Download and verify all artifacts of a distribution set
+    Download {
+        distribution_set: u64,
+        #[arg(long, default_value = ".")]
+        dir: PathBuf,
+    },
+    /// Compare a local build output directory against the artifacts on hawkBit
+    Verify { distribution_set: u64, dir: PathBuf },
+}
+
+pub async fn run(client: &HawkbitMgmtClient, command: ArtifactsCommand) -> ExitCode {
+    let result = match command {
+        ArtifactsCommand::List { distribution_set } => {
+            list_artifacts(client, distribution_set).await
+        }
+        ArtifactsCommand::Download {
+            distribution_set,
+            dir,
+        } => download_artifacts(client, distribution_set, &dir).await,
+        ArtifactsCommand::Verify {
+            distribution_set,
+            dir,
+        } => verify_artifacts(client, distribution_set, &dir).await,
+    };
+    match result {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(e) => {
+            println!("Error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn list_artifacts(client: &HawkbitMgmtClient, distribution_set: u64) -> HawkbitResult<bool> {
+    let set = client.get_distribution_set(distribution_set).await?;
+    println!(
+        "Distribution set: {} {} ({})",
+        set.name, set.version, set.id
+    );
+    for entry in artifacts::list_distribution_set_artifacts(client, &set).await? {
+        println!(
+            "  Module: {} {} ({}, id {})",
+            entry.module.name, entry.module.version, entry.module.type_name, entry.module.id
+        );
+        for artifact in &entry.artifacts {
+            println!(
+                "    {} size={} sha256={} sha1={} md5={}",
+                artifact.provided_filename,
+                artifact.size,
+                artifact.hashes.sha256.as_deref().unwrap_or("-"),
+                artifact.hashes.sha1.as_deref().unwrap_or("-"),
+                artifact.hashes.md5.as_deref().unwrap_or("-"),
+            );
+        }
+    }
+    Ok(true)
+}
+
+async fn download_artifacts(
+    client: &HawkbitMgmtClient,
+    distribution_set: u64,
+    dir: &Path,
+) -> HawkbitResult<bool> {
+    let set = client.get_distribution_set(distribution_set).await?;
+    let files = artifacts::download_distribution_set(client, &set, dir).await?;
+    for file in &files {
+        println!("Downloaded and verified: {:?}", file);
+    }
+    Ok(true)
+}
+
+async fn verify_artifacts(
+    client: &HawkbitMgmtClient,
+    distribution_set: u64,
+    dir: &Path,
+) -> HawkbitResult<bool> {
+    let set = client.get_distribution_set(distribution_set).await?;
+    let audits = artifacts::audit_distribution_set(client, &set, dir).await?;
+    let mut all_match = true;
+    for audit in &audits {
+        println!(
+            "{:?}: {} / {} ({} bytes) {:?} {}",
+            audit.status,
+            audit.module,
+            audit.filename,
+            audit.size,
+            audit.local_path,
+            audit.detail.as_deref().unwrap_or("")
+        );
+        all_match &= audit.status == AuditStatus::Match;
+    }
+    Ok(all_match)
+}
@@ -0,0 +1,119 @@
+use std::process::ExitCode;
+
+use chrono::DateTime;
+use clap::Args;
+use hawkbit_data_proxy_rs::hawkbit::{
+    ActionType, AssignmentRequest, HawkbitError, HawkbitMgmtClient, HawkbitResult,
+    MaintenanceWindow,
+};
+
+/// Number of targets sent per assignment request
+const ASSIGNMENT_BATCH_SIZE: usize = 100;
+
+#[derive(Args)]
+pub struct AssignArgs {
+    /// ID of the distribution set to assign
+    distribution_set: u64,
+    /// Controller IDs of the targets
+    targets: Vec<String>,
+    /// FIQL query selecting additional targets, e.g. `attribute.update_channel==stable`
+    #[arg(long)]
+    filter: Option<String>,
+    /// soft, forced, timeforced or downloadonly
+    #[arg(long = "type", default_value = "forced")]
+    action_type: ActionType,
+    /// RFC 3339 time after which a timeforced action is forced
+    #[arg(long)]
+    forcetime: Option<String>,
+    /// Action weight (0-1000), higher weights are processed first
+    #[arg(long)]
+    weight: Option<u32>,
+    /// Require the device (or an operator) to confirm the action
+    #[arg(long)]
+    confirmation_required: bool,
+    /// Quartz cron expression for the start of the maintenance window, e.g. `0 0 1 * * ?`
+    #[arg(long, requires_all = ["maintenance_duration", "maintenance_timezone"])]
+    maintenance_schedule: Option<String>,
+    /// Length of the maintenance window as HH:mm:ss
+    #[arg(long, requires = "maintenance_schedule")]
+    maintenance_duration: Option<String>,
+    /// Timezone offset of the maintenance schedule, e.g. `+01:00`
+    #[arg(long, requires = "maintenance_schedule")]
+    maintenance_timezone: Option<String>,
+}
+
+impl AssignArgs {
+    fn assignment_request(&self) -> HawkbitResult<AssignmentRequest> {
+        let mut request = AssignmentRequest::new(self.action_type);
+        if let Some(forcetime) = &self.forcetime {
+            let forcetime = DateTime::parse_from_rfc3339(forcetime)
+                .map_err(|e| HawkbitError::new(format!("Invalid forcetime: {}", e)))?;
+            request = request.with_forcetime(forcetime.timestamp_millis());
+        }
+        if let Some(weight) = self.weight {
+            request = request.with_weight(weight);
+        }
+        if self.confirmation_required {
+            request = request.with_confirmation_required(true);
+        }
+        if let (Some(schedule), Some(duration), Some(timezone)) = (
+            &self.maintenance_schedule,
+            &self.maintenance_duration,
+            &self.maintenance_timezone,
+        ) {
+            request = request.with_maintenance_window(MaintenanceWindow {
+                schedule: schedule.clone(),
+                duration: duration.clone(),
+                timezone: timezone.clone(),
+            });
+        }
+        request.validate()?;
+        Ok(request)
+    }
+}
+
+pub async fn run(client: &HawkbitMgmtClient, args: AssignArgs) -> ExitCode {
+    match assign(client, &args).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            println!("Error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn assign(client: &HawkbitMgmtClient, args: &AssignArgs) -> HawkbitResult<()> {
+    let request = args.assignment_request()?;
+
+    let mut targets = args.targets.clone();
+    if let Some(filter) = &args.filter {
+        for target in client.get_targets(Some(filter)).await? {
+            if !targets.contains(&target.controller_id) {
+                targets.push(target.controller_id);
+            }
+        }
+    }
+    if targets.is_empty() {
+        return Err(HawkbitError::new("No targets selected"));
+    }
+
+    let mut assigned = 0;
+    let mut already_assigned = 0;
+    for batch in targets.chunks(ASSIGNMENT_BATCH_SIZE) {
+        let ids: Vec<&str> = batch.iter().map(String::as_str).collect();
+        let response = client
+            .assign_targets(args.distribution_set, &ids, &request)
+            .await?;
+        assigned += response.assigned;
+        already_assigned += response.already_assigned;
+    }
+
+    println!(
+        "Assigned distribution set {} to {} targets ({} already assigned, {} requested)",
+        args.distribution_set,
+        assigned,
+        already_assigned,
+        targets.len()
+    );
+    Ok(())
+}
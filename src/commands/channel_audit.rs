@@ -0,0 +1,89 @@
+use std::process::ExitCode;
+
+use clap::Args;
+use hawkbit_data_proxy_rs::channels;
+use hawkbit_data_proxy_rs::hawkbit::{
+    ActionType, AssignmentRequest, HawkbitMgmtClient, HawkbitResult,
+};
+
+#[derive(Args)]
+pub struct ChannelAuditArgs {
+    /// FIQL query restricting the audited targets
+    #[arg(long)]
+    filter: Option<String>,
+    /// Number of attribute requests in flight
+    #[arg(long, default_value_t = 8)]
+    concurrency: usize,
+    /// Assign the newest distribution set of the update channel to mismatching targets
+    #[arg(long)]
+    fix: bool,
+    /// Action type used by --fix
+    #[arg(long = "type", default_value = "forced")]
+    action_type: ActionType,
+    /// Tag mismatching targets with this tag
+    #[arg(long)]
+    tag: Option<String>,
+    /// Print the audit as JSON
+    #[arg(long)]
+    json: bool,
+}
+
+pub async fn run(client: &HawkbitMgmtClient, args: ChannelAuditArgs) -> ExitCode {
+    match audit(client, &args).await {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(e) => {
+            println!("Error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn audit(client: &HawkbitMgmtClient, args: &ChannelAuditArgs) -> HawkbitResult<bool> {
+    let audit = channels::audit_channels(client, args.filter.as_deref(), args.concurrency).await?;
+
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&audit)?);
+    } else {
+        for group in &audit.mismatches {
+            println!(
+                "update_channel={:?} build_channel={:?}: {} targets",
+                group.update_channel,
+                group.build_channel,
+                group.targets.len()
+            );
+            for mismatch in &group.targets {
+                println!("  {}", mismatch.controller_id);
+            }
+        }
+        println!(
+            "Checked {} targets: {} mismatches, {} failed",
+            audit.checked,
+            audit.mismatch_count(),
+            audit.failed.len()
+        );
+    }
+
+    if let Some(tag) = &args.tag {
+        let tagged = channels::tag_mismatches(client, &audit, tag).await?;
+        println!("Tagged {} targets with {:?}", tagged, tag);
+    }
+
+    let mut ok = audit.failed.is_empty();
+    if args.fix {
+        let request = AssignmentRequest::new(args.action_type);
+        let result = channels::fix_mismatches(client, &audit, &request).await?;
+        println!("Reassigned {} targets", result.assigned.len());
+        for controller_id in &result.no_distribution_set {
+            println!(
+                "No distribution set for the update channel of {:?}",
+                controller_id
+            );
+        }
+        for (controller_id, error) in &result.failed {
+            println!("Failed to reassign {:?}: {}", controller_id, error);
+        }
+        ok &= result.failed.is_empty();
+    }
+    Ok(ok)
+}
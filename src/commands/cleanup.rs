@@ -0,0 +1,132 @@
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::time::Duration;
+
+use clap::Args;
+use hawkbit_data_proxy_rs::cleanup::{self, CleanupPolicy, CleanupReason, CleanupReport};
+use hawkbit_data_proxy_rs::hawkbit::{HawkbitMgmtClient, HawkbitResult};
+use regex::Regex;
+
+#[derive(Args)]
+pub struct CleanupArgs {
+    /// FIQL query restricting the targets considered for deletion
+    #[arg(long)]
+    pub(crate) filter: Option<String>,
+    /// Regex matched against controller IDs, may be repeated
+    #[arg(long = "pattern", default_value = "-999")]
+    patterns: Vec<Regex>,
+    /// Only delete matching targets that did not poll for this long
+    #[arg(long, value_parser = humantime::parse_duration, default_value = "3days")]
+    min_age: Duration,
+    /// Also delete matching targets that never polled
+    #[arg(long)]
+    include_never_seen: bool,
+    /// Controller ID that is never deleted, may be repeated
+    #[arg(long = "allow")]
+    allow_list: Vec<String>,
+    /// Controller ID that is always deleted, may be repeated
+    #[arg(long = "deny")]
+    deny_list: Vec<String>,
+    /// Delete nothing if more targets than this would be deleted
+    #[arg(long, default_value_t = 50)]
+    max_deletions: usize,
+    /// Directory receiving the record of every deleted target; `tenants
+    /// cleanup` uses one subdirectory per profile
+    #[arg(long, default_value = "cleanup-exports")]
+    export_dir: PathBuf,
+    /// Undo journal receiving a snapshot of every deleted target
+    #[arg(long, default_value = "undo-journal.jsonl")]
+    pub(crate) journal: PathBuf,
+    /// Actually delete; without it only the plan is printed
+    #[arg(long)]
+    yes: bool,
+    /// Print the report as JSON
+    #[arg(long)]
+    pub(crate) json: bool,
+}
+
+impl CleanupArgs {
+    pub(crate) fn policy(&self) -> CleanupPolicy {
+        CleanupPolicy {
+            id_patterns: self.patterns.clone(),
+            min_age: self.min_age,
+            include_never_seen: self.include_never_seen,
+            allow_list: self.allow_list.iter().cloned().collect(),
+            deny_list: self.deny_list.iter().cloned().collect(),
+            max_deletions: self.max_deletions,
+            export_dir: self.export_dir.clone(),
+            journal: self.journal.clone(),
+            dry_run: !self.yes,
+        }
+    }
+}
+
+pub async fn run(client: &HawkbitMgmtClient, args: CleanupArgs) -> ExitCode {
+    match run_cleanup(client, &args).await {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(e) => {
+            println!("Error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn run_cleanup(client: &HawkbitMgmtClient, args: &CleanupArgs) -> HawkbitResult<bool> {
+    let targets = client.get_targets(args.filter.as_deref()).await?;
+    let report = cleanup::run_cleanup(client, &args.policy(), &targets).await;
+
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        print_report(&report, &args.journal);
+    }
+    Ok(report.aborted.is_none() && report.failed.is_empty())
+}
+
+pub fn print_report(report: &CleanupReport, journal: &Path) {
+    for candidate in &report.candidates {
+        println!(
+            "{} ({})",
+            candidate.controller_id,
+            describe_reason(&candidate.reason)
+        );
+    }
+
+    if let Some(reason) = &report.aborted {
+        println!("Cleanup aborted, nothing deleted: {}", reason);
+    } else if report.dry_run {
+        println!(
+            "Dry run: {} targets would be deleted",
+            report.candidates.len()
+        );
+    } else {
+        println!(
+            "Deleted {} targets, records exported to {} files, restore them from {}",
+            report.deleted.len(),
+            report.exports.len(),
+            journal.display()
+        );
+    }
+    for (controller_id, error) in &report.failed {
+        println!("Failed to delete {:?}: {}", controller_id, error);
+    }
+}
+
+pub(crate) fn describe_reason(reason: &CleanupReason) -> String {
+    match reason {
+        CleanupReason::DenyListed => "deny list".to_string(),
+        CleanupReason::Inactive {
+            pattern,
+            idle_secs: Some(idle_secs),
+        } => format!(
+            "matches {:?}, idle for {}",
+            pattern,
+            humantime::format_duration(Duration::from_secs(*idle_secs))
+        ),
+        CleanupReason::Inactive {
+            pattern,
+            idle_secs: None,
+        } => format!("matches {:?}, never seen", pattern),
+    }
+}
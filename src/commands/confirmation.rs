@@ -0,0 +1,145 @@
+use std::process::ExitCode;
+use std::time::Duration;
+
+use clap::Subcommand;
+use hawkbit_data_proxy_rs::confirmation;
+use hawkbit_data_proxy_rs::hawkbit::{HawkbitMgmtClient, HawkbitResult};
+
+#[derive(Subcommand)]
+pub enum ConfirmationCommand {
+    /// Report actions stuck in wait_for_confirmation across all targets
+    Pending {
+        /// Only report actions waiting longer than this, e.g. `24h` or `3days`
+        #[arg(long, value_parser = humantime::parse_duration)]
+        older_than: Option<Duration>,
+    },
+    /// List the actions of a target that wait for confirmation
+    List { controller_id: String },
+    /// Confirm an action on behalf of the target
+    Confirm {
+        controller_id: String,
+        action_id: i64,
+        /// Message stored with the confirmation
+        #[arg(long)]
+        remark: Option<String>,
+    },
+    /// Deny an action on behalf of the target
+    Deny {
+        controller_id: String,
+        action_id: i64,
+        #[arg(long)]
+        remark: Option<String>,
+    },
+    /// Show, activate or deactivate auto-confirm of a target
+    #[command(subcommand)]
+    AutoConfirm(AutoConfirmCommand),
+}
+
+#[derive(Subcommand)]
+pub enum AutoConfirmCommand {
+    Status {
+        controller_id: String,
+    },
+    Activate {
+        controller_id: String,
+        /// Who requested the activation
+        #[arg(long)]
+        initiator: Option<String>,
+        #[arg(long)]
+        remark: Option<String>,
+    },
+    Deactivate {
+        controller_id: String,
+    },
+}
+
+pub async fn run(client: &HawkbitMgmtClient, command: ConfirmationCommand) -> ExitCode {
+    match run_command(client, command).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            println!("Error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn run_command(
+    client: &HawkbitMgmtClient,
+    command: ConfirmationCommand,
+) -> HawkbitResult<()> {
+    match command {
+        ConfirmationCommand::Pending { older_than } => {
+            let pending = confirmation::pending_confirmations(client, older_than).await?;
+            for entry in &pending {
+                println!(
+                    "{:?} action {} ({}) waiting for {} auto_confirm={}",
+                    entry.controller_id,
+                    entry.action_id,
+                    entry.distribution_set.as_deref().unwrap_or("unknown DS"),
+                    humantime::format_duration(Duration::from_secs(entry.waiting_for().as_secs())),
+                    entry
+                        .auto_confirm_active
+                        .map(|active| active.to_string())
+                        .unwrap_or_else(|| "unknown".to_string())
+                );
+            }
+            println!("Actions waiting for confirmation: {}", pending.len());
+        }
+        ConfirmationCommand::List { controller_id } => {
+            for action in client
+                .get_actions_waiting_for_confirmation(&controller_id)
+                .await?
+            {
+                println!(
+                    "Action {} ({}) since {}",
+                    action.id,
+                    action.distribution_set_name().unwrap_or_default(),
+                    action.last_modified_at
+                );
+            }
+        }
+        ConfirmationCommand::Confirm {
+            controller_id,
+            action_id,
+            remark,
+        } => {
+            let details: Vec<String> = remark.into_iter().collect();
+            client
+                .confirm_action(&controller_id, &action_id, true, None, &details)
+                .await?;
+            println!("Confirmed action {} of {:?}", action_id, controller_id);
+        }
+        ConfirmationCommand::Deny {
+            controller_id,
+            action_id,
+            remark,
+        } => {
+            let details: Vec<String> = remark.into_iter().collect();
+            client
+                .confirm_action(&controller_id, &action_id, false, None, &details)
+                .await?;
+            println!("Denied action {} of {:?}", action_id, controller_id);
+        }
+        ConfirmationCommand::AutoConfirm(command) => match command {
+            AutoConfirmCommand::Status { controller_id } => {
+                let state = client.get_auto_confirm(&controller_id).await?;
+                println!("{:?}: {:?}", controller_id, state);
+            }
+            AutoConfirmCommand::Activate {
+                controller_id,
+                initiator,
+                remark,
+            } => {
+                client
+                    .activate_auto_confirm(&controller_id, initiator.as_deref(), remark.as_deref())
+                    .await?;
+                println!("Activated auto-confirm for {:?}", controller_id);
+            }
+            AutoConfirmCommand::Deactivate { controller_id } => {
+                client.deactivate_auto_confirm(&controller_id).await?;
+                println!("Deactivated auto-confirm for {:?}", controller_id);
+            }
+        },
+    }
+    Ok(())
+}
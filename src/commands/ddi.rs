@@ -0,0 +1,337 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use clap::{Args, Subcommand, ValueEnum};
+use hawkbit_data_proxy_rs::artifacts;
+use hawkbit_data_proxy_rs::ddi::{
+    ConfigDataMode, DdiAuth, DdiClient, DeploymentBase, Execution, Feedback, Finished,
+};
+use hawkbit_data_proxy_rs::hawkbit::{HawkbitConfig, HawkbitError, HawkbitResult};
+use hawkbit_data_proxy_rs::secrets::{SecretSource, VaultLocation};
+use hawkbit_data_proxy_rs::transport::TransportConfig;
+
+#[derive(Args)]
+pub struct DdiArgs {
+    /// Controller ID of the device to act as
+    #[arg(long)]
+    controller_id: String,
+    /// Vault entry holding the security token of the target. Without it the
+    /// token is read from $HAWKBIT_TARGET_TOKEN or $HAWKBIT_GATEWAY_TOKEN,
+    /// or their `_FILE`/`_COMMAND` forms
+    #[arg(long, conflicts_with = "gateway_token_vault")]
+    target_token_vault: Option<String>,
+    /// Vault entry holding the gateway token of the tenant
+    #[arg(long)]
+    gateway_token_vault: Option<String>,
+    /// hawkBit URL; without it host, tenant and TLS settings come from the
+    /// config profile
+    #[arg(long)]
+    host: Option<String>,
+    /// Tenant, used with `--host`
+    #[arg(long, requires = "host")]
+    tenant: Option<String>,
+    #[command(subcommand)]
+    command: DdiCommand,
+}
+
+#[derive(Subcommand)]
+pub enum DdiCommand {
+    /// Poll the controller base resource and show what the server offers
+    Poll,
+    /// Show the deployment of an action, by default the one currently offered
+    Deployment {
+        #[arg(long)]
+        action: Option<String>,
+    },
+    /// Download and verify the artifacts of the offered deployment
+    Download {
+        #[arg(long, default_value = ".")]
+        dir: PathBuf,
+    },
+    /// Send feedback for an action
+    Feedback {
+        action: String,
+        #[arg(long, value_enum, default_value_t = FeedbackStatus::Success)]
+        status: FeedbackStatus,
+        /// Detail message, may be repeated
+        #[arg(long)]
+        message: Vec<String>,
+        /// Progress as `done/total`, e.g. `2/5`
+        #[arg(long)]
+        progress: Option<String>,
+        /// Feedback for a cancel action instead of a deployment
+        #[arg(long)]
+        cancel: bool,
+    },
+    /// Report device attributes given as `key=value`
+    ConfigData {
+        #[arg(required = true)]
+        attributes: Vec<String>,
+        /// Replace all stored attributes instead of merging
+        #[arg(long)]
+        replace: bool,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum FeedbackStatus {
+    Proceeding,
+    Success,
+    Failure,
+    Canceled,
+    Rejected,
+    Downloaded,
+}
+
+impl FeedbackStatus {
+    fn feedback(self) -> Feedback {
+        match self {
+            FeedbackStatus::Proceeding => Feedback::proceeding(),
+            FeedbackStatus::Success => Feedback::success(),
+            FeedbackStatus::Failure => Feedback::failure(),
+            FeedbackStatus::Canceled => Feedback::new(Execution::Canceled, Finished::Success),
+            FeedbackStatus::Rejected => Feedback::new(Execution::Rejected, Finished::None),
+            FeedbackStatus::Downloaded => Feedback::new(Execution::Downloaded, Finished::None),
+        }
+    }
+}
+
+pub async fn run(config: Option<&Path>, profile: Option<&str>, args: DdiArgs) -> ExitCode {
+    match run_command(config, profile, args).await {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(e) => {
+            println!("Error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Where a token may come from, and the kind of token it is
+type TokenSource = (Option<SecretSource>, fn(String) -> DdiAuth);
+
+/// Resolves the device credentials. Tokens are never taken from the command
+/// line, where other users could read them from the process list.
+fn auth(args: &DdiArgs) -> HawkbitResult<DdiAuth> {
+    dotenv::dotenv().ok();
+    let vault_entry =
+        |name: &Option<String>| name.clone().map(|vault| SecretSource::Vault { vault });
+    let sources: [TokenSource; 4] = [
+        (vault_entry(&args.target_token_vault), DdiAuth::TargetToken),
+        (
+            vault_entry(&args.gateway_token_vault),
+            DdiAuth::GatewayToken,
+        ),
+        (
+            SecretSource::from_env("HAWKBIT_TARGET_TOKEN"),
+            DdiAuth::TargetToken,
+        ),
+        (
+            SecretSource::from_env("HAWKBIT_GATEWAY_TOKEN"),
+            DdiAuth::GatewayToken,
+        ),
+    ];
+    let vault = VaultLocation::from_env(None);
+    for (source, auth) in sources {
+        if let Some(source) = source {
+            let token = source.resolve(&vault)?;
+            if !token.expose().is_empty() {
+                return Ok(auth(token.expose().to_string()));
+            }
+        }
+    }
+    Ok(DdiAuth::None)
+}
+
+fn client(
+    config: Option<&Path>,
+    profile: Option<&str>,
+    args: &DdiArgs,
+) -> HawkbitResult<DdiClient> {
+    let auth = auth(args)?;
+    match &args.host {
+        Some(host) => DdiClient::new(
+            host,
+            args.tenant.as_deref(),
+            &args.controller_id,
+            &auth,
+            &TransportConfig::default(),
+        ),
+        None => {
+            let config = HawkbitConfig::load(config, profile)?;
+            DdiClient::from_config(&config, &args.controller_id, &auth)
+        }
+    }
+}
+
+async fn offered_deployment(client: &DdiClient) -> HawkbitResult<Option<DeploymentBase>> {
+    let base = client.poll().await?;
+    match &base.links.deployment_base {
+        Some(link) => Ok(Some(client.get_deployment_base(&link.href).await?)),
+        None => Ok(None),
+    }
+}
+
+fn print_deployment(deployment: &DeploymentBase) {
+    println!(
+        "Action {}: download {:?}, update {:?}{}",
+        deployment.id,
+        deployment.deployment.download,
+        deployment.deployment.update,
+        match &deployment.deployment.maintenance_window {
+            Some(window) => format!(", maintenance window {}", window),
+            None => String::new(),
+        }
+    );
+    for chunk in &deployment.deployment.chunks {
+        println!("  {} {} {}", chunk.part, chunk.name, chunk.version);
+        for artifact in &chunk.artifacts {
+            println!("    {} ({} bytes)", artifact.filename, artifact.size);
+        }
+    }
+}
+
+fn parse_progress(progress: &str) -> HawkbitResult<(u32, u32)> {
+    let invalid = || {
+        HawkbitError::new(format!(
+            "Invalid progress {:?}, expected done/total",
+            progress
+        ))
+    };
+    let (cnt, of) = progress.split_once('/').ok_or_else(invalid)?;
+    Ok((
+        cnt.trim().parse().map_err(|_| invalid())?,
+        of.trim().parse().map_err(|_| invalid())?,
+    ))
+}
+
+async fn run_command(
+    config: Option<&Path>,
+    profile: Option<&str>,
+    args: DdiArgs,
+) -> HawkbitResult<bool> {
+    let client = client(config, profile, &args)?;
+
+    match args.command {
+        DdiCommand::Poll => {
+            let base = client.poll().await?;
+            println!(
+                "{}: next poll in {}",
+                client.controller_id(),
+                base.config.polling.sleep
+            );
+            let links = &base.links;
+            let offered = [
+                ("deployment", &links.deployment_base),
+                ("cancel action", &links.cancel_action),
+                ("confirmation", &links.confirmation_base),
+                ("config data request", &links.config_data),
+                ("installed distribution set", &links.installed_base),
+            ];
+            for (what, link) in offered {
+                if let Some(link) = link {
+                    println!("  {}: {}", what, link.href);
+                }
+            }
+            if let Some(link) = &links.cancel_action {
+                let cancel = client.get_cancel_action(&link.href).await?;
+                println!(
+                    "  cancel requested for action {}",
+                    cancel.cancel_action.stop_id
+                );
+            }
+        }
+        DdiCommand::Deployment { action } => {
+            let deployment = match action {
+                Some(action) => client.get_deployment(&action).await?,
+                None => match offered_deployment(&client).await? {
+                    Some(deployment) => deployment,
+                    None => {
+                        println!("No deployment offered to {}", client.controller_id());
+                        return Ok(true);
+                    }
+                },
+            };
+            print_deployment(&deployment);
+        }
+        DdiCommand::Download { dir } => {
+            let Some(deployment) = offered_deployment(&client).await? else {
+                println!("No deployment offered to {}", client.controller_id());
+                return Ok(true);
+            };
+            tokio::fs::create_dir_all(&dir).await?;
+            let mut ok = true;
+            for chunk in &deployment.deployment.chunks {
+                for artifact in &chunk.artifacts {
+                    let name = match artifacts::safe_file_name(&artifact.filename) {
+                        Ok(name) => name,
+                        Err(e) => {
+                            println!("{:?}\tFAILED: {}", artifact.filename, e);
+                            ok = false;
+                            continue;
+                        }
+                    };
+                    let dest = dir.join(name);
+                    match client.download_artifact(artifact, &dest).await {
+                        Ok(size) => println!("{}\t{} bytes, verified", dest.display(), size),
+                        Err(e) => {
+                            println!("{}\tFAILED: {}", dest.display(), e);
+                            ok = false;
+                        }
+                    }
+                }
+            }
+            return Ok(ok);
+        }
+        DdiCommand::Feedback {
+            action,
+            status,
+            message,
+            progress,
+            cancel,
+        } => {
+            let mut feedback = status.feedback();
+            for message in message {
+                feedback = feedback.with_message(message);
+            }
+            if let Some(progress) = progress {
+                let (cnt, of) = parse_progress(&progress)?;
+                feedback = feedback.with_progress(cnt, of);
+            }
+            if cancel {
+                client.send_cancel_feedback(&action, &feedback).await?;
+            } else {
+                client.send_deployment_feedback(&action, &feedback).await?;
+            }
+            println!("Sent feedback for action {}", action);
+        }
+        DdiCommand::ConfigData {
+            attributes,
+            replace,
+        } => {
+            let attributes = attributes
+                .iter()
+                .map(|attribute| {
+                    attribute
+                        .split_once('=')
+                        .map(|(key, value)| (key.to_string(), value.to_string()))
+                        .ok_or_else(|| {
+                            HawkbitError::new(format!(
+                                "Invalid attribute {:?}, expected key=value",
+                                attribute
+                            ))
+                        })
+                })
+                .collect::<HawkbitResult<HashMap<_, _>>>()?;
+            let mode = if replace {
+                ConfigDataMode::Replace
+            } else {
+                ConfigDataMode::Merge
+            };
+            client.put_config_data(&attributes, mode).await?;
+            println!("Reported {} attributes", attributes.len());
+        }
+    }
+    Ok(true)
+}
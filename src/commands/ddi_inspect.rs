@@ -0,0 +1,197 @@
+use std::process::ExitCode;
+
+use chrono::{DateTime, Utc};
+use clap::Args;
+use hawkbit_data_proxy_rs::ddi_recorder::{self, DeviceSummary, Exchange};
+use hawkbit_data_proxy_rs::hawkbit::HawkbitResult;
+use hawkbit_data_proxy_rs::transport::{TransportConfig, build_client};
+use reqwest::header::HeaderMap;
+use serde_json::Value;
+
+#[derive(Args)]
+pub struct DdiInspectArgs {
+    /// Device to show in detail; without it all devices are summarized
+    controller_id: Option<String>,
+    /// Inspection API of a `ddi-proxy --record`
+    #[arg(long, default_value = "http://127.0.0.1:8082")]
+    proxy: String,
+    /// Print the recorded requests as JSON, including their bodies
+    #[arg(long)]
+    json: bool,
+}
+
+pub async fn run(args: DdiInspectArgs) -> ExitCode {
+    match inspect(&args).await {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(e) => {
+            println!("Error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn format_timestamp(timestamp: i64) -> String {
+    DateTime::<Utc>::from_timestamp_millis(timestamp)
+        .map(|t| t.format("%Y-%m-%d %H:%M:%S%.3f UTC").to_string())
+        .unwrap_or_else(|| timestamp.to_string())
+}
+
+fn text(value: Option<&Value>) -> &str {
+    value.and_then(Value::as_str).unwrap_or("?")
+}
+
+/// What the server told the device to do on its last poll
+fn describe_poll(response: &Value) -> String {
+    let sleep = text(response.pointer("/config/polling/sleep"));
+    let offered: Vec<_> = response
+        .get("_links")
+        .and_then(Value::as_object)
+        .map(|links| links.keys().map(String::as_str).collect())
+        .unwrap_or_default();
+    if offered.is_empty() {
+        format!("nothing to do, next poll in {}", sleep)
+    } else {
+        format!("offered {}, next poll in {}", offered.join(", "), sleep)
+    }
+}
+
+fn describe_deployment(response: &Value) -> String {
+    let chunks: Vec<_> = response
+        .pointer("/deployment/chunks")
+        .and_then(Value::as_array)
+        .map(|chunks| {
+            chunks
+                .iter()
+                .map(|chunk| format!("{} {}", text(chunk.get("name")), text(chunk.get("version"))))
+                .collect()
+        })
+        .unwrap_or_default();
+    format!(
+        "action {}: download {}, update {}, {}",
+        text(response.get("id")),
+        text(response.pointer("/deployment/download")),
+        text(response.pointer("/deployment/update")),
+        chunks.join(", ")
+    )
+}
+
+fn describe_feedback(request: &Value) -> String {
+    let details: Vec<_> = request
+        .pointer("/status/details")
+        .and_then(Value::as_array)
+        .map(|details| details.iter().filter_map(Value::as_str).collect())
+        .unwrap_or_default();
+    let mut description = format!(
+        "{}/{}",
+        text(request.pointer("/status/execution")),
+        text(request.pointer("/status/result/finished"))
+    );
+    if !details.is_empty() {
+        description.push_str(&format!(": {}", details.join("; ")));
+    }
+    description
+}
+
+fn print_exchange(what: &str, exchange: &Option<Exchange>, describe: fn(&Exchange) -> String) {
+    let Some(exchange) = exchange else {
+        println!("  {:<11} none recorded", what);
+        return;
+    };
+    // Bodies of failed requests are error messages, not DDI resources
+    let description = if (200..300).contains(&exchange.status) {
+        describe(exchange)
+    } else {
+        exchange
+            .error
+            .clone()
+            .unwrap_or_else(|| "request failed".to_string())
+    };
+    println!(
+        "  {:<11} {} HTTP {} in {} ms, {}",
+        what,
+        format_timestamp(exchange.timestamp),
+        exchange.status,
+        exchange.duration_ms,
+        description
+    );
+}
+
+fn print_summary(summary: &DeviceSummary) {
+    println!(
+        "{}: last seen {}, {} requests",
+        summary.controller_id,
+        format_timestamp(summary.last_seen),
+        summary.exchanges
+    );
+    print_exchange("poll:", &summary.last_poll, |exchange| {
+        exchange
+            .response
+            .as_ref()
+            .map(describe_poll)
+            .unwrap_or_default()
+    });
+    print_exchange("deployment:", &summary.last_deployment, |exchange| {
+        exchange
+            .response
+            .as_ref()
+            .map(describe_deployment)
+            .unwrap_or_default()
+    });
+    print_exchange("feedback:", &summary.last_feedback, |exchange| {
+        let feedback = exchange
+            .request
+            .as_ref()
+            .map(describe_feedback)
+            .unwrap_or_default();
+        format!("{} {}", exchange.path, feedback)
+    });
+}
+
+async fn inspect(args: &DdiInspectArgs) -> HawkbitResult<bool> {
+    let client = build_client(&TransportConfig::default(), HeaderMap::new())?;
+
+    let Some(controller_id) = &args.controller_id else {
+        let devices = ddi_recorder::fetch_devices(&client, &args.proxy).await?;
+        if args.json {
+            println!("{}", serde_json::to_string_pretty(&devices)?);
+            return Ok(true);
+        }
+        if devices.is_empty() {
+            println!("No devices recorded");
+        }
+        for summary in &devices {
+            print_summary(summary);
+        }
+        return Ok(true);
+    };
+
+    let Some(device) = ddi_recorder::fetch_device(&client, &args.proxy, controller_id).await?
+    else {
+        println!("No requests of {} recorded", controller_id);
+        return Ok(false);
+    };
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&device)?);
+        return Ok(true);
+    }
+    print_summary(&device.summary);
+    println!("\nLast {} requests:", device.history.len());
+    for exchange in &device.history {
+        println!(
+            "  {} {:<4} {} -> {} in {} ms{}{}",
+            format_timestamp(exchange.timestamp),
+            exchange.method,
+            exchange.path,
+            exchange.status,
+            exchange.duration_ms,
+            if exchange.cached { " (cached)" } else { "" },
+            exchange
+                .error
+                .as_ref()
+                .map(|e| format!(": {}", e))
+                .unwrap_or_default()
+        );
+    }
+    Ok(true)
+}
@@ -0,0 +1,108 @@
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use clap::Args;
+use hawkbit_data_proxy_rs::ddi_proxy::{self, CacheConfig, ProxyConfig, parse_size};
+use hawkbit_data_proxy_rs::ddi_recorder::RecorderConfig;
+use hawkbit_data_proxy_rs::hawkbit::{HawkbitConfig, HawkbitResult};
+use hawkbit_data_proxy_rs::transport::TransportConfig;
+
+#[derive(Args)]
+pub struct DdiProxyArgs {
+    /// Address to accept device connections on
+    #[arg(long, default_value = "0.0.0.0:8081")]
+    listen: SocketAddr,
+    /// URL under which devices reach the proxy, used in the links handed
+    /// to them
+    #[arg(long)]
+    public_url: String,
+    /// hawkBit URL; without it host and TLS settings come from the config
+    /// profile
+    #[arg(long)]
+    upstream: Option<String>,
+    /// Directory holding the cached artifacts
+    #[arg(long, default_value = "artifact-cache")]
+    cache_dir: PathBuf,
+    /// Maximum size of the cache, e.g. `500M` or `20GiB`
+    #[arg(long, default_value = "10G")]
+    cache_size: String,
+    /// Pass artifact downloads through instead of caching them
+    #[arg(long)]
+    no_cache: bool,
+    /// Record the requests of each device for `ddi-inspect`
+    #[arg(long)]
+    record: bool,
+    /// Address of the inspection API of `--record`
+    #[arg(long, default_value = "127.0.0.1:8082")]
+    inspect_listen: SocketAddr,
+    /// Number of requests kept per device
+    #[arg(long, default_value_t = 50)]
+    history: usize,
+    /// Number of devices recorded; the least recently seen make room for new
+    /// ones
+    #[arg(long, default_value_t = 10_000)]
+    max_devices: usize,
+    /// Also append every request to this JSON lines file
+    #[arg(long, requires = "record")]
+    record_log: Option<PathBuf>,
+    /// Size at which the request log is rotated
+    #[arg(long, default_value = "100M")]
+    record_log_size: String,
+}
+
+pub async fn run(config: Option<&Path>, profile: Option<&str>, args: DdiProxyArgs) -> ExitCode {
+    match serve(config, profile, args).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            println!("Error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn serve(
+    config: Option<&Path>,
+    profile: Option<&str>,
+    args: DdiProxyArgs,
+) -> HawkbitResult<()> {
+    let (upstream, transport) = match args.upstream {
+        Some(upstream) => (upstream, TransportConfig::default()),
+        None => {
+            let config = HawkbitConfig::load(config, profile)?;
+            (config.host().to_string(), config.transport().clone())
+        }
+    };
+    let config = ProxyConfig {
+        listen: args.listen,
+        upstream,
+        public_url: args.public_url,
+        cache: if args.no_cache {
+            None
+        } else {
+            Some(CacheConfig {
+                dir: args.cache_dir,
+                max_size: parse_size(&args.cache_size)?,
+            })
+        },
+        recorder: if args.record {
+            Some(RecorderConfig {
+                listen: args.inspect_listen,
+                history: args.history,
+                max_devices: args.max_devices,
+                log: args.record_log,
+                log_max_size: parse_size(&args.record_log_size)?,
+            })
+        } else {
+            None
+        },
+        transport,
+    };
+
+    let shutdown = async {
+        if tokio::signal::ctrl_c().await.is_err() {
+            std::future::pending::<()>().await;
+        }
+    };
+    ddi_proxy::run(&config, shutdown).await
+}
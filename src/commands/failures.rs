@@ -0,0 +1,91 @@
+use std::process::ExitCode;
+use std::time::Duration;
+
+use chrono::Utc;
+use clap::Args;
+use hawkbit_data_proxy_rs::failures::{self, SignatureCount};
+use hawkbit_data_proxy_rs::hawkbit::{HawkbitMgmtClient, HawkbitResult};
+
+#[derive(Args)]
+pub struct FailuresArgs {
+    /// Only analyze actions that failed within this window, e.g. `7days`
+    #[arg(long, value_parser = humantime::parse_duration, default_value = "7days")]
+    since: Duration,
+    /// Number of signatures shown per group
+    #[arg(long, default_value_t = 5)]
+    top: usize,
+    /// Print the full report as JSON
+    #[arg(long)]
+    json: bool,
+}
+
+pub async fn run(client: &HawkbitMgmtClient, args: FailuresArgs) -> ExitCode {
+    match analyze(client, &args).await {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(e) => {
+            println!("Error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn print_signatures(signatures: &[SignatureCount], top: usize, indent: &str) {
+    for signature in signatures.iter().take(top) {
+        println!(
+            "{}{:5.1}% {:4}x {}",
+            indent,
+            signature.share * 100.0,
+            signature.count,
+            signature.signature
+        );
+    }
+    if signatures.len() > top {
+        println!("{}... {} more signatures", indent, signatures.len() - top);
+    }
+}
+
+async fn analyze(client: &HawkbitMgmtClient, args: &FailuresArgs) -> HawkbitResult<bool> {
+    let now = Utc::now().timestamp_millis().max(0) as u64;
+    let since = now.saturating_sub(args.since.as_millis() as u64);
+
+    let failures = failures::collect_failures(client, since).await?;
+    let report = failures::build_report(since, failures);
+
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        return Ok(report.unreadable.is_empty());
+    }
+
+    println!(
+        "Failed actions in the last {}: {}",
+        humantime::format_duration(args.since),
+        report.total
+    );
+    print_signatures(&report.signatures, args.top, "  ");
+
+    println!("\nBy distribution set:");
+    for (distribution_set, signatures) in &report.by_distribution_set {
+        let count: usize = signatures.iter().map(|s| s.count).sum();
+        println!("  {} ({} failures)", distribution_set, count);
+        print_signatures(signatures, args.top, "    ");
+    }
+
+    println!("\nBy channel:");
+    for (channel, signatures) in &report.by_channel {
+        let count: usize = signatures.iter().map(|s| s.count).sum();
+        println!("  {} ({} failures)", channel, count);
+        print_signatures(signatures, args.top, "    ");
+    }
+
+    if !report.unreadable.is_empty() {
+        println!("\nNot included, status unreadable:");
+        for action in &report.unreadable {
+            println!(
+                "  {:?} action {}: {}",
+                action.controller_id, action.action_id, action.error
+            );
+        }
+    }
+    Ok(report.unreadable.is_empty())
+}
@@ -0,0 +1,272 @@
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use clap::Subcommand;
+use hawkbit_data_proxy_rs::hawkbit::{
+    HawkbitMgmtClient, HawkbitResult, MetadataEntry, SoftwareModuleMetadata,
+};
+use hawkbit_data_proxy_rs::metadata;
+
+#[derive(Subcommand)]
+pub enum MetadataCommand {
+    /// Manage target metadata
+    #[command(subcommand)]
+    Target(TargetMetadataCommand),
+    /// Manage distribution set metadata
+    #[command(subcommand)]
+    Ds(DistributionSetMetadataCommand),
+    /// Manage software module metadata
+    #[command(subcommand)]
+    Sm(SoftwareModuleMetadataCommand),
+}
+
+#[derive(Subcommand)]
+pub enum TargetMetadataCommand {
+    /// List all metadata of a target
+    List {
+        controller_id: String,
+        /// FIQL filter, e.g. `key==serial*`
+        #[arg(long)]
+        filter: Option<String>,
+    },
+    /// Show a single metadata value
+    Get { controller_id: String, key: String },
+    /// Create or update a metadata value
+    Set {
+        controller_id: String,
+        key: String,
+        value: String,
+    },
+    /// Delete a metadata key
+    Delete { controller_id: String, key: String },
+    /// Bulk-set metadata from a CSV with a controllerId column and one column per key
+    Import { csv: PathBuf },
+}
+
+#[derive(Subcommand)]
+pub enum DistributionSetMetadataCommand {
+    /// List all metadata of a distribution set
+    List {
+        distribution_set: u64,
+        #[arg(long)]
+        filter: Option<String>,
+    },
+    /// Show a single metadata value
+    Get { distribution_set: u64, key: String },
+    /// Create or update a metadata value
+    Set {
+        distribution_set: u64,
+        key: String,
+        value: String,
+    },
+    /// Delete a metadata key
+    Delete { distribution_set: u64, key: String },
+}
+
+#[derive(Subcommand)]
+pub enum SoftwareModuleMetadataCommand {
+    /// List all metadata of a software module
+    List {
+        module: u64,
+        #[arg(long)]
+        filter: Option<String>,
+    },
+    /// Show a single metadata value
+    Get { module: u64, key: String },
+    /// Create or update a metadata value
+    Set {
+        module: u64,
+        key: String,
+        value: String,
+        /// Deliver the entry to devices as part of the deployment
+        #[arg(long)]
+        target_visible: bool,
+    },
+    /// Delete a metadata key
+    Delete { module: u64, key: String },
+}
+
+pub async fn run(client: &HawkbitMgmtClient, command: MetadataCommand) -> ExitCode {
+    let result = match command {
+        MetadataCommand::Target(command) => run_target(client, command).await,
+        MetadataCommand::Ds(command) => run_distribution_set(client, command).await,
+        MetadataCommand::Sm(command) => run_software_module(client, command).await,
+    };
+    match result {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(e) => {
+            println!("Error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn print_entries(entries: &[MetadataEntry]) {
+    for entry in entries {
+        println!("{}={}", entry.key, entry.value);
+    }
+}
+
+async fn run_target(
+    client: &HawkbitMgmtClient,
+    command: TargetMetadataCommand,
+) -> HawkbitResult<bool> {
+    match command {
+        TargetMetadataCommand::List {
+            controller_id,
+            filter,
+        } => {
+            let entries = client
+                .get_target_metadata(&controller_id, filter.as_deref())
+                .await?;
+            print_entries(&entries);
+        }
+        TargetMetadataCommand::Get { controller_id, key } => {
+            let entry = client
+                .get_target_metadata_value(&controller_id, &key)
+                .await?;
+            print_entries(&[entry]);
+        }
+        TargetMetadataCommand::Set {
+            controller_id,
+            key,
+            value,
+        } => {
+            let changes = metadata::upsert_target_metadata(
+                client,
+                &controller_id,
+                &[MetadataEntry { key, value }],
+            )
+            .await?;
+            println!("{:?}: {:?}", controller_id, changes);
+        }
+        TargetMetadataCommand::Delete { controller_id, key } => {
+            client.delete_target_metadata(&controller_id, &key).await?;
+            println!("Deleted {:?} from {:?}", key, controller_id);
+        }
+        TargetMetadataCommand::Import { csv } => {
+            let rows = metadata::read_metadata_csv(&csv)?;
+            let mut failed = 0;
+            for row in &rows {
+                match metadata::upsert_target_metadata(client, &row.controller_id, &row.entries)
+                    .await
+                {
+                    Ok(changes) => println!(
+                        "{:?}: created {:?}, updated {:?}, unchanged {:?}",
+                        row.controller_id, changes.created, changes.updated, changes.unchanged
+                    ),
+                    Err(e) => {
+                        failed += 1;
+                        println!("{:?}: failed to set metadata: {}", row.controller_id, e);
+                    }
+                }
+            }
+            println!(
+                "Imported metadata for {} of {} targets",
+                rows.len() - failed,
+                rows.len()
+            );
+            return Ok(failed == 0);
+        }
+    }
+    Ok(true)
+}
+
+async fn run_distribution_set(
+    client: &HawkbitMgmtClient,
+    command: DistributionSetMetadataCommand,
+) -> HawkbitResult<bool> {
+    match command {
+        DistributionSetMetadataCommand::List {
+            distribution_set,
+            filter,
+        } => {
+            let entries = client
+                .get_distribution_set_metadata(distribution_set, filter.as_deref())
+                .await?;
+            print_entries(&entries);
+        }
+        DistributionSetMetadataCommand::Get {
+            distribution_set,
+            key,
+        } => {
+            let entry = client
+                .get_distribution_set_metadata_value(distribution_set, &key)
+                .await?;
+            print_entries(&[entry]);
+        }
+        DistributionSetMetadataCommand::Set {
+            distribution_set,
+            key,
+            value,
+        } => {
+            let changes = metadata::upsert_distribution_set_metadata(
+                client,
+                distribution_set,
+                &[MetadataEntry { key, value }],
+            )
+            .await?;
+            println!("{}: {:?}", distribution_set, changes);
+        }
+        DistributionSetMetadataCommand::Delete {
+            distribution_set,
+            key,
+        } => {
+            client
+                .delete_distribution_set_metadata(distribution_set, &key)
+                .await?;
+            println!("Deleted {:?} from {}", key, distribution_set);
+        }
+    }
+    Ok(true)
+}
+
+fn print_module_entries(entries: &[SoftwareModuleMetadata]) {
+    for entry in entries {
+        println!(
+            "{}={} (targetVisible={})",
+            entry.key, entry.value, entry.target_visible
+        );
+    }
+}
+
+async fn run_software_module(
+    client: &HawkbitMgmtClient,
+    command: SoftwareModuleMetadataCommand,
+) -> HawkbitResult<bool> {
+    match command {
+        SoftwareModuleMetadataCommand::List { module, filter } => {
+            let entries = client
+                .get_software_module_metadata(module, filter.as_deref())
+                .await?;
+            print_module_entries(&entries);
+        }
+        SoftwareModuleMetadataCommand::Get { module, key } => {
+            let entry = client
+                .get_software_module_metadata_value(module, &key)
+                .await?;
+            print_module_entries(&[entry]);
+        }
+        SoftwareModuleMetadataCommand::Set {
+            module,
+            key,
+            value,
+            target_visible,
+        } => {
+            let entry = SoftwareModuleMetadata {
+                key,
+                value,
+                target_visible,
+            };
+            let changes =
+                metadata::upsert_software_module_metadata(client, module, &[entry]).await?;
+            println!("{}: {:?}", module, changes);
+        }
+        SoftwareModuleMetadataCommand::Delete { module, key } => {
+            client.delete_software_module_metadata(module, &key).await?;
+            println!("Deleted {:?} from {}", key, module);
+        }
+    }
+    Ok(true)
+}
@@ -0,0 +1,93 @@
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use clap::Args;
+use hawkbit_data_proxy_rs::hawkbit::{
+    HawkbitConfig, HawkbitError, HawkbitMgmtClient, HawkbitResult,
+};
+use hawkbit_data_proxy_rs::migration::{Migration, MigrationOptions, MigrationState};
+
+#[derive(Args)]
+pub struct MigrateArgs {
+    /// Profile of the instance to copy from
+    #[arg(long)]
+    from: String,
+    /// Profile of the instance to copy to
+    #[arg(long)]
+    to: String,
+    /// Progress file, rerunning with it resumes an interrupted migration
+    #[arg(long, default_value = "migration-state.jsonl")]
+    state: PathBuf,
+    /// Directory holding artifacts while they are copied
+    #[arg(long, default_value = "migration-artifacts")]
+    download_dir: PathBuf,
+    /// FIQL query restricting the migrated targets
+    #[arg(long)]
+    filter: Option<String>,
+    /// Print the report as JSON
+    #[arg(long)]
+    json: bool,
+}
+
+pub async fn run(config: Option<&Path>, args: MigrateArgs) -> ExitCode {
+    match migrate(config, args).await {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(e) => {
+            println!("Error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Identifies an instance in the state file
+fn instance(config: &HawkbitConfig) -> String {
+    match config.tenant() {
+        Some(tenant) => format!("{} ({})", config.host(), tenant),
+        None => config.host().to_string(),
+    }
+}
+
+async fn migrate(config: Option<&Path>, args: MigrateArgs) -> HawkbitResult<bool> {
+    let mut profiles =
+        HawkbitConfig::load_profiles(config, &[args.from.clone(), args.to.clone()])?.into_iter();
+    let (_, source) = profiles.next().expect("two profiles were requested");
+    let (_, destination) = profiles.next().expect("two profiles were requested");
+    let (source, destination) = (source?, destination?);
+    if instance(&source) == instance(&destination) {
+        return Err(HawkbitError::new(
+            "Source and destination are the same instance",
+        ));
+    }
+
+    let mut state = MigrationState::open(&args.state, &instance(&source), &instance(&destination))?;
+    let options = MigrationOptions {
+        target_filter: args.filter,
+        download_dir: args.download_dir,
+    };
+    let source = HawkbitMgmtClient::from_config(&source)?;
+    let destination = HawkbitMgmtClient::from_config(&destination)?;
+    let reports = Migration::new(&source, &destination, &mut state, &options)
+        .run()
+        .await?;
+
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&reports)?);
+    } else {
+        for report in &reports {
+            println!(
+                "{}: {} created, {} existing, {} resumed, {} failed",
+                report.phase,
+                report.created,
+                report.existing,
+                report.resumed,
+                report.failed.len()
+            );
+            for (key, error) in &report.failed {
+                println!("  FAILED {}: {}", key, error);
+            }
+        }
+        println!("Progress saved to {}", args.state.display());
+    }
+    Ok(reports.iter().all(|report| report.failed.is_empty()))
+}
@@ -0,0 +1,21 @@
+pub mod artifacts;
+pub mod assign;
+pub mod channel_audit;
+pub mod cleanup;
+pub mod confirmation;
+pub mod ddi;
+pub mod ddi_inspect;
+pub mod ddi_proxy;
+pub mod failures;
+pub mod metadata;
+pub mod migrate;
+pub mod provision;
+pub mod restore;
+pub mod setup;
+pub mod simulate;
+pub mod stale_actions;
+pub mod target_types;
+pub mod tenants;
+pub mod timeline;
+pub mod tokens;
+pub mod vault;
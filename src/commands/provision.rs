@@ -0,0 +1,58 @@
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use clap::Args;
+use hawkbit_data_proxy_rs::hawkbit::{HawkbitMgmtClient, HawkbitResult};
+use hawkbit_data_proxy_rs::provision::{self, ImportStatus};
+
+#[derive(Args)]
+pub struct ProvisionArgs {
+    /// CSV or JSON file with the targets to register
+    file: PathBuf,
+    /// CSV file receiving the outcome and security token of every target
+    #[arg(long, default_value = "provision-results.csv")]
+    results: PathBuf,
+    /// Number of targets created per request
+    #[arg(long, default_value_t = 50)]
+    batch_size: usize,
+    /// Validate and check for existing targets without creating any
+    #[arg(long)]
+    dry_run: bool,
+}
+
+pub async fn run(client: &HawkbitMgmtClient, args: ProvisionArgs) -> ExitCode {
+    match provision_targets(client, &args).await {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(e) => {
+            println!("Error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn provision_targets(
+    client: &HawkbitMgmtClient,
+    args: &ProvisionArgs,
+) -> HawkbitResult<bool> {
+    let rows = provision::read_targets(&args.file)?;
+    let results = provision::import_targets(client, &rows, args.batch_size, args.dry_run).await;
+    provision::write_results(&args.results, &results)?;
+
+    let count = |status| results.iter().filter(|r| r.status == status).count();
+    for result in &results {
+        if let Some(error) = &result.error {
+            println!("{:?}: {}", result.controller_id, error);
+        }
+    }
+    println!(
+        "{} created, {} planned, {} already registered, {} invalid, {} failed; results written to {}",
+        count(ImportStatus::Created),
+        count(ImportStatus::Planned),
+        count(ImportStatus::Exists),
+        count(ImportStatus::Invalid),
+        count(ImportStatus::Failed),
+        args.results.display()
+    );
+    Ok(count(ImportStatus::Invalid) == 0 && count(ImportStatus::Failed) == 0)
+}
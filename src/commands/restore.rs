@@ -0,0 +1,86 @@
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use chrono::DateTime;
+use clap::Args;
+use hawkbit_data_proxy_rs::hawkbit::{
+    ActionType, AssignmentRequest, HawkbitMgmtClient, HawkbitResult,
+};
+use hawkbit_data_proxy_rs::undo::{self, UndoJournal};
+
+#[derive(Args)]
+pub struct RestoreArgs {
+    /// Controller IDs to restore from their latest snapshot
+    controller_ids: Vec<String>,
+    /// Undo journal written by the cleanup
+    #[arg(long, default_value = "undo-journal.jsonl")]
+    journal: PathBuf,
+    /// Action type used to reassign the distribution set
+    #[arg(long = "type", default_value = "forced")]
+    action_type: ActionType,
+    /// List the snapshots in the journal instead of restoring
+    #[arg(long)]
+    list: bool,
+}
+
+pub async fn run(client: &HawkbitMgmtClient, args: RestoreArgs) -> ExitCode {
+    match restore(client, &args).await {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(e) => {
+            println!("Error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn restore(client: &HawkbitMgmtClient, args: &RestoreArgs) -> HawkbitResult<bool> {
+    let journal = UndoJournal::new(&args.journal);
+
+    if args.list {
+        for snapshot in journal.entries()? {
+            let captured_at = DateTime::from_timestamp_millis(snapshot.captured_at)
+                .map(|t| t.to_rfc3339())
+                .unwrap_or_default();
+            let set = snapshot
+                .assigned_distribution_set
+                .as_ref()
+                .or(snapshot.installed_distribution_set.as_ref())
+                .map(|set| format!("{} {}", set.name, set.version))
+                .unwrap_or_else(|| "-".to_string());
+            println!("{} {} {}", captured_at, snapshot.controller_id, set);
+        }
+        return Ok(true);
+    }
+
+    let request = AssignmentRequest::new(args.action_type);
+    let mut ok = true;
+    for controller_id in &args.controller_ids {
+        let Some(snapshot) = journal.latest(controller_id)? else {
+            println!(
+                "No snapshot of {:?} in {}",
+                controller_id,
+                args.journal.display()
+            );
+            ok = false;
+            continue;
+        };
+
+        match undo::restore_target(client, &snapshot, &request).await {
+            Ok(result) => {
+                println!(
+                    "Restored {:?}: {} tags, {} metadata entries, distribution set {:?}",
+                    controller_id, result.tags, result.metadata, result.assigned_distribution_set
+                );
+                for warning in &result.warnings {
+                    println!("  Warning: {}", warning);
+                }
+            }
+            Err(e) => {
+                println!("Failed to restore {:?}: {}", controller_id, e);
+                ok = false;
+            }
+        }
+    }
+    Ok(ok)
+}
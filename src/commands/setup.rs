@@ -0,0 +1,87 @@
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use clap::Subcommand;
+use hawkbit_data_proxy_rs::hawkbit::{HawkbitMgmtClient, HawkbitResult};
+use hawkbit_data_proxy_rs::setup::{self, ChangeKind, Setup};
+
+#[derive(Subcommand)]
+pub enum SetupCommand {
+    /// Write types, tags, software modules, distribution sets, target filters,
+    /// rollouts and tenant settings to a directory of YAML files
+    Export { dir: PathBuf },
+    /// Create and update what differs between the YAML files and the server;
+    /// nothing is deleted
+    Apply {
+        dir: PathBuf,
+        /// Only show the changes
+        #[arg(long)]
+        dry_run: bool,
+        /// Print the changes as JSON
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+pub async fn run(client: &HawkbitMgmtClient, command: SetupCommand) -> ExitCode {
+    match run_command(client, command).await {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(e) => {
+            println!("Error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn run_command(client: &HawkbitMgmtClient, command: SetupCommand) -> HawkbitResult<bool> {
+    match command {
+        SetupCommand::Export { dir } => {
+            let setup = Setup::export(client).await?;
+            for path in setup.write(&dir)? {
+                println!("Wrote {}", path.display());
+            }
+            Ok(true)
+        }
+        SetupCommand::Apply { dir, dry_run, json } => {
+            let setup = Setup::read(&dir)?;
+            let changes = setup::apply(client, &setup, dry_run).await?;
+            let failed = changes.iter().filter(|c| c.error.is_some()).count();
+            if json {
+                println!("{}", serde_json::to_string_pretty(&changes)?);
+                return Ok(failed == 0);
+            }
+            for change in &changes {
+                let kind = match change.kind {
+                    ChangeKind::Create => "create",
+                    ChangeKind::Update => "update",
+                    ChangeKind::Drift => "drift",
+                };
+                let mut line = format!("{}\t{}", kind, change.entity);
+                if !change.detail.is_empty() {
+                    line.push_str(&format!("\t{}", change.detail));
+                }
+                if let Some(error) = &change.error {
+                    line.push_str(&format!("\tFAILED: {}", error));
+                }
+                println!("{}", line);
+            }
+            let applied = changes
+                .iter()
+                .filter(|c| c.kind != ChangeKind::Drift && c.error.is_none())
+                .count();
+            let drift = changes
+                .iter()
+                .filter(|c| c.kind == ChangeKind::Drift)
+                .count();
+            println!(
+                "{} changes {}, {} failed, {} drifted and need manual attention",
+                applied,
+                if dry_run { "planned" } else { "applied" },
+                failed,
+                drift
+            );
+            Ok(failed == 0)
+        }
+    }
+}
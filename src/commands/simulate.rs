@@ -0,0 +1,68 @@
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use clap::Args;
+use hawkbit_data_proxy_rs::hawkbit::{HawkbitConfig, HawkbitResult};
+use hawkbit_data_proxy_rs::simulator::{self, Scenario, SimulationReport};
+
+#[derive(Args)]
+pub struct SimulateArgs {
+    /// TOML file describing the devices and their behaviour
+    scenario: PathBuf,
+    /// Print the report as JSON
+    #[arg(long)]
+    json: bool,
+}
+
+pub async fn run(config: Option<&Path>, profile: Option<&str>, args: SimulateArgs) -> ExitCode {
+    match simulate(config, profile, args).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            println!("Error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn simulate(
+    config: Option<&Path>,
+    profile: Option<&str>,
+    args: SimulateArgs,
+) -> HawkbitResult<()> {
+    let scenario = Scenario::read(&args.scenario)?;
+    // The profile is only needed for settings the scenario leaves out
+    let config = match &scenario.host {
+        Some(_) => None,
+        None => Some(HawkbitConfig::load(config, profile)?),
+    };
+
+    let shutdown = async {
+        if tokio::signal::ctrl_c().await.is_err() {
+            std::future::pending::<()>().await;
+        }
+    };
+    let report = simulator::run(&scenario, config.as_ref(), shutdown).await?;
+
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        print_report(&report);
+    }
+    Ok(())
+}
+
+fn print_report(report: &SimulationReport) {
+    println!("Devices:          {}", report.devices);
+    println!(
+        "Polls:            {} ({} failed)",
+        report.polls, report.poll_errors
+    );
+    println!("Deployments:      {}", report.deployments);
+    println!("  succeeded:      {}", report.succeeded);
+    println!("  failed:         {}", report.failed);
+    println!("  hung:           {}", report.hung);
+    println!("  went offline:   {}", report.went_offline);
+    println!("Canceled:         {}", report.canceled);
+    println!("Feedback errors:  {}", report.feedback_errors);
+    println!("Downloaded bytes: {}", report.downloaded_bytes);
+}
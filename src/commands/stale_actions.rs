@@ -0,0 +1,92 @@
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::time::Duration;
+
+use clap::Args;
+use hawkbit_data_proxy_rs::hawkbit::{HawkbitMgmtClient, HawkbitResult};
+use hawkbit_data_proxy_rs::stale_actions::{self, StaleActionPolicy, StaleActionReport};
+
+#[derive(Args)]
+pub struct StaleActionsArgs {
+    /// FIQL query selecting the targets to check
+    #[arg(long, default_value = "updatestatus==pending or updatestatus==error")]
+    pub(crate) filter: String,
+    /// Also cancel actions without a status event for this long, e.g. `72h`,
+    /// even if they are the target's newest; only superseded ones otherwise
+    #[arg(long, value_parser = humantime::parse_duration)]
+    stuck_after: Option<Duration>,
+    /// Force-cancel actions that are still canceling after this long, e.g.
+    /// `24h`
+    #[arg(long, value_parser = humantime::parse_duration)]
+    force_after: Option<Duration>,
+    /// Only report which actions would be canceled
+    #[arg(long)]
+    dry_run: bool,
+    /// Append each decision as a JSON line to this file
+    #[arg(long)]
+    pub(crate) log: Option<PathBuf>,
+}
+
+impl StaleActionsArgs {
+    pub(crate) fn policy(&self) -> StaleActionPolicy {
+        StaleActionPolicy {
+            cancel_superseded: true,
+            stuck_after: self.stuck_after,
+            force_after: self.force_after,
+            dry_run: self.dry_run,
+        }
+    }
+}
+
+pub async fn run(client: &HawkbitMgmtClient, args: StaleActionsArgs) -> ExitCode {
+    match cancel_stale_actions(client, &args).await {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(e) => {
+            println!("Error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn cancel_stale_actions(
+    client: &HawkbitMgmtClient,
+    args: &StaleActionsArgs,
+) -> HawkbitResult<bool> {
+    let report = stale_actions::process_targets(client, &args.filter, &args.policy()).await?;
+    if let Some(log) = &args.log {
+        stale_actions::append_log(log, &report.decisions)?;
+    }
+    print_report(&report, args.dry_run);
+    Ok(report.failures() == 0)
+}
+
+fn print_report(report: &StaleActionReport, dry_run: bool) {
+    for decision in &report.decisions {
+        println!(
+            "{:?} action {}: {} {:?}{}",
+            decision.controller_id,
+            decision.action_id,
+            if decision.force {
+                "force-cancel"
+            } else {
+                "cancel"
+            },
+            decision.reason,
+            match &decision.error {
+                Some(e) => format!(" FAILED: {}", e),
+                None => String::new(),
+            }
+        );
+    }
+    for (controller_id, error) in &report.failed {
+        println!("{:?}: failed to check actions: {}", controller_id, error);
+    }
+    println!(
+        "Checked {} targets, {} actions {}canceled, {} failures",
+        report.checked,
+        report.decisions.len(),
+        if dry_run { "would be " } else { "" },
+        report.failures()
+    );
+}
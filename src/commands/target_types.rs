@@ -0,0 +1,199 @@
+use std::process::ExitCode;
+
+use clap::Subcommand;
+use hawkbit_data_proxy_rs::hawkbit::{
+    HawkbitError, HawkbitMgmtClient, HawkbitResult, NewTargetType,
+};
+
+#[derive(Subcommand)]
+pub enum TargetTypesCommand {
+    /// List target types
+    List {
+        #[arg(long)]
+        filter: Option<String>,
+    },
+    /// Show a target type and its compatible distribution set types
+    Show { target_type: u64 },
+    /// Create a target type
+    Create {
+        #[arg(long)]
+        name: String,
+        #[arg(long)]
+        key: Option<String>,
+        #[arg(long)]
+        description: Option<String>,
+        #[arg(long)]
+        colour: Option<String>,
+        /// Keys of compatible distribution set types
+        #[arg(long, value_delimiter = ',')]
+        compatible: Vec<String>,
+    },
+    /// Update name, description or colour of a target type
+    Update {
+        target_type: u64,
+        #[arg(long)]
+        name: Option<String>,
+        #[arg(long)]
+        description: Option<String>,
+        #[arg(long)]
+        colour: Option<String>,
+    },
+    /// Delete a target type
+    Delete { target_type: u64 },
+    /// Mark distribution set types (by key) as compatible with a target type
+    AddCompatible {
+        target_type: u64,
+        #[arg(required = true)]
+        ds_types: Vec<String>,
+    },
+    /// Remove a distribution set type (by key) from a target type
+    RemoveCompatible { target_type: u64, ds_type: String },
+    /// List the available distribution set types
+    DsTypes,
+    /// Check whether a distribution set may be assigned to a target
+    Check {
+        controller_id: String,
+        distribution_set: u64,
+    },
+}
+
+pub async fn run(client: &HawkbitMgmtClient, command: TargetTypesCommand) -> ExitCode {
+    match run_command(client, command).await {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(e) => {
+            println!("Error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Resolves distribution set type keys to their IDs.
+async fn resolve_ds_types(client: &HawkbitMgmtClient, keys: &[String]) -> HawkbitResult<Vec<u64>> {
+    let ds_types = client.get_distribution_set_types(None).await?;
+    keys.iter()
+        .map(|key| {
+            ds_types
+                .iter()
+                .find(|t| t.key == *key)
+                .map(|t| t.id)
+                .ok_or_else(|| {
+                    HawkbitError::new(format!("Unknown distribution set type {:?}", key))
+                })
+        })
+        .collect()
+}
+
+async fn run_command(
+    client: &HawkbitMgmtClient,
+    command: TargetTypesCommand,
+) -> HawkbitResult<bool> {
+    match command {
+        TargetTypesCommand::List { filter } => {
+            for target_type in client.get_target_types(filter.as_deref()).await? {
+                println!(
+                    "{}: {} (key {:?}) {}",
+                    target_type.id,
+                    target_type.name,
+                    target_type.key.unwrap_or_default(),
+                    target_type.description.unwrap_or_default()
+                );
+            }
+        }
+        TargetTypesCommand::Show { target_type } => {
+            let tt = client.get_target_type(target_type).await?;
+            println!("Target type: {:?}", tt);
+            for ds_type in client
+                .get_compatible_distribution_set_types(target_type)
+                .await?
+            {
+                println!(
+                    "  Compatible: {} ({}, id {})",
+                    ds_type.name, ds_type.key, ds_type.id
+                );
+            }
+        }
+        TargetTypesCommand::Create {
+            name,
+            key,
+            description,
+            colour,
+            compatible,
+        } => {
+            let new_type = NewTargetType {
+                name,
+                key,
+                description,
+                colour,
+                compatible_distribution_set_types: resolve_ds_types(client, &compatible).await?,
+            };
+            for created in client.create_target_types(&[new_type]).await? {
+                println!("Created target type {} ({})", created.name, created.id);
+            }
+        }
+        TargetTypesCommand::Update {
+            target_type,
+            name,
+            description,
+            colour,
+        } => {
+            client
+                .update_target_type(
+                    target_type,
+                    name.as_deref(),
+                    description.as_deref(),
+                    colour.as_deref(),
+                )
+                .await?;
+            println!("Updated target type {}", target_type);
+        }
+        TargetTypesCommand::Delete { target_type } => {
+            client.delete_target_type(target_type).await?;
+            println!("Deleted target type {}", target_type);
+        }
+        TargetTypesCommand::AddCompatible {
+            target_type,
+            ds_types,
+        } => {
+            let ids = resolve_ds_types(client, &ds_types).await?;
+            client
+                .add_compatible_distribution_set_types(target_type, &ids)
+                .await?;
+            println!("Added {:?} to target type {}", ds_types, target_type);
+        }
+        TargetTypesCommand::RemoveCompatible {
+            target_type,
+            ds_type,
+        } => {
+            let ids = resolve_ds_types(client, std::slice::from_ref(&ds_type)).await?;
+            client
+                .remove_compatible_distribution_set_type(target_type, ids[0])
+                .await?;
+            println!("Removed {:?} from target type {}", ds_type, target_type);
+        }
+        TargetTypesCommand::DsTypes => {
+            for ds_type in client.get_distribution_set_types(None).await? {
+                println!("{}: {} (key {:?})", ds_type.id, ds_type.name, ds_type.key);
+            }
+        }
+        TargetTypesCommand::Check {
+            controller_id,
+            distribution_set,
+        } => {
+            return match client
+                .check_compatibility(&controller_id, distribution_set)
+                .await?
+            {
+                None => {
+                    println!("Compatible");
+                    Ok(true)
+                }
+                Some(reason) => {
+                    println!("Incompatible: {}", reason);
+                    Ok(false)
+                }
+            };
+        }
+    }
+    Ok(true)
+}
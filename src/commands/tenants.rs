@@ -0,0 +1,271 @@
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use clap::{Args, Subcommand};
+use hawkbit_data_proxy_rs::cleanup::{self, CleanupReport};
+use hawkbit_data_proxy_rs::hawkbit::{HawkbitConfig, HawkbitResult};
+use hawkbit_data_proxy_rs::stale_actions::{self, StaleActionReport};
+use hawkbit_data_proxy_rs::tenants::{self, StatusReport, TenantOutcome};
+
+use super::cleanup::{CleanupArgs, describe_reason};
+use super::stale_actions::StaleActionsArgs;
+
+#[derive(Args)]
+pub struct TenantsArgs {
+    /// Comma separated profiles to run against, all profiles by default
+    #[arg(long, value_delimiter = ',')]
+    profiles: Vec<String>,
+    /// Number of tenants processed at the same time
+    #[arg(long, default_value_t = 4)]
+    concurrency: usize,
+    #[command(subcommand)]
+    command: TenantsCommand,
+}
+
+#[derive(Subcommand)]
+pub enum TenantsCommand {
+    /// Count the targets of every tenant by update status
+    Report {
+        /// Print the report as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Delete inactive factory machines; each tenant gets its own undo
+    /// journal named after the profile, e.g. `undo-journal.<profile>.jsonl`
+    Cleanup(CleanupArgs),
+    /// Cancel superseded and stuck actions; a `--log` file is split per
+    /// profile like the cleanup journal
+    StaleActions(StaleActionsArgs),
+}
+
+pub async fn run(config: Option<&Path>, args: TenantsArgs) -> ExitCode {
+    match run_command(config, args).await {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(e) => {
+            println!("Error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// `undo-journal.jsonl` becomes `undo-journal.<profile>.jsonl`
+fn per_profile(path: &Path, profile: &str) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(extension) => format!("{}.{}.{}", stem, profile, extension.to_string_lossy()),
+        None => format!("{}.{}", stem, profile),
+    };
+    path.with_file_name(name)
+}
+
+fn label<T>(outcome: &TenantOutcome<T>) -> String {
+    match &outcome.tenant {
+        Some(tenant) if tenant != &outcome.profile => format!("{}/{}", outcome.profile, tenant),
+        _ => outcome.profile.clone(),
+    }
+}
+
+/// Prints the tenants that failed as a whole and returns whether all succeeded.
+fn print_tenant_errors<T>(outcomes: &[TenantOutcome<T>]) -> bool {
+    for outcome in outcomes {
+        if let Some(error) = &outcome.error {
+            println!("{}: FAILED: {}", label(outcome), error);
+        }
+    }
+    outcomes.iter().all(|outcome| outcome.error.is_none())
+}
+
+async fn run_command(config: Option<&Path>, args: TenantsArgs) -> HawkbitResult<bool> {
+    let configs = HawkbitConfig::load_profiles(config, &args.profiles)?;
+
+    match args.command {
+        TenantsCommand::Report { json } => {
+            let outcomes = tenants::fan_out(configs, args.concurrency, async |_, client| {
+                tenants::status_report(client).await
+            })
+            .await;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&outcomes)?);
+                return Ok(outcomes.iter().all(|outcome| outcome.error.is_none()));
+            }
+            print_status_reports(&outcomes);
+            Ok(print_tenant_errors(&outcomes))
+        }
+        TenantsCommand::Cleanup(cleanup_args) => {
+            let outcomes = tenants::fan_out(configs, args.concurrency, async |profile, client| {
+                let mut policy = cleanup_args.policy();
+                policy.journal = per_profile(&policy.journal, profile);
+                // Exports are named by controller ID, which tenants may share
+                policy.export_dir = policy.export_dir.join(profile);
+                let targets = client.get_targets(cleanup_args.filter.as_deref()).await?;
+                Ok(cleanup::run_cleanup(client, &policy, &targets).await)
+            })
+            .await;
+            let cleaned = outcomes.iter().all(|outcome| {
+                outcome
+                    .result
+                    .as_ref()
+                    .is_some_and(|report| report.aborted.is_none() && report.failed.is_empty())
+            });
+            if cleanup_args.json {
+                println!("{}", serde_json::to_string_pretty(&outcomes)?);
+                return Ok(cleaned);
+            }
+            print_cleanup_reports(&outcomes, &cleanup_args.journal);
+            Ok(print_tenant_errors(&outcomes) && cleaned)
+        }
+        TenantsCommand::StaleActions(stale_args) => {
+            let policy = stale_args.policy();
+            let outcomes = tenants::fan_out(configs, args.concurrency, async |profile, client| {
+                let report =
+                    stale_actions::process_targets(client, &stale_args.filter, &policy).await?;
+                if let Some(log) = &stale_args.log {
+                    stale_actions::append_log(&per_profile(log, profile), &report.decisions)?;
+                }
+                Ok(report)
+            })
+            .await;
+            print_stale_action_reports(&outcomes, policy.dry_run);
+            let clean = outcomes
+                .iter()
+                .filter_map(|outcome| outcome.result.as_ref())
+                .all(|report| report.failures() == 0);
+            Ok(print_tenant_errors(&outcomes) && clean)
+        }
+    }
+}
+
+fn print_status_reports(outcomes: &[TenantOutcome<StatusReport>]) {
+    let width = outcomes
+        .iter()
+        .map(|outcome| label(outcome).len())
+        .max()
+        .unwrap_or_default()
+        .max("TENANT".len());
+    println!(
+        "{:<width$} {:>8} {:>8} {:>8} {:>8} {:>10} {:>8} {:>10}",
+        "TENANT", "TARGETS", "IN_SYNC", "PENDING", "ERROR", "REGISTERED", "OVERDUE", "NEVER_SEEN"
+    );
+    let mut total = StatusReport::default();
+    for outcome in outcomes {
+        let Some(report) = &outcome.result else {
+            println!("{:<width$} {:>8}", label(outcome), "-");
+            continue;
+        };
+        println!(
+            "{:<width$} {:>8} {:>8} {:>8} {:>8} {:>10} {:>8} {:>10}",
+            label(outcome),
+            report.targets,
+            report.in_sync,
+            report.pending,
+            report.error,
+            report.registered,
+            report.overdue,
+            report.never_seen
+        );
+        total.targets += report.targets;
+        total.in_sync += report.in_sync;
+        total.pending += report.pending;
+        total.error += report.error;
+        total.registered += report.registered;
+        total.overdue += report.overdue;
+        total.never_seen += report.never_seen;
+    }
+    println!(
+        "{:<width$} {:>8} {:>8} {:>8} {:>8} {:>10} {:>8} {:>10}",
+        "TOTAL",
+        total.targets,
+        total.in_sync,
+        total.pending,
+        total.error,
+        total.registered,
+        total.overdue,
+        total.never_seen
+    );
+}
+
+fn print_cleanup_reports(outcomes: &[TenantOutcome<CleanupReport>], journal: &Path) {
+    for outcome in outcomes {
+        let Some(report) = &outcome.result else {
+            continue;
+        };
+        let tenant = label(outcome);
+        for candidate in &report.candidates {
+            let controller_id = &candidate.controller_id;
+            let status = if let Some((_, error)) =
+                report.failed.iter().find(|(id, _)| id == controller_id)
+            {
+                format!("FAILED: {}", error)
+            } else if report.deleted.contains(controller_id) {
+                "deleted".to_string()
+            } else if report.aborted.is_some() {
+                "kept".to_string()
+            } else {
+                "would delete".to_string()
+            };
+            println!(
+                "{}\t{}\t{}\t{}",
+                tenant,
+                controller_id,
+                status,
+                describe_reason(&candidate.reason)
+            );
+        }
+        match &report.aborted {
+            Some(reason) => println!("{}: cleanup aborted, nothing deleted: {}", tenant, reason),
+            None if report.dry_run => println!(
+                "{}: dry run, {} targets would be deleted",
+                tenant,
+                report.candidates.len()
+            ),
+            None => println!(
+                "{}: deleted {} targets, restore them from {}",
+                tenant,
+                report.deleted.len(),
+                per_profile(journal, &outcome.profile).display()
+            ),
+        }
+    }
+}
+
+fn print_stale_action_reports(outcomes: &[TenantOutcome<StaleActionReport>], dry_run: bool) {
+    for outcome in outcomes {
+        let Some(report) = &outcome.result else {
+            continue;
+        };
+        let tenant = label(outcome);
+        for decision in &report.decisions {
+            println!(
+                "{}\t{}\taction {}\t{}\t{:?}{}",
+                tenant,
+                decision.controller_id,
+                decision.action_id,
+                if decision.force {
+                    "force-cancel"
+                } else {
+                    "cancel"
+                },
+                decision.reason,
+                match &decision.error {
+                    Some(e) => format!("\tFAILED: {}", e),
+                    None => String::new(),
+                }
+            );
+        }
+        for (controller_id, error) in &report.failed {
+            println!(
+                "{}\t{}\tfailed to check actions: {}",
+                tenant, controller_id, error
+            );
+        }
+        println!(
+            "{}: checked {} targets, {} actions {}canceled, {} failures",
+            tenant,
+            report.checked,
+            report.decisions.len(),
+            if dry_run { "would be " } else { "" },
+            report.failures()
+        );
+    }
+}
@@ -0,0 +1,39 @@
+use std::process::ExitCode;
+
+use clap::Args;
+use hawkbit_data_proxy_rs::hawkbit::HawkbitMgmtClient;
+use hawkbit_data_proxy_rs::timeline;
+
+#[derive(Args)]
+pub struct TimelineArgs {
+    controller_id: String,
+    /// Number of most recent actions to include
+    #[arg(long, default_value_t = 20)]
+    actions: usize,
+    /// Print the timeline as JSON
+    #[arg(long)]
+    json: bool,
+}
+
+pub async fn run(client: &HawkbitMgmtClient, args: TimelineArgs) -> ExitCode {
+    let timeline = match timeline::build_timeline(client, &args.controller_id, args.actions).await {
+        Ok(timeline) => timeline,
+        Err(e) => {
+            println!("Error: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    if args.json {
+        match serde_json::to_string_pretty(&timeline) {
+            Ok(json) => println!("{}", json),
+            Err(e) => {
+                println!("Error: {}", e);
+                return ExitCode::FAILURE;
+            }
+        }
+    } else {
+        print!("{}", timeline);
+    }
+    ExitCode::SUCCESS
+}
@@ -0,0 +1,144 @@
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::time::Duration;
+
+use clap::{Args, Subcommand};
+use hawkbit_data_proxy_rs::crypto;
+use hawkbit_data_proxy_rs::hawkbit::{HawkbitError, HawkbitMgmtClient, HawkbitResult};
+use hawkbit_data_proxy_rs::secrets::{SecretSource, VaultLocation};
+use hawkbit_data_proxy_rs::token_rotation::{self, RotationOutcome, RotationStatus, RotationStore};
+
+#[derive(Args)]
+pub struct TokensArgs {
+    /// Encrypted rotation state holding the old and new tokens
+    #[arg(long, global = true, default_value = "token-rotation.enc")]
+    state: PathBuf,
+    /// Environment variable holding the passphrase of the encrypted files,
+    /// or with a `_FILE`/`_COMMAND` suffix a file or command providing it
+    #[arg(long, global = true, default_value = "HAWKBIT_TOKEN_PASSPHRASE")]
+    passphrase_env: String,
+    #[command(subcommand)]
+    command: TokensCommand,
+}
+
+#[derive(Subcommand)]
+pub enum TokensCommand {
+    /// Give the next stage of targets new security tokens
+    Rotate {
+        /// FIQL query selecting the targets
+        #[arg(long)]
+        filter: Option<String>,
+        /// Number of targets rotated in this stage
+        #[arg(long, default_value_t = 20)]
+        limit: usize,
+        /// Encrypted file receiving the current tokens for provisioning
+        #[arg(long, default_value = "tokens.enc")]
+        export: PathBuf,
+        /// Only list the targets that would be rotated
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Check which devices picked up their new token
+    Status,
+    /// Restore the old token of devices that did not pick up the new one
+    Revert {
+        /// Time a device gets to poll with its new token, e.g. `48h`
+        #[arg(long, value_parser = humantime::parse_duration, default_value = "48h")]
+        grace: Duration,
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Write the current tokens to an encrypted file for provisioning
+    Export { output: PathBuf },
+    /// Print the content of an encrypted file
+    Decrypt { file: PathBuf },
+}
+
+pub async fn run(client: &HawkbitMgmtClient, args: TokensArgs) -> ExitCode {
+    match run_command(client, args).await {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(e) => {
+            println!("Error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn print_outcomes(outcomes: &[RotationOutcome], verb: &str) -> bool {
+    for outcome in outcomes {
+        match &outcome.error {
+            None => println!("{} {:?}", verb, outcome.controller_id),
+            Some(e) => println!("Failed {:?}: {}", outcome.controller_id, e),
+        }
+    }
+    outcomes.iter().all(|outcome| outcome.error.is_none())
+}
+
+async fn run_command(client: &HawkbitMgmtClient, args: TokensArgs) -> HawkbitResult<bool> {
+    let passphrase = SecretSource::from_env(&args.passphrase_env)
+        .ok_or_else(|| {
+            HawkbitError::new(format!(
+                "Set {} to the passphrase of the encrypted files",
+                args.passphrase_env
+            ))
+        })?
+        .resolve(&VaultLocation::from_env(None))?;
+    let passphrase = passphrase.expose();
+    let mut store = RotationStore::open(&args.state, passphrase)?;
+
+    match args.command {
+        TokensCommand::Rotate {
+            filter,
+            limit,
+            export,
+            dry_run,
+        } => {
+            let targets = client.get_targets(filter.as_deref()).await?;
+            let outcomes =
+                token_rotation::rotate(client, &mut store, &targets, limit, dry_run).await?;
+            let ok = print_outcomes(&outcomes, if dry_run { "Would rotate" } else { "Rotated" });
+            if !dry_run {
+                let count = token_rotation::export_tokens(&store, &export, passphrase)?;
+                println!("Exported {} tokens to {}", count, export.display());
+            }
+            Ok(ok)
+        }
+        TokensCommand::Status => {
+            token_rotation::refresh_status(client, &mut store).await?;
+            let count = |status| store.entries.iter().filter(|e| e.status == status).count();
+            for entry in &store.entries {
+                if entry.status == RotationStatus::Pending {
+                    println!("Pending: {:?}", entry.controller_id);
+                }
+            }
+            println!(
+                "{} pending, {} confirmed, {} reverted, {} failed",
+                count(RotationStatus::Pending),
+                count(RotationStatus::Confirmed),
+                count(RotationStatus::Reverted),
+                count(RotationStatus::Failed)
+            );
+            Ok(true)
+        }
+        TokensCommand::Revert { grace, dry_run } => {
+            // Devices that polled in the meantime must keep their new token
+            token_rotation::refresh_status(client, &mut store).await?;
+            let outcomes = token_rotation::revert_stale(client, &mut store, grace, dry_run).await?;
+            Ok(print_outcomes(
+                &outcomes,
+                if dry_run { "Would revert" } else { "Reverted" },
+            ))
+        }
+        TokensCommand::Export { output } => {
+            let count = token_rotation::export_tokens(&store, &output, passphrase)?;
+            println!("Exported {} tokens to {}", count, output.display());
+            Ok(true)
+        }
+        TokensCommand::Decrypt { file } => {
+            let content = crypto::read_encrypted(&file, passphrase)?;
+            println!("{}", String::from_utf8_lossy(&content));
+            Ok(true)
+        }
+    }
+}
@@ -0,0 +1,71 @@
+use std::io::BufRead;
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use clap::{Args, Subcommand};
+use hawkbit_data_proxy_rs::hawkbit::{HawkbitError, HawkbitResult};
+use hawkbit_data_proxy_rs::secrets::{Secret, VaultLocation};
+
+#[derive(Args)]
+pub struct VaultArgs {
+    /// Vault file, defaults to $HAWKBIT_VAULT or ~/.config/hawkbit/vault.enc
+    #[arg(long, global = true)]
+    vault: Option<PathBuf>,
+    #[command(subcommand)]
+    command: VaultCommand,
+}
+
+#[derive(Subcommand)]
+pub enum VaultCommand {
+    /// Store a secret read from the first line of stdin
+    Set { name: String },
+    /// List the names of the stored secrets
+    List,
+    /// Remove a secret
+    Remove { name: String },
+}
+
+pub fn run(args: VaultArgs) -> ExitCode {
+    match run_command(args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            println!("Error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run_command(args: VaultArgs) -> HawkbitResult<()> {
+    let location = VaultLocation::from_env(args.vault);
+    let mut vault = location.open()?;
+
+    match args.command {
+        VaultCommand::Set { name } => {
+            let mut value = String::new();
+            std::io::stdin().lock().read_line(&mut value)?;
+            let value = value.trim_end_matches(['\n', '\r']);
+            if value.is_empty() {
+                return Err(HawkbitError::new("No secret on stdin"));
+            }
+            vault.set(&name, Secret::new(value));
+            vault.save()?;
+            println!("Stored {:?} in {}", name, location.path.display());
+        }
+        VaultCommand::List => {
+            for name in vault.names() {
+                println!("{}", name);
+            }
+        }
+        VaultCommand::Remove { name } => {
+            if !vault.remove(&name) {
+                return Err(HawkbitError::new(format!(
+                    "No secret {:?} in the vault",
+                    name
+                )));
+            }
+            vault.save()?;
+            println!("Removed {:?}", name);
+        }
+    }
+    Ok(())
+}
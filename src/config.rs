@@ -0,0 +1,605 @@
+use dotenv::dotenv;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::env;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::auth::AuthConfig;
+use crate::hawkbit::{HawkbitError, HawkbitResult};
+use crate::secrets::{SecretSource, VaultLocation};
+use crate::transport::{TransportConfig, parse_tls_version};
+
+pub const DEFAULT_PAGE_SIZE: usize = 50;
+// hawkBit rejects larger pages
+const MAX_PAGE_SIZE: usize = 500;
+
+/// Resolved settings of one hawkBit instance
+#[derive(Debug, Clone)]
+pub struct HawkbitConfig {
+    host: String,
+    tenant: Option<String>,
+    auth: AuthConfig,
+    transport: TransportConfig,
+    channel: Option<String>,
+    page_size: usize,
+}
+
+impl HawkbitConfig {
+    /// Reads the configuration from environment variables only.
+    pub fn from_env() -> HawkbitResult<Self> {
+        dotenv().ok(); // Load from .env file into environment variables
+        let mut profile = ProfileConfig::default();
+        profile.apply_overrides(&env_var)?;
+        profile.resolve("environment")
+    }
+
+    /// Loads `profile` from the config file and applies environment
+    /// overrides. Without a config file only the environment is used.
+    ///
+    /// The file is `path`, else `$HAWKBIT_CONFIG`, else `./hawkbit.toml` or
+    /// `~/.config/hawkbit/config.toml` if present. The profile is `profile`,
+    /// else `$HAWKBIT_PROFILE`, else the file's `default_profile`.
+    pub fn load(path: Option<&Path>, profile: Option<&str>) -> HawkbitResult<Self> {
+        dotenv().ok();
+
+        let path = config_path(path);
+        let profile_name = profile
+            .map(str::to_string)
+            .or_else(|| env::var("HAWKBIT_PROFILE").ok());
+
+        let Some(path) = path else {
+            if let Some(name) = profile_name {
+                return Err(HawkbitError::new(format!(
+                    "Profile {:?} requested, but no config file was found",
+                    name
+                )));
+            }
+            return Self::from_env();
+        };
+
+        let mut file = ConfigFile::read(&path)?;
+        let name = profile_name
+            .or(file.default_profile.clone())
+            .unwrap_or_else(|| "default".to_string());
+        let Some(mut profile) = file.profiles.remove(&name) else {
+            let available: Vec<&String> = file.profiles.keys().collect();
+            return Err(HawkbitError::new(format!(
+                "Profile {:?} not found in {}, available: {:?}",
+                name,
+                path.display(),
+                available
+            )));
+        };
+        profile.apply_overrides(&env_var)?;
+        profile.resolve(&format!("profile {:?}", name))
+    }
+
+    /// Loads several profiles of the config file for commands that run
+    /// against multiple tenants; an empty `names` selects all of them. A
+    /// profile that fails to resolve, e.g. because a secret is missing, does
+    /// not prevent loading the others.
+    ///
+    /// Unlike `load`, the `HAWKBIT_*` overrides are not applied as they would
+    /// point every profile at the same tenant.
+    pub fn load_profiles(
+        path: Option<&Path>,
+        names: &[String],
+    ) -> HawkbitResult<Vec<(String, HawkbitResult<Self>)>> {
+        dotenv().ok();
+        let path = config_path(path).ok_or_else(|| {
+            HawkbitError::new("Running against several tenants needs a config file with profiles")
+        })?;
+        let mut file = ConfigFile::read(&path)?;
+
+        let names = if names.is_empty() {
+            file.profiles.keys().cloned().collect()
+        } else {
+            names.to_vec()
+        };
+        if names.is_empty() {
+            return Err(HawkbitError::new(format!(
+                "No profiles defined in {}",
+                path.display()
+            )));
+        }
+
+        let mut configs = Vec::with_capacity(names.len());
+        for name in names {
+            let Some(profile) = file.profiles.remove(&name) else {
+                return Err(HawkbitError::new(format!(
+                    "Profile {:?} not found in {}",
+                    name,
+                    path.display()
+                )));
+            };
+            let config = profile.resolve(&format!("profile {:?}", name));
+            configs.push((name, config));
+        }
+        Ok(configs)
+    }
+
+    pub fn host(&self) -> &str {
+        &self.host
+    }
+
+    pub fn tenant(&self) -> Option<&str> {
+        self.tenant.as_deref()
+    }
+
+    pub fn auth(&self) -> &AuthConfig {
+        &self.auth
+    }
+
+    pub fn transport(&self) -> &TransportConfig {
+        &self.transport
+    }
+
+    /// Update channel assumed for targets that do not report one
+    pub fn channel(&self) -> Option<&str> {
+        self.channel.as_deref()
+    }
+
+    /// Number of entries requested per page from collection endpoints
+    pub fn page_size(&self) -> usize {
+        self.page_size
+    }
+}
+
+#[cfg(test)]
+impl HawkbitConfig {
+    /// Basic auth as admin against a local test server
+    pub(crate) fn for_tests(host: &str) -> Self {
+        let profile = ProfileConfig {
+            host: Some(host.to_string()),
+            auth: AuthSection {
+                username: Some("admin".to_string()),
+                password: Some(SecretSource::Value(crate::secrets::Secret::new("admin"))),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        profile.resolve("tests").unwrap()
+    }
+}
+
+fn config_path(path: Option<&Path>) -> Option<PathBuf> {
+    path.map(Path::to_path_buf)
+        .or_else(|| env::var("HAWKBIT_CONFIG").ok().map(PathBuf::from))
+        .or_else(default_config_path)
+}
+
+fn default_config_path() -> Option<PathBuf> {
+    let local = PathBuf::from("hawkbit.toml");
+    if local.exists() {
+        return Some(local);
+    }
+    let config_dir = env::var("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|_| env::var("HOME").map(|home| Path::new(&home).join(".config")))
+        .ok()?;
+    let path = config_dir.join("hawkbit").join("config.toml");
+    path.exists().then_some(path)
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigFile {
+    pub default_profile: Option<String>,
+    #[serde(default)]
+    pub profiles: BTreeMap<String, ProfileConfig>,
+}
+
+impl ConfigFile {
+    pub fn read(path: &Path) -> HawkbitResult<Self> {
+        let content = std::fs::read_to_string(path).map_err(|e| {
+            HawkbitError::new(format!("Failed to read config {}: {}", path.display(), e))
+        })?;
+        toml::from_str(&content)
+            .map_err(|e| HawkbitError::new(format!("Invalid config {}: {}", path.display(), e)))
+    }
+}
+
+/// One profile as written in the config file; everything is optional so the
+/// environment can fill the gaps.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProfileConfig {
+    pub host: Option<String>,
+    pub tenant: Option<String>,
+    pub channel: Option<String>,
+    pub page_size: Option<usize>,
+    /// Encrypted vault referenced by `{ vault = "name" }` secrets
+    pub vault: Option<PathBuf>,
+    #[serde(default)]
+    pub auth: AuthSection,
+    #[serde(default)]
+    pub transport: TransportSection,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AuthSection {
+    /// basic (default), bearer, oauth2 or header
+    #[serde(rename = "type")]
+    pub auth_type: Option<String>,
+    pub username: Option<String>,
+    pub password: Option<SecretSource>,
+    pub token: Option<SecretSource>,
+    pub token_url: Option<String>,
+    pub client_id: Option<String>,
+    pub client_secret: Option<SecretSource>,
+    pub scope: Option<String>,
+    pub header: Option<String>,
+    pub header_value: Option<SecretSource>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TransportSection {
+    pub client_cert: Option<PathBuf>,
+    pub client_key: Option<PathBuf>,
+    pub ca_bundle: Option<PathBuf>,
+    pub min_tls_version: Option<String>,
+    pub proxy: Option<String>,
+    pub no_proxy: Option<String>,
+    /// e.g. `10s`
+    pub connect_timeout: Option<String>,
+    pub read_timeout: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+type Lookup<'a> = &'a dyn Fn(&str) -> Option<String>;
+
+fn env_var(name: &str) -> Option<String> {
+    env::var(name).ok()
+}
+
+fn override_from(target: &mut Option<String>, var: Lookup, name: &str) {
+    if let Some(value) = var(name) {
+        *target = Some(value);
+    }
+}
+
+fn override_secret(target: &mut Option<SecretSource>, var: Lookup, name: &str) {
+    if let Some(source) = SecretSource::from_vars(name, var) {
+        *target = Some(source);
+    }
+}
+
+fn override_path(target: &mut Option<PathBuf>, var: Lookup, name: &str) {
+    if let Some(value) = var(name) {
+        *target = Some(PathBuf::from(value));
+    }
+}
+
+fn parse_duration(field: &str, value: Option<&String>) -> HawkbitResult<Option<Duration>> {
+    value
+        .map(|value| {
+            humantime::parse_duration(value).map_err(|e| {
+                HawkbitError::new(format!("{} {:?} is not a duration: {}", field, value, e))
+            })
+        })
+        .transpose()
+}
+
+impl ProfileConfig {
+    /// Environment variables, looked up by `var`, take precedence over the
+    /// file.
+    fn apply_overrides(&mut self, var: Lookup) -> HawkbitResult<()> {
+        override_from(&mut self.host, var, "HAWKBIT_HOST");
+        override_from(&mut self.tenant, var, "HAWKBIT_TENANT");
+        override_from(&mut self.channel, var, "HAWKBIT_CHANNEL");
+        if let Some(value) = var("HAWKBIT_PAGE_SIZE") {
+            self.page_size = Some(value.parse().map_err(|_| {
+                HawkbitError::new(format!("HAWKBIT_PAGE_SIZE {:?} is not a number", value))
+            })?);
+        }
+
+        let auth = &mut self.auth;
+        override_from(&mut auth.auth_type, var, "HAWKBIT_AUTH");
+        override_from(&mut auth.username, var, "HAWKBIT_USERNAME");
+        override_secret(&mut auth.password, var, "HAWKBIT_PASSWORD");
+        override_secret(&mut auth.token, var, "HAWKBIT_TOKEN");
+        override_from(&mut auth.token_url, var, "HAWKBIT_OAUTH_TOKEN_URL");
+        override_from(&mut auth.client_id, var, "HAWKBIT_OAUTH_CLIENT_ID");
+        override_secret(&mut auth.client_secret, var, "HAWKBIT_OAUTH_CLIENT_SECRET");
+        override_from(&mut auth.scope, var, "HAWKBIT_OAUTH_SCOPE");
+        override_from(&mut auth.header, var, "HAWKBIT_AUTH_HEADER");
+        override_secret(&mut auth.header_value, var, "HAWKBIT_AUTH_HEADER_VALUE");
+
+        let transport = &mut self.transport;
+        override_path(&mut transport.client_cert, var, "HAWKBIT_CLIENT_CERT");
+        override_path(&mut transport.client_key, var, "HAWKBIT_CLIENT_KEY");
+        override_path(&mut transport.ca_bundle, var, "HAWKBIT_CA_BUNDLE");
+        override_from(
+            &mut transport.min_tls_version,
+            var,
+            "HAWKBIT_TLS_MIN_VERSION",
+        );
+        override_from(&mut transport.proxy, var, "HAWKBIT_PROXY");
+        override_from(&mut transport.no_proxy, var, "HAWKBIT_NO_PROXY");
+        override_from(
+            &mut transport.connect_timeout,
+            var,
+            "HAWKBIT_CONNECT_TIMEOUT",
+        );
+        override_from(&mut transport.read_timeout, var, "HAWKBIT_READ_TIMEOUT");
+        override_from(&mut transport.user_agent, var, "HAWKBIT_USER_AGENT");
+        Ok(())
+    }
+
+    /// Validates the profile; `source` names it in error messages.
+    fn resolve(self, source: &str) -> HawkbitResult<HawkbitConfig> {
+        let missing = |field: &str, var: &str| {
+            HawkbitError::new(format!(
+                "Missing {} in {}, set it in the config file or via {}",
+                field, source, var
+            ))
+        };
+
+        let host = self.host.unwrap_or_else(|| "http://localhost".to_string());
+        if !host.starts_with("http://") && !host.starts_with("https://") {
+            return Err(HawkbitError::new(format!(
+                "host {:?} in {} must start with http:// or https://",
+                host, source
+            )));
+        }
+
+        let page_size = self.page_size.unwrap_or(DEFAULT_PAGE_SIZE);
+        if !(1..=MAX_PAGE_SIZE).contains(&page_size) {
+            return Err(HawkbitError::new(format!(
+                "page_size {} in {} must be between 1 and {}",
+                page_size, source, MAX_PAGE_SIZE
+            )));
+        }
+
+        let vault = VaultLocation::from_env(self.vault);
+        let secret = |value: Option<SecretSource>, field: &str, var: &str| {
+            value
+                .ok_or_else(|| missing(field, &format!("{0}, {0}_FILE or {0}_COMMAND", var)))?
+                .resolve(&vault)
+                .map_err(|e| HawkbitError::new(format!("{} in {}: {}", field, source, e)))
+        };
+
+        let auth = self.auth;
+        let auth = match auth.auth_type.as_deref().unwrap_or("basic") {
+            "basic" => {
+                let username = auth
+                    .username
+                    .ok_or_else(|| missing("auth.username", "HAWKBIT_USERNAME"))?;
+                AuthConfig::Basic {
+                    // Multi-tenant hawkBit expects TENANT\user
+                    username: match &self.tenant {
+                        Some(tenant) if !username.contains('\\') => {
+                            format!("{}\\{}", tenant, username)
+                        }
+                        _ => username,
+                    },
+                    password: secret(auth.password, "auth.password", "HAWKBIT_PASSWORD")?,
+                }
+            }
+            "bearer" => AuthConfig::Bearer {
+                token: secret(auth.token, "auth.token", "HAWKBIT_TOKEN")?,
+            },
+            "oauth2" => AuthConfig::OAuth2 {
+                token_url: auth
+                    .token_url
+                    .ok_or_else(|| missing("auth.token_url", "HAWKBIT_OAUTH_TOKEN_URL"))?,
+                client_id: auth
+                    .client_id
+                    .ok_or_else(|| missing("auth.client_id", "HAWKBIT_OAUTH_CLIENT_ID"))?,
+                client_secret: secret(
+                    auth.client_secret,
+                    "auth.client_secret",
+                    "HAWKBIT_OAUTH_CLIENT_SECRET",
+                )?,
+                scope: auth.scope,
+            },
+            "header" => AuthConfig::Header {
+                name: auth.header.unwrap_or_else(|| "Authorization".to_string()),
+                value: secret(
+                    auth.header_value,
+                    "auth.header_value",
+                    "HAWKBIT_AUTH_HEADER_VALUE",
+                )?,
+            },
+            other => {
+                return Err(HawkbitError::new(format!(
+                    "Unknown auth type {:?} in {}, expected basic, bearer, oauth2 or header",
+                    other, source
+                )));
+            }
+        };
+
+        let section = self.transport;
+        let transport = TransportConfig {
+            min_tls_version: section
+                .min_tls_version
+                .as_deref()
+                .map(parse_tls_version)
+                .transpose()?,
+            connect_timeout: parse_duration(
+                "transport.connect_timeout",
+                section.connect_timeout.as_ref(),
+            )?,
+            read_timeout: parse_duration("transport.read_timeout", section.read_timeout.as_ref())?,
+            client_cert: section.client_cert,
+            client_key: section.client_key,
+            ca_bundle: section.ca_bundle,
+            proxy: section.proxy,
+            no_proxy: section.no_proxy,
+            user_agent: section.user_agent,
+        };
+
+        Ok(HawkbitConfig {
+            host,
+            tenant: self.tenant,
+            auth,
+            transport,
+            channel: self.channel,
+            page_size,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    const CONFIG: &str = r#"
+default_profile = "staging"
+
+[profiles.staging]
+host = "https://staging.example.com"
+tenant = "STAGING"
+page_size = 100
+
+[profiles.staging.auth]
+username = "admin"
+password = "secret"
+
+[profiles.production]
+host = "https://hawkbit.example.com"
+channel = "stable"
+
+[profiles.production.auth]
+type = "bearer"
+token = { file = "/run/secrets/hawkbit" }
+
+[profiles.production.transport]
+ca_bundle = "/etc/hawkbit/ca.pem"
+read_timeout = "30s"
+"#;
+
+    fn profile(toml: &str) -> ProfileConfig {
+        toml::from_str(toml).unwrap()
+    }
+
+    fn basic_username(config: &HawkbitConfig) -> &str {
+        match config.auth() {
+            AuthConfig::Basic { username, .. } => username,
+            other => panic!("expected basic auth, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parses_profiles() {
+        let file: ConfigFile = toml::from_str(CONFIG).unwrap();
+        assert_eq!(file.default_profile.as_deref(), Some("staging"));
+        assert_eq!(
+            file.profiles.keys().collect::<Vec<_>>(),
+            ["production", "staging"]
+        );
+
+        let production = &file.profiles["production"];
+        assert_eq!(production.channel.as_deref(), Some("stable"));
+        assert_eq!(production.auth.auth_type.as_deref(), Some("bearer"));
+        assert!(matches!(
+            &production.auth.token,
+            Some(SecretSource::File { file }) if file == Path::new("/run/secrets/hawkbit")
+        ));
+        assert_eq!(production.transport.read_timeout.as_deref(), Some("30s"));
+    }
+
+    #[test]
+    fn rejects_unknown_fields() {
+        for toml in [
+            "default_profil = \"a\"",
+            "[profiles.a]\nhots = \"https://a\"",
+            "[profiles.a.auth]\nuser = \"admin\"",
+            "[profiles.a.transport]\ntimeout = \"5s\"",
+        ] {
+            assert!(toml::from_str::<ConfigFile>(toml).is_err(), "{}", toml);
+        }
+    }
+
+    #[test]
+    fn environment_overrides_the_file() {
+        let vars = HashMap::from([
+            ("HAWKBIT_HOST", "https://override.example.com"),
+            ("HAWKBIT_PAGE_SIZE", "200"),
+            ("HAWKBIT_PASSWORD_FILE", "/run/secrets/password"),
+            ("HAWKBIT_READ_TIMEOUT", "1m"),
+        ]);
+        let lookup = |name: &str| vars.get(name).map(|value| value.to_string());
+
+        let mut profile = profile(
+            "host = \"https://file.example.com\"\ntenant = \"T\"\n[auth]\nusername = \"admin\"\npassword = \"file\"",
+        );
+        profile.apply_overrides(&lookup).unwrap();
+        assert_eq!(
+            profile.host.as_deref(),
+            Some("https://override.example.com")
+        );
+        assert_eq!(profile.tenant.as_deref(), Some("T"));
+        assert_eq!(profile.page_size, Some(200));
+        assert_eq!(profile.auth.username.as_deref(), Some("admin"));
+        assert!(matches!(
+            &profile.auth.password,
+            Some(SecretSource::File { file }) if file == Path::new("/run/secrets/password")
+        ));
+        assert_eq!(profile.transport.read_timeout.as_deref(), Some("1m"));
+
+        let invalid = |name: &str| (name == "HAWKBIT_PAGE_SIZE").then(|| "many".to_string());
+        assert!(ProfileConfig::default().apply_overrides(&invalid).is_err());
+    }
+
+    #[test]
+    fn basic_auth_username_gets_the_tenant_prefix() {
+        let resolve = |toml: &str| profile(toml).resolve("test").unwrap();
+
+        let config = resolve("tenant = \"T1\"\n[auth]\nusername = \"admin\"\npassword = \"x\"");
+        assert_eq!(basic_username(&config), "T1\\admin");
+        let config =
+            resolve("tenant = \"T1\"\n[auth]\nusername = \"T2\\\\admin\"\npassword = \"x\"");
+        assert_eq!(basic_username(&config), "T2\\admin");
+        let config = resolve("[auth]\nusername = \"admin\"\npassword = \"x\"");
+        assert_eq!(basic_username(&config), "admin");
+    }
+
+    #[test]
+    fn resolve_validates_the_profile() {
+        for toml in [
+            "host = \"hawkbit.example.com\"\n[auth]\nusername = \"a\"\npassword = \"x\"",
+            "page_size = 501\n[auth]\nusername = \"a\"\npassword = \"x\"",
+            "[auth]\nusername = \"a\"",
+            "[auth]\ntype = \"kerberos\"",
+            "[auth]\ntype = \"bearer\"\ntoken = \"t\"\n[transport]\nread_timeout = \"soon\"",
+        ] {
+            assert!(profile(toml).resolve("test").is_err(), "{}", toml);
+        }
+    }
+
+    // Unlike `load`, `load_profiles` skips the HAWKBIT_* overrides, so every
+    // profile keeps the host of the file
+    #[test]
+    fn load_profiles_uses_the_file_only() {
+        let path = std::env::temp_dir().join(format!("hawkbit-config-{}.toml", std::process::id()));
+        std::fs::write(&path, CONFIG).unwrap();
+
+        let configs = HawkbitConfig::load_profiles(Some(&path), &[]).unwrap();
+        let hosts: Vec<_> = configs
+            .iter()
+            .map(|(name, config)| (name.as_str(), config.as_ref().ok().map(|c| c.host())))
+            .collect();
+        // The token file does not exist, which fails only that profile
+        assert_eq!(
+            hosts,
+            [
+                ("production", None),
+                ("staging", Some("https://staging.example.com"))
+            ]
+        );
+
+        let selected = HawkbitConfig::load_profiles(Some(&path), &["staging".to_string()]).unwrap();
+        assert_eq!(selected.len(), 1);
+        assert_eq!(
+            basic_username(selected[0].1.as_ref().unwrap()),
+            "STAGING\\admin"
+        );
+        assert!(HawkbitConfig::load_profiles(Some(&path), &["missing".to_string()]).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
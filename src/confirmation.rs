@@ -0,0 +1,78 @@
+use chrono::Utc;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::time::Duration;
+
+use crate::hawkbit::{HawkbitMgmtClient, HawkbitResult};
+
+/// An action that has been waiting for confirmation.
+#[derive(Debug, Serialize)]
+pub struct PendingConfirmation {
+    pub controller_id: String,
+    pub action_id: i64,
+    pub distribution_set: Option<String>,
+    /// Time the action entered its current state, in ms since epoch
+    pub waiting_since: u64,
+    pub auto_confirm_active: Option<bool>,
+}
+
+impl PendingConfirmation {
+    pub fn waiting_for(&self) -> Duration {
+        let now = Utc::now().timestamp_millis().max(0) as u64;
+        Duration::from_millis(now.saturating_sub(self.waiting_since))
+    }
+}
+
+/// Collects all actions in `wait_for_confirmation`, optionally only those
+/// waiting for longer than `older_than`, sorted with the longest waiting first.
+pub async fn pending_confirmations(
+    client: &HawkbitMgmtClient,
+    older_than: Option<Duration>,
+) -> HawkbitResult<Vec<PendingConfirmation>> {
+    let actions = client
+        .get_actions(Some("detailStatus==wait_for_confirmation"))
+        .await?;
+
+    let mut auto_confirm: HashMap<String, Option<bool>> = HashMap::new();
+    let mut pending = Vec::new();
+    for action in actions {
+        // The server side filter is only a pre-selection, double check the state
+        if action.detail_status != "wait_for_confirmation" {
+            continue;
+        }
+        let Some(controller_id) = action.target_id() else {
+            continue;
+        };
+
+        let entry = PendingConfirmation {
+            distribution_set: action.distribution_set_name(),
+            action_id: action.id,
+            waiting_since: action.last_modified_at,
+            auto_confirm_active: None,
+            controller_id,
+        };
+        if let Some(min_age) = older_than
+            && entry.waiting_for() < min_age
+        {
+            continue;
+        }
+        pending.push(entry);
+    }
+
+    for entry in &mut pending {
+        entry.auto_confirm_active = match auto_confirm.entry(entry.controller_id.clone()) {
+            Entry::Occupied(cached) => *cached.get(),
+            Entry::Vacant(slot) => *slot.insert(
+                client
+                    .get_target(&entry.controller_id)
+                    .await
+                    .ok()
+                    .and_then(|target| target.auto_confirm_active),
+            ),
+        };
+    }
+
+    pending.sort_by_key(|entry| entry.waiting_since);
+    Ok(pending)
+}
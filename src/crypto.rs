@@ -0,0 +1,145 @@
+use argon2::Argon2;
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use rand::RngCore;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::hawkbit::{HawkbitError, HawkbitResult};
+
+const MAGIC: &[u8] = b"HBENC1";
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+
+fn derive_key(passphrase: &str, salt: &[u8]) -> HawkbitResult<Key> {
+    let mut key = Key::default();
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|e| HawkbitError::new(format!("Key derivation failed: {}", e)))?;
+    Ok(key)
+}
+
+/// Encrypts with a key derived from the passphrase. Output layout: magic,
+/// argon2id salt, nonce, ChaCha20-Poly1305 ciphertext.
+pub fn encrypt(passphrase: &str, plaintext: &[u8]) -> HawkbitResult<Vec<u8>> {
+    let mut salt = [0u8; SALT_LEN];
+    let mut nonce = [0u8; NONCE_LEN];
+    rand::thread_rng().fill_bytes(&mut salt);
+    rand::thread_rng().fill_bytes(&mut nonce);
+
+    let cipher = ChaCha20Poly1305::new(&derive_key(passphrase, &salt)?);
+    let ciphertext = cipher
+        .encrypt(Nonce::from_slice(&nonce), plaintext)
+        .map_err(|_| HawkbitError::new("Encryption failed"))?;
+
+    let mut out = Vec::with_capacity(MAGIC.len() + SALT_LEN + NONCE_LEN + ciphertext.len());
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(&salt);
+    out.extend_from_slice(&nonce);
+    out.extend_from_slice(&ciphertext);
+    Ok(out)
+}
+
+pub fn decrypt(passphrase: &str, data: &[u8]) -> HawkbitResult<Vec<u8>> {
+    let header_len = MAGIC.len() + SALT_LEN + NONCE_LEN;
+    if data.len() < header_len || !data.starts_with(MAGIC) {
+        return Err(HawkbitError::new("Not an encrypted file"));
+    }
+    let salt = &data[MAGIC.len()..MAGIC.len() + SALT_LEN];
+    let nonce = &data[MAGIC.len() + SALT_LEN..header_len];
+
+    let cipher = ChaCha20Poly1305::new(&derive_key(passphrase, salt)?);
+    cipher
+        .decrypt(Nonce::from_slice(nonce), &data[header_len..])
+        .map_err(|_| HawkbitError::new("Decryption failed, wrong passphrase or corrupted file"))
+}
+
+/// Encrypts `plaintext` into `path`, replacing the file atomically. The file
+/// is only readable by the current user.
+pub fn write_encrypted(path: &Path, passphrase: &str, plaintext: &[u8]) -> HawkbitResult<()> {
+    let tmp = temp_path(path);
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options
+        .open(&tmp)?
+        .write_all(&encrypt(passphrase, plaintext)?)?;
+    std::fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+/// `<path>.tmp`, keeping the full file name so `a.enc` and `a.json` do not
+/// share a temporary file
+fn temp_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(".tmp");
+    PathBuf::from(name)
+}
+
+pub fn read_encrypted(path: &Path, passphrase: &str) -> HawkbitResult<Vec<u8>> {
+    decrypt(passphrase, &std::fs::read(path)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decrypts_what_it_encrypted() {
+        let data = encrypt("correct horse", b"token-1234").unwrap();
+        assert!(data.starts_with(MAGIC));
+        assert!(!data.windows(10).any(|window| window == b"token-1234"));
+        assert_eq!(decrypt("correct horse", &data).unwrap(), b"token-1234");
+    }
+
+    #[test]
+    fn encryptions_of_the_same_data_differ() {
+        let first = encrypt("pass", b"same").unwrap();
+        let second = encrypt("pass", b"same").unwrap();
+        assert_ne!(first, second);
+    }
+
+    #[test]
+    fn wrong_passphrase_fails() {
+        let data = encrypt("correct horse", b"token-1234").unwrap();
+        assert!(decrypt("battery staple", &data).is_err());
+    }
+
+    #[test]
+    fn tampered_or_foreign_data_fails() {
+        let mut data = encrypt("pass", b"token").unwrap();
+        let last = data.len() - 1;
+        data[last] ^= 1;
+        assert!(decrypt("pass", &data).is_err());
+        assert!(decrypt("pass", b"HBENC1 too short").is_err());
+        assert!(decrypt("pass", b"{\"plain\": \"json file, not encrypted at all\"}").is_err());
+    }
+
+    #[test]
+    fn temp_file_keeps_the_full_name() {
+        assert_eq!(
+            temp_path(Path::new("dir/vault.enc")),
+            Path::new("dir/vault.enc.tmp")
+        );
+        assert_ne!(
+            temp_path(Path::new("vault.enc")),
+            temp_path(Path::new("vault.json"))
+        );
+    }
+
+    #[test]
+    fn writes_and_reads_files() {
+        let path = std::env::temp_dir().join(format!("hawkbit-crypto-{}.enc", std::process::id()));
+        write_encrypted(&path, "pass", b"secret").unwrap();
+        assert!(!temp_path(&path).exists());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        assert_eq!(read_encrypted(&path, "pass").unwrap(), b"secret");
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,601 @@
+use reqwest::header::{self, HeaderValue};
+use reqwest::{Client, Method, RequestBuilder, StatusCode};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::artifacts;
+use crate::hawkbit::{ArtifactHashes, HawkbitConfig, HawkbitError, HawkbitResult, Link};
+use crate::transport::{TransportConfig, build_client};
+
+const DEFAULT_TENANT: &str = "DEFAULT";
+
+/// Credentials of a device. hawkBit accepts the target's own security token
+/// or the tenant-wide gateway token, depending on the tenant settings.
+#[derive(Clone)]
+pub enum DdiAuth {
+    TargetToken(String),
+    GatewayToken(String),
+    /// For tenants that allow anonymous downloads or sit behind an
+    /// authenticating proxy
+    None,
+}
+
+impl fmt::Debug for DdiAuth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DdiAuth::TargetToken(_) => write!(f, "TargetToken(..)"),
+            DdiAuth::GatewayToken(_) => write!(f, "GatewayToken(..)"),
+            DdiAuth::None => write!(f, "None"),
+        }
+    }
+}
+
+impl DdiAuth {
+    fn header(&self) -> HawkbitResult<Option<HeaderValue>> {
+        let value = match self {
+            DdiAuth::TargetToken(token) => format!("TargetToken {}", token),
+            DdiAuth::GatewayToken(token) => format!("GatewayToken {}", token),
+            DdiAuth::None => return Ok(None),
+        };
+        let mut value = HeaderValue::from_str(&value)
+            .map_err(|e| HawkbitError::new(format!("Invalid DDI token: {}", e)))?;
+        value.set_sensitive(true);
+        Ok(Some(value))
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Polling {
+    /// Time until the next poll as `HH:MM:SS`
+    #[serde(rename = "sleep")]
+    pub sleep: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ControllerConfig {
+    #[serde(rename = "polling")]
+    pub polling: Polling,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct ControllerLinks {
+    #[serde(rename = "deploymentBase")]
+    pub deployment_base: Option<Link>,
+
+    #[serde(rename = "cancelAction")]
+    pub cancel_action: Option<Link>,
+
+    /// Present when the server wants the device attributes
+    #[serde(rename = "configData")]
+    pub config_data: Option<Link>,
+
+    #[serde(rename = "installedBase")]
+    pub installed_base: Option<Link>,
+
+    #[serde(rename = "confirmationBase")]
+    pub confirmation_base: Option<Link>,
+}
+
+/// Answer to a poll of the controller base resource
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ControllerBase {
+    #[serde(rename = "config")]
+    pub config: ControllerConfig,
+
+    #[serde(rename = "_links", default)]
+    pub links: ControllerLinks,
+}
+
+impl ControllerBase {
+    /// The poll interval requested by the server
+    pub fn sleep(&self) -> HawkbitResult<Duration> {
+        parse_sleep(&self.config.polling.sleep)
+    }
+}
+
+fn parse_sleep(value: &str) -> HawkbitResult<Duration> {
+    let parts: Vec<&str> = value.split(':').collect();
+    let invalid = || HawkbitError::new(format!("Invalid polling interval {:?}", value));
+    let [hours, minutes, seconds] = parts.as_slice() else {
+        return Err(invalid());
+    };
+    let parse = |part: &str| part.parse::<u64>().map_err(|_| invalid());
+    Ok(Duration::from_secs(
+        parse(hours)? * 3600 + parse(minutes)? * 60 + parse(seconds)?,
+    ))
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum HandlingType {
+    Skip,
+    Attempt,
+    Forced,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct DdiArtifactLinks {
+    #[serde(rename = "download")]
+    pub download: Option<Link>,
+
+    #[serde(rename = "download-http")]
+    pub download_http: Option<Link>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DdiArtifact {
+    #[serde(rename = "filename")]
+    pub filename: String,
+
+    #[serde(rename = "hashes")]
+    pub hashes: ArtifactHashes,
+
+    #[serde(rename = "size")]
+    pub size: u64,
+
+    #[serde(rename = "_links", default)]
+    pub links: DdiArtifactLinks,
+}
+
+impl DdiArtifact {
+    /// HTTPS download link, falling back to plain HTTP
+    pub fn download_url(&self) -> Option<&str> {
+        self.links
+            .download
+            .as_ref()
+            .or(self.links.download_http.as_ref())
+            .map(|link| link.href.as_str())
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ChunkMetadata {
+    pub key: String,
+    pub value: String,
+}
+
+/// A software module of the offered distribution set
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Chunk {
+    /// Key of the software module type, e.g. `os` or `application`
+    #[serde(rename = "part")]
+    pub part: String,
+
+    #[serde(rename = "version")]
+    pub version: String,
+
+    #[serde(rename = "name")]
+    pub name: String,
+
+    #[serde(rename = "metadata", default)]
+    pub metadata: Vec<ChunkMetadata>,
+
+    #[serde(rename = "artifacts", default)]
+    pub artifacts: Vec<DdiArtifact>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Deployment {
+    #[serde(rename = "download")]
+    pub download: HandlingType,
+
+    #[serde(rename = "update")]
+    pub update: HandlingType,
+
+    /// `available` or `unavailable`, only set for actions with a
+    /// maintenance window
+    #[serde(rename = "maintenanceWindow")]
+    pub maintenance_window: Option<String>,
+
+    #[serde(rename = "chunks")]
+    pub chunks: Vec<Chunk>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ActionHistory {
+    #[serde(rename = "status")]
+    pub status: Option<String>,
+
+    #[serde(rename = "messages", default)]
+    pub messages: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DeploymentBase {
+    /// Action ID
+    #[serde(rename = "id")]
+    pub id: String,
+
+    #[serde(rename = "deployment")]
+    pub deployment: Deployment,
+
+    #[serde(rename = "actionHistory")]
+    pub action_history: Option<ActionHistory>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CancelActionDetail {
+    /// ID of the action to stop
+    #[serde(rename = "stopId")]
+    pub stop_id: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CancelAction {
+    #[serde(rename = "id")]
+    pub id: String,
+
+    #[serde(rename = "cancelAction")]
+    pub cancel_action: CancelActionDetail,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Execution {
+    Proceeding,
+    Closed,
+    Canceled,
+    Scheduled,
+    Rejected,
+    Resumed,
+    Downloaded,
+    Download,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Finished {
+    Success,
+    Failure,
+    None,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+pub struct Progress {
+    #[serde(rename = "cnt")]
+    pub cnt: u32,
+
+    #[serde(rename = "of")]
+    pub of: u32,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FeedbackResult {
+    #[serde(rename = "finished")]
+    pub finished: Finished,
+
+    #[serde(rename = "progress", skip_serializing_if = "Option::is_none")]
+    pub progress: Option<Progress>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FeedbackStatus {
+    #[serde(rename = "execution")]
+    pub execution: Execution,
+
+    #[serde(rename = "result")]
+    pub result: FeedbackResult,
+
+    /// Device specific status code
+    #[serde(rename = "code", skip_serializing_if = "Option::is_none")]
+    pub code: Option<i32>,
+
+    #[serde(rename = "details", default)]
+    pub details: Vec<String>,
+}
+
+/// Status of an action as reported by the device
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Feedback {
+    /// Milliseconds since the epoch
+    #[serde(rename = "timestamp", skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<i64>,
+
+    #[serde(rename = "status")]
+    pub status: FeedbackStatus,
+}
+
+impl Feedback {
+    pub fn new(execution: Execution, finished: Finished) -> Self {
+        Self {
+            timestamp: Some(chrono::Utc::now().timestamp_millis()),
+            status: FeedbackStatus {
+                execution,
+                result: FeedbackResult {
+                    finished,
+                    progress: None,
+                },
+                code: None,
+                details: Vec::new(),
+            },
+        }
+    }
+
+    pub fn proceeding() -> Self {
+        Self::new(Execution::Proceeding, Finished::None)
+    }
+
+    pub fn success() -> Self {
+        Self::new(Execution::Closed, Finished::Success)
+    }
+
+    pub fn failure() -> Self {
+        Self::new(Execution::Closed, Finished::Failure)
+    }
+
+    pub fn with_message(mut self, message: impl Into<String>) -> Self {
+        self.status.details.push(message.into());
+        self
+    }
+
+    pub fn with_progress(mut self, cnt: u32, of: u32) -> Self {
+        self.status.result.progress = Some(Progress { cnt, of });
+        self
+    }
+
+    pub fn with_code(mut self, code: i32) -> Self {
+        self.status.code = Some(code);
+        self
+    }
+}
+
+/// How `put_config_data` combines the attributes with the stored ones
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ConfigDataMode {
+    #[default]
+    Merge,
+    Replace,
+    Remove,
+}
+
+#[derive(Debug, Serialize)]
+struct ConfigData<'a> {
+    mode: ConfigDataMode,
+    data: &'a HashMap<String, String>,
+}
+
+/// Client of the Direct Device Integration API, acting as one device.
+#[derive(Debug, Clone)]
+pub struct DdiClient {
+    client: Client,
+    base_url: String,
+    controller_id: String,
+    /// Sent with every request rather than as a default header, so one HTTP
+    /// client can serve many devices
+    authorization: Option<HeaderValue>,
+}
+
+impl DdiClient {
+    pub fn new(
+        host: &str,
+        tenant: Option<&str>,
+        controller_id: &str,
+        auth: &DdiAuth,
+        transport: &TransportConfig,
+    ) -> HawkbitResult<Self> {
+        Self::with_client(
+            Self::http_client(transport)?,
+            host,
+            tenant,
+            controller_id,
+            auth,
+        )
+    }
+
+    /// Builds an HTTP client for [`DdiClient::with_client`]. It carries no
+    /// credentials and can be shared by any number of devices.
+    pub fn http_client(transport: &TransportConfig) -> HawkbitResult<Client> {
+        let mut headers = header::HeaderMap::new();
+        headers.insert(
+            header::ACCEPT,
+            HeaderValue::from_static("application/hal+json, application/json"),
+        );
+        build_client(transport, headers)
+    }
+
+    /// Acts as a device over an existing HTTP client, sharing its connection
+    /// pool, e.g. for many simulated devices.
+    pub fn with_client(
+        client: Client,
+        host: &str,
+        tenant: Option<&str>,
+        controller_id: &str,
+        auth: &DdiAuth,
+    ) -> HawkbitResult<Self> {
+        Ok(Self {
+            client,
+            base_url: format!(
+                "{}/{}/controller/v1/{}",
+                host.trim_end_matches('/'),
+                tenant.unwrap_or(DEFAULT_TENANT),
+                controller_id
+            ),
+            controller_id: controller_id.to_string(),
+            authorization: auth.header()?,
+        })
+    }
+
+    /// Uses host, tenant and connection settings of a profile. The
+    /// management credentials of the profile are not used.
+    pub fn from_config(
+        config: &HawkbitConfig,
+        controller_id: &str,
+        auth: &DdiAuth,
+    ) -> HawkbitResult<Self> {
+        Self::new(
+            config.host(),
+            config.tenant(),
+            controller_id,
+            auth,
+            config.transport(),
+        )
+    }
+
+    pub fn controller_id(&self) -> &str {
+        &self.controller_id
+    }
+
+    fn url(&self, resource: &str) -> String {
+        if resource.is_empty() {
+            self.base_url.clone()
+        } else {
+            format!("{}/{}", self.base_url, resource.trim_start_matches('/'))
+        }
+    }
+
+    fn request(&self, method: Method, url: &str) -> RequestBuilder {
+        let request = self.client.request(method, url);
+        match &self.authorization {
+            Some(value) => request.header(header::AUTHORIZATION, value.clone()),
+            None => request,
+        }
+    }
+
+    async fn get_json<T: DeserializeOwned>(&self, url: &str) -> HawkbitResult<T> {
+        let res = self.request(Method::GET, url).send().await?;
+        let status = res.status();
+        if status != StatusCode::OK {
+            let body = res.text().await.unwrap_or_default();
+            return Err(HawkbitError::http(status, body));
+        }
+        Ok(res.json().await?)
+    }
+
+    async fn send_json<T: Serialize + ?Sized>(
+        &self,
+        request: RequestBuilder,
+        body: &T,
+    ) -> HawkbitResult<()> {
+        let res = request.json(body).send().await?;
+        let status = res.status();
+        if !status.is_success() {
+            let body = res.text().await.unwrap_or_default();
+            return Err(HawkbitError::http(status, body));
+        }
+        Ok(())
+    }
+
+    /// Polls the controller base resource. An unknown controller ID is
+    /// registered by hawkBit on its first poll.
+    pub async fn poll(&self) -> HawkbitResult<ControllerBase> {
+        self.get_json(&self.url("")).await
+    }
+
+    /// Fetches the deployment offered by a poll. `href` is the
+    /// `deploymentBase` link of the controller base.
+    pub async fn get_deployment_base(&self, href: &str) -> HawkbitResult<DeploymentBase> {
+        self.get_json(href).await
+    }
+
+    pub async fn get_deployment(&self, action_id: &str) -> HawkbitResult<DeploymentBase> {
+        self.get_json(&self.url(&format!("deploymentBase/{}", action_id)))
+            .await
+    }
+
+    pub async fn get_cancel_action(&self, href: &str) -> HawkbitResult<CancelAction> {
+        self.get_json(href).await
+    }
+
+    pub async fn send_deployment_feedback(
+        &self,
+        action_id: &str,
+        feedback: &Feedback,
+    ) -> HawkbitResult<()> {
+        let url = self.url(&format!("deploymentBase/{}/feedback", action_id));
+        self.send_json(self.request(Method::POST, &url), feedback)
+            .await
+    }
+
+    pub async fn send_cancel_feedback(
+        &self,
+        action_id: &str,
+        feedback: &Feedback,
+    ) -> HawkbitResult<()> {
+        let url = self.url(&format!("cancelAction/{}/feedback", action_id));
+        self.send_json(self.request(Method::POST, &url), feedback)
+            .await
+    }
+
+    /// Reports the device attributes, e.g. `update_channel`.
+    pub async fn put_config_data(
+        &self,
+        attributes: &HashMap<String, String>,
+        mode: ConfigDataMode,
+    ) -> HawkbitResult<()> {
+        let body = ConfigData {
+            mode,
+            data: attributes,
+        };
+        self.send_json(self.request(Method::PUT, &self.url("configData")), &body)
+            .await
+    }
+
+    /// Streams an artifact to `dest` and verifies its hashes. An interrupted
+    /// download is resumed from `<dest>.part`. Returns the size of the file.
+    pub async fn download_artifact(
+        &self,
+        artifact: &DdiArtifact,
+        dest: &Path,
+    ) -> HawkbitResult<u64> {
+        let url = artifact.download_url().ok_or_else(|| {
+            HawkbitError::new(format!(
+                "Artifact {} has no download link",
+                artifact.filename
+            ))
+        })?;
+
+        artifacts::download_resumable(
+            self.request(Method::GET, url),
+            async |request| Ok(request.send().await?),
+            dest,
+            url,
+            artifact.size,
+            &artifact.hashes,
+        )
+        .await
+    }
+
+    /// Downloads an artifact without storing it and checks its SHA-256,
+    /// e.g. to put download load on a server. Returns the number of bytes.
+    pub async fn fetch_artifact(&self, artifact: &DdiArtifact) -> HawkbitResult<u64> {
+        let url = artifact.download_url().ok_or_else(|| {
+            HawkbitError::new(format!(
+                "Artifact {} has no download link",
+                artifact.filename
+            ))
+        })?;
+        let mut res = self
+            .request(Method::GET, url)
+            .header(header::ACCEPT, "application/octet-stream")
+            .send()
+            .await?;
+        let status = res.status();
+        if status != StatusCode::OK {
+            let body = res.text().await.unwrap_or_default();
+            return Err(HawkbitError::http(status, body));
+        }
+
+        let mut sha256 = Sha256::new();
+        let mut size = 0;
+        while let Some(chunk) = res.chunk().await? {
+            sha256.update(&chunk);
+            size += chunk.len() as u64;
+        }
+        let actual = hex::encode(sha256.finalize());
+        if let Some(expected) = &artifact.hashes.sha256
+            && !expected.eq_ignore_ascii_case(&actual)
+        {
+            return Err(HawkbitError::new(format!(
+                "sha256 mismatch for {}: expected {}, got {}",
+                artifact.filename, expected, actual
+            )));
+        }
+        Ok(size)
+    }
+}
@@ -0,0 +1,779 @@
+use axum::Json;
+use axum::Router;
+use axum::body::{Body, Bytes};
+use axum::extract::{Path, Request, State};
+use axum::http::request::Parts;
+use axum::http::{HeaderMap, HeaderValue, Method, StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use futures::FutureExt;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::artifact_cache::{ArtifactCache, CachedArtifact};
+use crate::ddi::DeploymentBase;
+use crate::ddi_recorder::{DeviceSummary, Exchange, ExchangeKind, Recorder, RecorderConfig};
+use crate::hawkbit::{HawkbitError, HawkbitResult};
+use crate::transport::{TransportConfig, build_client};
+
+/// Marks the DDI part of a URL; everything from the tenant segment before
+/// it on is the same for hawkBit and the proxy.
+const DDI_PATH: &str = "/controller/v1/";
+
+/// Largest request body forwarded, feedback and config data are small
+const MAX_REQUEST_BODY: usize = 1024 * 1024;
+
+/// How long a successful upstream check lets a device fetch an artifact
+/// from the cache before hawkBit is asked again
+const AUTHORIZATION_TTL: Duration = Duration::from_secs(300);
+
+/// Headers that only concern one connection and are not forwarded
+const HOP_BY_HOP: [header::HeaderName; 8] = [
+    header::CONNECTION,
+    header::HOST,
+    header::CONTENT_LENGTH,
+    header::TRANSFER_ENCODING,
+    header::TE,
+    header::TRAILER,
+    header::UPGRADE,
+    header::PROXY_AUTHORIZATION,
+];
+
+#[derive(Debug, Clone)]
+pub struct ProxyConfig {
+    pub listen: SocketAddr,
+    /// hawkBit URL the requests are forwarded to
+    pub upstream: String,
+    /// URL under which devices reach the proxy, used in rewritten links
+    pub public_url: String,
+    /// Artifact cache; without it downloads are passed through
+    pub cache: Option<CacheConfig>,
+    /// Records the traffic of each device when set
+    pub recorder: Option<RecorderConfig>,
+    pub transport: TransportConfig,
+}
+
+#[derive(Debug, Clone)]
+pub struct CacheConfig {
+    pub dir: PathBuf,
+    /// Maximum size of the cache in bytes
+    pub max_size: u64,
+}
+
+#[derive(Debug, Clone)]
+struct KnownArtifact {
+    sha256: String,
+    size: u64,
+}
+
+struct ProxyState {
+    client: reqwest::Client,
+    upstream: String,
+    public_url: String,
+    cache: Option<Arc<ArtifactCache>>,
+    recorder: Option<Arc<Recorder>>,
+    /// Artifacts seen in deployments, by `artifact_key` of their links
+    artifacts: Mutex<HashMap<String, KnownArtifact>>,
+    /// Time of the last successful upstream check, by artifact key and
+    /// fingerprint of the credentials used
+    authorized: Mutex<HashMap<(String, String), Instant>>,
+}
+
+/// Returns the part of `path` starting at the tenant segment, e.g.
+/// `/DEFAULT/controller/v1/dev-1`, or `None` for non-DDI paths.
+fn ddi_suffix(path: &str) -> Option<&str> {
+    let index = path.find(DDI_PATH)?;
+    let tenant_start = path[..index].rfind('/')?;
+    Some(&path[tenant_start..])
+}
+
+/// Splits a DDI suffix into the controller ID and the resource after it.
+fn controller_path(suffix: &str) -> Option<(&str, &str)> {
+    let (_, rest) = suffix.split_once(DDI_PATH)?;
+    Some(rest.split_once('/').unwrap_or((rest, "")))
+}
+
+/// Key of an artifact download path: the DDI suffix without its query. It
+/// includes the controller ID, so a device is only served the artifacts
+/// offered to it.
+fn artifact_key(suffix: &str) -> Option<String> {
+    let path = suffix.split_once('?').map_or(suffix, |(path, _)| path);
+    let (controller_id, resource) = controller_path(path)?;
+    if controller_id.is_empty() || resource.is_empty() {
+        return None;
+    }
+    Some(path.to_string())
+}
+
+/// Identifies the credentials of a request without keeping them around
+fn credentials_fingerprint(headers: &HeaderMap) -> String {
+    let mut hasher = Sha256::new();
+    if let Some(value) = headers.get(header::AUTHORIZATION) {
+        hasher.update(value.as_bytes());
+    }
+    hex::encode(hasher.finalize())
+}
+
+/// Parses a single `bytes=` range against `size` into `start..end`. Returns
+/// `Ok(None)` for ranges that are ignored, like multiple ranges.
+fn parse_range(value: &str, size: u64) -> Result<Option<(u64, u64)>, ()> {
+    let Some(spec) = value.trim().strip_prefix("bytes=") else {
+        return Ok(None);
+    };
+    if spec.contains(',') {
+        return Ok(None);
+    }
+    let (start, end) = spec.split_once('-').ok_or(())?;
+    let (start, end) = match (start.trim(), end.trim()) {
+        ("", suffix) => {
+            let suffix: u64 = suffix.parse().map_err(|_| ())?;
+            if suffix == 0 {
+                return Err(());
+            }
+            (size.saturating_sub(suffix), size)
+        }
+        (start, "") => (start.parse().map_err(|_| ())?, size),
+        (start, end) => {
+            let end: u64 = end.parse().map_err(|_| ())?;
+            (
+                start.parse().map_err(|_| ())?,
+                end.saturating_add(1).min(size),
+            )
+        }
+    };
+    if start >= size || start >= end {
+        return Err(());
+    }
+    Ok(Some((start, end)))
+}
+
+/// Headers of a device request as sent upstream. reqwest is built without
+/// decompression, so answers are asked for uncompressed to keep their JSON
+/// readable for the link rewriting.
+fn upstream_headers(headers: &HeaderMap) -> HeaderMap {
+    let mut forwarded = forwarded_headers(headers);
+    forwarded.remove(header::ACCEPT_ENCODING);
+    forwarded
+}
+
+fn forwarded_headers(headers: &HeaderMap) -> HeaderMap {
+    let mut forwarded = headers.clone();
+    for name in HOP_BY_HOP {
+        forwarded.remove(name);
+    }
+    forwarded
+}
+
+fn error_response(status: StatusCode, message: impl Into<String>) -> Response {
+    (status, message.into()).into_response()
+}
+
+impl ProxyState {
+    fn upstream_url(&self, suffix: &str, query: Option<&str>) -> String {
+        match query {
+            Some(query) => format!("{}{}?{}", self.upstream, suffix, query),
+            None => format!("{}{}", self.upstream, suffix),
+        }
+    }
+
+    /// Points every DDI link of a JSON answer to the proxy.
+    fn rewrite_links(&self, value: &mut Value) {
+        match value {
+            Value::Object(map) => {
+                for (key, value) in map.iter_mut() {
+                    if key == "href"
+                        && let Value::String(href) = value
+                        && let Some(suffix) = ddi_suffix(href)
+                    {
+                        *href = format!("{}{}", self.public_url, suffix);
+                    } else {
+                        self.rewrite_links(value);
+                    }
+                }
+            }
+            Value::Array(values) => values
+                .iter_mut()
+                .for_each(|value| self.rewrite_links(value)),
+            _ => {}
+        }
+    }
+
+    /// Remembers the hashes of the artifacts a deployment offers, so their
+    /// downloads can be served from the cache.
+    fn learn_artifacts(&self, body: &Value) {
+        let Ok(deployment) = serde_json::from_value::<DeploymentBase>(body.clone()) else {
+            return;
+        };
+        let mut artifacts = self.artifacts.lock().expect("artifacts lock poisoned");
+        for artifact in deployment
+            .deployment
+            .chunks
+            .iter()
+            .flat_map(|chunk| &chunk.artifacts)
+        {
+            let Some(sha256) = &artifact.hashes.sha256 else {
+                continue;
+            };
+            let links = [&artifact.links.download, &artifact.links.download_http];
+            for link in links.into_iter().flatten() {
+                if let Some(key) = ddi_suffix(&link.href).and_then(artifact_key) {
+                    artifacts.insert(
+                        key,
+                        KnownArtifact {
+                            sha256: sha256.to_lowercase(),
+                            size: artifact.size,
+                        },
+                    );
+                }
+            }
+        }
+    }
+
+    async fn forward(
+        &self,
+        method: Method,
+        suffix: &str,
+        query: Option<&str>,
+        headers: &HeaderMap,
+        body: Bytes,
+    ) -> HawkbitResult<(Response, Option<Value>)> {
+        let res = self
+            .client
+            .request(method, self.upstream_url(suffix, query))
+            .headers(upstream_headers(headers))
+            .body(body)
+            .send()
+            .await?;
+
+        let status = res.status();
+        let mut headers = forwarded_headers(res.headers());
+        let is_json = headers
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.contains("json"));
+        if !is_json {
+            let mut response = Response::new(Body::from_stream(res.bytes_stream()));
+            *response.status_mut() = status;
+            *response.headers_mut() = headers;
+            return Ok((response, None));
+        }
+
+        let bytes = res.bytes().await?;
+        let (body, value) = match serde_json::from_slice::<Value>(&bytes) {
+            Ok(mut value) => {
+                if self.cache.is_some()
+                    && status.is_success()
+                    && suffix.contains("/deploymentBase/")
+                {
+                    self.learn_artifacts(&value);
+                }
+                self.rewrite_links(&mut value);
+                // Re-serialized, so whatever encoding it came in is gone
+                headers.remove(header::CONTENT_ENCODING);
+                (Bytes::from(serde_json::to_vec(&value)?), Some(value))
+            }
+            // Passed on untouched, e.g. compressed by a server ignoring the
+            // missing Accept-Encoding
+            Err(_) => (bytes, None),
+        };
+        let mut response = Response::new(Body::from(body));
+        *response.status_mut() = status;
+        *response.headers_mut() = headers;
+        Ok((response, value))
+    }
+
+    /// Asks hawkBit with a HEAD request whether the device's own credentials
+    /// may download the artifact. A success is remembered per artifact and
+    /// credentials for `AUTHORIZATION_TTL`.
+    async fn authorize(
+        &self,
+        key: &str,
+        suffix: &str,
+        query: Option<&str>,
+        headers: &HeaderMap,
+    ) -> HawkbitResult<bool> {
+        let grant = (key.to_string(), credentials_fingerprint(headers));
+        {
+            let authorized = self.authorized.lock().expect("authorized lock poisoned");
+            if authorized
+                .get(&grant)
+                .is_some_and(|checked| checked.elapsed() < AUTHORIZATION_TTL)
+            {
+                return Ok(true);
+            }
+        }
+
+        let mut request_headers = upstream_headers(headers);
+        request_headers.remove(header::RANGE);
+        let res = self
+            .client
+            .head(self.upstream_url(suffix, query))
+            .headers(request_headers)
+            .send()
+            .await?;
+        if !res.status().is_success() {
+            tracing::debug!("hawkBit answered {} for {}", res.status(), suffix);
+            return Ok(false);
+        }
+
+        let mut authorized = self.authorized.lock().expect("authorized lock poisoned");
+        authorized.retain(|_, checked| checked.elapsed() < AUTHORIZATION_TTL);
+        authorized.insert(grant, Instant::now());
+        Ok(true)
+    }
+
+    /// Serves a known artifact from the cache, fetching it on a miss.
+    /// Returns `None` if it cannot be cached or hawkBit does not authorize
+    /// the device, in which case the request must be passed through.
+    async fn serve_artifact(
+        &self,
+        key: &str,
+        suffix: &str,
+        query: Option<&str>,
+        headers: &HeaderMap,
+        artifact: &KnownArtifact,
+    ) -> HawkbitResult<Option<Response>> {
+        let Some(cache) = &self.cache else {
+            return Ok(None);
+        };
+        if !self.authorize(key, suffix, query, headers).await? {
+            return Ok(None);
+        }
+        let cached = match cache.get(&artifact.sha256)? {
+            Some(cached) => {
+                tracing::debug!("Cache hit for {}", suffix);
+                cached
+            }
+            None => {
+                let mut request_headers = upstream_headers(headers);
+                request_headers.remove(header::RANGE);
+                let request = self
+                    .client
+                    .get(self.upstream_url(suffix, query))
+                    .headers(request_headers);
+                match cache.fill(&artifact.sha256, artifact.size, request)? {
+                    Some(cached) => cached,
+                    None => return Ok(None),
+                }
+            }
+        };
+        Ok(Some(artifact_response(cached, headers, &artifact.sha256)))
+    }
+}
+
+fn artifact_response(cached: CachedArtifact, headers: &HeaderMap, sha256: &str) -> Response {
+    let size = cached.size;
+    let range = headers
+        .get(header::RANGE)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| parse_range(value, size));
+
+    let (status, start, end) = match range {
+        Some(Err(())) => {
+            let mut response = error_response(StatusCode::RANGE_NOT_SATISFIABLE, "");
+            if let Ok(value) = HeaderValue::from_str(&format!("bytes */{}", size)) {
+                response.headers_mut().insert(header::CONTENT_RANGE, value);
+            }
+            return response;
+        }
+        Some(Ok(Some((start, end)))) => (StatusCode::PARTIAL_CONTENT, start, end),
+        Some(Ok(None)) | None => (StatusCode::OK, 0, size),
+    };
+
+    let mut response = Response::new(Body::from_stream(cached.stream(start, end)));
+    *response.status_mut() = status;
+    let headers = response.headers_mut();
+    headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/octet-stream"),
+    );
+    headers.insert(header::ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+    headers.insert(header::CONTENT_LENGTH, HeaderValue::from(end - start));
+    if let Ok(value) = HeaderValue::from_str(&format!("\"{}\"", sha256)) {
+        headers.insert(header::ETAG, value);
+    }
+    if status == StatusCode::PARTIAL_CONTENT
+        && let Ok(value) = HeaderValue::from_str(&format!("bytes {}-{}/{}", start, end - 1, size))
+    {
+        headers.insert(header::CONTENT_RANGE, value);
+    }
+    response
+}
+
+/// A device request as answered by the proxy, with what the recorder keeps
+struct Proxied {
+    response: Response,
+    request: Option<Value>,
+    body: Option<Value>,
+    cached: bool,
+    error: Option<String>,
+}
+
+impl Proxied {
+    fn new(response: Response) -> Self {
+        Self {
+            response,
+            request: None,
+            body: None,
+            cached: false,
+            error: None,
+        }
+    }
+}
+
+async fn proxy(state: &ProxyState, parts: &Parts, suffix: &str, body: Body) -> Proxied {
+    let query = parts.uri.query();
+
+    if parts.method == Method::GET || parts.method == Method::HEAD {
+        let known = artifact_key(suffix).and_then(|key| {
+            let artifacts = state.artifacts.lock().expect("artifacts lock poisoned");
+            artifacts.get(&key).cloned().map(|artifact| (key, artifact))
+        });
+        if let Some((key, artifact)) = known {
+            match state
+                .serve_artifact(&key, suffix, query, &parts.headers, &artifact)
+                .await
+            {
+                Ok(Some(response)) => {
+                    return Proxied {
+                        cached: true,
+                        ..Proxied::new(response)
+                    };
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    tracing::warn!("Serving {} from the cache failed: {}", suffix, e);
+                }
+            }
+        }
+    }
+
+    let body = match axum::body::to_bytes(body, MAX_REQUEST_BODY).await {
+        Ok(body) => body,
+        Err(e) => {
+            return Proxied::new(error_response(StatusCode::PAYLOAD_TOO_LARGE, e.to_string()));
+        }
+    };
+    let request = match &state.recorder {
+        Some(_) => serde_json::from_slice(&body).ok(),
+        None => None,
+    };
+    match state
+        .forward(parts.method.clone(), suffix, query, &parts.headers, body)
+        .await
+    {
+        Ok((response, body)) => Proxied {
+            request,
+            body,
+            ..Proxied::new(response)
+        },
+        Err(e) => {
+            tracing::warn!("Forwarding {} failed: {}", suffix, e);
+            Proxied {
+                request,
+                error: Some(e.to_string()),
+                ..Proxied::new(error_response(StatusCode::BAD_GATEWAY, e.to_string()))
+            }
+        }
+    }
+}
+
+async fn handle(State(state): State<Arc<ProxyState>>, request: Request) -> Response {
+    let started = Instant::now();
+    let timestamp = chrono::Utc::now().timestamp_millis();
+    let (parts, body) = request.into_parts();
+    let Some(suffix) = ddi_suffix(parts.uri.path()).map(str::to_string) else {
+        return error_response(StatusCode::NOT_FOUND, "Only DDI requests are proxied");
+    };
+
+    let proxied = proxy(&state, &parts, &suffix, body).await;
+
+    if let Some(recorder) = &state.recorder
+        && let Some((controller_id, resource)) = controller_path(&suffix)
+        && !controller_id.is_empty()
+    {
+        let method = parts.method.as_str();
+        recorder.record(
+            controller_id,
+            Exchange {
+                timestamp,
+                method: method.to_string(),
+                path: match parts.uri.query() {
+                    Some(query) => format!("{}?{}", suffix, query),
+                    None => suffix.clone(),
+                },
+                kind: ExchangeKind::classify(method, resource),
+                status: proxied.response.status().as_u16(),
+                duration_ms: started.elapsed().as_millis() as u64,
+                request: proxied.request,
+                response: proxied.body,
+                cached: proxied.cached,
+                error: proxied.error,
+            },
+        );
+    }
+    proxied.response
+}
+
+async fn list_devices(State(recorder): State<Arc<Recorder>>) -> Json<Vec<DeviceSummary>> {
+    Json(recorder.devices())
+}
+
+async fn show_device(
+    State(recorder): State<Arc<Recorder>>,
+    Path(controller_id): Path<String>,
+) -> Response {
+    match recorder.device(&controller_id) {
+        Some(history) => Json(history).into_response(),
+        None => error_response(
+            StatusCode::NOT_FOUND,
+            format!("No requests of {} recorded", controller_id),
+        ),
+    }
+}
+
+async fn bind(address: SocketAddr) -> HawkbitResult<tokio::net::TcpListener> {
+    tokio::net::TcpListener::bind(address)
+        .await
+        .map_err(|e| HawkbitError::new(format!("Failed to listen on {}: {}", address, e)))
+}
+
+/// Serves the DDI API of `config.upstream` on `config.listen` until
+/// `shutdown` completes. With a recorder, the recorded traffic is served
+/// on its own address under `/devices` and `/devices/{controller_id}`.
+pub async fn run(
+    config: &ProxyConfig,
+    shutdown: impl Future<Output = ()> + Send + 'static,
+) -> HawkbitResult<()> {
+    let cache = match &config.cache {
+        Some(cache) => Some(Arc::new(ArtifactCache::open(&cache.dir, cache.max_size)?)),
+        None => None,
+    };
+    let recorder = match &config.recorder {
+        Some(recorder) => Some(Arc::new(Recorder::new(recorder)?)),
+        None => None,
+    };
+    let state = Arc::new(ProxyState {
+        client: build_client(&config.transport, HeaderMap::new())?,
+        upstream: config.upstream.trim_end_matches('/').to_string(),
+        public_url: config.public_url.trim_end_matches('/').to_string(),
+        cache,
+        recorder: recorder.clone(),
+        artifacts: Mutex::new(HashMap::new()),
+        authorized: Mutex::new(HashMap::new()),
+    });
+    let shutdown = shutdown.shared();
+
+    let app = Router::new().fallback(handle).with_state(state);
+    let listener = bind(config.listen).await?;
+    tracing::info!(
+        "Proxying DDI requests on {} to {}",
+        config.listen,
+        config.upstream
+    );
+    let proxy = axum::serve(listener, app).with_graceful_shutdown(shutdown.clone());
+
+    let (Some(recorder_config), Some(recorder)) = (&config.recorder, recorder) else {
+        proxy.await?;
+        return Ok(());
+    };
+    let inspection = Router::new()
+        .route("/devices", get(list_devices))
+        .route("/devices/{controller_id}", get(show_device))
+        .with_state(recorder.clone());
+    let listener = bind(recorder_config.listen).await?;
+    tracing::info!("Serving recorded DDI traffic on {}", recorder_config.listen);
+    let inspection = axum::serve(listener, inspection).with_graceful_shutdown(shutdown);
+    tokio::try_join!(proxy.into_future(), inspection.into_future())?;
+    recorder.flush().await;
+    Ok(())
+}
+
+/// Parses sizes like `500M`, `20GiB` or `1T`, using binary multiples.
+pub fn parse_size(value: &str) -> HawkbitResult<u64> {
+    let value = value.trim();
+    let digits = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(digits);
+    let invalid = || HawkbitError::new(format!("Invalid size {:?}", value));
+    let number: u64 = number.parse().map_err(|_| invalid())?;
+    let shift = match unit
+        .trim()
+        .trim_end_matches("iB")
+        .trim_end_matches('B')
+        .to_ascii_uppercase()
+        .as_str()
+    {
+        "" => 0,
+        "K" => 10,
+        "M" => 20,
+        "G" => 30,
+        "T" => 40,
+        _ => return Err(invalid()),
+    };
+    number.checked_mul(1 << shift).ok_or_else(invalid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::Compression;
+    use flate2::write::GzEncoder;
+    use std::io::Write;
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    /// Answers polls with a link back to itself, gzip compressed if the
+    /// request accepts it or the controller ID is `always-gzip`.
+    async fn serve_upstream() -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let upstream = format!("http://{}", listener.local_addr().unwrap());
+        let base = upstream.clone();
+        let app = Router::new().fallback(async move |request: Request| {
+            let path = request.uri().path().to_string();
+            let body = serde_json::to_vec(&serde_json::json!({
+                "config": { "polling": { "sleep": "00:05:00" } },
+                "_links": { "deploymentBase": { "href": format!("{}{}/deploymentBase/7", base, path) } }
+            }))
+            .unwrap();
+            let accepts_gzip = request
+                .headers()
+                .get(header::ACCEPT_ENCODING)
+                .is_some_and(|value| value.to_str().unwrap().contains("gzip"));
+            let mut response = if accepts_gzip || path.ends_with("/always-gzip") {
+                let mut response = Response::new(Body::from(gzip(&body)));
+                response
+                    .headers_mut()
+                    .insert(header::CONTENT_ENCODING, HeaderValue::from_static("gzip"));
+                response
+            } else {
+                Response::new(Body::from(body))
+            };
+            response.headers_mut().insert(
+                header::CONTENT_TYPE,
+                HeaderValue::from_static("application/hal+json"),
+            );
+            response
+        });
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        upstream
+    }
+
+    fn state(upstream: String) -> ProxyState {
+        ProxyState {
+            client: reqwest::Client::new(),
+            upstream,
+            public_url: "http://proxy.local".to_string(),
+            cache: None,
+            recorder: None,
+            artifacts: Mutex::new(HashMap::new()),
+            authorized: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn accepting_gzip() -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::ACCEPT_ENCODING,
+            HeaderValue::from_static("gzip, br"),
+        );
+        headers
+    }
+
+    #[tokio::test]
+    async fn json_is_requested_uncompressed_and_rewritten() {
+        let state = state(serve_upstream().await);
+        let (response, value) = state
+            .forward(
+                Method::GET,
+                "/DEFAULT/controller/v1/dev-1",
+                None,
+                &accepting_gzip(),
+                Bytes::new(),
+            )
+            .await
+            .unwrap();
+
+        assert!(response.headers().get(header::CONTENT_ENCODING).is_none());
+        let href = &value.unwrap()["_links"]["deploymentBase"]["href"];
+        assert_eq!(
+            href,
+            "http://proxy.local/DEFAULT/controller/v1/dev-1/deploymentBase/7"
+        );
+    }
+
+    #[tokio::test]
+    async fn compressed_json_is_passed_on_with_its_encoding() {
+        let state = state(serve_upstream().await);
+        let (response, value) = state
+            .forward(
+                Method::GET,
+                "/DEFAULT/controller/v1/always-gzip",
+                None,
+                &accepting_gzip(),
+                Bytes::new(),
+            )
+            .await
+            .unwrap();
+
+        assert!(value.is_none());
+        assert_eq!(response.headers()[header::CONTENT_ENCODING], "gzip");
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let mut decoded = String::new();
+        std::io::Read::read_to_string(&mut flate2::read::GzDecoder::new(&body[..]), &mut decoded)
+            .unwrap();
+        assert!(decoded.contains("/always-gzip/deploymentBase/7"));
+    }
+
+    #[test]
+    fn parses_single_ranges() {
+        assert_eq!(parse_range("bytes=0-99", 1000), Ok(Some((0, 100))));
+        assert_eq!(parse_range("bytes=500-", 1000), Ok(Some((500, 1000))));
+        assert_eq!(parse_range("bytes=-100", 1000), Ok(Some((900, 1000))));
+        assert_eq!(parse_range(" bytes=900-5000 ", 1000), Ok(Some((900, 1000))));
+        assert_eq!(parse_range("bytes=-2000", 1000), Ok(Some((0, 1000))));
+    }
+
+    #[test]
+    fn ignores_multiple_ranges_and_other_units() {
+        assert_eq!(parse_range("bytes=0-1,5-6", 1000), Ok(None));
+        assert_eq!(parse_range("items=0-1", 1000), Ok(None));
+    }
+
+    #[test]
+    fn rejects_unsatisfiable_ranges() {
+        assert_eq!(parse_range("bytes=1000-", 1000), Err(()));
+        assert_eq!(parse_range("bytes=5-4", 1000), Err(()));
+        assert_eq!(parse_range("bytes=-0", 1000), Err(()));
+        assert_eq!(parse_range("bytes=a-b", 1000), Err(()));
+        assert_eq!(parse_range("bytes=0-", 0), Err(()));
+    }
+
+    #[test]
+    fn artifact_keys_are_per_device() {
+        let path = "/DEFAULT/controller/v1/dev-1/softwaremodules/3/artifacts/fw.bin";
+        assert_eq!(artifact_key(path).as_deref(), Some(path));
+        assert_eq!(
+            artifact_key(&format!("{}?c=1", path)).as_deref(),
+            Some(path)
+        );
+        assert_ne!(
+            artifact_key(path),
+            artifact_key(&path.replace("dev-1", "dev-2"))
+        );
+        assert_eq!(artifact_key("/DEFAULT/controller/v1/dev-1"), None);
+    }
+}
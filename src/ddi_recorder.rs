@@ -0,0 +1,415 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{HashMap, VecDeque};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::{mpsc, oneshot};
+
+use crate::hawkbit::{HawkbitError, HawkbitResult};
+
+#[derive(Debug, Clone)]
+pub struct RecorderConfig {
+    /// Address of the inspection API, kept apart from the device listener
+    pub listen: SocketAddr,
+    /// Number of exchanges kept per device
+    pub history: usize,
+    /// Number of devices tracked; a new device replaces the one seen least
+    /// recently, so made-up controller IDs cannot grow the record unbounded
+    pub max_devices: usize,
+    /// JSON lines file every exchange is appended to
+    pub log: Option<PathBuf>,
+    /// Size at which the log file is rotated to `<log>.1`
+    pub log_max_size: u64,
+}
+
+/// What a DDI request was about, derived from its method and path
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExchangeKind {
+    Poll,
+    Deployment,
+    DeploymentFeedback,
+    CancelAction,
+    CancelFeedback,
+    Confirmation,
+    ConfirmationFeedback,
+    ConfigData,
+    InstalledBase,
+    Artifact,
+    Other,
+}
+
+impl ExchangeKind {
+    /// Classifies a request by the part of its path after the controller ID.
+    pub fn classify(method: &str, resource: &str) -> Self {
+        let resource = resource.trim_matches('/');
+        let first = resource.split('/').next().unwrap_or_default();
+        let feedback = method == "POST" && resource.ends_with("/feedback");
+        match first {
+            "" => ExchangeKind::Poll,
+            "deploymentBase" if feedback => ExchangeKind::DeploymentFeedback,
+            "deploymentBase" => ExchangeKind::Deployment,
+            "cancelAction" if feedback => ExchangeKind::CancelFeedback,
+            "cancelAction" => ExchangeKind::CancelAction,
+            "confirmationBase" if feedback => ExchangeKind::ConfirmationFeedback,
+            "confirmationBase" => ExchangeKind::Confirmation,
+            "configData" => ExchangeKind::ConfigData,
+            "installedBase" => ExchangeKind::InstalledBase,
+            _ if resource.contains("/artifacts/") => ExchangeKind::Artifact,
+            _ => ExchangeKind::Other,
+        }
+    }
+
+    pub fn is_feedback(self) -> bool {
+        matches!(
+            self,
+            ExchangeKind::DeploymentFeedback
+                | ExchangeKind::CancelFeedback
+                | ExchangeKind::ConfirmationFeedback
+        )
+    }
+}
+
+/// One request of a device and the answer it got
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Exchange {
+    /// Milliseconds since the epoch when the request arrived
+    pub timestamp: i64,
+    pub method: String,
+    /// Path from the tenant segment on, with the query
+    pub path: String,
+    pub kind: ExchangeKind,
+    pub status: u16,
+    /// Time until the answer was ready; for streamed artifacts until the
+    /// first byte
+    pub duration_ms: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response: Option<Value>,
+    /// Artifact served from the proxy cache instead of hawkBit
+    #[serde(default)]
+    pub cached: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// The latest exchanges of each kind of interest for a device. They are kept
+/// even after the history has rolled past them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeviceSummary {
+    pub controller_id: String,
+    pub last_seen: i64,
+    pub exchanges: u64,
+    pub last_poll: Option<Exchange>,
+    pub last_deployment: Option<Exchange>,
+    pub last_feedback: Option<Exchange>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeviceHistory {
+    #[serde(flatten)]
+    pub summary: DeviceSummary,
+    /// Most recent exchanges, oldest first
+    pub history: Vec<Exchange>,
+}
+
+#[derive(Debug)]
+struct Device {
+    summary: DeviceSummary,
+    history: VecDeque<Exchange>,
+}
+
+/// Lines the log writer may fall behind before exchanges are dropped
+const LOG_QUEUE: usize = 4096;
+
+#[derive(Debug)]
+enum LogMessage {
+    Line(Vec<u8>),
+    /// Answered once everything sent before has been written
+    Flush(oneshot::Sender<()>),
+}
+
+struct Log {
+    path: PathBuf,
+    file: tokio::fs::File,
+    size: u64,
+    max_size: u64,
+}
+
+#[derive(Serialize)]
+struct LogLine<'a> {
+    controller_id: &'a str,
+    #[serde(flatten)]
+    exchange: &'a Exchange,
+}
+
+fn open_log(path: &Path) -> HawkbitResult<std::fs::File> {
+    Ok(std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?)
+}
+
+impl Log {
+    async fn append(&mut self, line: &[u8]) -> HawkbitResult<()> {
+        if self.size > 0 && self.size + line.len() as u64 > self.max_size {
+            self.file.flush().await?;
+            let mut rotated = self.path.clone().into_os_string();
+            rotated.push(".1");
+            tokio::fs::rename(&self.path, rotated).await?;
+            self.file = tokio::fs::File::from_std(open_log(&self.path)?);
+            self.size = 0;
+        }
+        self.file.write_all(line).await?;
+        self.size += line.len() as u64;
+        Ok(())
+    }
+
+    /// Writes the lines it receives until every sender is gone, so slow
+    /// disks never hold up the proxied requests.
+    async fn run(mut self, mut receiver: mpsc::Receiver<LogMessage>) {
+        while let Some(message) = receiver.recv().await {
+            let result = match message {
+                LogMessage::Line(line) => self.append(&line).await,
+                LogMessage::Flush(done) => {
+                    let result = self.file.flush().await;
+                    let _ = done.send(());
+                    result.map_err(Into::into)
+                }
+            };
+            if let Err(e) = result {
+                tracing::warn!("Writing the DDI log failed: {}", e);
+            }
+            if receiver.is_empty()
+                && let Err(e) = self.file.flush().await
+            {
+                tracing::warn!("Writing the DDI log failed: {}", e);
+            }
+        }
+    }
+}
+
+/// Rolling per-device record of the DDI traffic passing the proxy
+#[derive(Debug)]
+pub struct Recorder {
+    history: usize,
+    max_devices: usize,
+    devices: Mutex<HashMap<String, Device>>,
+    log: Option<mpsc::Sender<LogMessage>>,
+}
+
+impl Recorder {
+    /// Creates the recorder and, with a log file, starts its writer task.
+    pub fn new(config: &RecorderConfig) -> HawkbitResult<Self> {
+        let log = match &config.log {
+            Some(path) => {
+                let file = open_log(path)?;
+                let log = Log {
+                    path: path.clone(),
+                    size: file.metadata()?.len(),
+                    file: tokio::fs::File::from_std(file),
+                    max_size: config.log_max_size,
+                };
+                let (sender, receiver) = mpsc::channel(LOG_QUEUE);
+                tokio::spawn(log.run(receiver));
+                Some(sender)
+            }
+            None => None,
+        };
+        Ok(Self {
+            history: config.history,
+            max_devices: config.max_devices,
+            devices: Mutex::new(HashMap::new()),
+            log,
+        })
+    }
+
+    pub fn record(&self, controller_id: &str, exchange: Exchange) {
+        if let Some(log) = &self.log {
+            let line = serde_json::to_vec(&LogLine {
+                controller_id,
+                exchange: &exchange,
+            });
+            match line {
+                Ok(mut line) => {
+                    line.push(b'\n');
+                    if log.try_send(LogMessage::Line(line)).is_err() {
+                        tracing::warn!(
+                            "DDI log is falling behind, dropped a request of {}",
+                            controller_id
+                        );
+                    }
+                }
+                Err(e) => tracing::warn!("Writing the DDI log failed: {}", e),
+            }
+        }
+
+        let mut devices = self.devices.lock().expect("devices lock poisoned");
+        if !devices.contains_key(controller_id) && devices.len() >= self.max_devices {
+            let oldest = devices
+                .values()
+                .min_by_key(|device| device.summary.last_seen)
+                .map(|device| device.summary.controller_id.clone());
+            match oldest {
+                Some(oldest) => {
+                    devices.remove(&oldest);
+                }
+                None => return,
+            }
+        }
+        let device = devices
+            .entry(controller_id.to_string())
+            .or_insert_with(|| Device {
+                summary: DeviceSummary {
+                    controller_id: controller_id.to_string(),
+                    last_seen: exchange.timestamp,
+                    exchanges: 0,
+                    last_poll: None,
+                    last_deployment: None,
+                    last_feedback: None,
+                },
+                history: VecDeque::new(),
+            });
+        let summary = &mut device.summary;
+        summary.last_seen = exchange.timestamp;
+        summary.exchanges += 1;
+        match exchange.kind {
+            ExchangeKind::Poll => summary.last_poll = Some(exchange.clone()),
+            ExchangeKind::Deployment => summary.last_deployment = Some(exchange.clone()),
+            kind if kind.is_feedback() => summary.last_feedback = Some(exchange.clone()),
+            _ => {}
+        }
+        if device.history.len() >= self.history {
+            device.history.pop_front();
+        }
+        if self.history > 0 {
+            device.history.push_back(exchange);
+        }
+    }
+
+    /// Waits until the log holds every exchange recorded so far.
+    pub async fn flush(&self) {
+        if let Some(log) = &self.log {
+            let (done, wait) = oneshot::channel();
+            if log.send(LogMessage::Flush(done)).await.is_ok() {
+                let _ = wait.await;
+            }
+        }
+    }
+
+    /// Summaries of all devices seen, most recently seen first
+    pub fn devices(&self) -> Vec<DeviceSummary> {
+        let devices = self.devices.lock().expect("devices lock poisoned");
+        let mut summaries: Vec<_> = devices
+            .values()
+            .map(|device| device.summary.clone())
+            .collect();
+        summaries.sort_by_key(|summary| std::cmp::Reverse(summary.last_seen));
+        summaries
+    }
+
+    pub fn device(&self, controller_id: &str) -> Option<DeviceHistory> {
+        let devices = self.devices.lock().expect("devices lock poisoned");
+        devices.get(controller_id).map(|device| DeviceHistory {
+            summary: device.summary.clone(),
+            history: device.history.iter().cloned().collect(),
+        })
+    }
+}
+
+fn inspection_url(base: &str, controller_id: Option<&str>) -> HawkbitResult<reqwest::Url> {
+    let mut url = reqwest::Url::parse(base)
+        .map_err(|e| HawkbitError::new(format!("Invalid URL {:?}: {}", base, e)))?;
+    {
+        let mut segments = url
+            .path_segments_mut()
+            .map_err(|()| HawkbitError::new(format!("Invalid URL {:?}", base)))?;
+        segments.pop_if_empty().push("devices");
+        if let Some(controller_id) = controller_id {
+            segments.push(controller_id);
+        }
+    }
+    Ok(url)
+}
+
+/// Fetches the device summaries from the inspection API of a proxy at
+/// `base`.
+pub async fn fetch_devices(
+    client: &reqwest::Client,
+    base: &str,
+) -> HawkbitResult<Vec<DeviceSummary>> {
+    let res = client.get(inspection_url(base, None)?).send().await?;
+    let status = res.status();
+    if !status.is_success() {
+        return Err(HawkbitError::http(status, res.text().await?));
+    }
+    Ok(res.json().await?)
+}
+
+/// Fetches the recorded traffic of one device, `None` if the proxy has not
+/// seen it.
+pub async fn fetch_device(
+    client: &reqwest::Client,
+    base: &str,
+    controller_id: &str,
+) -> HawkbitResult<Option<DeviceHistory>> {
+    let res = client
+        .get(inspection_url(base, Some(controller_id))?)
+        .send()
+        .await?;
+    let status = res.status();
+    if status == reqwest::StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+    if !status.is_success() {
+        return Err(HawkbitError::http(status, res.text().await?));
+    }
+    Ok(Some(res.json().await?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn poll(timestamp: i64) -> Exchange {
+        Exchange {
+            timestamp,
+            method: "GET".to_string(),
+            path: "/DEFAULT/controller/v1/dev".to_string(),
+            kind: ExchangeKind::Poll,
+            status: 200,
+            duration_ms: 1,
+            request: None,
+            response: None,
+            cached: false,
+            error: None,
+        }
+    }
+
+    #[test]
+    fn least_recently_seen_device_makes_room() {
+        let recorder = Recorder::new(&RecorderConfig {
+            listen: "127.0.0.1:0".parse().unwrap(),
+            history: 5,
+            max_devices: 2,
+            log: None,
+            log_max_size: 0,
+        })
+        .unwrap();
+        recorder.record("dev-1", poll(1));
+        recorder.record("dev-2", poll(2));
+        recorder.record("dev-1", poll(3));
+        recorder.record("dev-3", poll(4));
+
+        let seen: Vec<_> = recorder
+            .devices()
+            .into_iter()
+            .map(|summary| summary.controller_id)
+            .collect();
+        assert_eq!(seen, ["dev-3", "dev-1"]);
+        assert_eq!(recorder.device("dev-1").unwrap().history.len(), 2);
+    }
+}
@@ -0,0 +1,288 @@
+use regex::Regex;
+use serde::Serialize;
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::LazyLock;
+
+use crate::channels::UPDATE_CHANNEL_ATTRIBUTE;
+use crate::hawkbit::{ActionStatusEvent, HawkbitMgmtClient, HawkbitResult};
+
+/// Prefix hawkBit uses for messages it generates itself
+const SERVER_MESSAGE_PREFIX: &str = "Update Server:";
+
+/// A path has to start a word, so ratios like `3/10` are left to `NUMBER`
+static PATH: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"(^|[\s'"(\[=,])((?:[A-Za-z]:)?(?:/[\w.\-@+~]+)+/?)"#).unwrap());
+static HEX: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\b(?:0x)?[0-9a-fA-F]{8,}\b").unwrap());
+static NUMBER: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\d+(?:\.\d+)*").unwrap());
+static WHITESPACE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\s+").unwrap());
+
+/// Reduces a status message to a failure signature by replacing the parts
+/// that vary between devices (paths, hashes, numbers) with placeholders.
+/// A path ends at the first whitespace.
+pub fn normalize_message(message: &str) -> String {
+    let message = message.trim().to_lowercase();
+    let message = PATH.replace_all(&message, "${1}<path>");
+    let message = HEX.replace_all(&message, "<hex>");
+    let message = NUMBER.replace_all(&message, "<n>");
+    WHITESPACE.replace_all(&message, " ").trim().to_string()
+}
+
+/// Picks the messages that describe the failure: the device messages of the
+/// most recent error event, falling back to the most recent event with any
+/// device message.
+fn failure_messages(events: &[ActionStatusEvent]) -> Vec<String> {
+    let mut events: Vec<&ActionStatusEvent> = events.iter().collect();
+    events.sort_by_key(|event| std::cmp::Reverse(event.reported_at));
+
+    let device_messages = |event: &ActionStatusEvent| -> Vec<String> {
+        event
+            .messages
+            .iter()
+            .filter(|m| !m.starts_with(SERVER_MESSAGE_PREFIX) && !m.trim().is_empty())
+            .cloned()
+            .collect()
+    };
+
+    events
+        .iter()
+        .filter(|event| event.event_type == "error")
+        .map(|event| device_messages(event))
+        .find(|messages| !messages.is_empty())
+        .or_else(|| {
+            events
+                .iter()
+                .map(|event| device_messages(event))
+                .find(|messages| !messages.is_empty())
+        })
+        .unwrap_or_default()
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FailureRecord {
+    pub controller_id: String,
+    pub action_id: i64,
+    pub distribution_set: String,
+    pub channel: String,
+    /// ms since epoch
+    pub failed_at: u64,
+    pub messages: Vec<String>,
+    pub signature: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SignatureCount {
+    pub signature: String,
+    pub count: usize,
+    /// Share of the failures in the group, 0.0 - 1.0
+    pub share: f64,
+    pub example: String,
+}
+
+/// A failed action whose status history could not be read
+#[derive(Debug, Clone, Serialize)]
+pub struct UnreadableAction {
+    pub controller_id: String,
+    pub action_id: i64,
+    pub error: String,
+}
+
+#[derive(Debug, Default)]
+pub struct CollectedFailures {
+    pub failures: Vec<FailureRecord>,
+    pub unreadable: Vec<UnreadableAction>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FailureReport {
+    pub since: u64,
+    pub total: usize,
+    pub signatures: Vec<SignatureCount>,
+    pub by_distribution_set: BTreeMap<String, Vec<SignatureCount>>,
+    pub by_channel: BTreeMap<String, Vec<SignatureCount>>,
+    pub failures: Vec<FailureRecord>,
+    pub unreadable: Vec<UnreadableAction>,
+}
+
+/// Collects all actions that failed since `since` (ms since epoch) together
+/// with their failure messages and the channel of the target. An action
+/// whose status can't be read is recorded and does not stop the others.
+pub async fn collect_failures(
+    client: &HawkbitMgmtClient,
+    since: u64,
+) -> HawkbitResult<CollectedFailures> {
+    let actions = client
+        .get_actions(Some(&format!(
+            "detailStatus==error;lastModifiedAt=ge={}",
+            since
+        )))
+        .await?;
+
+    let mut channels: HashMap<String, String> = HashMap::new();
+    let mut collected = CollectedFailures::default();
+    for action in actions {
+        if action.detail_status != "error" {
+            continue;
+        }
+        let Some(controller_id) = action.target_id() else {
+            continue;
+        };
+
+        let events = match client.get_action_status(&controller_id, &action.id).await {
+            Ok(events) => events,
+            Err(e) => {
+                tracing::warn!(
+                    "Failed to read the status of action {} of {:?}: {}",
+                    action.id,
+                    controller_id,
+                    e
+                );
+                collected.unreadable.push(UnreadableAction {
+                    controller_id,
+                    action_id: action.id,
+                    error: e.to_string(),
+                });
+                continue;
+            }
+        };
+        let messages = failure_messages(&events);
+        let signature = if messages.is_empty() {
+            "<no message>".to_string()
+        } else {
+            messages
+                .iter()
+                .map(|m| normalize_message(m))
+                .collect::<Vec<_>>()
+                .join(" | ")
+        };
+
+        let channel = match channels.entry(controller_id.clone()) {
+            Entry::Occupied(cached) => cached.get().clone(),
+            Entry::Vacant(slot) => slot
+                .insert(
+                    client
+                        .get_target_attributes(&controller_id, None)
+                        .await
+                        .ok()
+                        .and_then(|attributes| attributes.get(UPDATE_CHANNEL_ATTRIBUTE).cloned())
+                        .unwrap_or_else(|| "<unknown>".to_string()),
+                )
+                .clone(),
+        };
+
+        collected.failures.push(FailureRecord {
+            channel,
+            distribution_set: action
+                .distribution_set_name()
+                .unwrap_or_else(|| "<unknown>".to_string()),
+            action_id: action.id,
+            failed_at: action.last_modified_at,
+            messages,
+            signature,
+            controller_id,
+        });
+    }
+    Ok(collected)
+}
+
+fn count_signatures<'a>(failures: impl Iterator<Item = &'a FailureRecord>) -> Vec<SignatureCount> {
+    let mut counts: HashMap<&str, (usize, &FailureRecord)> = HashMap::new();
+    let mut total = 0;
+    for failure in failures {
+        counts.entry(&failure.signature).or_insert((0, failure)).0 += 1;
+        total += 1;
+    }
+
+    let mut signatures: Vec<SignatureCount> = counts
+        .into_iter()
+        .map(|(signature, (count, example))| SignatureCount {
+            signature: signature.to_string(),
+            count,
+            share: count as f64 / total as f64,
+            example: example.messages.join(" | "),
+        })
+        .collect();
+    signatures.sort_by(|a, b| b.count.cmp(&a.count).then(a.signature.cmp(&b.signature)));
+    signatures
+}
+
+/// Clusters failures by signature, overall and per distribution set and channel.
+pub fn build_report(since: u64, collected: CollectedFailures) -> FailureReport {
+    let CollectedFailures {
+        failures,
+        unreadable,
+    } = collected;
+    let mut by_distribution_set: BTreeMap<String, Vec<&FailureRecord>> = BTreeMap::new();
+    let mut by_channel: BTreeMap<String, Vec<&FailureRecord>> = BTreeMap::new();
+    for failure in &failures {
+        by_distribution_set
+            .entry(failure.distribution_set.clone())
+            .or_default()
+            .push(failure);
+        by_channel
+            .entry(failure.channel.clone())
+            .or_default()
+            .push(failure);
+    }
+
+    FailureReport {
+        since,
+        total: failures.len(),
+        signatures: count_signatures(failures.iter()),
+        by_distribution_set: by_distribution_set
+            .into_iter()
+            .map(|(ds, records)| (ds, count_signatures(records.into_iter())))
+            .collect(),
+        by_channel: by_channel
+            .into_iter()
+            .map(|(channel, records)| (channel, count_signatures(records.into_iter())))
+            .collect(),
+        failures,
+        unreadable,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::normalize_message;
+
+    #[test]
+    fn replaces_paths_hashes_and_numbers() {
+        assert_eq!(
+            normalize_message("  Write to /data/update/rootfs.img failed:  errno 28 "),
+            "write to <path> failed: errno <n>"
+        );
+        assert_eq!(
+            normalize_message("Checksum deadbeef01234567 does not match"),
+            "checksum <hex> does not match"
+        );
+        assert_eq!(
+            normalize_message("Cannot open C:/Data/rootfs.img"),
+            "cannot open <path>"
+        );
+        assert_eq!(
+            normalize_message("Mount failed (/dev/mmcblk0p2)"),
+            "mount failed (<path>)"
+        );
+    }
+
+    #[test]
+    fn keeps_ratios_out_of_paths() {
+        assert_eq!(
+            normalize_message("Retry 3/10 failed"),
+            "retry <n>/<n> failed"
+        );
+        assert_eq!(
+            normalize_message("Downloaded 512/2048 bytes of v1.2.3"),
+            "downloaded <n>/<n> bytes of v<n>"
+        );
+    }
+
+    #[test]
+    fn messages_differing_in_details_share_a_signature() {
+        assert_eq!(
+            normalize_message("Verify of /tmp/a1/img failed after 12 s"),
+            normalize_message("verify of /var/tmp/b/img   failed after 3 s")
+        );
+    }
+}
@@ -1,49 +1,47 @@
-use dotenv::dotenv;
+use crate::artifacts;
+use crate::auth::AuthStrategy;
+pub use crate::config::HawkbitConfig;
+use crate::transport::build_client;
+use percent_encoding::{AsciiSet, CONTROLS, utf8_percent_encode};
 use reqwest::Client;
 use reqwest::{StatusCode, header};
 use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use serde_json::{Value, json};
 use std::collections::HashMap;
-use std::env;
+use std::collections::hash_map::Entry;
 use std::fmt;
-use std::hash::Hash;
-
-#[derive(Debug, Clone)]
-pub struct HawkbitConfig {
-    host: String,
-    username: String,
-    password: String,
-    channel: Option<String>,
-}
-
-impl HawkbitConfig {
-    pub fn from_env() -> Self {
-        dotenv().ok(); // Load from .env file into environment variables
-
-        let host = env::var("HAWKBIT_HOST").unwrap_or_else(|_| "http://localhost".to_string());
-
-        let username = env::var("HAWKBIT_USERNAME").unwrap();
-        let password = env::var("HAWKBIT_PASSWORD").unwrap();
-        let channel = env::var("HAWKBIT_CHANNEL").ok();
-
-        HawkbitConfig {
-            host,
-            username,
-            password,
-            channel,
-        }
-    }
-}
+use std::path::Path;
+use std::sync::Arc;
 
 #[derive(Debug)]
 pub struct HawkbitError {
     msg: String,
+    status: Option<u16>,
 }
 
 impl HawkbitError {
     pub fn new<T: Into<String>>(msg: T) -> Self {
-        Self { msg: msg.into() }
+        Self {
+            msg: msg.into(),
+            status: None,
+        }
+    }
+
+    pub fn http(status: StatusCode, body: String) -> Self {
+        Self {
+            msg: format!("HTTP error {}: {}", status.as_u16(), body),
+            status: Some(status.as_u16()),
+        }
+    }
+
+    /// HTTP status code, if the error was returned by the server
+    pub fn status(&self) -> Option<u16> {
+        self.status
+    }
+
+    pub fn is_not_found(&self) -> bool {
+        self.status == Some(StatusCode::NOT_FOUND.as_u16())
     }
 }
 
@@ -61,9 +59,21 @@ impl From<reqwest::Error> for HawkbitError {
     }
 }
 
+impl From<serde_json::Error> for HawkbitError {
+    fn from(err: serde_json::Error) -> Self {
+        HawkbitError::new(err.to_string())
+    }
+}
+
+impl From<std::io::Error> for HawkbitError {
+    fn from(err: std::io::Error) -> Self {
+        HawkbitError::new(err.to_string())
+    }
+}
+
 pub type HawkbitResult<T> = std::result::Result<T, HawkbitError>;
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone)]
 pub struct MgmtTarget {
     #[serde(rename = "_links")]
     pub links: Value,
@@ -94,6 +104,32 @@ pub struct MgmtTarget {
     pub target_type_name: Option<String>,
     #[serde(rename = "autoConfirmActive")]
     pub auto_confirm_active: Option<bool>,
+    #[serde(rename = "createdAt")]
+    pub created_at: Option<u64>,
+    #[serde(rename = "lastModifiedAt")]
+    pub last_modified_at: Option<u64>,
+    #[serde(rename = "name")]
+    pub name: Option<String>,
+    #[serde(rename = "description")]
+    pub description: Option<String>,
+}
+
+/// Body of a target creation request
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct NewTarget {
+    #[serde(rename = "controllerId")]
+    pub controller_id: String,
+    pub name: String,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+
+    /// Generated by hawkBit when not set
+    #[serde(rename = "securityToken", skip_serializing_if = "Option::is_none")]
+    pub security_token: Option<String>,
+
+    #[serde(rename = "targetType", skip_serializing_if = "Option::is_none")]
+    pub target_type: Option<i64>,
 }
 
 #[derive(Debug)]
@@ -101,6 +137,21 @@ pub struct HawkbitMgmtClient {
     config: HawkbitConfig,
     client: Client,
     default_headers: header::HeaderMap,
+    auth: Arc<dyn AuthStrategy>,
+    compatibility_check: CompatibilityCheck,
+}
+
+/// What `assign_distribution` does when the distribution set type is not
+/// compatible with the target's type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CompatibilityCheck {
+    /// Refuse the assignment with an error
+    #[default]
+    Enforce,
+    /// Log a warning and let hawkBit decide
+    Warn,
+    /// Skip the pre-flight check
+    Off,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -141,6 +192,45 @@ pub struct Action {
     pub weight: Option<u32>,
 }
 
+impl Action {
+    fn linked_id(&self, rel: &str) -> Option<String> {
+        let href = self.links.get(rel)?.get("href")?.as_str()?;
+        href.trim_end_matches('/')
+            .rsplit('/')
+            .next()
+            .map(|id| id.to_string())
+    }
+
+    /// Controller ID of the target, taken from the action's links
+    pub fn target_id(&self) -> Option<String> {
+        self.linked_id("target")
+    }
+
+    pub fn distribution_set_id(&self) -> Option<u64> {
+        self.linked_id("distributionset")?.parse().ok()
+    }
+
+    pub fn distribution_set_name(&self) -> Option<String> {
+        self.links
+            .get("distributionset")?
+            .get("name")?
+            .as_str()
+            .map(|name| name.to_string())
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AutoConfirmState {
+    pub active: bool,
+
+    pub initiator: Option<String>,
+
+    pub remark: Option<String>,
+
+    #[serde(rename = "activatedAt")]
+    pub activated_at: Option<u64>,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Link {
     #[serde(rename = "href")]
@@ -267,6 +357,76 @@ pub struct SoftwareModule {
     pub type_name: String,
 
     pub version: String,
+
+    pub description: Option<String>,
+    pub vendor: Option<String>,
+}
+
+/// Body of a software module creation request
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct NewSoftwareModule {
+    pub name: String,
+    pub version: String,
+
+    /// Key of the software module type
+    #[serde(rename = "type")]
+    pub module_type: String,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vendor: Option<String>,
+
+    pub encrypted: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct ArtifactHashes {
+    #[serde(rename = "sha1")]
+    pub sha1: Option<String>,
+
+    #[serde(rename = "md5")]
+    pub md5: Option<String>,
+
+    #[serde(rename = "sha256")]
+    pub sha256: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ArtifactLinks {
+    #[serde(rename = "self")]
+    pub self_link: Option<Link>,
+
+    #[serde(rename = "download")]
+    pub download: Option<Link>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Artifact {
+    #[serde(rename = "_links")]
+    pub links: Option<ArtifactLinks>,
+
+    #[serde(rename = "createdAt")]
+    pub created_at: u64,
+
+    #[serde(rename = "createdBy")]
+    pub created_by: String,
+
+    pub id: u64,
+
+    #[serde(rename = "lastModifiedAt")]
+    pub last_modified_at: u64,
+
+    #[serde(rename = "lastModifiedBy")]
+    pub last_modified_by: String,
+
+    #[serde(rename = "providedFilename")]
+    pub provided_filename: String,
+
+    pub size: u64,
+
+    pub hashes: ArtifactHashes,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -289,6 +449,8 @@ pub struct DistributionSet {
     pub created_by: String,
 
     pub deleted: bool,
+    /// Omitted by the server when the set has none
+    #[serde(default)]
     pub description: String,
     pub id: u64,
 
@@ -315,413 +477,1838 @@ pub struct DistributionSet {
     pub version: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct PaginationResponse<T> {
-    pub content: T,
-    pub size: usize,
-    pub total: usize,
+/// Body of a distribution set creation request
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct NewDistributionSet {
+    pub name: String,
+    pub version: String,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+
+    /// Key of the distribution set type
+    #[serde(rename = "type")]
+    pub ds_type: String,
+
+    #[serde(rename = "requiredMigrationStep")]
+    pub required_migration_step: bool,
+
+    /// IDs of the software modules
+    #[serde(serialize_with = "serialize_id_refs")]
+    pub modules: Vec<u64>,
 }
 
-impl HawkbitMgmtClient {
-    pub fn from_config(config: &HawkbitConfig) -> Self {
-        let mut headers = header::HeaderMap::new();
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct MetadataEntry {
+    pub key: String,
+    pub value: String,
+}
 
-        headers.insert(
-            header::ACCEPT,
-            header::HeaderValue::from_static("application/json"),
-        );
-        headers.insert(
-            header::CONTENT_TYPE,
-            header::HeaderValue::from_static("application/json"),
-        );
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct SoftwareModuleMetadata {
+    pub key: String,
+    pub value: String,
 
-        let client = reqwest::Client::builder()
-            .default_headers(headers.clone())
-            .build()
-            .expect("Failed to build HTTP client");
+    /// Whether the entry is delivered to devices via the DDI deployment
+    #[serde(rename = "targetVisible", default)]
+    pub target_visible: bool,
+}
 
-        Self {
-            config: config.clone(),
-            client,
-            default_headers: headers,
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ActionType {
+    Soft,
+    #[default]
+    Forced,
+    /// Soft until `forcetime`, forced afterwards
+    TimeForced,
+    DownloadOnly,
+}
+
+impl std::str::FromStr for ActionType {
+    type Err = HawkbitError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "soft" => Ok(ActionType::Soft),
+            "forced" => Ok(ActionType::Forced),
+            "timeforced" => Ok(ActionType::TimeForced),
+            "downloadonly" => Ok(ActionType::DownloadOnly),
+            _ => Err(HawkbitError::new(format!("Unknown action type {:?}", s))),
         }
     }
+}
 
-    fn build_url(&self, endpoint: &str) -> String {
-        self.config.host.trim_end_matches('/').to_string()
-            + "/rest/v1/"
-            + endpoint.trim_start_matches('/')
-    }
+/// Restricts when the update of an assignment may be installed. `schedule` is
+/// a Quartz cron expression for the window start, `duration` is `HH:mm:ss`
+/// and `timezone` an offset like `+01:00`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct MaintenanceWindow {
+    pub schedule: String,
+    pub duration: String,
+    pub timezone: String,
+}
 
-    pub async fn get<T: DeserializeOwned>(
-        &self,
-        endpoint: &str,
-        query_params: Option<HashMap<String, String>>,
-    ) -> HawkbitResult<T> {
-        let url = self.build_url(endpoint);
-        let mut req = self
-            .client
-            .get(&url)
-            .headers(self.default_headers.clone())
-            .basic_auth(&self.config.username, Some(&self.config.password));
-        if let Some(params) = query_params {
-            for (k, v) in params {
-                req = req.query(&[(k.to_string(), v.to_string())]);
-            }
-        }
-        let res = req.send().await?;
+/// Options of a distribution set assignment.
+#[derive(Debug, Clone, Serialize, Default)]
+pub struct AssignmentRequest {
+    #[serde(rename = "type")]
+    pub action_type: ActionType,
 
-        let status = res.status();
-        if status != StatusCode::OK {
-            let body = res.text().await.unwrap();
-            return Err(HawkbitError::new(format!(
-                "HTTP error {}: {}",
-                status.as_u16(),
-                body
-            )));
-        }
+    /// Point in time (ms since epoch) after which a `timeforced` action is forced
+    #[serde(rename = "forcetime", skip_serializing_if = "Option::is_none")]
+    pub forcetime: Option<i64>,
 
-        Ok(res.json::<T>().await?)
-    }
+    #[serde(rename = "weight", skip_serializing_if = "Option::is_none")]
+    pub weight: Option<u32>,
 
-    pub async fn delete(
-        &self,
-        endpoint: &str,
-        query_params: Option<HashMap<String, String>>,
-    ) -> Result<String, HawkbitError> {
-        let url = self.build_url(endpoint);
-        let mut req = self
-            .client
-            .delete(&url)
-            .headers(self.default_headers.clone())
-            .basic_auth(&self.config.username, Some(&self.config.password));
-        if let Some(params) = query_params {
-            for (k, v) in params {
-                req = req.query(&[(k.to_string(), v.to_string())]);
-            }
-        }
-        let res = req.send().await?;
+    #[serde(
+        rename = "confirmationRequired",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub confirmation_required: Option<bool>,
 
-        let status = res.status();
-        if status == StatusCode::NO_CONTENT {
-            return Ok("No Content".to_string());
-        }
-        if status != StatusCode::OK {
-            let body = res.text().await.unwrap();
-            return Err(HawkbitError::new(format!(
-                "HTTP error {}: {}",
-                status.as_u16(),
-                body
-            )));
+    #[serde(rename = "maintenanceWindow", skip_serializing_if = "Option::is_none")]
+    pub maintenance_window: Option<MaintenanceWindow>,
+}
+
+impl AssignmentRequest {
+    pub fn new(action_type: ActionType) -> Self {
+        Self {
+            action_type,
+            ..Default::default()
         }
+    }
 
-        Ok("OK".to_string())
+    pub fn with_forcetime(mut self, forcetime: i64) -> Self {
+        self.forcetime = Some(forcetime);
+        self
     }
-    pub async fn post<T: Serialize + ?Sized>(
-        &self,
-        endpoint: &str,
-        json_data: &T,
-    ) -> HawkbitResult<Value> {
-        let url = self.build_url(endpoint);
-        let res = self
-            .client
-            .post(&url)
-            .headers(self.default_headers.clone())
-            .basic_auth(&self.config.username, Some(&self.config.password))
-            .json(json_data)
-            .send()
-            .await?;
 
-        let status = res.status();
-        if status != StatusCode::OK && status != StatusCode::CREATED {
-            let body = res.text().await.unwrap();
-            return Err(HawkbitError::new(format!(
-                "HTTP error {}: {}",
-                status.as_u16(),
-                body
-            )));
-        }
+    pub fn with_weight(mut self, weight: u32) -> Self {
+        self.weight = Some(weight);
+        self
+    }
 
-        Ok(res.json().await?)
+    pub fn with_confirmation_required(mut self, required: bool) -> Self {
+        self.confirmation_required = Some(required);
+        self
     }
 
-    pub async fn put<T: Serialize + ?Sized>(
-        &self,
-        endpoint: &str,
-        json_data: &T,
-    ) -> HawkbitResult<Option<Value>> {
-        let url = self.build_url(endpoint);
-        let res = self
-            .client
-            .put(&url)
-            .headers(self.default_headers.clone())
-            .basic_auth(&self.config.username, Some(&self.config.password))
-            .json(json_data)
-            .send()
-            .await?;
+    pub fn with_maintenance_window(mut self, window: MaintenanceWindow) -> Self {
+        self.maintenance_window = Some(window);
+        self
+    }
 
-        let status = res.status();
-        if status != StatusCode::OK && status != StatusCode::NO_CONTENT {
-            let body = res.text().await.unwrap();
-            return Err(HawkbitError::new(format!(
-                "HTTP error {}: {}",
-                status.as_u16(),
-                body
-            )));
+    pub fn validate(&self) -> HawkbitResult<()> {
+        if self.action_type == ActionType::TimeForced && self.forcetime.is_none() {
+            return Err(HawkbitError::new(
+                "A timeforced assignment requires a forcetime",
+            ));
         }
-
-        if status == StatusCode::NO_CONTENT {
-            Ok(None)
-        } else {
-            Ok(Some(res.json().await?))
+        if let Some(weight) = self.weight
+            && weight > 1000
+        {
+            return Err(HawkbitError::new(
+                "Assignment weight must be within 0..=1000",
+            ));
+        }
+        if let Some(window) = &self.maintenance_window
+            && (window.schedule.is_empty()
+                || window.duration.is_empty()
+                || window.timezone.is_empty())
+        {
+            return Err(HawkbitError::new(
+                "A maintenance window needs a schedule, duration and timezone",
+            ));
         }
+        Ok(())
     }
+}
 
-    pub async fn get_targets(&self, filter_query: Option<&str>) -> HawkbitResult<Vec<MgmtTarget>> {
-        let mut offset = 0;
-        let mut total = usize::MAX; // Will be overwritten on first request
-        let mut targets = Vec::new();
-        while offset < total {
-            // Construct query parameters for pagination and filtering
-            let mut query_params = HashMap::new();
-            if let Some(filter) = filter_query {
-                query_params.insert("q".to_string(), filter.to_string());
-            }
-            query_params.insert("offset".to_string(), offset.to_string());
-            query_params.insert("limit".to_string(), 50.to_string());
+#[derive(Serialize)]
+struct AssignmentEntry<'a, I: Serialize> {
+    id: I,
+    #[serde(flatten)]
+    request: &'a AssignmentRequest,
+}
 
-            let new_page = self
-                .get::<PaginationResponse<Vec<MgmtTarget>>>("/targets", Some(query_params))
-                .await?;
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AssignedAction {
+    pub id: u64,
+}
 
-            total = new_page.total;
-            targets.extend(new_page.content);
-            if new_page.size == 0 {
-                break;
-            }
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AssignmentResponse {
+    #[serde(default)]
+    pub assigned: usize,
 
-            offset += new_page.size;
-        }
-        Ok(targets)
-    }
+    #[serde(rename = "alreadyAssigned", default)]
+    pub already_assigned: usize,
 
-    pub async fn get_target(&self, target_id: &str) -> HawkbitResult<MgmtTarget> {
-        let endpoint = &format!("targets/{}", target_id);
-        self.get::<MgmtTarget>(endpoint, None).await
-    }
+    #[serde(rename = "assignedActions", default)]
+    pub assigned_actions: Vec<AssignedAction>,
 
-    pub async fn delete_target(&self, target_id: &str) -> HawkbitResult<String> {
-        let endpoint = &format!("targets/{}", target_id);
-        self.delete(endpoint, None).await
-    }
+    #[serde(default)]
+    pub total: usize,
+}
 
-    pub async fn modify_target(&self, target_id: &str, json_data: Value) -> HawkbitResult<Value> {
-        let endpoint = &format!("targets/{}", target_id);
-        self.put::<Value>(endpoint, &json_data)
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DistributionSetType {
+    #[serde(rename = "_links")]
+    pub links: Option<Value>,
+
+    pub id: u64,
+    pub key: String,
+    pub name: String,
+    pub description: Option<String>,
+    pub colour: Option<String>,
+
+    #[serde(default)]
+    pub deleted: bool,
+}
+
+/// Body of a distribution set type creation request
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct NewDistributionSetType {
+    pub key: String,
+    pub name: String,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub colour: Option<String>,
+
+    /// IDs of the software module types a distribution set must contain
+    #[serde(rename = "mandatorymodules", serialize_with = "serialize_id_refs")]
+    pub mandatory_modules: Vec<u64>,
+
+    /// IDs of the software module types a distribution set may contain
+    #[serde(rename = "optionalmodules", serialize_with = "serialize_id_refs")]
+    pub optional_modules: Vec<u64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SoftwareModuleType {
+    #[serde(rename = "_links")]
+    pub links: Option<Value>,
+
+    pub id: u64,
+    pub key: String,
+    pub name: String,
+    pub description: Option<String>,
+    pub colour: Option<String>,
+
+    /// How many modules of this type a distribution set may contain
+    #[serde(rename = "maxAssignments")]
+    pub max_assignments: Option<u32>,
+
+    #[serde(default)]
+    pub deleted: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct NewSoftwareModuleType {
+    pub key: String,
+    pub name: String,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub colour: Option<String>,
+
+    #[serde(rename = "maxAssignments", skip_serializing_if = "Option::is_none")]
+    pub max_assignments: Option<u32>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TargetType {
+    #[serde(rename = "_links")]
+    pub links: Option<Value>,
+
+    pub id: u64,
+    pub name: String,
+    pub key: Option<String>,
+    pub description: Option<String>,
+    pub colour: Option<String>,
+
+    #[serde(default)]
+    pub deleted: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct NewTargetType {
+    pub name: String,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub colour: Option<String>,
+
+    /// IDs of the distribution set types targets of this type can install
+    #[serde(
+        rename = "compatibledistributionsettypes",
+        serialize_with = "serialize_id_refs"
+    )]
+    pub compatible_distribution_set_types: Vec<u64>,
+}
+
+fn serialize_id_refs<S: serde::Serializer>(ids: &[u64], serializer: S) -> Result<S::Ok, S::Error> {
+    use serde::ser::SerializeSeq;
+    let mut seq = serializer.serialize_seq(Some(ids.len()))?;
+    for id in ids {
+        seq.serialize_element(&json!({ "id": id }))?;
+    }
+    seq.end()
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TargetTag {
+    #[serde(rename = "_links")]
+    pub links: Option<Value>,
+
+    pub id: u64,
+    pub name: String,
+    pub description: Option<String>,
+    pub colour: Option<String>,
+}
+
+/// Stored FIQL query, optionally auto-assigning a distribution set to the
+/// targets it matches
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TargetFilter {
+    #[serde(rename = "_links")]
+    pub links: Option<Value>,
+
+    pub id: u64,
+    pub name: String,
+    pub query: String,
+
+    #[serde(rename = "autoAssignDistributionSet")]
+    pub auto_assign_distribution_set: Option<u64>,
+
+    #[serde(rename = "autoAssignActionType")]
+    pub auto_assign_action_type: Option<ActionType>,
+
+    #[serde(rename = "autoAssignWeight")]
+    pub auto_assign_weight: Option<u32>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Rollout {
+    #[serde(rename = "_links")]
+    pub links: Option<Value>,
+
+    pub id: u64,
+    pub name: String,
+    pub description: Option<String>,
+
+    #[serde(rename = "distributionSetId")]
+    pub distribution_set_id: u64,
+
+    #[serde(rename = "targetFilterQuery")]
+    pub target_filter_query: String,
+
+    pub status: String,
+
+    #[serde(rename = "type")]
+    pub action_type: Option<ActionType>,
+
+    #[serde(rename = "totalGroups")]
+    pub total_groups: Option<u32>,
+
+    pub weight: Option<u32>,
+}
+
+/// Threshold in percent of a rollout group's targets
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct RolloutCondition {
+    pub condition: String,
+    pub expression: String,
+}
+
+impl RolloutCondition {
+    pub fn threshold(percent: u32) -> Self {
+        Self {
+            condition: "THRESHOLD".to_string(),
+            expression: percent.to_string(),
+        }
+    }
+
+    /// The percentage of a `THRESHOLD` condition
+    pub fn percent(&self) -> Option<u32> {
+        if self.condition != "THRESHOLD" {
+            return None;
+        }
+        self.expression.trim().parse().ok()
+    }
+}
+
+/// Deployment group of a rollout with the conditions it was created with
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RolloutGroup {
+    pub id: u64,
+    pub name: String,
+
+    #[serde(rename = "successCondition")]
+    pub success_condition: Option<RolloutCondition>,
+
+    #[serde(rename = "errorCondition")]
+    pub error_condition: Option<RolloutCondition>,
+}
+
+/// Body of a rollout creation request
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct NewRollout {
+    pub name: String,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+
+    #[serde(rename = "distributionSetId")]
+    pub distribution_set_id: u64,
+
+    #[serde(rename = "targetFilterQuery")]
+    pub target_filter_query: String,
+
+    #[serde(rename = "amountGroups")]
+    pub amount_groups: u32,
+
+    #[serde(rename = "type")]
+    pub action_type: ActionType,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub weight: Option<u32>,
+
+    /// Share of finished targets after which the next group starts
+    #[serde(rename = "successCondition", skip_serializing_if = "Option::is_none")]
+    pub success_condition: Option<RolloutCondition>,
+
+    /// Share of failed targets after which the rollout is paused
+    #[serde(rename = "errorCondition", skip_serializing_if = "Option::is_none")]
+    pub error_condition: Option<RolloutCondition>,
+}
+
+/// Tenant configuration value; `global` means the system default applies
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SystemConfigValue {
+    pub value: Value,
+
+    #[serde(default)]
+    pub global: bool,
+}
+
+/// Distribution set tags have the same shape as target tags
+pub type DistributionSetTag = TargetTag;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct NewTag {
+    pub name: String,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub colour: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PaginationResponse<T> {
+    pub content: T,
+    pub size: usize,
+    pub total: usize,
+}
+
+impl HawkbitMgmtClient {
+    pub fn from_config(config: &HawkbitConfig) -> HawkbitResult<Self> {
+        let mut headers = header::HeaderMap::new();
+
+        headers.insert(
+            header::ACCEPT,
+            header::HeaderValue::from_static("application/json"),
+        );
+        headers.insert(
+            header::CONTENT_TYPE,
+            header::HeaderValue::from_static("application/json"),
+        );
+
+        let client = build_client(config.transport(), headers.clone())?;
+        let auth = config.auth().strategy(&client)?;
+
+        Ok(Self {
+            config: config.clone(),
+            client,
+            default_headers: headers,
+            auth,
+            compatibility_check: CompatibilityCheck::default(),
+        })
+    }
+
+    pub fn with_compatibility_check(mut self, check: CompatibilityCheck) -> Self {
+        self.compatibility_check = check;
+        self
+    }
+
+    /// Authenticates and sends a request. A request rejected with 401 is
+    /// retried once if the auth strategy could refresh its credentials.
+    async fn send(&self, request: reqwest::RequestBuilder) -> HawkbitResult<reqwest::Response> {
+        let retry = request.try_clone();
+        let res = self.auth.authenticate(request).await?.send().await?;
+        if res.status() == StatusCode::UNAUTHORIZED
+            && let Some(retry) = retry
+            && self.auth.invalidate().await
+        {
+            return Ok(self.auth.authenticate(retry).await?.send().await?);
+        }
+        Ok(res)
+    }
+
+    fn build_url(&self, endpoint: &str) -> String {
+        self.config.host().trim_end_matches('/').to_string()
+            + "/rest/v1/"
+            + endpoint.trim_start_matches('/')
+    }
+
+    pub async fn get<T: DeserializeOwned>(
+        &self,
+        endpoint: &str,
+        query_params: Option<HashMap<String, String>>,
+    ) -> HawkbitResult<T> {
+        let url = self.build_url(endpoint);
+        let mut req = self.client.get(&url).headers(self.default_headers.clone());
+        if let Some(params) = query_params {
+            for (k, v) in params {
+                req = req.query(&[(k.to_string(), v.to_string())]);
+            }
+        }
+        let res = self.send(req).await?;
+
+        let status = res.status();
+        if status != StatusCode::OK {
+            let body = res.text().await.unwrap();
+            return Err(HawkbitError::http(status, body));
+        }
+
+        Ok(res.json::<T>().await?)
+    }
+
+    /// Like `get`, but maps `204 No Content` to `None`.
+    pub async fn get_optional<T: DeserializeOwned>(
+        &self,
+        endpoint: &str,
+    ) -> HawkbitResult<Option<T>> {
+        let url = self.build_url(endpoint);
+        let res = self
+            .send(self.client.get(&url).headers(self.default_headers.clone()))
+            .await?;
+
+        let status = res.status();
+        if status == StatusCode::NO_CONTENT {
+            return Ok(None);
+        }
+        if status != StatusCode::OK {
+            let body = res.text().await.unwrap();
+            return Err(HawkbitError::http(status, body));
+        }
+
+        Ok(Some(res.json::<T>().await?))
+    }
+
+    pub async fn delete(
+        &self,
+        endpoint: &str,
+        query_params: Option<HashMap<String, String>>,
+    ) -> Result<String, HawkbitError> {
+        let url = self.build_url(endpoint);
+        let mut req = self
+            .client
+            .delete(&url)
+            .headers(self.default_headers.clone());
+        if let Some(params) = query_params {
+            for (k, v) in params {
+                req = req.query(&[(k.to_string(), v.to_string())]);
+            }
+        }
+        let res = self.send(req).await?;
+
+        let status = res.status();
+        if status == StatusCode::NO_CONTENT {
+            return Ok("No Content".to_string());
+        }
+        if status != StatusCode::OK {
+            let body = res.text().await.unwrap();
+            return Err(HawkbitError::http(status, body));
+        }
+
+        Ok("OK".to_string())
+    }
+    pub async fn post<T: Serialize + ?Sized>(
+        &self,
+        endpoint: &str,
+        json_data: &T,
+    ) -> HawkbitResult<Value> {
+        let url = self.build_url(endpoint);
+        let res = self
+            .send(
+                self.client
+                    .post(&url)
+                    .headers(self.default_headers.clone())
+                    .json(json_data),
+            )
+            .await?;
+
+        let status = res.status();
+        if status != StatusCode::OK
+            && status != StatusCode::CREATED
+            && status != StatusCode::NO_CONTENT
+        {
+            let body = res.text().await.unwrap();
+            return Err(HawkbitError::http(status, body));
+        }
+
+        // Some endpoints (e.g. assignments) answer without a body
+        let body = res.text().await?;
+        if body.trim().is_empty() {
+            return Ok(Value::Null);
+        }
+        Ok(serde_json::from_str(&body)?)
+    }
+
+    pub async fn put<T: Serialize + ?Sized>(
+        &self,
+        endpoint: &str,
+        json_data: &T,
+    ) -> HawkbitResult<Option<Value>> {
+        let url = self.build_url(endpoint);
+        let res = self
+            .send(
+                self.client
+                    .put(&url)
+                    .headers(self.default_headers.clone())
+                    .json(json_data),
+            )
+            .await?;
+
+        let status = res.status();
+        if status != StatusCode::OK && status != StatusCode::NO_CONTENT {
+            let body = res.text().await.unwrap();
+            return Err(HawkbitError::http(status, body));
+        }
+
+        if status == StatusCode::NO_CONTENT {
+            Ok(None)
+        } else {
+            Ok(Some(res.json().await?))
+        }
+    }
+
+    pub async fn get_targets(&self, filter_query: Option<&str>) -> HawkbitResult<Vec<MgmtTarget>> {
+        self.get_paged("targets", filter_query).await
+    }
+
+    pub async fn get_target(&self, target_id: &str) -> HawkbitResult<MgmtTarget> {
+        let endpoint = &format!("targets/{}", encode_segment(target_id));
+        self.get::<MgmtTarget>(endpoint, None).await
+    }
+
+    pub async fn create_targets(&self, targets: &[NewTarget]) -> HawkbitResult<Vec<MgmtTarget>> {
+        let created = self.post("targets", targets).await?;
+        Ok(serde_json::from_value(created)?)
+    }
+
+    pub async fn get_assigned_distribution_set(
+        &self,
+        target_id: &str,
+    ) -> HawkbitResult<Option<DistributionSet>> {
+        let endpoint = &format!("targets/{}/assignedDS", encode_segment(target_id));
+        self.get_optional(endpoint).await
+    }
+
+    pub async fn get_installed_distribution_set(
+        &self,
+        target_id: &str,
+    ) -> HawkbitResult<Option<DistributionSet>> {
+        let endpoint = &format!("targets/{}/installedDS", encode_segment(target_id));
+        self.get_optional(endpoint).await
+    }
+
+    pub async fn delete_target(&self, target_id: &str) -> HawkbitResult<String> {
+        let endpoint = &format!("targets/{}", encode_segment(target_id));
+        self.delete(endpoint, None).await
+    }
+
+    pub async fn modify_target(&self, target_id: &str, json_data: Value) -> HawkbitResult<Value> {
+        let endpoint = &format!("targets/{}", encode_segment(target_id));
+        self.put::<Value>(endpoint, &json_data)
             .await?
             .ok_or_else(|| HawkbitError::new("No response from modify_target"))
     }
-    pub async fn target_request_attributes(&self, target_id: &str) -> HawkbitResult<Value> {
-        let data =
-            json!({ "name": target_id, "controllerId": target_id, "requestAttributes": true });
-        self.modify_target(target_id, data).await
+    pub async fn target_request_attributes(&self, target_id: &str) -> HawkbitResult<Value> {
+        let data =
+            json!({ "name": target_id, "controllerId": target_id, "requestAttributes": true });
+        self.modify_target(target_id, data).await
+    }
+
+    pub async fn get_target_actions(
+        &self,
+        target_id: &str,
+        limit: Option<usize>,
+        filter_query: Option<&str>,
+    ) -> HawkbitResult<Vec<Action>> {
+        let mut query_params = HashMap::new();
+
+        query_params.insert("sort".to_string(), "id:DESC".to_string());
+        query_params.insert("limit".to_string(), limit.unwrap_or(10).to_string());
+        if let Some(filter_query) = filter_query {
+            query_params.insert("q".to_string(), filter_query.to_string());
+        }
+        let endpoint = &format!("targets/{}/actions", encode_segment(target_id));
+
+        let resp = self
+            .get::<PaginationResponse<Vec<Action>>>(endpoint, Some(query_params))
+            .await?;
+        Ok(resp.content)
+    }
+
+    pub async fn get_target_attributes(
+        &self,
+        target_id: &str,
+        filter_query: Option<&str>,
+    ) -> HawkbitResult<HashMap<String, String>> {
+        let endpoint = &format!("targets/{}/attributes", encode_segment(target_id));
+
+        // Construct query parameters for pagination and filtering
+        let mut query_params = HashMap::new();
+        if let Some(filter) = filter_query {
+            query_params.insert("q".to_string(), filter.to_string());
+        }
+
+        let response = self.get::<Value>(endpoint, Some(query_params)).await?;
+
+        let mut attributes = HashMap::new();
+        if let Some(map) = response.as_object() {
+            for (key, value) in map {
+                attributes.insert(
+                    key.to_string(),
+                    value.as_str().unwrap_or_default().to_string(),
+                );
+            }
+        }
+
+        Ok(attributes)
+    }
+
+    pub async fn get_action_detail(
+        &self,
+        target_id: &str,
+        action_id: &i64,
+    ) -> HawkbitResult<ActionDetail> {
+        let endpoint = &format!(
+            "/targets/{}/actions/{}",
+            encode_segment(target_id),
+            action_id
+        );
+
+        self.get::<ActionDetail>(endpoint, None).await
+    }
+
+    pub async fn cancel_action(
+        &self,
+        target_id: &str,
+        action_id: &i64,
+        force: bool,
+    ) -> HawkbitResult<String> {
+        let endpoint = &format!(
+            "/targets/{}/actions/{}",
+            encode_segment(target_id),
+            action_id
+        );
+        let mut query_params: HashMap<String, String> = HashMap::new();
+        query_params.insert("force".to_string(), force.to_string());
+        self.delete(endpoint, Some(query_params)).await
+    }
+
+    pub async fn get_action_status(
+        &self,
+        target_id: &str,
+        action_id: &i64,
+    ) -> HawkbitResult<Vec<ActionStatusEvent>> {
+        let endpoint = &format!(
+            "targets/{}/actions/{}/status?sort=id:DESC&representation=full",
+            encode_segment(target_id),
+            action_id
+        );
+        self.get_paged(endpoint, None).await
+    }
+
+    /// Most recent status event of an action, without paging through the history.
+    pub async fn get_latest_action_status(
+        &self,
+        target_id: &str,
+        action_id: &i64,
+    ) -> HawkbitResult<Option<ActionStatusEvent>> {
+        let endpoint = &format!(
+            "/targets/{}/actions/{}/status",
+            encode_segment(target_id),
+            action_id
+        );
+        let mut query_params = HashMap::new();
+        query_params.insert("sort".to_string(), "reportedAt:DESC".to_string());
+        query_params.insert("limit".to_string(), 1.to_string());
+
+        let page = self
+            .get::<PaginationResponse<Vec<ActionStatusEvent>>>(endpoint, Some(query_params))
+            .await?;
+        Ok(page.content.into_iter().next())
+    }
+
+    pub async fn assign_distribution(
+        &self,
+        target_id: &str,
+        distribution_id: &u64,
+        request: &AssignmentRequest,
+    ) -> HawkbitResult<AssignmentResponse> {
+        request.validate()?;
+        self.preflight_compatibility(&[target_id], *distribution_id)
+            .await?;
+
+        let endpoint = format!("targets/{}/assignedDS", encode_segment(target_id));
+        let data = vec![AssignmentEntry {
+            id: *distribution_id,
+            request,
+        }];
+        let response = self.post(&endpoint, &data).await?;
+        Ok(serde_json::from_value(response)?)
+    }
+
+    pub async fn get_distribution_sets(
+        &self,
+        filter_query: Option<&str>,
+    ) -> HawkbitResult<Vec<DistributionSet>> {
+        self.get_paged("distributionsets?sort=createdAt:DESC", filter_query)
+            .await
+    }
+
+    pub async fn get_distributionset(&self, distribution_id: &str) -> HawkbitResult<Value> {
+        let mut query_params = HashMap::new();
+        query_params.insert("sort".to_string(), "id:DESC".to_string());
+        let endpoint = &format!("/distributionsets/{}", encode_segment(distribution_id));
+
+        self.get::<Value>(endpoint, Some(query_params)).await
+    }
+
+    pub async fn get_latest_distribution(&self) -> HawkbitResult<Value> {
+        let v: Value = self
+            .get("distributionsets?sort=createdAt:DESC&limit=1", None)
+            .await?;
+        if let Some(arr) = v.get("content").and_then(|c| c.as_array())
+            && let Some(first) = arr.first()
+        {
+            return Ok(first.clone());
+        }
+        Err(HawkbitError::new("No available distributions found"))
+    }
+
+    pub async fn update_target(
+        &self,
+        target_id: &str,
+        target_name: &str,
+        controller_id: &str,
+        update: HashMap<String, String>,
+    ) -> HawkbitResult<Option<Value>> {
+        let mut body = update.clone();
+        body.insert("name".to_string(), target_name.to_string());
+        body.insert("controllerId".to_string(), controller_id.to_string());
+        self.put(
+            &format!("targets/{}", encode_segment(target_id)),
+            &json!(body),
+        )
+        .await
+    }
+
+    pub async fn request_attributes(
+        &self,
+        target_id: &str,
+        target_name: &str,
+        controller_id: &str,
+    ) -> HawkbitResult<Option<Value>> {
+        let mut update = HashMap::new();
+        update.insert("requestAttributes".to_string(), "true".to_string());
+        self.update_target(target_id, target_name, controller_id, update)
+            .await
+    }
+
+    pub async fn get_distribution_set(
+        &self,
+        distribution_id: u64,
+    ) -> HawkbitResult<DistributionSet> {
+        let endpoint = &format!("distributionsets/{}", distribution_id);
+        self.get::<DistributionSet>(endpoint, None).await
+    }
+
+    pub async fn get_software_module(&self, module_id: u64) -> HawkbitResult<SoftwareModule> {
+        let endpoint = &format!("softwaremodules/{}", module_id);
+        self.get::<SoftwareModule>(endpoint, None).await
+    }
+
+    pub async fn get_artifacts(&self, module_id: u64) -> HawkbitResult<Vec<Artifact>> {
+        let endpoint = &format!("softwaremodules/{}/artifacts", module_id);
+        let mut query_params = HashMap::new();
+        query_params.insert("representation".to_string(), "full".to_string());
+        self.get::<Vec<Artifact>>(endpoint, Some(query_params))
+            .await
+    }
+
+    pub async fn get_software_modules(
+        &self,
+        filter_query: Option<&str>,
+    ) -> HawkbitResult<Vec<SoftwareModule>> {
+        self.get_paged("softwaremodules", filter_query).await
+    }
+
+    pub async fn create_software_modules(
+        &self,
+        modules: &[NewSoftwareModule],
+    ) -> HawkbitResult<Vec<SoftwareModule>> {
+        let created = self.post("softwaremodules", modules).await?;
+        Ok(serde_json::from_value(created)?)
+    }
+
+    pub async fn update_software_module(
+        &self,
+        module_id: u64,
+        description: Option<&str>,
+        vendor: Option<&str>,
+    ) -> HawkbitResult<Option<Value>> {
+        let endpoint = &format!("softwaremodules/{}", module_id);
+        let data = json!({ "description": description, "vendor": vendor });
+        self.put(endpoint, &data).await
+    }
+
+    /// Uploads `file` as an artifact of a software module. The server rejects
+    /// the upload if the content does not match the given hashes.
+    pub async fn upload_artifact(
+        &self,
+        module_id: u64,
+        file: &Path,
+        filename: &str,
+        hashes: &ArtifactHashes,
+    ) -> HawkbitResult<Artifact> {
+        let url = self.build_url(&format!("softwaremodules/{}/artifacts", module_id));
+        let content = tokio::fs::File::open(file).await?;
+        let length = content.metadata().await?.len();
+        let part = reqwest::multipart::Part::stream_with_length(content, length)
+            .file_name(filename.to_string());
+
+        let mut form = reqwest::multipart::Form::new()
+            .part("file", part)
+            .text("filename", filename.to_string());
+        for (field, hash) in [
+            ("sha1sum", &hashes.sha1),
+            ("md5sum", &hashes.md5),
+            ("sha256sum", &hashes.sha256),
+        ] {
+            if let Some(hash) = hash {
+                form = form.text(field, hash.clone());
+            }
+        }
+
+        // The multipart body sets its own content type instead of JSON
+        let mut headers = self.default_headers.clone();
+        headers.remove(header::CONTENT_TYPE);
+        let res = self
+            .send(self.client.post(&url).headers(headers).multipart(form))
+            .await?;
+
+        let status = res.status();
+        if status != StatusCode::CREATED && status != StatusCode::OK {
+            let body = res.text().await.unwrap_or_default();
+            return Err(HawkbitError::http(status, body));
+        }
+        Ok(res.json::<Artifact>().await?)
+    }
+
+    /// Streams an artifact to `dest` and verifies its hashes. An interrupted
+    /// download is resumed from `<dest>.part`. Returns the size of the file.
+    pub async fn download_artifact(
+        &self,
+        module_id: u64,
+        artifact: &Artifact,
+        dest: &Path,
+    ) -> HawkbitResult<u64> {
+        let url = self.build_url(&format!(
+            "softwaremodules/{}/artifacts/{}/download",
+            module_id, artifact.id
+        ));
+        let identity = match &artifact.hashes.sha256 {
+            Some(sha256) => sha256.to_lowercase(),
+            None => format!("{}:{}", artifact.id, artifact.size),
+        };
+        artifacts::download_resumable(
+            self.client.get(&url).headers(self.default_headers.clone()),
+            async |request| self.send(request).await,
+            dest,
+            &identity,
+            artifact.size,
+            &artifact.hashes,
+        )
+        .await
+    }
+
+    /// Fetches every page of a paginated collection endpoint.
+    async fn get_paged<T: DeserializeOwned>(
+        &self,
+        endpoint: &str,
+        filter_query: Option<&str>,
+    ) -> HawkbitResult<Vec<T>> {
+        let mut offset = 0;
+        let mut total = usize::MAX; // Will be overwritten on first request
+        let mut items = Vec::new();
+        while offset < total {
+            let mut query_params = HashMap::new();
+            if let Some(filter) = filter_query {
+                query_params.insert("q".to_string(), filter.to_string());
+            }
+            query_params.insert("offset".to_string(), offset.to_string());
+            query_params.insert("limit".to_string(), self.config.page_size().to_string());
+
+            let new_page = self
+                .get::<PaginationResponse<Vec<T>>>(endpoint, Some(query_params))
+                .await?;
+
+            total = new_page.total;
+            items.extend(new_page.content);
+            if new_page.size == 0 {
+                break;
+            }
+
+            offset += new_page.size;
+        }
+        Ok(items)
+    }
+
+    pub async fn get_target_metadata(
+        &self,
+        target_id: &str,
+        filter_query: Option<&str>,
+    ) -> HawkbitResult<Vec<MetadataEntry>> {
+        let endpoint = &format!("targets/{}/metadata", encode_segment(target_id));
+        self.get_paged(endpoint, filter_query).await
+    }
+
+    pub async fn get_target_metadata_value(
+        &self,
+        target_id: &str,
+        key: &str,
+    ) -> HawkbitResult<MetadataEntry> {
+        let endpoint = &format!(
+            "targets/{}/metadata/{}",
+            encode_segment(target_id),
+            encode_segment(key)
+        );
+        self.get::<MetadataEntry>(endpoint, None).await
+    }
+
+    pub async fn create_target_metadata(
+        &self,
+        target_id: &str,
+        entries: &[MetadataEntry],
+    ) -> HawkbitResult<Value> {
+        let endpoint = &format!("targets/{}/metadata", encode_segment(target_id));
+        self.post(endpoint, entries).await
+    }
+
+    pub async fn update_target_metadata(
+        &self,
+        target_id: &str,
+        key: &str,
+        value: &str,
+    ) -> HawkbitResult<Option<Value>> {
+        let endpoint = &format!(
+            "targets/{}/metadata/{}",
+            encode_segment(target_id),
+            encode_segment(key)
+        );
+        self.put(endpoint, &json!({ "value": value })).await
+    }
+
+    pub async fn delete_target_metadata(
+        &self,
+        target_id: &str,
+        key: &str,
+    ) -> HawkbitResult<String> {
+        let endpoint = &format!(
+            "targets/{}/metadata/{}",
+            encode_segment(target_id),
+            encode_segment(key)
+        );
+        self.delete(endpoint, None).await
+    }
+
+    pub async fn get_distribution_set_metadata(
+        &self,
+        distribution_id: u64,
+        filter_query: Option<&str>,
+    ) -> HawkbitResult<Vec<MetadataEntry>> {
+        let endpoint = &format!("distributionsets/{}/metadata", distribution_id);
+        self.get_paged(endpoint, filter_query).await
+    }
+
+    pub async fn get_distribution_set_metadata_value(
+        &self,
+        distribution_id: u64,
+        key: &str,
+    ) -> HawkbitResult<MetadataEntry> {
+        let endpoint = &format!(
+            "distributionsets/{}/metadata/{}",
+            encode_segment(&distribution_id.to_string()),
+            encode_segment(key)
+        );
+        self.get::<MetadataEntry>(endpoint, None).await
+    }
+
+    pub async fn create_distribution_set_metadata(
+        &self,
+        distribution_id: u64,
+        entries: &[MetadataEntry],
+    ) -> HawkbitResult<Value> {
+        let endpoint = &format!("distributionsets/{}/metadata", distribution_id);
+        self.post(endpoint, entries).await
+    }
+
+    pub async fn update_distribution_set_metadata(
+        &self,
+        distribution_id: u64,
+        key: &str,
+        value: &str,
+    ) -> HawkbitResult<Option<Value>> {
+        let endpoint = &format!(
+            "distributionsets/{}/metadata/{}",
+            encode_segment(&distribution_id.to_string()),
+            encode_segment(key)
+        );
+        self.put(endpoint, &json!({ "value": value })).await
+    }
+
+    pub async fn delete_distribution_set_metadata(
+        &self,
+        distribution_id: u64,
+        key: &str,
+    ) -> HawkbitResult<String> {
+        let endpoint = &format!(
+            "distributionsets/{}/metadata/{}",
+            encode_segment(&distribution_id.to_string()),
+            encode_segment(key)
+        );
+        self.delete(endpoint, None).await
+    }
+
+    pub async fn get_software_module_metadata(
+        &self,
+        module_id: u64,
+        filter_query: Option<&str>,
+    ) -> HawkbitResult<Vec<SoftwareModuleMetadata>> {
+        let endpoint = &format!("softwaremodules/{}/metadata", module_id);
+        self.get_paged(endpoint, filter_query).await
+    }
+
+    pub async fn get_software_module_metadata_value(
+        &self,
+        module_id: u64,
+        key: &str,
+    ) -> HawkbitResult<SoftwareModuleMetadata> {
+        let endpoint = &format!(
+            "softwaremodules/{}/metadata/{}",
+            encode_segment(&module_id.to_string()),
+            encode_segment(key)
+        );
+        self.get::<SoftwareModuleMetadata>(endpoint, None).await
+    }
+
+    pub async fn create_software_module_metadata(
+        &self,
+        module_id: u64,
+        entries: &[SoftwareModuleMetadata],
+    ) -> HawkbitResult<Value> {
+        let endpoint = &format!("softwaremodules/{}/metadata", module_id);
+        self.post(endpoint, entries).await
+    }
+
+    pub async fn update_software_module_metadata(
+        &self,
+        module_id: u64,
+        key: &str,
+        value: &str,
+        target_visible: bool,
+    ) -> HawkbitResult<Option<Value>> {
+        let endpoint = &format!(
+            "softwaremodules/{}/metadata/{}",
+            encode_segment(&module_id.to_string()),
+            encode_segment(key)
+        );
+        let data = json!({ "value": value, "targetVisible": target_visible });
+        self.put(endpoint, &data).await
+    }
+
+    pub async fn delete_software_module_metadata(
+        &self,
+        module_id: u64,
+        key: &str,
+    ) -> HawkbitResult<String> {
+        let endpoint = &format!(
+            "softwaremodules/{}/metadata/{}",
+            encode_segment(&module_id.to_string()),
+            encode_segment(key)
+        );
+        self.delete(endpoint, None).await
+    }
+
+    pub async fn get_distribution_set_types(
+        &self,
+        filter_query: Option<&str>,
+    ) -> HawkbitResult<Vec<DistributionSetType>> {
+        self.get_paged("distributionsettypes", filter_query).await
+    }
+
+    pub async fn create_distribution_set_types(
+        &self,
+        types: &[NewDistributionSetType],
+    ) -> HawkbitResult<Vec<DistributionSetType>> {
+        let created = self.post("distributionsettypes", types).await?;
+        Ok(serde_json::from_value(created)?)
+    }
+
+    pub async fn update_distribution_set_type(
+        &self,
+        ds_type_id: u64,
+        description: Option<&str>,
+        colour: Option<&str>,
+    ) -> HawkbitResult<Option<Value>> {
+        let endpoint = &format!("distributionsettypes/{}", ds_type_id);
+        let data = json!({ "description": description, "colour": colour });
+        self.put(endpoint, &data).await
+    }
+
+    pub async fn get_mandatory_module_types(
+        &self,
+        ds_type_id: u64,
+    ) -> HawkbitResult<Vec<SoftwareModuleType>> {
+        let endpoint = &format!("distributionsettypes/{}/mandatorymoduletypes", ds_type_id);
+        self.get::<Vec<SoftwareModuleType>>(endpoint, None).await
+    }
+
+    pub async fn get_optional_module_types(
+        &self,
+        ds_type_id: u64,
+    ) -> HawkbitResult<Vec<SoftwareModuleType>> {
+        let endpoint = &format!("distributionsettypes/{}/optionalmoduletypes", ds_type_id);
+        self.get::<Vec<SoftwareModuleType>>(endpoint, None).await
     }
 
-    pub async fn get_target_actions(
+    pub async fn get_software_module_types(
         &self,
-        target_id: &str,
-        limit: Option<usize>,
         filter_query: Option<&str>,
-    ) -> HawkbitResult<Vec<Action>> {
-        let mut query_params = HashMap::new();
+    ) -> HawkbitResult<Vec<SoftwareModuleType>> {
+        self.get_paged("softwaremoduletypes", filter_query).await
+    }
 
-        query_params.insert("sort".to_string(), "id:DESC".to_string());
-        query_params.insert("limit".to_string(), limit.unwrap_or(10).to_string());
-        if let Some(filter_query) = filter_query {
-            query_params.insert("q".to_string(), filter_query.to_string());
-        }
-        let endpoint = &format!("targets/{}/actions", target_id);
+    pub async fn create_software_module_types(
+        &self,
+        types: &[NewSoftwareModuleType],
+    ) -> HawkbitResult<Vec<SoftwareModuleType>> {
+        let created = self.post("softwaremoduletypes", types).await?;
+        Ok(serde_json::from_value(created)?)
+    }
 
-        let resp = self
-            .get::<PaginationResponse<Vec<Action>>>(endpoint, Some(query_params))
-            .await?;
-        Ok(resp.content)
+    pub async fn update_software_module_type(
+        &self,
+        module_type_id: u64,
+        description: Option<&str>,
+        colour: Option<&str>,
+    ) -> HawkbitResult<Option<Value>> {
+        let endpoint = &format!("softwaremoduletypes/{}", module_type_id);
+        let data = json!({ "description": description, "colour": colour });
+        self.put(endpoint, &data).await
     }
 
-    pub async fn get_target_attributes(
+    pub async fn update_distribution_set(
+        &self,
+        distribution_id: u64,
+        description: Option<&str>,
+        required_migration_step: bool,
+    ) -> HawkbitResult<Option<Value>> {
+        let endpoint = &format!("distributionsets/{}", distribution_id);
+        let data = json!({
+            "description": description,
+            "requiredMigrationStep": required_migration_step,
+        });
+        self.put(endpoint, &data).await
+    }
+
+    pub async fn get_target_filters(
         &self,
-        target_id: &str,
         filter_query: Option<&str>,
-    ) -> HawkbitResult<HashMap<String, String>> {
-        let endpoint = &format!("targets/{}/attributes", target_id);
+    ) -> HawkbitResult<Vec<TargetFilter>> {
+        self.get_paged("targetfilters", filter_query).await
+    }
 
-        // Construct query parameters for pagination and filtering
-        let mut query_params = HashMap::new();
-        if let Some(filter) = filter_query {
-            query_params.insert("q".to_string(), filter.to_string());
+    pub async fn create_target_filter(
+        &self,
+        name: &str,
+        query: &str,
+    ) -> HawkbitResult<TargetFilter> {
+        let created = self
+            .post("targetfilters", &json!({ "name": name, "query": query }))
+            .await?;
+        Ok(serde_json::from_value(created)?)
+    }
+
+    pub async fn update_target_filter(
+        &self,
+        filter_id: u64,
+        name: &str,
+        query: &str,
+    ) -> HawkbitResult<Option<Value>> {
+        let endpoint = &format!("targetfilters/{}", filter_id);
+        self.put(endpoint, &json!({ "name": name, "query": query }))
+            .await
+    }
+
+    /// Makes the filter assign the distribution set to every matching target.
+    pub async fn set_auto_assign_distribution_set(
+        &self,
+        filter_id: u64,
+        distribution_id: u64,
+        action_type: ActionType,
+        weight: Option<u32>,
+    ) -> HawkbitResult<Value> {
+        let endpoint = &format!("targetfilters/{}/autoAssignDS", filter_id);
+        let mut data = json!({ "id": distribution_id, "type": action_type });
+        if let Some(weight) = weight {
+            data["weight"] = json!(weight);
         }
+        self.post(endpoint, &data).await
+    }
 
-        let response = self.get::<Value>(endpoint, Some(query_params)).await?;
+    pub async fn remove_auto_assign_distribution_set(
+        &self,
+        filter_id: u64,
+    ) -> HawkbitResult<String> {
+        let endpoint = &format!("targetfilters/{}/autoAssignDS", filter_id);
+        self.delete(endpoint, None).await
+    }
 
-        let mut attributes = HashMap::new();
-        if let Some(map) = response.as_object() {
-            for (key, value) in map {
-                attributes.insert(
-                    key.to_string(),
-                    value.as_str().unwrap_or_default().to_string(),
-                );
-            }
+    pub async fn get_rollouts(&self, filter_query: Option<&str>) -> HawkbitResult<Vec<Rollout>> {
+        self.get_paged("rollouts?representation=full", filter_query)
+            .await
+    }
+
+    pub async fn get_rollout_groups(&self, rollout_id: u64) -> HawkbitResult<Vec<RolloutGroup>> {
+        let endpoint = &format!("rollouts/{}/deploygroups?representation=full", rollout_id);
+        self.get_paged(endpoint, None).await
+    }
+
+    /// Creates a rollout in the `creating`/`ready` state; it is not started.
+    pub async fn create_rollout(&self, rollout: &NewRollout) -> HawkbitResult<Rollout> {
+        let created = self.post("rollouts", rollout).await?;
+        Ok(serde_json::from_value(created)?)
+    }
+
+    pub async fn get_system_configs(&self) -> HawkbitResult<HashMap<String, SystemConfigValue>> {
+        self.get("system/configs", None).await
+    }
+
+    pub async fn set_system_config(
+        &self,
+        key: &str,
+        value: &Value,
+    ) -> HawkbitResult<Option<Value>> {
+        let endpoint = &format!("system/configs/{}", encode_segment(key));
+        self.put(endpoint, &json!({ "value": value })).await
+    }
+
+    pub async fn create_distribution_sets(
+        &self,
+        sets: &[NewDistributionSet],
+    ) -> HawkbitResult<Vec<DistributionSet>> {
+        let created = self.post("distributionsets", sets).await?;
+        Ok(serde_json::from_value(created)?)
+    }
+
+    pub async fn get_target_types(
+        &self,
+        filter_query: Option<&str>,
+    ) -> HawkbitResult<Vec<TargetType>> {
+        self.get_paged("targettypes", filter_query).await
+    }
+
+    pub async fn get_target_type(&self, target_type_id: u64) -> HawkbitResult<TargetType> {
+        let endpoint = &format!("targettypes/{}", target_type_id);
+        self.get::<TargetType>(endpoint, None).await
+    }
+
+    pub async fn create_target_types(
+        &self,
+        target_types: &[NewTargetType],
+    ) -> HawkbitResult<Vec<TargetType>> {
+        let created = self.post("targettypes", target_types).await?;
+        Ok(serde_json::from_value(created)?)
+    }
+
+    pub async fn update_target_type(
+        &self,
+        target_type_id: u64,
+        name: Option<&str>,
+        description: Option<&str>,
+        colour: Option<&str>,
+    ) -> HawkbitResult<Option<Value>> {
+        let endpoint = &format!("targettypes/{}", target_type_id);
+        let mut data = serde_json::Map::new();
+        if let Some(name) = name {
+            data.insert("name".to_string(), json!(name));
+        }
+        if let Some(description) = description {
+            data.insert("description".to_string(), json!(description));
+        }
+        if let Some(colour) = colour {
+            data.insert("colour".to_string(), json!(colour));
         }
+        self.put(endpoint, &Value::Object(data)).await
+    }
 
-        Ok(attributes)
+    pub async fn delete_target_type(&self, target_type_id: u64) -> HawkbitResult<String> {
+        let endpoint = &format!("targettypes/{}", target_type_id);
+        self.delete(endpoint, None).await
     }
 
-    pub async fn get_action_detail(
+    pub async fn get_compatible_distribution_set_types(
         &self,
-        target_id: &String,
-        action_id: &i64,
-    ) -> HawkbitResult<ActionDetail> {
-        let endpoint = &format!("/targets/{}/actions/{}", target_id, action_id.to_string());
+        target_type_id: u64,
+    ) -> HawkbitResult<Vec<DistributionSetType>> {
+        let endpoint = &format!(
+            "targettypes/{}/compatibledistributionsettypes",
+            target_type_id
+        );
+        self.get::<Vec<DistributionSetType>>(endpoint, None).await
+    }
 
-        self.get::<ActionDetail>(&endpoint, None).await
+    pub async fn add_compatible_distribution_set_types(
+        &self,
+        target_type_id: u64,
+        distribution_set_type_ids: &[u64],
+    ) -> HawkbitResult<()> {
+        let endpoint = &format!(
+            "targettypes/{}/compatibledistributionsettypes",
+            target_type_id
+        );
+        let data: Vec<Value> = distribution_set_type_ids
+            .iter()
+            .map(|id| json!({ "id": id }))
+            .collect();
+        self.post(endpoint, &data).await?;
+        Ok(())
     }
 
-    pub async fn cancel_action(
+    pub async fn remove_compatible_distribution_set_type(
         &self,
-        target_id: &String,
-        action_id: &i64,
-        force: bool,
+        target_type_id: u64,
+        distribution_set_type_id: u64,
     ) -> HawkbitResult<String> {
-        let endpoint = &format!("/targets/{}/actions/{}", target_id, action_id.to_string());
-        let mut query_params: HashMap<String, String> = HashMap::new();
-        query_params.insert("force".to_string(), force.to_string());
-        self.delete(&endpoint, Some(query_params)).await
+        let endpoint = &format!(
+            "targettypes/{}/compatibledistributionsettypes/{}",
+            target_type_id, distribution_set_type_id
+        );
+        self.delete(endpoint, None).await
     }
 
-    pub async fn get_action_status(
+    /// Lists actions across all targets, e.g. `detailStatus==wait_for_confirmation`.
+    pub async fn get_actions(&self, filter_query: Option<&str>) -> HawkbitResult<Vec<Action>> {
+        self.get_paged("actions?representation=full", filter_query)
+            .await
+    }
+
+    /// Actions of a target that wait for the device or an operator to confirm them.
+    pub async fn get_actions_waiting_for_confirmation(
+        &self,
+        target_id: &str,
+    ) -> HawkbitResult<Vec<Action>> {
+        let actions = self
+            .get_target_actions(target_id, Some(50), Some("status==\"pending\""))
+            .await?;
+        Ok(actions
+            .into_iter()
+            .filter(|action| action.detail_status == "wait_for_confirmation")
+            .collect())
+    }
+
+    /// Confirms or denies an action on behalf of the target.
+    pub async fn confirm_action(
         &self,
-        target_id: &String,
+        target_id: &str,
         action_id: &i64,
-    ) -> HawkbitResult<Vec<ActionStatusEvent>> {
+        confirmed: bool,
+        code: Option<i32>,
+        details: &[String],
+    ) -> HawkbitResult<Option<Value>> {
         let endpoint = &format!(
-            "/targets/{}/actions/{}/status",
-            target_id,
-            action_id.to_string()
+            "targets/{}/actions/{}/confirmation",
+            encode_segment(target_id),
+            action_id
         );
+        let mut data = json!({
+            "confirmation": if confirmed { "confirmed" } else { "denied" },
+            "details": details,
+        });
+        if let Some(code) = code {
+            data["code"] = json!(code);
+        }
+        self.put(endpoint, &data).await
+    }
 
-        let mut offset = 0;
-        let mut total = usize::MAX; // Will be overwritten on first request
-        let mut status = Vec::new();
-        while offset < total {
-            let mut query_params = HashMap::new();
-            query_params.insert("sort".to_string(), "id:DESC".to_string());
-            query_params.insert("representation".to_string(), "full".to_string());
+    pub async fn get_auto_confirm(&self, target_id: &str) -> HawkbitResult<AutoConfirmState> {
+        let endpoint = &format!("targets/{}/autoConfirm", encode_segment(target_id));
+        self.get::<AutoConfirmState>(endpoint, None).await
+    }
 
-            query_params.insert("offset".to_string(), offset.to_string());
-            query_params.insert("limit".to_string(), 50.to_string());
+    /// Activates auto-confirm so future actions of the target are confirmed
+    /// automatically. Actions already waiting for confirmation are confirmed too.
+    pub async fn activate_auto_confirm(
+        &self,
+        target_id: &str,
+        initiator: Option<&str>,
+        remark: Option<&str>,
+    ) -> HawkbitResult<Value> {
+        let endpoint = &format!("targets/{}/autoConfirm/activate", encode_segment(target_id));
+        let mut data = serde_json::Map::new();
+        if let Some(initiator) = initiator {
+            data.insert("initiator".to_string(), json!(initiator));
+        }
+        if let Some(remark) = remark {
+            data.insert("remark".to_string(), json!(remark));
+        }
+        self.post(endpoint, &Value::Object(data)).await
+    }
 
-            let new_page = self
-                .get::<PaginationResponse<Vec<ActionStatusEvent>>>(&endpoint, Some(query_params))
-                .await?;
+    pub async fn deactivate_auto_confirm(&self, target_id: &str) -> HawkbitResult<Value> {
+        let endpoint = &format!(
+            "targets/{}/autoConfirm/deactivate",
+            encode_segment(target_id)
+        );
+        self.post(endpoint, &json!({})).await
+    }
 
-            total = new_page.total;
-            status.extend(new_page.content);
-            if new_page.size == 0 {
-                break;
-            }
+    pub async fn get_target_tags(
+        &self,
+        filter_query: Option<&str>,
+    ) -> HawkbitResult<Vec<TargetTag>> {
+        self.get_paged("targettags", filter_query).await
+    }
 
-            offset += new_page.size;
+    pub async fn create_target_tags(&self, tags: &[NewTag]) -> HawkbitResult<Vec<TargetTag>> {
+        let created = self.post("targettags", tags).await?;
+        Ok(serde_json::from_value(created)?)
+    }
+
+    /// Returns the tag with the given name, creating it if it does not exist.
+    pub async fn ensure_target_tag(&self, name: &str) -> HawkbitResult<TargetTag> {
+        let filter = format!("name=={}", fiql_quote(name));
+        if let Some(tag) = self
+            .get_target_tags(Some(&filter))
+            .await?
+            .into_iter()
+            .find(|tag| tag.name == name)
+        {
+            return Ok(tag);
         }
-        Ok(status)
+        let new_tag = NewTag {
+            name: name.to_string(),
+            description: None,
+            colour: None,
+        };
+        self.create_target_tags(&[new_tag])
+            .await?
+            .into_iter()
+            .next()
+            .ok_or_else(|| HawkbitError::new(format!("Tag {:?} was not created", name)))
     }
 
-    pub async fn assign_distribution(
+    pub async fn assign_target_tag(
         &self,
-        target_id: &str,
-        distribution_id: &u64,
+        tag_id: u64,
+        target_ids: &[&str],
     ) -> HawkbitResult<Value> {
-        let endpoint = format!("targets/{}/assignedDS", target_id);
-        let data = vec![json!({ "id": distribution_id, "type": "forced" })];
-        self.post(&endpoint, &data).await
+        let endpoint = &format!("targettags/{}/assigned", tag_id);
+        let data: Vec<Value> = target_ids
+            .iter()
+            .map(|id| json!({ "controllerId": id }))
+            .collect();
+        self.post(endpoint, &data).await
     }
 
-    pub async fn get_distribution_sets(
+    pub async fn unassign_target_tag(&self, tag_id: u64, target_id: &str) -> HawkbitResult<String> {
+        let endpoint = &format!(
+            "targettags/{}/assigned/{}",
+            tag_id,
+            encode_segment(target_id)
+        );
+        self.delete(endpoint, None).await
+    }
+
+    /// Targets the tag is assigned to
+    pub async fn get_target_tag_assignments(&self, tag_id: u64) -> HawkbitResult<Vec<MgmtTarget>> {
+        let endpoint = &format!("targettags/{}/assigned", tag_id);
+        self.get_paged(endpoint, None).await
+    }
+
+    pub async fn update_target_tag(
+        &self,
+        tag_id: u64,
+        tag: &NewTag,
+    ) -> HawkbitResult<Option<Value>> {
+        let endpoint = &format!("targettags/{}", tag_id);
+        self.put(endpoint, tag).await
+    }
+
+    pub async fn update_distribution_set_tag(
+        &self,
+        tag_id: u64,
+        tag: &NewTag,
+    ) -> HawkbitResult<Option<Value>> {
+        let endpoint = &format!("distributionsettags/{}", tag_id);
+        self.put(endpoint, tag).await
+    }
+
+    pub async fn get_distribution_set_tags(
         &self,
         filter_query: Option<&str>,
+    ) -> HawkbitResult<Vec<DistributionSetTag>> {
+        self.get_paged("distributionsettags", filter_query).await
+    }
+
+    pub async fn create_distribution_set_tags(
+        &self,
+        tags: &[NewTag],
+    ) -> HawkbitResult<Vec<DistributionSetTag>> {
+        let created = self.post("distributionsettags", tags).await?;
+        Ok(serde_json::from_value(created)?)
+    }
+
+    /// Distribution sets the tag is assigned to
+    pub async fn get_distribution_set_tag_assignments(
+        &self,
+        tag_id: u64,
     ) -> HawkbitResult<Vec<DistributionSet>> {
-        let mut offset = 0;
-        let mut total = usize::MAX; // Will be overwritten on first request
-        let mut distribution_sets = Vec::new();
-        while offset < total {
-            // Construct query parameters for pagination and filtering
-            let mut query_params = HashMap::new();
-            if let Some(filter) = filter_query {
-                query_params.insert("q".to_string(), filter.to_string());
-            }
-            query_params.insert("offset".to_string(), offset.to_string());
-            query_params.insert("limit".to_string(), 50.to_string());
+        let endpoint = &format!("distributionsettags/{}/assigned", tag_id);
+        self.get_paged(endpoint, None).await
+    }
 
-            let new_page = self
-                .get::<PaginationResponse<Vec<DistributionSet>>>(
-                    "/distributionsets?sort=createdAt:DESC",
-                    Some(query_params),
-                )
-                .await?;
+    pub async fn assign_distribution_set_tag(
+        &self,
+        tag_id: u64,
+        distribution_ids: &[u64],
+    ) -> HawkbitResult<Value> {
+        let endpoint = &format!("distributionsettags/{}/assigned", tag_id);
+        let data: Vec<Value> = distribution_ids
+            .iter()
+            .map(|id| json!({ "id": id }))
+            .collect();
+        self.post(endpoint, &data).await
+    }
 
-            total = new_page.total;
-            distribution_sets.extend(new_page.content);
-            if new_page.size == 0 {
-                break;
-            }
+    /// Tags assigned to a target
+    pub async fn get_tags_of_target(&self, target_id: &str) -> HawkbitResult<Vec<TargetTag>> {
+        let endpoint = &format!("targets/{}/tags", encode_segment(target_id));
+        self.get::<Vec<TargetTag>>(endpoint, None).await
+    }
 
-            offset += new_page.size;
-        }
-        Ok(distribution_sets)
+    /// Checks whether a distribution set may be assigned to a target based on
+    /// the target type's compatible distribution set types. Returns the reason
+    /// if it is incompatible. Targets without a type accept any distribution set.
+    pub async fn check_compatibility(
+        &self,
+        target_id: &str,
+        distribution_id: u64,
+    ) -> HawkbitResult<Option<String>> {
+        let target = self.get_target(target_id).await?;
+        let distribution_set = self.get_distribution_set(distribution_id).await?;
+        self.incompatibility(&target, &distribution_set, &mut HashMap::new())
+            .await
     }
 
-    pub async fn get_distributionset(&self, distribution_id: &str) -> HawkbitResult<Value> {
-        let mut query_params = HashMap::new();
-        query_params.insert("sort".to_string(), "id:DESC".to_string());
-        let endpoint = &format!("/distributionsets/{}", distribution_id.to_string());
+    async fn incompatibility(
+        &self,
+        target: &MgmtTarget,
+        distribution_set: &DistributionSet,
+        compatible_by_type: &mut HashMap<u64, Vec<DistributionSetType>>,
+    ) -> HawkbitResult<Option<String>> {
+        let Some(target_type) = target.target_type else {
+            return Ok(None);
+        };
+
+        let target_type = target_type as u64;
+        let compatible = match compatible_by_type.entry(target_type) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(
+                self.get_compatible_distribution_set_types(target_type)
+                    .await?,
+            ),
+        };
+        if compatible
+            .iter()
+            .any(|ds_type| ds_type.key == distribution_set.ds_type)
+        {
+            return Ok(None);
+        }
 
-        self.get::<Value>(&endpoint, Some(query_params)).await
+        Ok(Some(format!(
+            "Distribution set {:?} ({}) of type {:?} is not compatible with target type {:?} of {:?}; compatible types: {:?}",
+            distribution_set.name,
+            distribution_set.id,
+            distribution_set.ds_type,
+            target
+                .target_type_name
+                .clone()
+                .unwrap_or_else(|| target_type.to_string()),
+            target.controller_id,
+            compatible
+                .iter()
+                .map(|t| t.key.as_str())
+                .collect::<Vec<_>>()
+        )))
     }
 
-    pub async fn get_latest_distribution(&self) -> HawkbitResult<Value> {
-        let v: Value = self
-            .get("distributionsets?sort=createdAt:DESC&limit=1", None)
-            .await?;
-        if let Some(arr) = v.get("content").and_then(|c| c.as_array()) {
-            if let Some(first) = arr.first() {
-                return Ok(first.clone());
+    /// Runs the compatibility pre-flight for an assignment according to the
+    /// configured `CompatibilityCheck` mode. Lookups are shared within one
+    /// call only, so a long-running command sees type changes made meanwhile.
+    async fn preflight_compatibility(
+        &self,
+        target_ids: &[&str],
+        distribution_id: u64,
+    ) -> HawkbitResult<()> {
+        if self.compatibility_check == CompatibilityCheck::Off {
+            return Ok(());
+        }
+
+        let distribution_set = self.get_distribution_set(distribution_id).await?;
+        let mut compatible_by_type = HashMap::new();
+        let mut reasons = Vec::new();
+        for target_id in target_ids {
+            let target = self.get_target(target_id).await?;
+            if let Some(reason) = self
+                .incompatibility(&target, &distribution_set, &mut compatible_by_type)
+                .await?
+            {
+                reasons.push(reason);
             }
         }
-        Err(HawkbitError::new("No available distributions found"))
+        compatibility_verdict(self.compatibility_check, reasons)
     }
 
-    pub async fn update_target(
+    /// Assigns a distribution set to many targets with a single request.
+    pub async fn assign_targets(
         &self,
-        target_id: &str,
-        target_name: &str,
-        controller_id: &str,
-        update: HashMap<String, String>,
-    ) -> HawkbitResult<Option<Value>> {
-        let mut body = update.clone();
-        body.insert("name".to_string(), target_name.to_string());
-        body.insert("controllerId".to_string(), controller_id.to_string());
-        self.put(&format!("targets/{}", target_id), &json!(body))
-            .await
+        distribution_id: u64,
+        target_ids: &[&str],
+        request: &AssignmentRequest,
+    ) -> HawkbitResult<AssignmentResponse> {
+        request.validate()?;
+        self.preflight_compatibility(target_ids, distribution_id)
+            .await?;
+
+        let endpoint = format!("distributionsets/{}/assignedTargets", distribution_id);
+        let data: Vec<AssignmentEntry<&str>> = target_ids
+            .iter()
+            .map(|id| AssignmentEntry { id: *id, request })
+            .collect();
+        let response = self.post(&endpoint, &data).await?;
+        Ok(serde_json::from_value(response)?)
     }
+}
 
-    pub async fn request_attributes(
-        &self,
-        target_id: &str,
-        target_name: &str,
-        controller_id: &str,
-    ) -> HawkbitResult<Option<Value>> {
-        let mut update = HashMap::new();
-        update.insert("requestAttributes".to_string(), "true".to_string());
-        self.update_target(target_id, target_name, controller_id, update)
-            .await
+/// Applies the `CompatibilityCheck` mode to the incompatibilities found by
+/// a pre-flight.
+fn compatibility_verdict(check: CompatibilityCheck, reasons: Vec<String>) -> HawkbitResult<()> {
+    if reasons.is_empty() || check == CompatibilityCheck::Off {
+        return Ok(());
+    }
+    if check == CompatibilityCheck::Enforce {
+        return Err(HawkbitError::new(reasons.join("\n")));
+    }
+    for reason in reasons {
+        tracing::warn!("{}", reason);
+    }
+    Ok(())
+}
+
+/// Characters that would end or restructure a URL path segment
+const PATH_SEGMENT: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'/')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'\\')
+    .add(b'`')
+    .add(b'{')
+    .add(b'}');
+
+/// Escapes a user-supplied value for use as one path segment, so keys like
+/// `a/b` or `x?y` address the intended resource.
+pub(crate) fn encode_segment(value: &str) -> String {
+    utf8_percent_encode(value, PATH_SEGMENT).to_string()
+}
+
+/// Quotes a value for a FIQL comparison, escaping quotes and backslashes
+/// so it can't end the string and inject further conditions.
+pub fn fiql_quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compatible_assignments_pass_in_every_mode() {
+        for check in [
+            CompatibilityCheck::Enforce,
+            CompatibilityCheck::Warn,
+            CompatibilityCheck::Off,
+        ] {
+            assert!(compatibility_verdict(check, Vec::new()).is_ok());
+        }
+    }
+
+    #[test]
+    fn only_enforce_refuses_incompatible_assignments() {
+        let reasons = || {
+            vec![
+                "dev-1 is an app target".to_string(),
+                "dev-2 too".to_string(),
+            ]
+        };
+        let err = compatibility_verdict(CompatibilityCheck::Enforce, reasons()).unwrap_err();
+        assert_eq!(err.to_string(), "dev-1 is an app target\ndev-2 too");
+        assert!(compatibility_verdict(CompatibilityCheck::Warn, reasons()).is_ok());
+        assert!(compatibility_verdict(CompatibilityCheck::Off, reasons()).is_ok());
+    }
+
+    #[test]
+    fn path_segments_are_escaped() {
+        assert_eq!(encode_segment("dev-1_a.b~c"), "dev-1_a.b~c");
+        assert_eq!(encode_segment("a/b"), "a%2Fb");
+        assert_eq!(encode_segment("x?y#z"), "x%3Fy%23z");
+        assert_eq!(encode_segment("my device"), "my%20device");
+        assert_eq!(encode_segment("100%"), "100%25");
+        assert_eq!(encode_segment("gerät"), "ger%C3%A4t");
     }
 }
@@ -0,0 +1,25 @@
+pub mod artifact_cache;
+pub mod artifacts;
+pub mod auth;
+pub mod channels;
+pub mod cleanup;
+pub mod config;
+pub mod confirmation;
+pub mod crypto;
+pub mod ddi;
+pub mod ddi_proxy;
+pub mod ddi_recorder;
+pub mod failures;
+pub mod hawkbit;
+pub mod metadata;
+pub mod migration;
+pub mod provision;
+pub mod secrets;
+pub mod setup;
+pub mod simulator;
+pub mod stale_actions;
+pub mod tenants;
+pub mod timeline;
+pub mod token_rotation;
+pub mod transport;
+pub mod undo;
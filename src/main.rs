@@ -1,112 +1,161 @@
-mod hawkbit;
-use std::{
-    collections::HashMap,
-    time::{Duration, UNIX_EPOCH},
-};
+mod commands;
 
-use chrono::{DateTime, TimeZone, Utc};
-use hawkbit::HawkbitConfig;
+use std::{collections::HashMap, path::PathBuf, process::ExitCode};
 
-use tracing_subscriber;
+use chrono::Utc;
+use clap::{Parser, Subcommand};
+use hawkbit_data_proxy_rs::channels;
+use hawkbit_data_proxy_rs::cleanup::{self, CleanupPolicy};
+use hawkbit_data_proxy_rs::hawkbit::{self, ActionType, AssignmentRequest, HawkbitConfig};
+use hawkbit_data_proxy_rs::stale_actions::{self, ActionDecision, StaleActionPolicy};
 
-use crate::hawkbit::DistributionSet;
-
-// #[tokio::main]
-// async fn main() {
-//     // tracing_subscriber::fmt()
-//     //     .with_max_level(tracing::Level::DEBUG)
-//     //     .init();
-//     let config = HawkbitConfig::from_env();
-//     let client = hawkbit::HawkbitMgmtClient::from_config(&config);
-
-//     let targets = client
-//         .get_targets(Some("attribute.update_channel != attribute.build_channel"))
-//         .await
-//         .unwrap();
-//     println!(
-//         "Targets with mismatched update/build channels: {:?}",
-//         targets.len()
-//     );
-//     for target in &targets {
-//         let attributes = client
-//             .get_target_attributes(&target.controller_id, None)
-//             .await
-//             .unwrap();
-//         if attributes.get("update_channel").unwrap_or(&"".to_owned())
-//             != attributes.get("build_channel").unwrap_or(&"".to_owned())
-//         {
-//             println!(
-//                 "Target: {:?} {:?} v {:?}",
-//                 target.controller_id,
-//                 attributes.get("update_channel").unwrap_or(&"".to_owned()),
-//                 attributes.get("build_channel").unwrap_or(&"".to_owned())
-//             );
-//         }
-//     }
-// }
+#[derive(Parser)]
+#[command(version, about = "Maintenance tooling for the hawkBit management API")]
+struct Cli {
+    /// TOML config file with named profiles
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
+    /// Profile of the config file to use
+    #[arg(long, global = true)]
+    profile: Option<String>,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
 
-async fn print_actions(controller_id: &String, client: &hawkbit::HawkbitMgmtClient) {
-    let attributes = client.get_target_attributes(&controller_id, None).await;
-    if attributes.is_err() {
-        println!(
-            "Failed to get attributes for controller: {:?}",
-            controller_id
-        );
-        return;
-    }
-    let attributes = attributes.unwrap();
-    println!("Attributes: {:?}\n\n", attributes);
-    let actions = client
-        .get_target_actions(&controller_id, Some(5), None)
-        .await
-        .unwrap();
-    println!("Actions: {:?}\n\n", actions);
+#[derive(Subcommand)]
+enum Command {
+    /// Reassign failed targets, cancel stale actions and clean up factory machines (default)
+    Maintain,
+    /// Inspect, download and verify software module artifacts
+    #[command(subcommand)]
+    Artifacts(commands::artifacts::ArtifactsCommand),
+    /// Manage management-side metadata
+    #[command(subcommand)]
+    Metadata(commands::metadata::MetadataCommand),
+    /// Manage target types and their compatible distribution set types
+    #[command(subcommand)]
+    TargetTypes(commands::target_types::TargetTypesCommand),
+    /// Cancel superseded and stuck actions, escalating to force-cancel
+    StaleActions(commands::stale_actions::StaleActionsArgs),
+    /// Cluster the status messages of failed actions into failure signatures
+    Failures(commands::failures::FailuresArgs),
+    /// Show the chronological update history of a target
+    Timeline(commands::timeline::TimelineArgs),
+    /// Report targets whose update_channel differs from their build_channel
+    ChannelAudit(commands::channel_audit::ChannelAuditArgs),
+    /// Confirm or deny actions and manage auto-confirm
+    #[command(subcommand)]
+    Confirmation(commands::confirmation::ConfirmationCommand),
+    /// Assign a distribution set to one or more targets
+    Assign(commands::assign::AssignArgs),
+    /// Delete inactive factory machines according to a cleanup policy
+    Cleanup(commands::cleanup::CleanupArgs),
+    /// Recreate deleted targets from the undo journal
+    Restore(commands::restore::RestoreArgs),
+    /// Register a batch of targets from a CSV or JSON file
+    Provision(commands::provision::ProvisionArgs),
+    /// Rotate target security tokens in stages
+    Tokens(commands::tokens::TokensArgs),
+    /// Manage secrets in the encrypted local vault
+    Vault(commands::vault::VaultArgs),
+    /// Run a report, cleanup or stale-action cancellation across several tenants
+    Tenants(commands::tenants::TenantsArgs),
+    /// Copy types, software, distribution sets, tags and targets to another instance
+    Migrate(commands::migrate::MigrateArgs),
+    /// Act as a device through the DDI API: poll, download, send feedback
+    Ddi(commands::ddi::DdiArgs),
+    /// Front the DDI API for a site, caching artifact downloads on disk
+    DdiProxy(commands::ddi_proxy::DdiProxyArgs),
+    /// Show what devices requested through a recording `ddi-proxy`
+    DdiInspect(commands::ddi_inspect::DdiInspectArgs),
+    /// Run simulated devices against hawkBit to rehearse rollouts
+    Simulate(commands::simulate::SimulateArgs),
+    /// Export the management configuration to YAML files and apply it back
+    #[command(subcommand)]
+    Setup(commands::setup::SetupCommand),
+}
 
-    let first_action = actions.first().unwrap();
-    println!("First action: {:?}\n\n", first_action);
+#[tokio::main]
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+    tracing_subscriber::fmt()
+        .with_writer(std::io::stderr)
+        .init();
+    // Needs no connection, and the vault may hold the credentials
+    let command = match cli.command.unwrap_or(Command::Maintain) {
+        Command::Vault(args) => return commands::vault::run(args),
+        // Connects to each profile itself
+        Command::Tenants(args) => return commands::tenants::run(cli.config.as_deref(), args).await,
+        Command::Migrate(args) => return commands::migrate::run(cli.config.as_deref(), args).await,
+        // Authenticates as a device, not with the management credentials
+        Command::Ddi(args) => {
+            return commands::ddi::run(cli.config.as_deref(), cli.profile.as_deref(), args).await;
+        }
+        Command::DdiInspect(args) => return commands::ddi_inspect::run(args).await,
+        Command::DdiProxy(args) => {
+            return commands::ddi_proxy::run(cli.config.as_deref(), cli.profile.as_deref(), args)
+                .await;
+        }
+        Command::Simulate(args) => {
+            return commands::simulate::run(cli.config.as_deref(), cli.profile.as_deref(), args)
+                .await;
+        }
+        command => command,
+    };
 
-    for action in &actions {
-        let action_details = client
-            .get_action_detail(&controller_id, &action.id)
-            .await
-            .unwrap();
-        println!("Action details: {:?}\n\n", action_details);
-    }
+    let config = match HawkbitConfig::load(cli.config.as_deref(), cli.profile.as_deref()) {
+        Ok(config) => config,
+        Err(e) => {
+            println!("Error: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let client = match hawkbit::HawkbitMgmtClient::from_config(&config) {
+        Ok(client) => client,
+        Err(e) => {
+            println!("Error: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
 
-    let action_status = client
-        .get_action_status(&controller_id, &first_action.id)
-        .await
-        .unwrap();
-    for status in action_status {
-        println!("{}: {:?}", status.event_type, status.messages);
+    match command {
+        Command::Maintain => maintain(&client, config.channel()).await,
+        Command::Artifacts(command) => return commands::artifacts::run(&client, command).await,
+        Command::Metadata(command) => return commands::metadata::run(&client, command).await,
+        Command::StaleActions(args) => return commands::stale_actions::run(&client, args).await,
+        Command::Failures(args) => return commands::failures::run(&client, args).await,
+        Command::Timeline(args) => return commands::timeline::run(&client, args).await,
+        Command::ChannelAudit(args) => return commands::channel_audit::run(&client, args).await,
+        Command::Confirmation(command) => {
+            return commands::confirmation::run(&client, command).await;
+        }
+        Command::Assign(args) => return commands::assign::run(&client, args).await,
+        Command::Cleanup(args) => return commands::cleanup::run(&client, args).await,
+        Command::Restore(args) => return commands::restore::run(&client, args).await,
+        Command::Provision(args) => return commands::provision::run(&client, args).await,
+        Command::Tokens(args) => return commands::tokens::run(&client, args).await,
+        Command::Setup(command) => return commands::setup::run(&client, command).await,
+        Command::Vault(_)
+        | Command::Tenants(_)
+        | Command::Migrate(_)
+        | Command::Ddi(_)
+        | Command::DdiProxy(_)
+        | Command::DdiInspect(_)
+        | Command::Simulate(_) => {
+            unreachable!("handled before connecting")
+        }
+        Command::TargetTypes(command) => {
+            return commands::target_types::run(&client, command).await;
+        }
     }
+    ExitCode::SUCCESS
 }
 
-#[tokio::main]
-async fn main() {
-    let config = HawkbitConfig::from_env();
-    let client = hawkbit::HawkbitMgmtClient::from_config(&config);
-    let mut dist_sets_lookup: HashMap<String, DistributionSet> = HashMap::new();
+async fn maintain(client: &hawkbit::HawkbitMgmtClient, default_channel: Option<&str>) {
     let sets = client.get_distribution_sets(None).await.unwrap();
-    for set in sets {
-        let name = set.name.clone();
-        if dist_sets_lookup.contains_key(&name) {
-            if dist_sets_lookup.get(&name).unwrap().created_at >= set.created_at {
-                // println!(
-                //     "Skipping older distribution set: {:?} {:?}",
-                //     name, set.created_at
-                // );
-                continue;
-            } else {
-                println!(
-                    "Replacing distribution set with newer one: {:?} {:?}",
-                    name, set.created_at
-                );
-            }
-        } else {
-            println!("Distribution set: {:?} {:?}", name, set.created_at);
-        }
-        dist_sets_lookup.insert(name, set);
+    let dist_sets_lookup = channels::latest_by_name(sets);
+    for set in dist_sets_lookup.values() {
+        println!("Distribution set: {:?} {:?}", set.name, set.created_at);
     }
     // tracing_subscriber::fmt()
     //     .with_max_level(tracing::Level::DEBUG)
@@ -123,21 +172,29 @@ async fn main() {
 
     // let target = client.get_target(&controller_id).await.unwrap();
     // println!("Target: {:?}\n\n", target);
-    let mut factory_machines = Vec::new();
-    let mut canceled_actions: Vec<hawkbit::Action> = Vec::new();
+    let cleanup_policy = CleanupPolicy {
+        // Known issue, the device is deleted while it is in error or
+        // registered until it is fixed
+        deny_list: targets
+            .iter()
+            .filter(|target| target.controller_id == "meticulousDarkPumpkinSpiceLatteREL21Q-000021")
+            .map(|target| target.controller_id.clone())
+            .collect(),
+        dry_run: false,
+        ..Default::default()
+    };
+    // Only superseded actions; stuck ones are left to `stale-actions`
+    let stale_policy = StaleActionPolicy {
+        cancel_superseded: true,
+        stuck_after: None,
+        ..Default::default()
+    };
+    let mut canceled_actions: Vec<ActionDecision> = Vec::new();
     let mut last_seen_map = HashMap::new();
 
     for target in &targets {
         // println!("Target: {:?}", target.controller_id);
-        if (target.controller_id == "meticulousDarkPumpkinSpiceLatteREL21Q-000021") {
-            client
-                .delete_target(target.controller_id.as_str())
-                .await
-                .unwrap();
-            println!(
-                "Deleted target {:?} due to known issue",
-                target.controller_id
-            );
+        if cleanup_policy.deny_list.contains(&target.controller_id) {
             continue;
         }
         println!("========================================");
@@ -155,7 +212,7 @@ async fn main() {
             // println!("Attributes: {:?}\n\n", attributes);
             if s == "error" || s == "registered" {
                 // println!("Reassign target: {:?}", target);
-                let attributes = client.get_target_attributes(&controller_id, None).await;
+                let attributes = client.get_target_attributes(controller_id, None).await;
 
                 if attributes.is_err() {
                     println!(
@@ -166,7 +223,14 @@ async fn main() {
                 }
                 let attributes = attributes.unwrap();
                 println!("Attributes: {:?}", attributes);
-                let update_channel = attributes.get("update_channel").unwrap();
+                let Some(update_channel) = attributes
+                    .get(channels::UPDATE_CHANNEL_ATTRIBUTE)
+                    .map(String::as_str)
+                    .or(default_channel)
+                else {
+                    println!("No update channel for controller: {:?}", controller_id);
+                    continue;
+                };
                 // if s == "error" {
                 //     let last_action: Vec<hawkbit::Action> = client
                 //         .get_target_actions(&controller_id, Some(1), None)
@@ -186,7 +250,7 @@ async fn main() {
                 //     }
                 // }
                 println!("Update channel: {:?}", update_channel);
-                let dist_set_name = update_channel.to_owned() + " EMMC";
+                let dist_set_name = channels::distribution_set_name(update_channel);
                 if !dist_sets_lookup.contains_key(&dist_set_name) {
                     println!(
                         "No distribution set found for update channel: {:?}",
@@ -207,9 +271,13 @@ async fn main() {
                     controller_id, dist_set.id, dist_set.name
                 );
 
-                match (client
-                    .assign_distribution(&controller_id, &dist_set.id)
-                    .await)
+                match client
+                    .assign_distribution(
+                        controller_id,
+                        &dist_set.id,
+                        &AssignmentRequest::new(ActionType::Forced),
+                    )
+                    .await
                 {
                     Ok(_) => {
                         println!("Reassigned distribution set");
@@ -224,26 +292,18 @@ async fn main() {
             }
         }
 
-        let actions: Vec<hawkbit::Action> = client
-            .get_target_actions(&controller_id, Some(50), Some("status==\"pending\""))
-            .await
-            .unwrap();
-        let mut has_active = false;
-        for action in &actions {
-            if action.action_type != "update" {
-                continue;
-            }
-            if !has_active && (action.status == "pending" || action.status == "updating") {
-                has_active = true;
-                continue;
+        match stale_actions::process_target(client, controller_id, &stale_policy).await {
+            Ok(decisions) => {
+                for decision in &decisions {
+                    println!("Canceled action: {:?}", decision);
+                }
+                canceled_actions.extend(decisions);
             }
-            if action.status != "finished" && action.detail_status != "running" && has_active {
-                println!("Should cancel action: {:?}", action);
-                canceled_actions.push(action.clone());
-                client
-                    .cancel_action(&controller_id, &action.id, false)
-                    .await
-                    .unwrap();
+            Err(e) => {
+                println!(
+                    "Failed to check actions of target {:?}: {}",
+                    controller_id, e
+                );
             }
         }
     }
@@ -260,14 +320,6 @@ async fn main() {
     let pending_count = pending_targets.len();
     let all_targets = client.get_targets(None).await.unwrap();
     for target in &all_targets {
-        // if target.controller_id.contains("XYZ") {
-        //     let d = UNIX_EPOCH + Duration::from_secs(TryInto::<u64>::try_into(target.last_controller_request_at.unwrap()).unwrap() / 1000);
-        //     let datetime = DateTime::<Utc>::from(d);
-        //     let timestamp_str = datetime.format("%Y-%m-%d %H:%M:%S.%f '%Z'").to_string();
-        //     println!("[{}]: Last seen: {}", &target.controller_id, timestamp_str);
-        //     print_actions(&target.controller_id, &client).await;
-        // }
-
         println!(
             "Requesting attributes for target: {:?}",
             target.controller_id
@@ -307,11 +359,6 @@ async fn main() {
             let entry = last_seen_map.entry(bucket).or_insert(Vec::new());
             entry.push(&target.controller_id);
             // println!("  [{}] status={}", target.controller_id, target.update_status.clone().unwrap_or("default".to_string()));
-            let is_sn_unset = target.controller_id.contains("-999");
-            // Delete factory machines that have never connected or connected within last 3 days
-            if is_sn_unset && last_ts <= now_ts - 3 * 24 * 3600 {
-                factory_machines.push(target.controller_id.clone());
-            }
         }
     }
     println!("\n\nStatus summary:");
@@ -338,14 +385,8 @@ async fn main() {
         //     println!("  Controller: {:?}", controller);
         // }
     }
-    println!(
-        "Factory machines that we are going to delete: {:?}",
-        factory_machines.len()
-    );
-    for target_to_delete in &factory_machines {
-        println!("Target to delete: {:?}", target_to_delete);
-        client.delete_target(target_to_delete).await.unwrap();
-    }
+    let report = cleanup::run_cleanup(client, &cleanup_policy, &all_targets).await;
+    commands::cleanup::print_report(&report, &cleanup_policy.journal);
     let targets = client.get_targets(None).await.unwrap();
     println!("Remaining targets after deletion: {:?}", targets.len());
 }
@@ -0,0 +1,191 @@
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::hawkbit::{
+    HawkbitError, HawkbitMgmtClient, HawkbitResult, MetadataEntry, SoftwareModuleMetadata,
+};
+
+/// Metadata to set on one target, as read from a bulk import file.
+#[derive(Debug, Clone)]
+pub struct TargetMetadataRow {
+    pub controller_id: String,
+    pub entries: Vec<MetadataEntry>,
+}
+
+/// Reads a CSV file with a `controllerId` column followed by one column per
+/// metadata key, e.g. `controllerId,serial_number,customer_id`. Empty cells
+/// are skipped so a row only touches the keys it has values for.
+pub fn read_metadata_csv(path: &Path) -> HawkbitResult<Vec<TargetMetadataRow>> {
+    let mut reader = csv::Reader::from_path(path).map_err(csv_error)?;
+    let headers = reader.headers().map_err(csv_error)?.clone();
+
+    let id_column = headers
+        .iter()
+        .position(|h| h == "controllerId")
+        .ok_or_else(|| HawkbitError::new("CSV is missing a controllerId column"))?;
+
+    let mut rows = Vec::new();
+    for (line, record) in reader.records().enumerate() {
+        let record = record.map_err(csv_error)?;
+        let controller_id = record.get(id_column).unwrap_or_default().trim();
+        if controller_id.is_empty() {
+            return Err(HawkbitError::new(format!(
+                "Row {} has an empty controllerId",
+                line + 2
+            )));
+        }
+
+        let entries = headers
+            .iter()
+            .zip(record.iter())
+            .enumerate()
+            .filter(|(i, (_, value))| *i != id_column && !value.trim().is_empty())
+            .map(|(_, (key, value))| MetadataEntry {
+                key: key.to_string(),
+                value: value.trim().to_string(),
+            })
+            .collect();
+
+        rows.push(TargetMetadataRow {
+            controller_id: controller_id.to_string(),
+            entries,
+        });
+    }
+    Ok(rows)
+}
+
+fn csv_error(err: csv::Error) -> HawkbitError {
+    HawkbitError::new(format!("CSV error: {}", err))
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct MetadataChanges {
+    pub created: Vec<String>,
+    pub updated: Vec<String>,
+    pub unchanged: Vec<String>,
+}
+
+/// A metadata entry identified by its key, compared as a whole to decide
+/// whether an existing entry needs an update.
+trait KeyedEntry: Clone + PartialEq {
+    fn key(&self) -> &str;
+}
+
+impl KeyedEntry for MetadataEntry {
+    fn key(&self) -> &str {
+        &self.key
+    }
+}
+
+impl KeyedEntry for SoftwareModuleMetadata {
+    fn key(&self) -> &str {
+        &self.key
+    }
+}
+
+/// Compares `entries` against the `existing` ones, updates each changed key
+/// and creates all missing keys in one request.
+async fn upsert_metadata<E: KeyedEntry>(
+    existing: Vec<E>,
+    entries: &[E],
+    update: impl AsyncFn(&E) -> HawkbitResult<Option<Value>>,
+    create: impl AsyncFn(&[E]) -> HawkbitResult<Value>,
+) -> HawkbitResult<MetadataChanges> {
+    let existing: HashMap<String, E> = existing
+        .into_iter()
+        .map(|entry| (entry.key().to_string(), entry))
+        .collect();
+
+    let mut changes = MetadataChanges::default();
+    let mut to_create = Vec::new();
+    for entry in entries {
+        match existing.get(entry.key()) {
+            None => to_create.push(entry.clone()),
+            Some(current) if current == entry => changes.unchanged.push(entry.key().to_string()),
+            Some(_) => {
+                update(entry).await?;
+                changes.updated.push(entry.key().to_string());
+            }
+        }
+    }
+
+    if !to_create.is_empty() {
+        create(&to_create).await?;
+        changes
+            .created
+            .extend(to_create.iter().map(|entry| entry.key().to_string()));
+    }
+    Ok(changes)
+}
+
+/// Creates or updates the given metadata keys on a target. Keys that are not
+/// listed are left untouched.
+pub async fn upsert_target_metadata(
+    client: &HawkbitMgmtClient,
+    controller_id: &str,
+    entries: &[MetadataEntry],
+) -> HawkbitResult<MetadataChanges> {
+    upsert_metadata(
+        client.get_target_metadata(controller_id, None).await?,
+        entries,
+        async |entry| {
+            client
+                .update_target_metadata(controller_id, &entry.key, &entry.value)
+                .await
+        },
+        async |new| client.create_target_metadata(controller_id, new).await,
+    )
+    .await
+}
+
+/// Creates or updates distribution set metadata, e.g. while publishing a release.
+pub async fn upsert_distribution_set_metadata(
+    client: &HawkbitMgmtClient,
+    distribution_id: u64,
+    entries: &[MetadataEntry],
+) -> HawkbitResult<MetadataChanges> {
+    upsert_metadata(
+        client
+            .get_distribution_set_metadata(distribution_id, None)
+            .await?,
+        entries,
+        async |entry| {
+            client
+                .update_distribution_set_metadata(distribution_id, &entry.key, &entry.value)
+                .await
+        },
+        async |new| {
+            client
+                .create_distribution_set_metadata(distribution_id, new)
+                .await
+        },
+    )
+    .await
+}
+
+/// Creates or updates software module metadata. An entry whose value matches
+/// but whose `targetVisible` flag differs is updated as well.
+pub async fn upsert_software_module_metadata(
+    client: &HawkbitMgmtClient,
+    module_id: u64,
+    entries: &[SoftwareModuleMetadata],
+) -> HawkbitResult<MetadataChanges> {
+    upsert_metadata(
+        client.get_software_module_metadata(module_id, None).await?,
+        entries,
+        async |entry| {
+            client
+                .update_software_module_metadata(
+                    module_id,
+                    &entry.key,
+                    &entry.value,
+                    entry.target_visible,
+                )
+                .await
+        },
+        async |new| client.create_software_module_metadata(module_id, new).await,
+    )
+    .await
+}
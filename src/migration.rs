@@ -0,0 +1,1011 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+use crate::hawkbit::{
+    HawkbitError, HawkbitMgmtClient, HawkbitResult, MetadataEntry, NewDistributionSet,
+    NewDistributionSetType, NewSoftwareModule, NewSoftwareModuleType, NewTag, NewTarget,
+    NewTargetType, SoftwareModuleType,
+};
+
+/// First line of the state file, naming the instances it belongs to
+#[derive(Debug, Serialize, Deserialize)]
+struct StateHeader {
+    source: String,
+    destination: String,
+}
+
+/// Progress of a migration, kept as JSON lines: a header followed by the key
+/// of every migrated item. A key is appended as soon as its item is done, so
+/// an interrupted run continues where it stopped.
+#[derive(Debug)]
+pub struct MigrationState {
+    pub source: String,
+    pub destination: String,
+    /// Natural keys of completely migrated items, e.g. `target:device-1`
+    pub completed: BTreeSet<String>,
+    file: std::fs::File,
+}
+
+impl MigrationState {
+    /// Opens the state of a migration, starting fresh if the file does not
+    /// exist. A state file of a migration between other instances is refused.
+    pub fn open(path: &Path, source: &str, destination: &str) -> HawkbitResult<Self> {
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .read(true)
+            .open(path)?;
+        let mut content = String::new();
+        file.read_to_string(&mut content)?;
+
+        let mut lines = content.lines();
+        let Some(header) = lines.next() else {
+            let header = StateHeader {
+                source: source.to_string(),
+                destination: destination.to_string(),
+            };
+            writeln!(file, "{}", serde_json::to_string(&header)?)?;
+            return Ok(Self {
+                source: header.source,
+                destination: header.destination,
+                completed: BTreeSet::new(),
+                file,
+            });
+        };
+        let header: StateHeader = serde_json::from_str(header).map_err(|e| {
+            HawkbitError::new(format!("Invalid state file {}: {}", path.display(), e))
+        })?;
+        if header.source != source || header.destination != destination {
+            return Err(HawkbitError::new(format!(
+                "State file {} belongs to the migration from {} to {}",
+                path.display(),
+                header.source,
+                header.destination
+            )));
+        }
+
+        // A line cut off by an interruption is dropped, its item is simply
+        // migrated again
+        let completed = lines
+            .filter_map(|line| serde_json::from_str::<String>(line).ok())
+            .collect();
+        if !content.ends_with('\n') {
+            writeln!(file)?;
+        }
+        Ok(Self {
+            source: header.source,
+            destination: header.destination,
+            completed,
+            file,
+        })
+    }
+
+    fn is_completed(&self, key: &str) -> bool {
+        self.completed.contains(key)
+    }
+
+    fn complete(&mut self, key: String) -> HawkbitResult<()> {
+        writeln!(self.file, "{}", serde_json::to_string(&key)?)?;
+        self.completed.insert(key);
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MigrationOptions {
+    /// FIQL query restricting the migrated targets
+    pub target_filter: Option<String>,
+    /// Directory holding artifacts while they are copied
+    pub download_dir: PathBuf,
+}
+
+/// Outcome of one kind of entity
+#[derive(Debug, Default, Serialize)]
+pub struct PhaseReport {
+    pub phase: String,
+    pub created: usize,
+    /// Already present on the destination and synced
+    pub existing: usize,
+    /// Completed by an earlier run
+    pub resumed: usize,
+    pub failed: Vec<(String, String)>,
+}
+
+impl PhaseReport {
+    fn new(phase: &str) -> Self {
+        Self {
+            phase: phase.to_string(),
+            ..Default::default()
+        }
+    }
+}
+
+fn first<T>(items: Vec<T>, what: &str) -> HawkbitResult<T> {
+    items
+        .into_iter()
+        .next()
+        .ok_or_else(|| HawkbitError::new(format!("{} was not created", what)))
+}
+
+fn mapped(ids: &HashMap<u64, u64>, id: u64, what: &str) -> HawkbitResult<u64> {
+    ids.get(&id)
+        .copied()
+        .ok_or_else(|| HawkbitError::new(format!("{} {} was not migrated", what, id)))
+}
+
+/// Metadata entries of `source` whose key is missing in `existing`
+fn missing_metadata<T: Clone>(source: &[T], existing: &[T], key: impl Fn(&T) -> &str) -> Vec<T> {
+    let keys: HashSet<&str> = existing.iter().map(&key).collect();
+    source
+        .iter()
+        .filter(|entry| !keys.contains(key(entry)))
+        .cloned()
+        .collect()
+}
+
+/// Copies the management configuration and targets from `source` to
+/// `destination`. Entities are matched by natural key (type keys, tag and
+/// target type names, name and version, controller ID), so entities already
+/// present are reused and a repeated run only copies what is missing.
+pub struct Migration<'a> {
+    source: &'a HawkbitMgmtClient,
+    destination: &'a HawkbitMgmtClient,
+    state: &'a mut MigrationState,
+    options: &'a MigrationOptions,
+    // Source ID to destination ID
+    module_types: HashMap<u64, u64>,
+    ds_types: HashMap<u64, u64>,
+    target_types: HashMap<u64, u64>,
+    target_tags: HashMap<u64, u64>,
+    ds_tags: HashMap<u64, u64>,
+    modules: HashMap<u64, u64>,
+    distribution_sets: HashMap<u64, u64>,
+    /// Controller IDs present on the destination
+    targets: HashSet<String>,
+}
+
+impl<'a> Migration<'a> {
+    pub fn new(
+        source: &'a HawkbitMgmtClient,
+        destination: &'a HawkbitMgmtClient,
+        state: &'a mut MigrationState,
+        options: &'a MigrationOptions,
+    ) -> Self {
+        Self {
+            source,
+            destination,
+            state,
+            options,
+            module_types: HashMap::new(),
+            ds_types: HashMap::new(),
+            target_types: HashMap::new(),
+            target_tags: HashMap::new(),
+            ds_tags: HashMap::new(),
+            modules: HashMap::new(),
+            distribution_sets: HashMap::new(),
+            targets: HashSet::new(),
+        }
+    }
+
+    /// Runs all phases in dependency order. Failures of single entities are
+    /// reported and skipped; failing to list an entity kind stops the run.
+    pub async fn run(&mut self) -> HawkbitResult<Vec<PhaseReport>> {
+        Ok(vec![
+            self.migrate_module_types().await?,
+            self.migrate_ds_types().await?,
+            self.migrate_target_types().await?,
+            self.migrate_tags().await?,
+            self.migrate_modules().await?,
+            self.migrate_distribution_sets().await?,
+            self.migrate_targets().await?,
+            self.migrate_tag_assignments().await?,
+        ])
+    }
+
+    async fn migrate_module_types(&mut self) -> HawkbitResult<PhaseReport> {
+        let mut report = PhaseReport::new("software module types");
+        let existing: HashMap<String, u64> = self
+            .destination
+            .get_software_module_types(None)
+            .await?
+            .into_iter()
+            .map(|module_type| (module_type.key, module_type.id))
+            .collect();
+
+        for module_type in self.source.get_software_module_types(None).await? {
+            if module_type.deleted {
+                continue;
+            }
+            let id = match existing.get(&module_type.key) {
+                Some(id) => {
+                    report.existing += 1;
+                    *id
+                }
+                None => {
+                    let new_type = NewSoftwareModuleType {
+                        key: module_type.key.clone(),
+                        name: module_type.name.clone(),
+                        description: module_type.description.clone(),
+                        colour: module_type.colour.clone(),
+                        max_assignments: module_type.max_assignments,
+                    };
+                    let created = self
+                        .destination
+                        .create_software_module_types(&[new_type])
+                        .await
+                        .and_then(|created| first(created, "Software module type"));
+                    match created {
+                        Ok(created) => {
+                            tracing::info!("Created software module type {:?}", module_type.key);
+                            report.created += 1;
+                            created.id
+                        }
+                        Err(e) => {
+                            report.failed.push((module_type.key, e.to_string()));
+                            continue;
+                        }
+                    }
+                }
+            };
+            self.module_types.insert(module_type.id, id);
+        }
+        Ok(report)
+    }
+
+    async fn migrate_ds_types(&mut self) -> HawkbitResult<PhaseReport> {
+        let mut report = PhaseReport::new("distribution set types");
+        let existing: HashMap<String, u64> = self
+            .destination
+            .get_distribution_set_types(None)
+            .await?
+            .into_iter()
+            .map(|ds_type| (ds_type.key, ds_type.id))
+            .collect();
+
+        for ds_type in self.source.get_distribution_set_types(None).await? {
+            if ds_type.deleted {
+                continue;
+            }
+            if let Some(id) = existing.get(&ds_type.key) {
+                report.existing += 1;
+                self.ds_types.insert(ds_type.id, *id);
+                continue;
+            }
+            let created: HawkbitResult<u64> = async {
+                let module_type_ids = |types: Vec<SoftwareModuleType>| {
+                    types
+                        .iter()
+                        .map(|t| mapped(&self.module_types, t.id, "Software module type"))
+                        .collect::<HawkbitResult<Vec<u64>>>()
+                };
+                let new_type = NewDistributionSetType {
+                    key: ds_type.key.clone(),
+                    name: ds_type.name.clone(),
+                    description: ds_type.description.clone(),
+                    colour: ds_type.colour.clone(),
+                    mandatory_modules: module_type_ids(
+                        self.source.get_mandatory_module_types(ds_type.id).await?,
+                    )?,
+                    optional_modules: module_type_ids(
+                        self.source.get_optional_module_types(ds_type.id).await?,
+                    )?,
+                };
+                let created = self
+                    .destination
+                    .create_distribution_set_types(&[new_type])
+                    .await?;
+                Ok(first(created, "Distribution set type")?.id)
+            }
+            .await;
+            match created {
+                Ok(id) => {
+                    tracing::info!("Created distribution set type {:?}", ds_type.key);
+                    report.created += 1;
+                    self.ds_types.insert(ds_type.id, id);
+                }
+                Err(e) => report.failed.push((ds_type.key, e.to_string())),
+            }
+        }
+        Ok(report)
+    }
+
+    async fn migrate_target_types(&mut self) -> HawkbitResult<PhaseReport> {
+        let mut report = PhaseReport::new("target types");
+        let existing: HashMap<String, u64> = self
+            .destination
+            .get_target_types(None)
+            .await?
+            .into_iter()
+            .map(|target_type| (target_type.name, target_type.id))
+            .collect();
+
+        for target_type in self.source.get_target_types(None).await? {
+            if target_type.deleted {
+                continue;
+            }
+            if let Some(id) = existing.get(&target_type.name) {
+                report.existing += 1;
+                self.target_types.insert(target_type.id, *id);
+                continue;
+            }
+            let created: HawkbitResult<u64> = async {
+                let compatible = self
+                    .source
+                    .get_compatible_distribution_set_types(target_type.id)
+                    .await?
+                    .iter()
+                    .map(|ds_type| mapped(&self.ds_types, ds_type.id, "Distribution set type"))
+                    .collect::<HawkbitResult<Vec<u64>>>()?;
+                let new_type = NewTargetType {
+                    name: target_type.name.clone(),
+                    key: target_type.key.clone(),
+                    description: target_type.description.clone(),
+                    colour: target_type.colour.clone(),
+                    compatible_distribution_set_types: compatible,
+                };
+                let created = self.destination.create_target_types(&[new_type]).await?;
+                Ok(first(created, "Target type")?.id)
+            }
+            .await;
+            match created {
+                Ok(id) => {
+                    tracing::info!("Created target type {:?}", target_type.name);
+                    report.created += 1;
+                    self.target_types.insert(target_type.id, id);
+                }
+                Err(e) => report.failed.push((target_type.name, e.to_string())),
+            }
+        }
+        Ok(report)
+    }
+
+    async fn migrate_tags(&mut self) -> HawkbitResult<PhaseReport> {
+        let mut report = PhaseReport::new("tags");
+
+        let existing: HashMap<String, u64> = self
+            .destination
+            .get_target_tags(None)
+            .await?
+            .into_iter()
+            .map(|tag| (tag.name, tag.id))
+            .collect();
+        for tag in self.source.get_target_tags(None).await? {
+            if let Some(id) = existing.get(&tag.name) {
+                report.existing += 1;
+                self.target_tags.insert(tag.id, *id);
+                continue;
+            }
+            let new_tag = NewTag {
+                name: tag.name.clone(),
+                description: tag.description.clone(),
+                colour: tag.colour.clone(),
+            };
+            match self
+                .destination
+                .create_target_tags(&[new_tag])
+                .await
+                .and_then(|created| first(created, "Target tag"))
+            {
+                Ok(created) => {
+                    report.created += 1;
+                    self.target_tags.insert(tag.id, created.id);
+                }
+                Err(e) => report
+                    .failed
+                    .push((format!("target tag {}", tag.name), e.to_string())),
+            }
+        }
+
+        let existing: HashMap<String, u64> = self
+            .destination
+            .get_distribution_set_tags(None)
+            .await?
+            .into_iter()
+            .map(|tag| (tag.name, tag.id))
+            .collect();
+        for tag in self.source.get_distribution_set_tags(None).await? {
+            if let Some(id) = existing.get(&tag.name) {
+                report.existing += 1;
+                self.ds_tags.insert(tag.id, *id);
+                continue;
+            }
+            let new_tag = NewTag {
+                name: tag.name.clone(),
+                description: tag.description.clone(),
+                colour: tag.colour.clone(),
+            };
+            match self
+                .destination
+                .create_distribution_set_tags(&[new_tag])
+                .await
+                .and_then(|created| first(created, "Distribution set tag"))
+            {
+                Ok(created) => {
+                    report.created += 1;
+                    self.ds_tags.insert(tag.id, created.id);
+                }
+                Err(e) => report
+                    .failed
+                    .push((format!("distribution set tag {}", tag.name), e.to_string())),
+            }
+        }
+        Ok(report)
+    }
+
+    async fn migrate_modules(&mut self) -> HawkbitResult<PhaseReport> {
+        let mut report = PhaseReport::new("software modules");
+        let existing: HashMap<(String, String, String), u64> = self
+            .destination
+            .get_software_modules(None)
+            .await?
+            .into_iter()
+            .map(|module| ((module.module_type, module.name, module.version), module.id))
+            .collect();
+
+        for module in self.source.get_software_modules(None).await? {
+            if module.deleted {
+                continue;
+            }
+            let key = format!(
+                "module:{}:{}:{}",
+                module.module_type, module.name, module.version
+            );
+            let natural_key = (
+                module.module_type.clone(),
+                module.name.clone(),
+                module.version.clone(),
+            );
+
+            let id = match existing.get(&natural_key) {
+                Some(id) => *id,
+                None => {
+                    let new_module = NewSoftwareModule {
+                        name: module.name.clone(),
+                        version: module.version.clone(),
+                        module_type: module.module_type.clone(),
+                        description: module.description.clone(),
+                        vendor: module.vendor.clone(),
+                        encrypted: module.encrypted,
+                    };
+                    match self
+                        .destination
+                        .create_software_modules(&[new_module])
+                        .await
+                        .and_then(|created| first(created, "Software module"))
+                    {
+                        Ok(created) => created.id,
+                        Err(e) => {
+                            report.failed.push((key, e.to_string()));
+                            continue;
+                        }
+                    }
+                }
+            };
+            self.modules.insert(module.id, id);
+
+            if existing.contains_key(&natural_key) && self.state.is_completed(&key) {
+                report.resumed += 1;
+                continue;
+            }
+            if let Err(e) = self.copy_module_content(module.id, id).await {
+                report.failed.push((key, e.to_string()));
+                continue;
+            }
+            tracing::info!("Migrated software module {}", key);
+            if existing.contains_key(&natural_key) {
+                report.existing += 1;
+            } else {
+                report.created += 1;
+            }
+            self.state.complete(key)?;
+        }
+        Ok(report)
+    }
+
+    /// Copies the metadata and the artifacts missing on the destination.
+    async fn copy_module_content(&self, source_id: u64, destination_id: u64) -> HawkbitResult<()> {
+        let metadata = missing_metadata(
+            &self
+                .source
+                .get_software_module_metadata(source_id, None)
+                .await?,
+            &self
+                .destination
+                .get_software_module_metadata(destination_id, None)
+                .await?,
+            |entry| entry.key.as_str(),
+        );
+        if !metadata.is_empty() {
+            self.destination
+                .create_software_module_metadata(destination_id, &metadata)
+                .await?;
+        }
+
+        let present: HashSet<String> = self
+            .destination
+            .get_artifacts(destination_id)
+            .await?
+            .into_iter()
+            .map(|artifact| artifact.provided_filename)
+            .collect();
+        for artifact in self.source.get_artifacts(source_id).await? {
+            if present.contains(&artifact.provided_filename) {
+                continue;
+            }
+            tokio::fs::create_dir_all(&self.options.download_dir).await?;
+            // Named by ID, the provided file name may contain path separators
+            let file = self
+                .options
+                .download_dir
+                .join(format!("{}-{}", source_id, artifact.id));
+            tracing::info!(
+                "Copying artifact {:?} ({} bytes)",
+                artifact.provided_filename,
+                artifact.size
+            );
+            self.source
+                .download_artifact(source_id, &artifact, &file)
+                .await?;
+            let uploaded = self
+                .destination
+                .upload_artifact(
+                    destination_id,
+                    &file,
+                    &artifact.provided_filename,
+                    &artifact.hashes,
+                )
+                .await;
+            // A failed upload downloads the artifact again on the next run
+            let removed = tokio::fs::remove_file(&file).await;
+            uploaded?;
+            removed?;
+        }
+        Ok(())
+    }
+
+    async fn migrate_distribution_sets(&mut self) -> HawkbitResult<PhaseReport> {
+        let mut report = PhaseReport::new("distribution sets");
+        let existing: HashMap<(String, String), u64> = self
+            .destination
+            .get_distribution_sets(None)
+            .await?
+            .into_iter()
+            .map(|set| ((set.name, set.version), set.id))
+            .collect();
+
+        for set in self.source.get_distribution_sets(None).await? {
+            if set.deleted {
+                continue;
+            }
+            let key = format!("distribution-set:{}:{}", set.name, set.version);
+            let natural_key = (set.name.clone(), set.version.clone());
+
+            let id = match existing.get(&natural_key) {
+                Some(id) => *id,
+                None => {
+                    let created: HawkbitResult<u64> = async {
+                        let modules = set
+                            .modules
+                            .iter()
+                            .map(|module| mapped(&self.modules, module.id, "Software module"))
+                            .collect::<HawkbitResult<Vec<u64>>>()?;
+                        let new_set = NewDistributionSet {
+                            name: set.name.clone(),
+                            version: set.version.clone(),
+                            description: Some(set.description.clone())
+                                .filter(|description| !description.is_empty()),
+                            ds_type: set.ds_type.clone(),
+                            required_migration_step: set.required_migration_step,
+                            modules,
+                        };
+                        let created = self
+                            .destination
+                            .create_distribution_sets(&[new_set])
+                            .await?;
+                        Ok(first(created, "Distribution set")?.id)
+                    }
+                    .await;
+                    match created {
+                        Ok(id) => id,
+                        Err(e) => {
+                            report.failed.push((key, e.to_string()));
+                            continue;
+                        }
+                    }
+                }
+            };
+            self.distribution_sets.insert(set.id, id);
+
+            if existing.contains_key(&natural_key) && self.state.is_completed(&key) {
+                report.resumed += 1;
+                continue;
+            }
+            let copied: HawkbitResult<()> = async {
+                let metadata = missing_metadata(
+                    &self
+                        .source
+                        .get_distribution_set_metadata(set.id, None)
+                        .await?,
+                    &self
+                        .destination
+                        .get_distribution_set_metadata(id, None)
+                        .await?,
+                    |entry: &MetadataEntry| entry.key.as_str(),
+                );
+                if !metadata.is_empty() {
+                    self.destination
+                        .create_distribution_set_metadata(id, &metadata)
+                        .await?;
+                }
+                Ok(())
+            }
+            .await;
+            if let Err(e) = copied {
+                report.failed.push((key, e.to_string()));
+                continue;
+            }
+            tracing::info!("Migrated distribution set {}", key);
+            if existing.contains_key(&natural_key) {
+                report.existing += 1;
+            } else {
+                report.created += 1;
+            }
+            self.state.complete(key)?;
+        }
+        Ok(report)
+    }
+
+    async fn migrate_targets(&mut self) -> HawkbitResult<PhaseReport> {
+        let mut report = PhaseReport::new("targets");
+        let existing: HashSet<String> = self
+            .destination
+            .get_targets(None)
+            .await?
+            .into_iter()
+            .map(|target| target.controller_id)
+            .collect();
+        self.targets = existing.clone();
+
+        let targets = self
+            .source
+            .get_targets(self.options.target_filter.as_deref())
+            .await?;
+        for target in targets {
+            let key = format!("target:{}", target.controller_id);
+            let controller_id = target.controller_id.clone();
+            if existing.contains(&controller_id) && self.state.is_completed(&key) {
+                report.resumed += 1;
+                continue;
+            }
+
+            let migrated: HawkbitResult<()> = async {
+                // Devices keep authenticating with their current token, so a
+                // target must not be created with a generated one. The list
+                // endpoint omits tokens, only the single target has them.
+                let token = self
+                    .source
+                    .get_target(&controller_id)
+                    .await?
+                    .security_token
+                    .ok_or_else(|| {
+                        HawkbitError::new(
+                            "Security token not readable, the source user needs \
+                             READ_TARGET_SECURITY_TOKEN",
+                        )
+                    })?;
+                if existing.contains(&controller_id) {
+                    let current = self
+                        .destination
+                        .get_target(&controller_id)
+                        .await?
+                        .security_token;
+                    if current.as_deref() != Some(token.as_str()) {
+                        self.destination
+                            .modify_target(
+                                &controller_id,
+                                serde_json::json!({ "securityToken": token }),
+                            )
+                            .await?;
+                    }
+                } else {
+                    let target_type = target
+                        .target_type
+                        .map(|id| mapped(&self.target_types, id as u64, "Target type"))
+                        .transpose()?;
+                    let new_target = NewTarget {
+                        controller_id: controller_id.clone(),
+                        name: target.name.clone().unwrap_or_else(|| controller_id.clone()),
+                        description: target.description.clone(),
+                        security_token: Some(token),
+                        target_type: target_type.map(|id| id as i64),
+                    };
+                    self.destination.create_targets(&[new_target]).await?;
+                }
+
+                let metadata = missing_metadata(
+                    &self
+                        .source
+                        .get_target_metadata(&controller_id, None)
+                        .await?,
+                    &self
+                        .destination
+                        .get_target_metadata(&controller_id, None)
+                        .await?,
+                    |entry: &MetadataEntry| entry.key.as_str(),
+                );
+                if !metadata.is_empty() {
+                    self.destination
+                        .create_target_metadata(&controller_id, &metadata)
+                        .await?;
+                }
+                Ok(())
+            }
+            .await;
+
+            if let Err(e) = migrated {
+                report.failed.push((key, e.to_string()));
+                continue;
+            }
+            if existing.contains(&controller_id) {
+                report.existing += 1;
+            } else {
+                tracing::info!("Created target {:?}", controller_id);
+                report.created += 1;
+            }
+            self.targets.insert(controller_id);
+            self.state.complete(key)?;
+        }
+        Ok(report)
+    }
+
+    /// Assigns the tags like on the source; counts new assignments as created.
+    async fn migrate_tag_assignments(&mut self) -> HawkbitResult<PhaseReport> {
+        let mut report = PhaseReport::new("tag assignments");
+
+        for (source_tag, destination_tag) in &self.target_tags {
+            let assigned: HashSet<String> = self
+                .destination
+                .get_target_tag_assignments(*destination_tag)
+                .await?
+                .into_iter()
+                .map(|target| target.controller_id)
+                .collect();
+            let missing: Vec<String> = self
+                .source
+                .get_target_tag_assignments(*source_tag)
+                .await?
+                .into_iter()
+                .map(|target| target.controller_id)
+                .filter(|id| self.targets.contains(id) && !assigned.contains(id))
+                .collect();
+            report.existing += assigned.len();
+            if missing.is_empty() {
+                continue;
+            }
+            let ids: Vec<&str> = missing.iter().map(String::as_str).collect();
+            match self
+                .destination
+                .assign_target_tag(*destination_tag, &ids)
+                .await
+            {
+                Ok(_) => report.created += missing.len(),
+                Err(e) => report
+                    .failed
+                    .push((format!("target tag {}", destination_tag), e.to_string())),
+            }
+        }
+
+        for (source_tag, destination_tag) in &self.ds_tags {
+            let assigned: HashSet<u64> = self
+                .destination
+                .get_distribution_set_tag_assignments(*destination_tag)
+                .await?
+                .into_iter()
+                .map(|set| set.id)
+                .collect();
+            let missing: Vec<u64> = self
+                .source
+                .get_distribution_set_tag_assignments(*source_tag)
+                .await?
+                .into_iter()
+                .filter_map(|set| self.distribution_sets.get(&set.id).copied())
+                .filter(|id| !assigned.contains(id))
+                .collect();
+            report.existing += assigned.len();
+            if missing.is_empty() {
+                continue;
+            }
+            match self
+                .destination
+                .assign_distribution_set_tag(*destination_tag, &missing)
+                .await
+            {
+                Ok(_) => report.created += missing.len(),
+                Err(e) => report.failed.push((
+                    format!("distribution set tag {}", destination_tag),
+                    e.to_string(),
+                )),
+            }
+        }
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::HawkbitConfig;
+    use axum::Router;
+    use axum::extract::Request;
+    use axum::http::{Method, StatusCode};
+    use axum::response::{IntoResponse, Response};
+    use serde_json::{Value, json};
+    use sha2::{Digest, Sha256};
+
+    const ARTIFACT: &[u8] = b"rootfs image";
+
+    fn temp_path(test: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("hawkbit-migration-{}-{}", test, std::process::id()))
+    }
+
+    fn page(content: Value) -> Response {
+        let size = content.as_array().unwrap().len();
+        axum::Json(json!({ "content": content, "size": size, "total": size })).into_response()
+    }
+
+    fn module_type(id: u64, key: &str) -> Value {
+        json!({ "id": id, "key": key, "name": key.to_uppercase() })
+    }
+
+    fn module(id: u64) -> Value {
+        json!({
+            "_links": {},
+            "createdAt": 0,
+            "createdBy": "admin",
+            "deleted": false,
+            "encrypted": false,
+            "id": id,
+            "lastModifiedAt": 0,
+            "lastModifiedBy": "admin",
+            "name": "rootfs",
+            "type": "os",
+            "typeName": "OS",
+            "version": "1.0",
+        })
+    }
+
+    /// Serves `respond` as the management API and returns a client for it
+    async fn serve(respond: fn(&Method, &str) -> Response) -> HawkbitMgmtClient {
+        let app = Router::new().fallback(async move |request: Request| {
+            respond(request.method(), request.uri().path())
+        });
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let host = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        HawkbitMgmtClient::from_config(&HawkbitConfig::for_tests(&host)).unwrap()
+    }
+
+    fn options(test: &str) -> MigrationOptions {
+        MigrationOptions {
+            target_filter: None,
+            download_dir: temp_path(&format!("{}-downloads", test)),
+        }
+    }
+
+    #[test]
+    fn state_resumes_completed_items() {
+        let path = temp_path("state.jsonl");
+        let mut state = MigrationState::open(&path, "old", "new").unwrap();
+        assert!(state.completed.is_empty());
+        state.complete("module:os:rootfs:1.0".to_string()).unwrap();
+        state.complete("target:dev-1".to_string()).unwrap();
+        drop(state);
+
+        // An interrupted write leaves a cut off line, which is dropped
+        std::fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(b"\"target:dev")
+            .unwrap();
+        let mut state = MigrationState::open(&path, "old", "new").unwrap();
+        assert_eq!(
+            state.completed,
+            BTreeSet::from([
+                "module:os:rootfs:1.0".to_string(),
+                "target:dev-1".to_string()
+            ])
+        );
+        assert!(state.is_completed("target:dev-1"));
+        assert!(!state.is_completed("target:dev"));
+        state.complete("target:dev-2".to_string()).unwrap();
+        drop(state);
+
+        let state = MigrationState::open(&path, "old", "new").unwrap();
+        assert_eq!(state.completed.len(), 3);
+        assert!(state.is_completed("target:dev-2"));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn state_of_another_migration_is_refused() {
+        let path = temp_path("other.jsonl");
+        MigrationState::open(&path, "old", "new").unwrap();
+        assert!(MigrationState::open(&path, "old", "staging").is_err());
+        assert!(MigrationState::open(&path, "staging", "new").is_err());
+
+        std::fs::write(&path, "not json\n").unwrap();
+        assert!(MigrationState::open(&path, "old", "new").is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn module_types_are_matched_by_key() {
+        let source = serve(|_, _| page(json!([module_type(1, "os"), module_type(2, "app")]))).await;
+        let destination = serve(|method, _| {
+            if method == Method::POST {
+                return axum::Json(json!([module_type(20, "app")])).into_response();
+            }
+            page(json!([module_type(10, "os")]))
+        })
+        .await;
+        let path = temp_path("types.jsonl");
+        let mut state = MigrationState::open(&path, "old", "new").unwrap();
+        let options = options("types");
+        let mut migration = Migration::new(&source, &destination, &mut state, &options);
+
+        let report = migration.migrate_module_types().await.unwrap();
+        assert_eq!((report.existing, report.created), (1, 1));
+        assert!(report.failed.is_empty());
+        assert_eq!(migration.module_types, HashMap::from([(1, 10), (2, 20)]));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn failed_upload_removes_the_downloaded_artifact() {
+        let source = serve(|_, path| match path {
+            "/rest/v1/softwaremodules" => page(json!([module(1)])),
+            "/rest/v1/softwaremodules/1/artifacts" => axum::Json(json!([{
+                "createdAt": 0,
+                "createdBy": "admin",
+                "id": 5,
+                "lastModifiedAt": 0,
+                "lastModifiedBy": "admin",
+                "providedFilename": "rootfs.img",
+                "size": ARTIFACT.len(),
+                "hashes": { "sha256": hex::encode(Sha256::digest(ARTIFACT)) },
+            }]))
+            .into_response(),
+            "/rest/v1/softwaremodules/1/artifacts/5/download" => ARTIFACT.into_response(),
+            _ => page(json!([])),
+        })
+        .await;
+        // The same module exists with another ID, uploads fail
+        let destination = serve(|method, path| match (method, path) {
+            (&Method::POST, _) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+            (_, "/rest/v1/softwaremodules") => page(json!([module(7)])),
+            (_, "/rest/v1/softwaremodules/7/artifacts") => axum::Json(json!([])).into_response(),
+            _ => page(json!([])),
+        })
+        .await;
+        let path = temp_path("upload.jsonl");
+        let mut state = MigrationState::open(&path, "old", "new").unwrap();
+        let options = options("upload");
+        let mut migration = Migration::new(&source, &destination, &mut state, &options);
+
+        let report = migration.migrate_modules().await.unwrap();
+        assert_eq!(migration.modules, HashMap::from([(1, 7)]));
+        assert_eq!(report.failed.len(), 1);
+        assert_eq!(report.failed[0].0, "module:os:rootfs:1.0");
+        assert!(!state.is_completed("module:os:rootfs:1.0"));
+        let left: Vec<_> = std::fs::read_dir(&options.download_dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert!(left.is_empty(), "{:?}", left);
+        std::fs::remove_dir_all(&options.download_dir).unwrap();
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,353 @@
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::Path;
+
+use crate::hawkbit::{HawkbitError, HawkbitMgmtClient, HawkbitResult, NewTarget, fiql_quote};
+
+// Column limits of the hawkBit target table
+const CONTROLLER_ID_MAX_LEN: usize = 256;
+const NAME_MAX_LEN: usize = 128;
+const DESCRIPTION_MAX_LEN: usize = 512;
+const SECURITY_TOKEN_MAX_LEN: usize = 128;
+
+/// One device of a provisioning batch, as read from CSV or JSON
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TargetRow {
+    #[serde(rename = "controllerId")]
+    pub controller_id: String,
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(rename = "securityToken", default)]
+    pub security_token: Option<String>,
+    #[serde(rename = "targetType", default)]
+    pub target_type: Option<i64>,
+}
+
+/// Reads targets from a JSON array if the file ends in `.json`, otherwise
+/// from a CSV file with the columns `controllerId,name,description,
+/// securityToken,targetType` (all but `controllerId` optional).
+pub fn read_targets(path: &Path) -> HawkbitResult<Vec<TargetRow>> {
+    let is_json = path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("json"));
+    let rows: Vec<TargetRow> = if is_json {
+        let content = std::fs::read_to_string(path)?;
+        serde_json::from_str(&content)?
+    } else {
+        let mut reader = csv::Reader::from_path(path).map_err(csv_error)?;
+        reader
+            .deserialize()
+            .collect::<Result<_, _>>()
+            .map_err(csv_error)?
+    };
+    Ok(rows.into_iter().map(without_empty_fields).collect())
+}
+
+/// Empty CSV cells and empty JSON strings mean "not set"
+fn without_empty_fields(mut row: TargetRow) -> TargetRow {
+    row.name = row.name.filter(|v| !v.trim().is_empty());
+    row.description = row.description.filter(|v| !v.trim().is_empty());
+    row.security_token = row.security_token.filter(|v| !v.trim().is_empty());
+    row
+}
+
+fn csv_error(err: csv::Error) -> HawkbitError {
+    HawkbitError::new(format!("CSV error: {}", err))
+}
+
+fn check_length(field: &str, value: Option<&str>, max: usize) -> Option<String> {
+    value
+        .filter(|value| value.chars().count() > max)
+        .map(|_| format!("{} is longer than {} characters", field, max))
+}
+
+/// Returns the problems of a single row; empty if the row is valid.
+pub fn validate_row(row: &TargetRow) -> Vec<String> {
+    let mut errors = Vec::new();
+    let controller_id = row.controller_id.as_str();
+    if controller_id.trim().is_empty() {
+        errors.push("controllerId is empty".to_string());
+    }
+    // The controller ID is part of every DDI and management URL and of the
+    // FIQL queries looking up existing targets
+    if controller_id
+        .chars()
+        .any(|c| c.is_whitespace() || matches!(c, '/' | '?' | '#' | '"'))
+    {
+        errors.push("controllerId contains whitespace, '/', '?', '#' or '\"'".to_string());
+    }
+    errors.extend(check_length(
+        "controllerId",
+        Some(controller_id),
+        CONTROLLER_ID_MAX_LEN,
+    ));
+    errors.extend(check_length("name", row.name.as_deref(), NAME_MAX_LEN));
+    errors.extend(check_length(
+        "description",
+        row.description.as_deref(),
+        DESCRIPTION_MAX_LEN,
+    ));
+    errors.extend(check_length(
+        "securityToken",
+        row.security_token.as_deref(),
+        SECURITY_TOKEN_MAX_LEN,
+    ));
+    errors
+}
+
+/// A random 128 bit token, hex encoded
+pub fn generate_security_token() -> String {
+    let mut bytes = [0u8; 16];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    hex::encode(bytes)
+}
+
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportStatus {
+    Created,
+    /// Would be created, only reported by a dry run
+    Planned,
+    /// Already registered in hawkBit, left untouched
+    Exists,
+    Invalid,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ImportResult {
+    #[serde(rename = "controllerId")]
+    pub controller_id: String,
+    pub status: ImportStatus,
+    #[serde(rename = "securityToken")]
+    pub security_token: Option<String>,
+    pub error: Option<String>,
+}
+
+impl ImportResult {
+    fn new(controller_id: &str, status: ImportStatus, error: Option<String>) -> Self {
+        Self {
+            controller_id: controller_id.to_string(),
+            status,
+            security_token: None,
+            error,
+        }
+    }
+}
+
+async fn existing_ids(
+    client: &HawkbitMgmtClient,
+    controller_ids: &[&str],
+) -> HawkbitResult<HashSet<String>> {
+    let quoted: Vec<String> = controller_ids.iter().map(|id| fiql_quote(id)).collect();
+    let filter = format!("controllerId=in=({})", quoted.join(","));
+    Ok(client
+        .get_targets(Some(&filter))
+        .await?
+        .into_iter()
+        .map(|target| target.controller_id)
+        .collect())
+}
+
+/// Registers the rows in batches of `batch_size`. Invalid rows, duplicates
+/// within the file and targets that already exist are skipped; missing
+/// security tokens are generated. With `dry_run` nothing is created. A batch
+/// that fails is reported as failed and the import goes on with the next.
+pub async fn import_targets(
+    client: &HawkbitMgmtClient,
+    rows: &[TargetRow],
+    batch_size: usize,
+    dry_run: bool,
+) -> Vec<ImportResult> {
+    let mut results = Vec::new();
+    let mut seen = HashSet::new();
+    let mut valid = Vec::new();
+    for row in rows {
+        let mut errors = validate_row(row);
+        if !seen.insert(row.controller_id.as_str()) {
+            errors.push("duplicate controllerId in the input".to_string());
+        }
+        if errors.is_empty() {
+            valid.push(row);
+        } else {
+            results.push(ImportResult::new(
+                &row.controller_id,
+                ImportStatus::Invalid,
+                Some(errors.join("; ")),
+            ));
+        }
+    }
+
+    for batch in valid.chunks(batch_size.max(1)) {
+        let ids: Vec<&str> = batch.iter().map(|row| row.controller_id.as_str()).collect();
+        // A failed lookup only fails its batch, the tokens of the batches
+        // already created must still reach the results file
+        let existing = match existing_ids(client, &ids).await {
+            Ok(existing) => existing,
+            Err(e) => {
+                results.extend(batch.iter().map(|row| {
+                    ImportResult::new(
+                        &row.controller_id,
+                        ImportStatus::Failed,
+                        Some(format!("looking up existing targets failed: {}", e)),
+                    )
+                }));
+                continue;
+            }
+        };
+
+        let mut new_targets = Vec::new();
+        for row in batch {
+            if existing.contains(&row.controller_id) {
+                results.push(ImportResult::new(
+                    &row.controller_id,
+                    ImportStatus::Exists,
+                    None,
+                ));
+                continue;
+            }
+            new_targets.push(NewTarget {
+                controller_id: row.controller_id.clone(),
+                name: row
+                    .name
+                    .clone()
+                    .unwrap_or_else(|| row.controller_id.clone()),
+                description: row.description.clone(),
+                security_token: Some(
+                    row.security_token
+                        .clone()
+                        .unwrap_or_else(generate_security_token),
+                ),
+                target_type: row.target_type,
+            });
+        }
+        if new_targets.is_empty() {
+            continue;
+        }
+
+        let outcome = if dry_run {
+            Ok(ImportStatus::Planned)
+        } else {
+            client
+                .create_targets(&new_targets)
+                .await
+                .map(|_| ImportStatus::Created)
+        };
+        for target in new_targets {
+            let (status, error) = match &outcome {
+                Ok(status) => (*status, None),
+                Err(e) => (ImportStatus::Failed, Some(e.to_string())),
+            };
+            results.push(ImportResult {
+                security_token: (status == ImportStatus::Created)
+                    .then_some(target.security_token)
+                    .flatten(),
+                ..ImportResult::new(&target.controller_id, status, error)
+            });
+        }
+    }
+    results
+}
+
+/// Writes the results as CSV, including the tokens of the created targets.
+/// The file is only readable by the current user.
+pub fn write_results(path: &Path, results: &[ImportResult]) -> HawkbitResult<()> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut writer = csv::Writer::from_writer(options.open(path)?);
+    for result in results {
+        writer.serialize(result).map_err(csv_error)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(controller_id: &str) -> TargetRow {
+        TargetRow {
+            controller_id: controller_id.to_string(),
+            name: None,
+            description: None,
+            security_token: None,
+            target_type: None,
+        }
+    }
+
+    fn write_input(name: &str, content: &str) -> std::path::PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("hawkbit-provision-{}-{}", std::process::id(), name));
+        std::fs::write(&path, content).unwrap();
+        path
+    }
+
+    #[test]
+    fn accepts_a_plain_row() {
+        assert!(validate_row(&row("device-0001")).is_empty());
+    }
+
+    #[test]
+    fn rejects_ids_that_break_urls_or_queries() {
+        for id in ["", " ", "a b", "a/b", "a?b", "a#b", "a\"b"] {
+            assert!(!validate_row(&row(id)).is_empty(), "{:?}", id);
+        }
+    }
+
+    #[test]
+    fn rejects_overlong_fields() {
+        let mut long = row(&"x".repeat(CONTROLLER_ID_MAX_LEN));
+        assert!(validate_row(&long).is_empty());
+        long.name = Some("n".repeat(NAME_MAX_LEN + 1));
+        long.security_token = Some("t".repeat(SECURITY_TOKEN_MAX_LEN + 1));
+        let errors = validate_row(&long);
+        assert_eq!(errors.len(), 2, "{:?}", errors);
+        assert!(!validate_row(&row(&"x".repeat(CONTROLLER_ID_MAX_LEN + 1))).is_empty());
+    }
+
+    #[test]
+    fn reads_csv_with_empty_cells() {
+        let path = write_input(
+            "rows.csv",
+            "controllerId,name,description,securityToken,targetType\n\
+             dev-1,Device 1,,,3\n\
+             dev-2,,,secret,\n",
+        );
+        let rows = read_targets(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].name.as_deref(), Some("Device 1"));
+        assert_eq!(rows[0].description, None);
+        assert_eq!(rows[0].security_token, None);
+        assert_eq!(rows[0].target_type, Some(3));
+        assert_eq!(rows[1].name, None);
+        assert_eq!(rows[1].security_token.as_deref(), Some("secret"));
+        assert_eq!(rows[1].target_type, None);
+    }
+
+    #[test]
+    fn reads_json_and_drops_empty_strings() {
+        let path = write_input(
+            "rows.json",
+            r#"[
+                {"controllerId": "dev-1", "name": "", "securityToken": " "},
+                {"controllerId": "dev-2", "description": "lab", "targetType": 4}
+            ]"#,
+        );
+        let rows = read_targets(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].name, None);
+        assert_eq!(rows[0].security_token, None);
+        assert_eq!(rows[1].description.as_deref(), Some("lab"));
+        assert_eq!(rows[1].target_type, Some(4));
+    }
+}
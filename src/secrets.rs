@@ -0,0 +1,332 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::crypto;
+use crate::hawkbit::{HawkbitError, HawkbitResult};
+
+/// A secret value that is never printed by `Debug` or `Display`.
+#[derive(Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Secret(String);
+
+impl Secret {
+    pub fn new(value: impl Into<String>) -> Self {
+        Self(value.into())
+    }
+
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Secret(***)")
+    }
+}
+
+impl fmt::Display for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "***")
+    }
+}
+
+/// Where a secret comes from. In the config file this is either a plain
+/// string or a table such as `{ file = "/run/secrets/password" }`,
+/// `{ command = "pass show hawkbit" }` or `{ vault = "production" }`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum SecretSource {
+    Value(Secret),
+    /// File holding the secret, e.g. a Docker or Kubernetes secret mount
+    File {
+        file: PathBuf,
+    },
+    /// Shell command printing the secret on stdout
+    Command {
+        command: String,
+    },
+    /// Entry of the encrypted local vault
+    Vault {
+        vault: String,
+    },
+}
+
+impl SecretSource {
+    /// Reads the secret from the environment: `NAME` holds the value,
+    /// `NAME_FILE` a path to it and `NAME_COMMAND` a command printing it.
+    pub fn from_env(name: &str) -> Option<Self> {
+        Self::from_vars(name, |name| std::env::var(name).ok())
+    }
+
+    /// Like `from_env`, with the variables looked up by `var`
+    pub fn from_vars(name: &str, var: impl Fn(&str) -> Option<String>) -> Option<Self> {
+        if let Some(value) = var(name) {
+            return Some(SecretSource::Value(Secret::new(value)));
+        }
+        if let Some(path) = var(&format!("{}_FILE", name)) {
+            return Some(SecretSource::File { file: path.into() });
+        }
+        var(&format!("{}_COMMAND", name)).map(|command| SecretSource::Command { command })
+    }
+
+    pub fn resolve(&self, vault: &VaultLocation) -> HawkbitResult<Secret> {
+        match self {
+            SecretSource::Value(secret) => Ok(secret.clone()),
+            SecretSource::File { file } => {
+                let content = std::fs::read_to_string(file).map_err(|e| {
+                    HawkbitError::new(format!(
+                        "Failed to read secret file {}: {}",
+                        file.display(),
+                        e
+                    ))
+                })?;
+                Ok(Secret::new(trim_newline(content)))
+            }
+            SecretSource::Command { command } => run_command(command),
+            SecretSource::Vault { vault: name } => {
+                vault.open()?.get(name).cloned().ok_or_else(|| {
+                    HawkbitError::new(format!(
+                        "No secret {:?} in vault {}",
+                        name,
+                        vault.path.display()
+                    ))
+                })
+            }
+        }
+    }
+}
+
+fn trim_newline(mut value: String) -> String {
+    let len = value.trim_end_matches(['\n', '\r']).len();
+    value.truncate(len);
+    value
+}
+
+fn run_command(command: &str) -> HawkbitResult<Secret> {
+    let output = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stderr(std::process::Stdio::inherit())
+        .output()
+        .map_err(|e| HawkbitError::new(format!("Failed to run {:?}: {}", command, e)))?;
+    if !output.status.success() {
+        return Err(HawkbitError::new(format!(
+            "Secret command {:?} failed with {}",
+            command, output.status
+        )));
+    }
+    let value = String::from_utf8(output.stdout).map_err(|_| {
+        HawkbitError::new(format!(
+            "Secret command {:?} printed invalid UTF-8",
+            command
+        ))
+    })?;
+    Ok(Secret::new(trim_newline(value)))
+}
+
+/// Path of the vault and the variable holding its passphrase
+#[derive(Debug, Clone)]
+pub struct VaultLocation {
+    pub path: PathBuf,
+    pub passphrase: Option<SecretSource>,
+}
+
+impl VaultLocation {
+    /// `$HAWKBIT_VAULT`, else `~/.config/hawkbit/vault.enc`. The passphrase is
+    /// read from `HAWKBIT_VAULT_PASSPHRASE` (or its `_FILE`/`_COMMAND` forms).
+    pub fn from_env(path: Option<PathBuf>) -> Self {
+        let path = path
+            .or_else(|| std::env::var("HAWKBIT_VAULT").ok().map(PathBuf::from))
+            .unwrap_or_else(|| {
+                let home = std::env::var("HOME").unwrap_or_default();
+                Path::new(&home).join(".config/hawkbit/vault.enc")
+            });
+        Self {
+            path,
+            passphrase: SecretSource::from_env("HAWKBIT_VAULT_PASSPHRASE"),
+        }
+    }
+
+    fn passphrase(&self) -> HawkbitResult<Secret> {
+        let source = self.passphrase.as_ref().ok_or_else(|| {
+            HawkbitError::new("Set HAWKBIT_VAULT_PASSPHRASE to open the secret vault")
+        })?;
+        match source {
+            // A passphrase stored in the vault itself cannot be resolved
+            SecretSource::Vault { .. } => Err(HawkbitError::new(
+                "The vault passphrase cannot come from the vault",
+            )),
+            source => source.resolve(self),
+        }
+    }
+
+    /// Opens the vault, starting empty if the file does not exist yet.
+    pub fn open(&self) -> HawkbitResult<Vault> {
+        let passphrase = self.passphrase()?;
+        let secrets = if self.path.exists() {
+            serde_json::from_slice(&crypto::read_encrypted(&self.path, passphrase.expose())?)?
+        } else {
+            BTreeMap::new()
+        };
+        Ok(Vault {
+            path: self.path.clone(),
+            passphrase,
+            secrets,
+        })
+    }
+}
+
+/// Named secrets in a passphrase encrypted file
+pub struct Vault {
+    path: PathBuf,
+    passphrase: Secret,
+    secrets: BTreeMap<String, Secret>,
+}
+
+impl Vault {
+    pub fn get(&self, name: &str) -> Option<&Secret> {
+        self.secrets.get(name)
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &String> {
+        self.secrets.keys()
+    }
+
+    pub fn set(&mut self, name: &str, secret: Secret) {
+        self.secrets.insert(name.to_string(), secret);
+    }
+
+    pub fn remove(&mut self, name: &str) -> bool {
+        self.secrets.remove(name).is_some()
+    }
+
+    pub fn save(&self) -> HawkbitResult<()> {
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        crypto::write_encrypted(
+            &self.path,
+            self.passphrase.expose(),
+            &serde_json::to_vec(&self.secrets)?,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Deserialize)]
+    struct Holder {
+        secret: SecretSource,
+    }
+
+    fn parse(toml: &str) -> SecretSource {
+        toml::from_str::<Holder>(toml).unwrap().secret
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("hawkbit-secrets-{}-{}", std::process::id(), name))
+    }
+
+    fn no_vault() -> VaultLocation {
+        VaultLocation {
+            path: temp_path("missing-vault.enc"),
+            passphrase: None,
+        }
+    }
+
+    #[test]
+    fn deserializes_every_source() {
+        assert!(matches!(
+            parse(r#"secret = "plain""#),
+            SecretSource::Value(secret) if secret.expose() == "plain"
+        ));
+        assert!(matches!(
+            parse(r#"secret = { file = "/run/secrets/token" }"#),
+            SecretSource::File { file } if file == Path::new("/run/secrets/token")
+        ));
+        assert!(matches!(
+            parse(r#"secret = { command = "pass show hawkbit" }"#),
+            SecretSource::Command { command } if command == "pass show hawkbit"
+        ));
+        assert!(matches!(
+            parse(r#"secret = { vault = "production" }"#),
+            SecretSource::Vault { vault } if vault == "production"
+        ));
+        assert!(toml::from_str::<Holder>(r#"secret = { env = "X" }"#).is_err());
+    }
+
+    #[test]
+    fn secrets_are_redacted() {
+        let secret = Secret::new("hunter2");
+        assert_eq!(format!("{:?}", secret), "Secret(***)");
+        assert_eq!(secret.to_string(), "***");
+        let source = SecretSource::Value(secret);
+        assert!(!format!("{:?}", source).contains("hunter2"));
+    }
+
+    #[test]
+    fn resolves_files_and_commands_without_trailing_newline() {
+        let path = temp_path("token");
+        std::fs::write(&path, "from-file\n").unwrap();
+        let file = SecretSource::File { file: path.clone() };
+        assert_eq!(file.resolve(&no_vault()).unwrap().expose(), "from-file");
+        std::fs::remove_file(&path).unwrap();
+
+        let command = SecretSource::Command {
+            command: "printf 'from-command\\r\\n'".to_string(),
+        };
+        assert_eq!(
+            command.resolve(&no_vault()).unwrap().expose(),
+            "from-command"
+        );
+        let failing = SecretSource::Command {
+            command: "exit 3".to_string(),
+        };
+        assert!(failing.resolve(&no_vault()).is_err());
+    }
+
+    #[test]
+    fn vault_round_trip() {
+        let path = temp_path("vault.enc");
+        let location = |passphrase: &str| VaultLocation {
+            path: path.clone(),
+            passphrase: Some(SecretSource::Value(Secret::new(passphrase))),
+        };
+
+        let mut vault = location("pass").open().unwrap();
+        assert_eq!(vault.names().count(), 0);
+        vault.set("production", Secret::new("gateway-token"));
+        vault.save().unwrap();
+
+        let source = SecretSource::Vault {
+            vault: "production".to_string(),
+        };
+        assert_eq!(
+            source.resolve(&location("pass")).unwrap().expose(),
+            "gateway-token"
+        );
+        let missing = SecretSource::Vault {
+            vault: "staging".to_string(),
+        };
+        assert!(missing.resolve(&location("pass")).is_err());
+        assert!(location("wrong").open().is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn vault_passphrase_cannot_come_from_the_vault() {
+        let location = VaultLocation {
+            path: temp_path("self-vault.enc"),
+            passphrase: Some(SecretSource::Vault {
+                vault: "passphrase".to_string(),
+            }),
+        };
+        assert!(location.open().is_err());
+    }
+}
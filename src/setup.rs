@@ -0,0 +1,1727 @@
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+
+use crate::hawkbit::{
+    ActionType, HawkbitError, HawkbitMgmtClient, HawkbitResult, MetadataEntry, NewDistributionSet,
+    NewDistributionSetType, NewRollout, NewSoftwareModule, NewSoftwareModuleType, NewTag,
+    NewTargetType, RolloutCondition,
+};
+
+// One file per section, so changes show up in small diffs
+const SOFTWARE_MODULE_TYPES_FILE: &str = "software-module-types.yaml";
+const DISTRIBUTION_SET_TYPES_FILE: &str = "distribution-set-types.yaml";
+const TARGET_TYPES_FILE: &str = "target-types.yaml";
+const TAGS_FILE: &str = "tags.yaml";
+const SOFTWARE_MODULES_FILE: &str = "software-modules.yaml";
+const DISTRIBUTION_SETS_FILE: &str = "distribution-sets.yaml";
+const TARGET_FILTERS_FILE: &str = "target-filters.yaml";
+const ROLLOUTS_FILE: &str = "rollouts.yaml";
+const SYSTEM_CONFIG_FILE: &str = "system-config.yaml";
+
+/// System configuration keys holding credentials. They are neither exported
+/// to the setup files, which are meant to be committed, nor applied from them.
+const SECRET_SYSTEM_CONFIGS: &[&str] = &["authentication.gatewaytoken.key"];
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SoftwareModuleTypeSpec {
+    pub key: String,
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub colour: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_assignments: Option<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DistributionSetTypeSpec {
+    pub key: String,
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub colour: Option<String>,
+    /// Keys of the software module types
+    #[serde(default)]
+    pub mandatory_modules: BTreeSet<String>,
+    #[serde(default)]
+    pub optional_modules: BTreeSet<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TargetTypeSpec {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub colour: Option<String>,
+    /// Keys of the distribution set types
+    #[serde(default)]
+    pub compatible_distribution_set_types: BTreeSet<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TagSpec {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub colour: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TagsSpec {
+    #[serde(default)]
+    pub target_tags: Vec<TagSpec>,
+    #[serde(default)]
+    pub distribution_set_tags: Vec<TagSpec>,
+}
+
+/// Software module without its artifacts
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SoftwareModuleSpec {
+    #[serde(rename = "type")]
+    pub module_type: String,
+    pub name: String,
+    pub version: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vendor: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ModuleRef {
+    #[serde(rename = "type")]
+    pub module_type: String,
+    pub name: String,
+    pub version: String,
+}
+
+impl fmt::Display for ModuleRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {}", self.module_type, self.name, self.version)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DistributionSetRef {
+    pub name: String,
+    pub version: String,
+}
+
+impl fmt::Display for DistributionSetRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.name, self.version)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DistributionSetSpec {
+    pub name: String,
+    pub version: String,
+    #[serde(rename = "type")]
+    pub ds_type: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub required_migration_step: bool,
+    #[serde(default)]
+    pub modules: BTreeSet<ModuleRef>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub metadata: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub tags: BTreeSet<String>,
+}
+
+impl DistributionSetSpec {
+    fn reference(&self) -> DistributionSetRef {
+        DistributionSetRef {
+            name: self.name.clone(),
+            version: self.version.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AutoAssignSpec {
+    pub distribution_set: DistributionSetRef,
+    #[serde(default)]
+    pub action_type: ActionType,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub weight: Option<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TargetFilterSpec {
+    pub name: String,
+    pub query: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auto_assign: Option<AutoAssignSpec>,
+}
+
+/// Rollouts are only created; once a rollout exists it is left alone.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RolloutSpec {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    pub distribution_set: DistributionSetRef,
+    pub target_filter_query: String,
+    /// Number of rollout groups
+    pub groups: u32,
+    #[serde(default)]
+    pub action_type: ActionType,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub weight: Option<u32>,
+    /// Percent of a group that must finish before the next group starts
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub success_threshold: Option<u32>,
+    /// Percent of failed targets in a group that pauses the rollout
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_threshold: Option<u32>,
+}
+
+/// The management-side configuration of a tenant, stored as a directory of
+/// YAML files. A missing file counts as an empty section.
+#[derive(Debug, Default, PartialEq)]
+pub struct Setup {
+    pub software_module_types: Vec<SoftwareModuleTypeSpec>,
+    pub distribution_set_types: Vec<DistributionSetTypeSpec>,
+    pub target_types: Vec<TargetTypeSpec>,
+    pub tags: TagsSpec,
+    pub software_modules: Vec<SoftwareModuleSpec>,
+    pub distribution_sets: Vec<DistributionSetSpec>,
+    pub target_filters: Vec<TargetFilterSpec>,
+    pub rollouts: Vec<RolloutSpec>,
+    /// Tenant settings that differ from the system defaults
+    pub system_config: BTreeMap<String, Value>,
+}
+
+fn yaml_error(path: &Path, err: serde_norway::Error) -> HawkbitError {
+    HawkbitError::new(format!("Invalid YAML in {}: {}", path.display(), err))
+}
+
+fn read_yaml<T: DeserializeOwned + Default>(dir: &Path, name: &str) -> HawkbitResult<T> {
+    let path = dir.join(name);
+    if !path.exists() {
+        return Ok(T::default());
+    }
+    let content = std::fs::read_to_string(&path)?;
+    serde_norway::from_str(&content).map_err(|e| yaml_error(&path, e))
+}
+
+fn write_yaml<T: Serialize>(dir: &Path, name: &str, value: &T) -> HawkbitResult<PathBuf> {
+    let path = dir.join(name);
+    let content = serde_norway::to_string(value).map_err(|e| yaml_error(&path, e))?;
+    std::fs::write(&path, content)?;
+    Ok(path)
+}
+
+/// hawkBit reports unset descriptions as empty strings
+fn non_empty(value: Option<String>) -> Option<String> {
+    value.filter(|value| !value.is_empty())
+}
+
+impl Setup {
+    pub fn read(dir: &Path) -> HawkbitResult<Self> {
+        if !dir.is_dir() {
+            return Err(HawkbitError::new(format!(
+                "{} is not a directory",
+                dir.display()
+            )));
+        }
+        Ok(Self {
+            software_module_types: read_yaml(dir, SOFTWARE_MODULE_TYPES_FILE)?,
+            distribution_set_types: read_yaml(dir, DISTRIBUTION_SET_TYPES_FILE)?,
+            target_types: read_yaml(dir, TARGET_TYPES_FILE)?,
+            tags: read_yaml(dir, TAGS_FILE)?,
+            software_modules: read_yaml(dir, SOFTWARE_MODULES_FILE)?,
+            distribution_sets: read_yaml(dir, DISTRIBUTION_SETS_FILE)?,
+            target_filters: read_yaml(dir, TARGET_FILTERS_FILE)?,
+            rollouts: read_yaml(dir, ROLLOUTS_FILE)?,
+            system_config: read_yaml(dir, SYSTEM_CONFIG_FILE)?,
+        })
+    }
+
+    /// Writes every section to its file and returns the written paths.
+    pub fn write(&self, dir: &Path) -> HawkbitResult<Vec<PathBuf>> {
+        std::fs::create_dir_all(dir)?;
+        Ok(vec![
+            write_yaml(dir, SOFTWARE_MODULE_TYPES_FILE, &self.software_module_types)?,
+            write_yaml(
+                dir,
+                DISTRIBUTION_SET_TYPES_FILE,
+                &self.distribution_set_types,
+            )?,
+            write_yaml(dir, TARGET_TYPES_FILE, &self.target_types)?,
+            write_yaml(dir, TAGS_FILE, &self.tags)?,
+            write_yaml(dir, SOFTWARE_MODULES_FILE, &self.software_modules)?,
+            write_yaml(dir, DISTRIBUTION_SETS_FILE, &self.distribution_sets)?,
+            write_yaml(dir, TARGET_FILTERS_FILE, &self.target_filters)?,
+            write_yaml(dir, ROLLOUTS_FILE, &self.rollouts)?,
+            write_yaml(dir, SYSTEM_CONFIG_FILE, &self.system_config)?,
+        ])
+    }
+
+    /// Reads the configuration from the server. Entries are sorted so that
+    /// repeated exports of an unchanged server produce identical files.
+    pub async fn export(client: &HawkbitMgmtClient) -> HawkbitResult<Self> {
+        let mut setup = Setup::default();
+
+        for module_type in client.get_software_module_types(None).await? {
+            if module_type.deleted {
+                continue;
+            }
+            setup.software_module_types.push(SoftwareModuleTypeSpec {
+                key: module_type.key,
+                name: module_type.name,
+                description: non_empty(module_type.description),
+                colour: module_type.colour,
+                max_assignments: module_type.max_assignments,
+            });
+        }
+        setup
+            .software_module_types
+            .sort_by(|a, b| a.key.cmp(&b.key));
+
+        for ds_type in client.get_distribution_set_types(None).await? {
+            if ds_type.deleted {
+                continue;
+            }
+            let keys = |types: Vec<crate::hawkbit::SoftwareModuleType>| {
+                types
+                    .into_iter()
+                    .map(|module_type| module_type.key)
+                    .collect()
+            };
+            setup.distribution_set_types.push(DistributionSetTypeSpec {
+                mandatory_modules: keys(client.get_mandatory_module_types(ds_type.id).await?),
+                optional_modules: keys(client.get_optional_module_types(ds_type.id).await?),
+                key: ds_type.key,
+                name: ds_type.name,
+                description: non_empty(ds_type.description),
+                colour: ds_type.colour,
+            });
+        }
+        setup
+            .distribution_set_types
+            .sort_by(|a, b| a.key.cmp(&b.key));
+
+        for target_type in client.get_target_types(None).await? {
+            if target_type.deleted {
+                continue;
+            }
+            setup.target_types.push(TargetTypeSpec {
+                compatible_distribution_set_types: client
+                    .get_compatible_distribution_set_types(target_type.id)
+                    .await?
+                    .into_iter()
+                    .map(|ds_type| ds_type.key)
+                    .collect(),
+                name: target_type.name,
+                key: target_type.key,
+                description: non_empty(target_type.description),
+                colour: target_type.colour,
+            });
+        }
+        setup.target_types.sort_by(|a, b| a.name.cmp(&b.name));
+
+        let tag_spec = |tag: crate::hawkbit::TargetTag| TagSpec {
+            name: tag.name,
+            description: non_empty(tag.description),
+            colour: tag.colour,
+        };
+        setup.tags.target_tags = client
+            .get_target_tags(None)
+            .await?
+            .into_iter()
+            .map(tag_spec)
+            .collect();
+        setup.tags.target_tags.sort_by(|a, b| a.name.cmp(&b.name));
+
+        // Tags of distribution sets are only listed per tag
+        let mut ds_tags: HashMap<u64, BTreeSet<String>> = HashMap::new();
+        for tag in client.get_distribution_set_tags(None).await? {
+            for set in client.get_distribution_set_tag_assignments(tag.id).await? {
+                ds_tags.entry(set.id).or_default().insert(tag.name.clone());
+            }
+            setup.tags.distribution_set_tags.push(tag_spec(tag));
+        }
+        setup
+            .tags
+            .distribution_set_tags
+            .sort_by(|a, b| a.name.cmp(&b.name));
+
+        for module in client.get_software_modules(None).await? {
+            if module.deleted {
+                continue;
+            }
+            setup.software_modules.push(SoftwareModuleSpec {
+                module_type: module.module_type,
+                name: module.name,
+                version: module.version,
+                vendor: non_empty(module.vendor),
+                description: non_empty(module.description),
+            });
+        }
+        setup.software_modules.sort_by(|a, b| {
+            (&a.module_type, &a.name, &a.version).cmp(&(&b.module_type, &b.name, &b.version))
+        });
+
+        let mut set_refs = HashMap::new();
+        for set in client.get_distribution_sets(None).await? {
+            if set.deleted {
+                continue;
+            }
+            let metadata = client
+                .get_distribution_set_metadata(set.id, None)
+                .await?
+                .into_iter()
+                .map(|entry| (entry.key, entry.value))
+                .collect();
+            let spec = DistributionSetSpec {
+                modules: set
+                    .modules
+                    .into_iter()
+                    .map(|module| ModuleRef {
+                        module_type: module.module_type,
+                        name: module.name,
+                        version: module.version,
+                    })
+                    .collect(),
+                tags: ds_tags.remove(&set.id).unwrap_or_default(),
+                metadata,
+                name: set.name,
+                version: set.version,
+                ds_type: set.ds_type,
+                description: non_empty(Some(set.description)),
+                required_migration_step: set.required_migration_step,
+            };
+            set_refs.insert(set.id, spec.reference());
+            setup.distribution_sets.push(spec);
+        }
+        setup
+            .distribution_sets
+            .sort_by(|a, b| (&a.name, &a.version).cmp(&(&b.name, &b.version)));
+
+        for filter in client.get_target_filters(None).await? {
+            let auto_assign = match filter.auto_assign_distribution_set {
+                Some(id) => match set_refs.get(&id) {
+                    Some(set) => Some(AutoAssignSpec {
+                        distribution_set: set.clone(),
+                        action_type: filter.auto_assign_action_type.unwrap_or_default(),
+                        weight: filter.auto_assign_weight,
+                    }),
+                    None => {
+                        tracing::warn!(
+                            "Target filter {:?} auto-assigns unknown distribution set {}",
+                            filter.name,
+                            id
+                        );
+                        None
+                    }
+                },
+                None => None,
+            };
+            setup.target_filters.push(TargetFilterSpec {
+                name: filter.name,
+                query: filter.query,
+                auto_assign,
+            });
+        }
+        setup.target_filters.sort_by(|a, b| a.name.cmp(&b.name));
+
+        for rollout in client.get_rollouts(None).await? {
+            if rollout.status == "deleted" || rollout.status == "deleting" {
+                continue;
+            }
+            let Some(set) = set_refs.get(&rollout.distribution_set_id) else {
+                tracing::warn!(
+                    "Rollout {:?} uses unknown distribution set {}",
+                    rollout.name,
+                    rollout.distribution_set_id
+                );
+                continue;
+            };
+            // Rollouts are created with the same conditions for every group
+            let groups = client.get_rollout_groups(rollout.id).await?;
+            let first = groups.first();
+            setup.rollouts.push(RolloutSpec {
+                success_threshold: first
+                    .and_then(|group| group.success_condition.as_ref())
+                    .and_then(RolloutCondition::percent),
+                error_threshold: first
+                    .and_then(|group| group.error_condition.as_ref())
+                    .and_then(RolloutCondition::percent),
+                name: rollout.name,
+                description: non_empty(rollout.description),
+                distribution_set: set.clone(),
+                target_filter_query: rollout.target_filter_query,
+                groups: rollout.total_groups.unwrap_or(1),
+                action_type: rollout.action_type.unwrap_or_default(),
+                weight: rollout.weight,
+            });
+        }
+        setup.rollouts.sort_by(|a, b| a.name.cmp(&b.name));
+
+        setup.system_config = client
+            .get_system_configs()
+            .await?
+            .into_iter()
+            .filter(|(key, config)| {
+                !config.global && !SECRET_SYSTEM_CONFIGS.contains(&key.as_str())
+            })
+            .map(|(key, config)| (key, config.value))
+            .collect();
+
+        Ok(setup)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeKind {
+    Create,
+    Update,
+    /// The server differs in a way that cannot be changed through the API
+    Drift,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Change {
+    pub kind: ChangeKind,
+    pub entity: String,
+    pub detail: String,
+    pub error: Option<String>,
+}
+
+/// Looks up the ID of a referenced entity. `Ok(None)` means it is only
+/// planned, which happens in dry runs.
+fn lookup<K, Q>(ids: &HashMap<K, Option<u64>>, key: &Q, what: &str) -> HawkbitResult<Option<u64>>
+where
+    K: std::borrow::Borrow<Q> + std::hash::Hash + Eq,
+    Q: std::hash::Hash + Eq + fmt::Display + ?Sized,
+{
+    ids.get(key)
+        .copied()
+        .ok_or_else(|| HawkbitError::new(format!("Unknown {} {}", what, key)))
+}
+
+/// Unwraps looked up IDs once the run is no dry run.
+fn created(ids: Vec<Option<u64>>) -> HawkbitResult<Vec<u64>> {
+    ids.into_iter()
+        .map(|id| id.ok_or_else(|| HawkbitError::new("Referenced entity was not created")))
+        .collect()
+}
+
+fn differs(spec: &Option<String>, live: &Option<String>) -> bool {
+    non_empty(spec.clone()) != non_empty(live.clone())
+}
+
+struct Applier<'a> {
+    client: &'a HawkbitMgmtClient,
+    dry_run: bool,
+    changes: Vec<Change>,
+}
+
+impl Applier<'_> {
+    /// Records a change and runs `op` unless this is a dry run.
+    async fn change<T>(
+        &mut self,
+        kind: ChangeKind,
+        entity: String,
+        detail: String,
+        op: impl Future<Output = HawkbitResult<T>>,
+    ) -> Option<T> {
+        if self.dry_run {
+            self.changes.push(Change {
+                kind,
+                entity,
+                detail,
+                error: None,
+            });
+            return None;
+        }
+        let (value, error) = match op.await {
+            Ok(value) => (Some(value), None),
+            Err(e) => (None, Some(e.to_string())),
+        };
+        self.changes.push(Change {
+            kind,
+            entity,
+            detail,
+            error,
+        });
+        value
+    }
+
+    fn drift(&mut self, entity: String, detail: String) {
+        self.changes.push(Change {
+            kind: ChangeKind::Drift,
+            entity,
+            detail,
+            error: None,
+        });
+    }
+
+    fn failed(&mut self, kind: ChangeKind, entity: String, error: HawkbitError) {
+        self.changes.push(Change {
+            kind,
+            entity,
+            detail: String::new(),
+            error: Some(error.to_string()),
+        });
+    }
+
+    /// Remembers the ID of a created entity, or a placeholder in dry runs.
+    fn created<K: std::hash::Hash + Eq>(
+        &self,
+        ids: &mut HashMap<K, Option<u64>>,
+        key: K,
+        id: Option<u64>,
+    ) {
+        if id.is_some() || self.dry_run {
+            ids.insert(key, id);
+        }
+    }
+}
+
+/// Compares the setup with the server and creates or updates what differs.
+/// Entities that exist only on the server are left alone.
+pub async fn apply(
+    client: &HawkbitMgmtClient,
+    setup: &Setup,
+    dry_run: bool,
+) -> HawkbitResult<Vec<Change>> {
+    let mut applier = Applier {
+        client,
+        dry_run,
+        changes: Vec::new(),
+    };
+    let module_types = apply_module_types(&mut applier, setup).await?;
+    let ds_types = apply_ds_types(&mut applier, setup, &module_types).await?;
+    apply_target_types(&mut applier, setup, &ds_types).await?;
+    let ds_tags = apply_tags(&mut applier, setup).await?;
+    let modules = apply_modules(&mut applier, setup).await?;
+    let sets = apply_distribution_sets(&mut applier, setup, &modules, &ds_tags).await?;
+    apply_target_filters(&mut applier, setup, &sets).await?;
+    apply_rollouts(&mut applier, setup, &sets).await?;
+    apply_system_config(&mut applier, setup).await?;
+    Ok(applier.changes)
+}
+
+async fn apply_module_types(
+    applier: &mut Applier<'_>,
+    setup: &Setup,
+) -> HawkbitResult<HashMap<String, Option<u64>>> {
+    let client = applier.client;
+    let live: HashMap<String, _> = client
+        .get_software_module_types(None)
+        .await?
+        .into_iter()
+        .filter(|module_type| !module_type.deleted)
+        .map(|module_type| (module_type.key.clone(), module_type))
+        .collect();
+    let mut ids: HashMap<String, Option<u64>> = live
+        .iter()
+        .map(|(key, module_type)| (key.clone(), Some(module_type.id)))
+        .collect();
+
+    for spec in &setup.software_module_types {
+        let entity = format!("software module type {}", spec.key);
+        let Some(current) = live.get(&spec.key) else {
+            let new_type = NewSoftwareModuleType {
+                key: spec.key.clone(),
+                name: spec.name.clone(),
+                description: spec.description.clone(),
+                colour: spec.colour.clone(),
+                max_assignments: spec.max_assignments,
+            };
+            let created = applier
+                .change(ChangeKind::Create, entity, String::new(), async {
+                    Ok(client
+                        .create_software_module_types(&[new_type])
+                        .await?
+                        .into_iter()
+                        .next()
+                        .map(|created| created.id))
+                })
+                .await
+                .flatten();
+            applier.created(&mut ids, spec.key.clone(), created);
+            continue;
+        };
+
+        if spec.name != current.name {
+            applier.drift(entity.clone(), format!("name is {:?}", current.name));
+        }
+        if spec.max_assignments.is_some() && spec.max_assignments != current.max_assignments {
+            applier.drift(
+                entity.clone(),
+                format!("max_assignments is {:?}", current.max_assignments),
+            );
+        }
+        if differs(&spec.description, &current.description) || spec.colour != current.colour {
+            applier
+                .change(
+                    ChangeKind::Update,
+                    entity,
+                    "description and colour".to_string(),
+                    client.update_software_module_type(
+                        current.id,
+                        spec.description.as_deref(),
+                        spec.colour.as_deref(),
+                    ),
+                )
+                .await;
+        }
+    }
+    Ok(ids)
+}
+
+async fn apply_ds_types(
+    applier: &mut Applier<'_>,
+    setup: &Setup,
+    module_types: &HashMap<String, Option<u64>>,
+) -> HawkbitResult<HashMap<String, Option<u64>>> {
+    let client = applier.client;
+    let live: HashMap<String, _> = client
+        .get_distribution_set_types(None)
+        .await?
+        .into_iter()
+        .filter(|ds_type| !ds_type.deleted)
+        .map(|ds_type| (ds_type.key.clone(), ds_type))
+        .collect();
+    let mut ids: HashMap<String, Option<u64>> = live
+        .iter()
+        .map(|(key, ds_type)| (key.clone(), Some(ds_type.id)))
+        .collect();
+
+    for spec in &setup.distribution_set_types {
+        let entity = format!("distribution set type {}", spec.key);
+        let Some(current) = live.get(&spec.key) else {
+            let resolve = |keys: &BTreeSet<String>| {
+                keys.iter()
+                    .map(|key| lookup(module_types, key.as_str(), "software module type"))
+                    .collect::<HawkbitResult<Vec<_>>>()
+            };
+            let (mandatory, optional) = match resolve(&spec.mandatory_modules)
+                .and_then(|m| Ok((m, resolve(&spec.optional_modules)?)))
+            {
+                Ok(refs) => refs,
+                Err(e) => {
+                    applier.failed(ChangeKind::Create, entity, e);
+                    continue;
+                }
+            };
+            let created = applier
+                .change(ChangeKind::Create, entity, String::new(), async {
+                    let new_type = NewDistributionSetType {
+                        key: spec.key.clone(),
+                        name: spec.name.clone(),
+                        description: spec.description.clone(),
+                        colour: spec.colour.clone(),
+                        mandatory_modules: created(mandatory)?,
+                        optional_modules: created(optional)?,
+                    };
+                    Ok(client
+                        .create_distribution_set_types(&[new_type])
+                        .await?
+                        .into_iter()
+                        .next()
+                        .map(|created| created.id))
+                })
+                .await
+                .flatten();
+            applier.created(&mut ids, spec.key.clone(), created);
+            continue;
+        };
+
+        if spec.name != current.name {
+            applier.drift(entity.clone(), format!("name is {:?}", current.name));
+        }
+        let keys = |types: Vec<crate::hawkbit::SoftwareModuleType>| -> BTreeSet<String> {
+            types
+                .into_iter()
+                .map(|module_type| module_type.key)
+                .collect()
+        };
+        let mandatory = keys(client.get_mandatory_module_types(current.id).await?);
+        let optional = keys(client.get_optional_module_types(current.id).await?);
+        if mandatory != spec.mandatory_modules || optional != spec.optional_modules {
+            applier.drift(
+                entity.clone(),
+                format!(
+                    "module types are {:?} mandatory, {:?} optional",
+                    mandatory, optional
+                ),
+            );
+        }
+        if differs(&spec.description, &current.description) || spec.colour != current.colour {
+            applier
+                .change(
+                    ChangeKind::Update,
+                    entity,
+                    "description and colour".to_string(),
+                    client.update_distribution_set_type(
+                        current.id,
+                        spec.description.as_deref(),
+                        spec.colour.as_deref(),
+                    ),
+                )
+                .await;
+        }
+    }
+    Ok(ids)
+}
+
+async fn apply_target_types(
+    applier: &mut Applier<'_>,
+    setup: &Setup,
+    ds_types: &HashMap<String, Option<u64>>,
+) -> HawkbitResult<()> {
+    let client = applier.client;
+    let live: HashMap<String, _> = client
+        .get_target_types(None)
+        .await?
+        .into_iter()
+        .filter(|target_type| !target_type.deleted)
+        .map(|target_type| (target_type.name.clone(), target_type))
+        .collect();
+
+    for spec in &setup.target_types {
+        let entity = format!("target type {}", spec.name);
+        let compatible = match spec
+            .compatible_distribution_set_types
+            .iter()
+            .map(|key| lookup(ds_types, key.as_str(), "distribution set type"))
+            .collect::<HawkbitResult<Vec<_>>>()
+        {
+            Ok(compatible) => compatible,
+            Err(e) => {
+                applier.failed(ChangeKind::Create, entity, e);
+                continue;
+            }
+        };
+
+        let Some(current) = live.get(&spec.name) else {
+            applier
+                .change(ChangeKind::Create, entity, String::new(), async {
+                    let new_type = NewTargetType {
+                        name: spec.name.clone(),
+                        key: spec.key.clone(),
+                        description: spec.description.clone(),
+                        colour: spec.colour.clone(),
+                        compatible_distribution_set_types: created(compatible)?,
+                    };
+                    client.create_target_types(&[new_type]).await
+                })
+                .await;
+            continue;
+        };
+
+        if spec.key.is_some() && spec.key != current.key {
+            applier.drift(entity.clone(), format!("key is {:?}", current.key));
+        }
+        if differs(&spec.description, &current.description) || spec.colour != current.colour {
+            applier
+                .change(
+                    ChangeKind::Update,
+                    entity.clone(),
+                    "description and colour".to_string(),
+                    client.update_target_type(
+                        current.id,
+                        None,
+                        spec.description.as_deref(),
+                        spec.colour.as_deref(),
+                    ),
+                )
+                .await;
+        }
+
+        let live_compatible: HashMap<String, u64> = client
+            .get_compatible_distribution_set_types(current.id)
+            .await?
+            .into_iter()
+            .map(|ds_type| (ds_type.key, ds_type.id))
+            .collect();
+        let added: Vec<(&String, Option<u64>)> = spec
+            .compatible_distribution_set_types
+            .iter()
+            .zip(compatible)
+            .filter(|(key, _)| !live_compatible.contains_key(*key))
+            .collect();
+        if !added.is_empty() {
+            let keys: Vec<&String> = added.iter().map(|(key, _)| *key).collect();
+            let ids: Vec<Option<u64>> = added.iter().map(|(_, id)| *id).collect();
+            applier
+                .change(
+                    ChangeKind::Update,
+                    entity.clone(),
+                    format!("add compatible distribution set types {:?}", keys),
+                    async {
+                        client
+                            .add_compatible_distribution_set_types(current.id, &created(ids)?)
+                            .await
+                    },
+                )
+                .await;
+        }
+        for (key, id) in &live_compatible {
+            if !spec.compatible_distribution_set_types.contains(key) {
+                applier
+                    .change(
+                        ChangeKind::Update,
+                        entity.clone(),
+                        format!("remove compatible distribution set type {}", key),
+                        client.remove_compatible_distribution_set_type(current.id, *id),
+                    )
+                    .await;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Returns the IDs of the distribution set tags by name.
+async fn apply_tags(
+    applier: &mut Applier<'_>,
+    setup: &Setup,
+) -> HawkbitResult<HashMap<String, Option<u64>>> {
+    let client = applier.client;
+
+    let live: HashMap<String, _> = client
+        .get_target_tags(None)
+        .await?
+        .into_iter()
+        .map(|tag| (tag.name.clone(), tag))
+        .collect();
+    for spec in &setup.tags.target_tags {
+        let entity = format!("target tag {}", spec.name);
+        let tag = NewTag {
+            name: spec.name.clone(),
+            description: spec.description.clone(),
+            colour: spec.colour.clone(),
+        };
+        match live.get(&spec.name) {
+            None => {
+                applier
+                    .change(
+                        ChangeKind::Create,
+                        entity,
+                        String::new(),
+                        client.create_target_tags(std::slice::from_ref(&tag)),
+                    )
+                    .await;
+            }
+            Some(current)
+                if differs(&spec.description, &current.description)
+                    || spec.colour != current.colour =>
+            {
+                applier
+                    .change(
+                        ChangeKind::Update,
+                        entity,
+                        "description and colour".to_string(),
+                        client.update_target_tag(current.id, &tag),
+                    )
+                    .await;
+            }
+            Some(_) => {}
+        }
+    }
+
+    let live: HashMap<String, _> = client
+        .get_distribution_set_tags(None)
+        .await?
+        .into_iter()
+        .map(|tag| (tag.name.clone(), tag))
+        .collect();
+    let mut ids: HashMap<String, Option<u64>> = live
+        .iter()
+        .map(|(name, tag)| (name.clone(), Some(tag.id)))
+        .collect();
+    for spec in &setup.tags.distribution_set_tags {
+        let entity = format!("distribution set tag {}", spec.name);
+        let tag = NewTag {
+            name: spec.name.clone(),
+            description: spec.description.clone(),
+            colour: spec.colour.clone(),
+        };
+        match live.get(&spec.name) {
+            None => {
+                let created = applier
+                    .change(ChangeKind::Create, entity, String::new(), async {
+                        Ok(client
+                            .create_distribution_set_tags(std::slice::from_ref(&tag))
+                            .await?
+                            .into_iter()
+                            .next()
+                            .map(|created| created.id))
+                    })
+                    .await
+                    .flatten();
+                applier.created(&mut ids, spec.name.clone(), created);
+            }
+            Some(current)
+                if differs(&spec.description, &current.description)
+                    || spec.colour != current.colour =>
+            {
+                applier
+                    .change(
+                        ChangeKind::Update,
+                        entity,
+                        "description and colour".to_string(),
+                        client.update_distribution_set_tag(current.id, &tag),
+                    )
+                    .await;
+            }
+            Some(_) => {}
+        }
+    }
+    Ok(ids)
+}
+
+async fn apply_modules(
+    applier: &mut Applier<'_>,
+    setup: &Setup,
+) -> HawkbitResult<HashMap<ModuleRef, Option<u64>>> {
+    let client = applier.client;
+    let live: HashMap<ModuleRef, _> = client
+        .get_software_modules(None)
+        .await?
+        .into_iter()
+        .filter(|module| !module.deleted)
+        .map(|module| {
+            let reference = ModuleRef {
+                module_type: module.module_type.clone(),
+                name: module.name.clone(),
+                version: module.version.clone(),
+            };
+            (reference, module)
+        })
+        .collect();
+    let mut ids: HashMap<ModuleRef, Option<u64>> = live
+        .iter()
+        .map(|(reference, module)| (reference.clone(), Some(module.id)))
+        .collect();
+
+    for spec in &setup.software_modules {
+        let reference = ModuleRef {
+            module_type: spec.module_type.clone(),
+            name: spec.name.clone(),
+            version: spec.version.clone(),
+        };
+        let entity = format!("software module {}", reference);
+        let Some(current) = live.get(&reference) else {
+            let new_module = NewSoftwareModule {
+                name: spec.name.clone(),
+                version: spec.version.clone(),
+                module_type: spec.module_type.clone(),
+                description: spec.description.clone(),
+                vendor: spec.vendor.clone(),
+                encrypted: false,
+            };
+            let created = applier
+                .change(
+                    ChangeKind::Create,
+                    entity,
+                    "without artifacts".to_string(),
+                    async {
+                        Ok(client
+                            .create_software_modules(&[new_module])
+                            .await?
+                            .into_iter()
+                            .next()
+                            .map(|created| created.id))
+                    },
+                )
+                .await
+                .flatten();
+            applier.created(&mut ids, reference, created);
+            continue;
+        };
+
+        if differs(&spec.description, &current.description)
+            || differs(&spec.vendor, &current.vendor)
+        {
+            applier
+                .change(
+                    ChangeKind::Update,
+                    entity,
+                    "description and vendor".to_string(),
+                    client.update_software_module(
+                        current.id,
+                        spec.description.as_deref(),
+                        spec.vendor.as_deref(),
+                    ),
+                )
+                .await;
+        }
+    }
+    Ok(ids)
+}
+
+async fn apply_distribution_sets(
+    applier: &mut Applier<'_>,
+    setup: &Setup,
+    modules: &HashMap<ModuleRef, Option<u64>>,
+    ds_tags: &HashMap<String, Option<u64>>,
+) -> HawkbitResult<HashMap<DistributionSetRef, Option<u64>>> {
+    let client = applier.client;
+    let live: HashMap<DistributionSetRef, _> = client
+        .get_distribution_sets(None)
+        .await?
+        .into_iter()
+        .filter(|set| !set.deleted)
+        .map(|set| {
+            let reference = DistributionSetRef {
+                name: set.name.clone(),
+                version: set.version.clone(),
+            };
+            (reference, set)
+        })
+        .collect();
+    let mut ids: HashMap<DistributionSetRef, Option<u64>> = live
+        .iter()
+        .map(|(reference, set)| (reference.clone(), Some(set.id)))
+        .collect();
+
+    // Tag names of each live distribution set
+    let mut live_tags: HashMap<u64, BTreeSet<String>> = HashMap::new();
+    for tag in client.get_distribution_set_tags(None).await? {
+        for set in client.get_distribution_set_tag_assignments(tag.id).await? {
+            live_tags
+                .entry(set.id)
+                .or_default()
+                .insert(tag.name.clone());
+        }
+    }
+
+    for spec in &setup.distribution_sets {
+        let reference = spec.reference();
+        let entity = format!("distribution set {}", reference);
+
+        let id = match live.get(&reference) {
+            None => {
+                let module_ids = match spec
+                    .modules
+                    .iter()
+                    .map(|module| lookup(modules, module, "software module"))
+                    .collect::<HawkbitResult<Vec<_>>>()
+                {
+                    Ok(module_ids) => module_ids,
+                    Err(e) => {
+                        applier.failed(ChangeKind::Create, entity, e);
+                        continue;
+                    }
+                };
+                let created = applier
+                    .change(ChangeKind::Create, entity.clone(), String::new(), async {
+                        let new_set = NewDistributionSet {
+                            name: spec.name.clone(),
+                            version: spec.version.clone(),
+                            description: spec.description.clone(),
+                            ds_type: spec.ds_type.clone(),
+                            required_migration_step: spec.required_migration_step,
+                            modules: created(module_ids)?,
+                        };
+                        Ok(client
+                            .create_distribution_sets(&[new_set])
+                            .await?
+                            .into_iter()
+                            .next()
+                            .map(|created| created.id))
+                    })
+                    .await
+                    .flatten();
+                applier.created(&mut ids, reference.clone(), created);
+                created
+            }
+            Some(current) => {
+                if spec.ds_type != current.ds_type {
+                    applier.drift(entity.clone(), format!("type is {:?}", current.ds_type));
+                }
+                let live_modules: BTreeSet<ModuleRef> = current
+                    .modules
+                    .iter()
+                    .map(|module| ModuleRef {
+                        module_type: module.module_type.clone(),
+                        name: module.name.clone(),
+                        version: module.version.clone(),
+                    })
+                    .collect();
+                if live_modules != spec.modules {
+                    let modules: Vec<String> =
+                        live_modules.iter().map(ModuleRef::to_string).collect();
+                    applier.drift(entity.clone(), format!("modules are {:?}", modules));
+                }
+                if differs(&spec.description, &Some(current.description.clone()))
+                    || spec.required_migration_step != current.required_migration_step
+                {
+                    applier
+                        .change(
+                            ChangeKind::Update,
+                            entity.clone(),
+                            "description and required_migration_step".to_string(),
+                            client.update_distribution_set(
+                                current.id,
+                                spec.description.as_deref(),
+                                spec.required_migration_step,
+                            ),
+                        )
+                        .await;
+                }
+                Some(current.id)
+            }
+        };
+
+        // Metadata and tags of a set that is only planned are planned with it
+        let Some(id) = id else {
+            continue;
+        };
+
+        let live_metadata: HashMap<String, String> = client
+            .get_distribution_set_metadata(id, None)
+            .await?
+            .into_iter()
+            .map(|entry| (entry.key, entry.value))
+            .collect();
+        let missing: Vec<MetadataEntry> = spec
+            .metadata
+            .iter()
+            .filter(|(key, _)| !live_metadata.contains_key(*key))
+            .map(|(key, value)| MetadataEntry {
+                key: key.clone(),
+                value: value.clone(),
+            })
+            .collect();
+        if !missing.is_empty() {
+            let keys: Vec<&String> = missing.iter().map(|entry| &entry.key).collect();
+            applier
+                .change(
+                    ChangeKind::Update,
+                    entity.clone(),
+                    format!("add metadata {:?}", keys),
+                    client.create_distribution_set_metadata(id, &missing),
+                )
+                .await;
+        }
+        for (key, value) in &spec.metadata {
+            if live_metadata
+                .get(key)
+                .is_some_and(|current| current != value)
+            {
+                applier
+                    .change(
+                        ChangeKind::Update,
+                        entity.clone(),
+                        format!("metadata {}", key),
+                        client.update_distribution_set_metadata(id, key, value),
+                    )
+                    .await;
+            }
+        }
+
+        let current_tags = live_tags.remove(&id).unwrap_or_default();
+        for tag in spec.tags.difference(&current_tags) {
+            let tag_id = match lookup(ds_tags, tag.as_str(), "distribution set tag") {
+                Ok(tag_id) => tag_id,
+                Err(e) => {
+                    applier.failed(ChangeKind::Update, entity.clone(), e);
+                    continue;
+                }
+            };
+            applier
+                .change(
+                    ChangeKind::Update,
+                    entity.clone(),
+                    format!("add tag {}", tag),
+                    async {
+                        let tag_id = created(vec![tag_id])?[0];
+                        client.assign_distribution_set_tag(tag_id, &[id]).await
+                    },
+                )
+                .await;
+        }
+    }
+    Ok(ids)
+}
+
+async fn apply_target_filters(
+    applier: &mut Applier<'_>,
+    setup: &Setup,
+    sets: &HashMap<DistributionSetRef, Option<u64>>,
+) -> HawkbitResult<()> {
+    let client = applier.client;
+    let live: HashMap<String, _> = client
+        .get_target_filters(None)
+        .await?
+        .into_iter()
+        .map(|filter| (filter.name.clone(), filter))
+        .collect();
+
+    for spec in &setup.target_filters {
+        let entity = format!("target filter {}", spec.name);
+        let auto_assign = match &spec.auto_assign {
+            Some(auto_assign) => {
+                match lookup(sets, &auto_assign.distribution_set, "distribution set") {
+                    Ok(id) => Some((auto_assign, id)),
+                    Err(e) => {
+                        applier.failed(ChangeKind::Update, entity, e);
+                        continue;
+                    }
+                }
+            }
+            None => None,
+        };
+
+        let (filter_id, current_auto_assign) = match live.get(&spec.name) {
+            None => {
+                let created = applier
+                    .change(
+                        ChangeKind::Create,
+                        entity.clone(),
+                        spec.query.clone(),
+                        client.create_target_filter(&spec.name, &spec.query),
+                    )
+                    .await;
+                (created.map(|filter| filter.id), None)
+            }
+            Some(current) => {
+                if current.query != spec.query {
+                    applier
+                        .change(
+                            ChangeKind::Update,
+                            entity.clone(),
+                            format!("query {:?}", spec.query),
+                            client.update_target_filter(current.id, &spec.name, &spec.query),
+                        )
+                        .await;
+                }
+                let current_auto_assign = current.auto_assign_distribution_set.map(|id| {
+                    (
+                        id,
+                        current.auto_assign_action_type.unwrap_or_default(),
+                        current.auto_assign_weight,
+                    )
+                });
+                (Some(current.id), current_auto_assign)
+            }
+        };
+
+        match auto_assign {
+            Some((auto_assign, ds_id)) => {
+                let wanted = ds_id.map(|id| (id, auto_assign.action_type, auto_assign.weight));
+                if wanted.is_some() && wanted == current_auto_assign {
+                    continue;
+                }
+                applier
+                    .change(
+                        ChangeKind::Update,
+                        entity,
+                        format!("auto-assign {}", auto_assign.distribution_set),
+                        async {
+                            let filter_id = created(vec![filter_id])?[0];
+                            let ds_id = created(vec![ds_id])?[0];
+                            client
+                                .set_auto_assign_distribution_set(
+                                    filter_id,
+                                    ds_id,
+                                    auto_assign.action_type,
+                                    auto_assign.weight,
+                                )
+                                .await
+                        },
+                    )
+                    .await;
+            }
+            None if current_auto_assign.is_some() => {
+                applier
+                    .change(
+                        ChangeKind::Update,
+                        entity,
+                        "remove auto-assignment".to_string(),
+                        async {
+                            let filter_id = created(vec![filter_id])?[0];
+                            client.remove_auto_assign_distribution_set(filter_id).await
+                        },
+                    )
+                    .await;
+            }
+            None => {}
+        }
+    }
+    Ok(())
+}
+
+async fn apply_rollouts(
+    applier: &mut Applier<'_>,
+    setup: &Setup,
+    sets: &HashMap<DistributionSetRef, Option<u64>>,
+) -> HawkbitResult<()> {
+    let client = applier.client;
+    let live: HashMap<String, _> = client
+        .get_rollouts(None)
+        .await?
+        .into_iter()
+        .map(|rollout| (rollout.name.clone(), rollout))
+        .collect();
+
+    for spec in &setup.rollouts {
+        let entity = format!("rollout {}", spec.name);
+        let ds_id = match lookup(sets, &spec.distribution_set, "distribution set") {
+            Ok(ds_id) => ds_id,
+            Err(e) => {
+                applier.failed(ChangeKind::Create, entity, e);
+                continue;
+            }
+        };
+
+        if let Some(current) = live.get(&spec.name) {
+            if ds_id.is_some_and(|id| id != current.distribution_set_id)
+                || current.target_filter_query != spec.target_filter_query
+            {
+                applier.drift(
+                    entity,
+                    format!(
+                        "distribution set {} and query {:?}, rollouts are not changed once created",
+                        current.distribution_set_id, current.target_filter_query
+                    ),
+                );
+            }
+            continue;
+        }
+
+        applier
+            .change(
+                ChangeKind::Create,
+                entity,
+                format!("not started, {} groups", spec.groups),
+                async {
+                    let rollout = NewRollout {
+                        name: spec.name.clone(),
+                        description: spec.description.clone(),
+                        distribution_set_id: created(vec![ds_id])?[0],
+                        target_filter_query: spec.target_filter_query.clone(),
+                        amount_groups: spec.groups,
+                        action_type: spec.action_type,
+                        weight: spec.weight,
+                        success_condition: spec.success_threshold.map(RolloutCondition::threshold),
+                        error_condition: spec.error_threshold.map(RolloutCondition::threshold),
+                    };
+                    client.create_rollout(&rollout).await
+                },
+            )
+            .await;
+    }
+    Ok(())
+}
+
+async fn apply_system_config(applier: &mut Applier<'_>, setup: &Setup) -> HawkbitResult<()> {
+    if setup.system_config.is_empty() {
+        return Ok(());
+    }
+    let client = applier.client;
+    let live = client.get_system_configs().await?;
+    for (key, value) in &setup.system_config {
+        if SECRET_SYSTEM_CONFIGS.contains(&key.as_str()) {
+            tracing::warn!(
+                "System config {} holds a secret and is not applied from the setup files",
+                key
+            );
+            continue;
+        }
+        if live.get(key).is_some_and(|current| &current.value == value) {
+            continue;
+        }
+        applier
+            .change(
+                ChangeKind::Update,
+                format!("system config {}", key),
+                value.to_string(),
+                client.set_system_config(key, value),
+            )
+            .await;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::HawkbitConfig;
+    use axum::Router;
+    use axum::extract::{Request, State};
+    use axum::http::{Method, StatusCode};
+    use axum::response::IntoResponse;
+    use serde_json::json;
+    use std::sync::{Arc, Mutex};
+
+    /// Method and path of every request the fake server received
+    type Requests = Arc<Mutex<Vec<(Method, String)>>>;
+
+    const GATEWAY_TOKEN: &str = "authentication.gatewaytoken.key";
+
+    fn page(content: Value) -> Value {
+        let size = content.as_array().unwrap().len();
+        json!({ "content": content, "size": size, "total": size })
+    }
+
+    /// A tenant with one software module type and a few system configs;
+    /// every other collection is empty and every change fails.
+    async fn serve() -> (HawkbitMgmtClient, Requests) {
+        async fn handle(
+            State(requests): State<Requests>,
+            request: Request,
+        ) -> axum::response::Response {
+            let path = request.uri().path().to_string();
+            requests
+                .lock()
+                .unwrap()
+                .push((request.method().clone(), path.clone()));
+            if request.method() != Method::GET {
+                return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+            }
+            let body = match path.as_str() {
+                "/rest/v1/softwaremoduletypes" => page(json!([{
+                    "id": 1,
+                    "key": "os",
+                    "name": "OS",
+                    "description": "Root file system",
+                }])),
+                "/rest/v1/system/configs" => json!({
+                    "pollingTime": { "value": "00:05:00", "global": false },
+                    "rollout.approval.enabled": { "value": false, "global": true },
+                    GATEWAY_TOKEN: { "value": "live-secret", "global": false },
+                }),
+                _ => page(json!([])),
+            };
+            axum::Json(body).into_response()
+        }
+
+        let requests = Requests::default();
+        let app = Router::new().fallback(handle).with_state(requests.clone());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let host = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        let client = HawkbitMgmtClient::from_config(&HawkbitConfig::for_tests(&host)).unwrap();
+        (client, requests)
+    }
+
+    fn temp_dir(test: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("hawkbit-setup-{}-{}", test, std::process::id()))
+    }
+
+    fn module_type(key: &str, name: &str, description: &str) -> SoftwareModuleTypeSpec {
+        SoftwareModuleTypeSpec {
+            key: key.to_string(),
+            name: name.to_string(),
+            description: Some(description.to_string()),
+            colour: None,
+            max_assignments: None,
+        }
+    }
+
+    fn full_setup() -> Setup {
+        let set = DistributionSetRef {
+            name: "firmware".to_string(),
+            version: "1.0".to_string(),
+        };
+        let module = ModuleRef {
+            module_type: "os".to_string(),
+            name: "rootfs".to_string(),
+            version: "1.0".to_string(),
+        };
+        let tag = |name: &str| TagSpec {
+            name: name.to_string(),
+            description: None,
+            colour: Some("#ff0000".to_string()),
+        };
+        Setup {
+            software_module_types: vec![SoftwareModuleTypeSpec {
+                max_assignments: Some(1),
+                ..module_type("os", "OS", "Root file system")
+            }],
+            distribution_set_types: vec![DistributionSetTypeSpec {
+                key: "os_app".to_string(),
+                name: "OS with apps".to_string(),
+                description: None,
+                colour: None,
+                mandatory_modules: BTreeSet::from(["os".to_string()]),
+                optional_modules: BTreeSet::from(["app".to_string()]),
+            }],
+            target_types: vec![TargetTypeSpec {
+                name: "gateway".to_string(),
+                key: Some("gw".to_string()),
+                description: None,
+                colour: None,
+                compatible_distribution_set_types: BTreeSet::from(["os_app".to_string()]),
+            }],
+            tags: TagsSpec {
+                target_tags: vec![tag("lab")],
+                distribution_set_tags: vec![tag("stable")],
+            },
+            software_modules: vec![SoftwareModuleSpec {
+                module_type: module.module_type.clone(),
+                name: module.name.clone(),
+                version: module.version.clone(),
+                vendor: Some("ACME".to_string()),
+                description: None,
+            }],
+            distribution_sets: vec![DistributionSetSpec {
+                name: set.name.clone(),
+                version: set.version.clone(),
+                ds_type: "os_app".to_string(),
+                description: Some("First release".to_string()),
+                required_migration_step: true,
+                modules: BTreeSet::from([module]),
+                metadata: BTreeMap::from([("channel".to_string(), "stable".to_string())]),
+                tags: BTreeSet::from(["stable".to_string()]),
+            }],
+            target_filters: vec![TargetFilterSpec {
+                name: "lab devices".to_string(),
+                query: "tag==lab".to_string(),
+                auto_assign: Some(AutoAssignSpec {
+                    distribution_set: set.clone(),
+                    action_type: ActionType::Soft,
+                    weight: Some(100),
+                }),
+            }],
+            rollouts: vec![RolloutSpec {
+                name: "firmware 1.0".to_string(),
+                description: None,
+                distribution_set: set,
+                target_filter_query: "tag==lab".to_string(),
+                groups: 4,
+                action_type: ActionType::TimeForced,
+                weight: None,
+                success_threshold: Some(90),
+                error_threshold: Some(10),
+            }],
+            system_config: BTreeMap::from([("pollingTime".to_string(), json!("00:10:00"))]),
+        }
+    }
+
+    #[test]
+    fn yaml_files_round_trip() {
+        let dir = temp_dir("round-trip");
+        let setup = full_setup();
+        assert_eq!(setup.write(&dir).unwrap().len(), 9);
+        assert_eq!(Setup::read(&dir).unwrap(), setup);
+
+        // Missing files are empty sections
+        std::fs::remove_file(dir.join(ROLLOUTS_FILE)).unwrap();
+        assert!(Setup::read(&dir).unwrap().rollouts.is_empty());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn unknown_yaml_fields_are_rejected() {
+        let dir = temp_dir("unknown");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join(TAGS_FILE),
+            "target_tags:\n- name: lab\n  color: red\n",
+        )
+        .unwrap();
+        let err = Setup::read(&dir).unwrap_err();
+        assert!(err.to_string().contains(TAGS_FILE), "{}", err);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn dry_run_reports_changes_without_making_them() {
+        let (client, requests) = serve().await;
+        let setup = Setup {
+            software_module_types: vec![
+                module_type("os", "Operating system", "Root file system v2"),
+                module_type("app", "Application", "Containers"),
+            ],
+            tags: TagsSpec {
+                target_tags: vec![TagSpec {
+                    name: "lab".to_string(),
+                    description: None,
+                    colour: None,
+                }],
+                distribution_set_tags: Vec::new(),
+            },
+            system_config: BTreeMap::from([
+                ("pollingTime".to_string(), json!("00:10:00")),
+                (GATEWAY_TOKEN.to_string(), json!("committed-secret")),
+            ]),
+            ..Default::default()
+        };
+
+        let changes = apply(&client, &setup, true).await.unwrap();
+        let changes: Vec<_> = changes
+            .iter()
+            .map(|change| (change.kind, change.entity.as_str()))
+            .collect();
+        assert_eq!(
+            changes,
+            [
+                (ChangeKind::Drift, "software module type os"),
+                (ChangeKind::Update, "software module type os"),
+                (ChangeKind::Create, "software module type app"),
+                (ChangeKind::Create, "target tag lab"),
+                (ChangeKind::Update, "system config pollingTime"),
+            ]
+        );
+        let requests = requests.lock().unwrap();
+        assert!(requests.iter().all(|(method, _)| method == Method::GET));
+    }
+
+    #[tokio::test]
+    async fn secret_system_configs_are_not_applied() {
+        let (client, requests) = serve().await;
+        let setup = Setup {
+            system_config: BTreeMap::from([(GATEWAY_TOKEN.to_string(), json!("committed-secret"))]),
+            ..Default::default()
+        };
+
+        let changes = apply(&client, &setup, false).await.unwrap();
+        assert!(changes.is_empty());
+        let requests = requests.lock().unwrap();
+        assert!(
+            !requests
+                .iter()
+                .any(|(_, path)| path.contains(GATEWAY_TOKEN))
+        );
+    }
+
+    #[tokio::test]
+    async fn export_skips_secret_and_global_system_configs() {
+        let (client, _requests) = serve().await;
+        let setup = Setup::export(&client).await.unwrap();
+        assert_eq!(
+            setup.system_config,
+            BTreeMap::from([("pollingTime".to_string(), json!("00:05:00"))])
+        );
+        assert_eq!(
+            setup.software_module_types,
+            [module_type("os", "OS", "Root file system")]
+        );
+    }
+}
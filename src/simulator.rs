@@ -0,0 +1,554 @@
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::future::Future;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::task::JoinSet;
+
+use crate::ddi::{ConfigDataMode, DdiAuth, DdiClient, DeploymentBase, Feedback};
+use crate::hawkbit::{HawkbitConfig, HawkbitError, HawkbitResult};
+use crate::secrets::{SecretSource, VaultLocation};
+use crate::transport::TransportConfig;
+
+/// Poll interval used when the server's interval cannot be parsed
+const FALLBACK_POLL_INTERVAL: Duration = Duration::from_secs(60);
+
+fn duration<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+    let value = String::deserialize(deserializer)?;
+    humantime::parse_duration(&value)
+        .map_err(|e| serde::de::Error::custom(format!("{:?} is not a duration: {}", value, e)))
+}
+
+fn optional_duration<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Duration>, D::Error> {
+    duration(deserializer).map(Some)
+}
+
+/// What a device does with the deployments it is offered, one entry per
+/// deployment. The last entry repeats.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case", deny_unknown_fields)]
+pub enum Behaviour {
+    /// Report success after the install time
+    Succeed,
+    /// Report failure with `message` after the install time
+    Fail { message: String },
+    /// Report that the update is proceeding, then never finish it and ignore
+    /// cancel requests for it
+    Hang,
+    /// Stop polling without feedback, for `duration` or for good
+    Offline {
+        #[serde(default, deserialize_with = "optional_duration")]
+        duration: Option<Duration>,
+    },
+}
+
+/// Devices sharing their settings. The controller IDs are either listed or
+/// generated as `<prefix><number>`, e.g. `sim-0001`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DeviceGroup {
+    #[serde(default)]
+    pub controller_ids: Vec<String>,
+    pub prefix: Option<String>,
+    #[serde(default)]
+    pub count: usize,
+    /// Number of the first generated controller ID
+    #[serde(default = "default_first")]
+    pub first: usize,
+    /// Fixed poll interval instead of the one requested by the server
+    #[serde(default, deserialize_with = "optional_duration")]
+    pub poll_interval: Option<Duration>,
+    /// Reported via configData, e.g. `update_channel` and `build_channel`
+    #[serde(default)]
+    pub attributes: BTreeMap<String, String>,
+    /// Time between accepting a deployment and the final feedback
+    #[serde(default, deserialize_with = "duration")]
+    pub install_time: Duration,
+    /// Download and verify the artifacts instead of only pretending to
+    #[serde(default)]
+    pub download: bool,
+    #[serde(default = "default_script")]
+    pub script: Vec<Behaviour>,
+}
+
+fn default_first() -> usize {
+    1
+}
+
+fn default_script() -> Vec<Behaviour> {
+    vec![Behaviour::Succeed]
+}
+
+impl DeviceGroup {
+    pub fn controller_ids(&self) -> Vec<String> {
+        let mut ids = self.controller_ids.clone();
+        if let Some(prefix) = &self.prefix {
+            ids.extend(
+                (self.first..self.first + self.count)
+                    .map(|number| format!("{}{:04}", prefix, number)),
+            );
+        }
+        ids
+    }
+}
+
+/// A simulation run, read from a TOML file
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Scenario {
+    /// hawkBit URL, defaults to the host of the config profile
+    pub host: Option<String>,
+    pub tenant: Option<String>,
+    /// Without a gateway token the devices poll anonymously
+    pub gateway_token: Option<SecretSource>,
+    /// Stop after this time, otherwise run until interrupted
+    #[serde(default, deserialize_with = "optional_duration")]
+    pub duration: Option<Duration>,
+    /// Delay between starting two devices so their polls spread out
+    #[serde(default = "default_ramp_up", deserialize_with = "duration")]
+    pub ramp_up: Duration,
+    pub devices: Vec<DeviceGroup>,
+}
+
+fn default_ramp_up() -> Duration {
+    Duration::from_millis(100)
+}
+
+impl Scenario {
+    pub fn read(path: &Path) -> HawkbitResult<Self> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| HawkbitError::new(format!("Failed to read {}: {}", path.display(), e)))?;
+        let scenario: Scenario = toml::from_str(&content)
+            .map_err(|e| HawkbitError::new(format!("Invalid {}: {}", path.display(), e)))?;
+        scenario.validate()?;
+        Ok(scenario)
+    }
+
+    fn validate(&self) -> HawkbitResult<()> {
+        let mut seen = HashSet::new();
+        for (index, group) in self.devices.iter().enumerate() {
+            if group.script.is_empty() {
+                return Err(HawkbitError::new(format!(
+                    "Device group {} has an empty script",
+                    index + 1
+                )));
+            }
+            let ids = group.controller_ids();
+            if ids.is_empty() {
+                return Err(HawkbitError::new(format!(
+                    "Device group {} has no devices, set controller_ids or prefix and count",
+                    index + 1
+                )));
+            }
+            for id in ids {
+                if !seen.insert(id.clone()) {
+                    return Err(HawkbitError::new(format!(
+                        "Controller ID {} is used by more than one device",
+                        id
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Counters over all devices of a simulation
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct SimulationReport {
+    pub devices: usize,
+    pub polls: u64,
+    pub poll_errors: u64,
+    pub deployments: u64,
+    pub succeeded: u64,
+    pub failed: u64,
+    pub hung: u64,
+    pub canceled: u64,
+    pub went_offline: u64,
+    pub feedback_errors: u64,
+    pub downloaded_bytes: u64,
+}
+
+type SharedReport = Arc<Mutex<SimulationReport>>;
+
+fn count(report: &SharedReport, update: impl FnOnce(&mut SimulationReport)) {
+    update(&mut report.lock().expect("report lock poisoned"));
+}
+
+/// Sleeps unless the simulation stops first. Returns false once stopped.
+async fn pause(stop: &mut watch::Receiver<bool>, duration: Duration) -> bool {
+    if *stop.borrow() {
+        return false;
+    }
+    tokio::select! {
+        _ = tokio::time::sleep(duration) => true,
+        _ = stop.changed() => false,
+    }
+}
+
+struct Device {
+    client: DdiClient,
+    group: Arc<DeviceGroup>,
+    report: SharedReport,
+    stop: watch::Receiver<bool>,
+    /// Number of deployments handled so far, selects the script entry
+    handled: usize,
+    /// Finished or canceled actions, which must not be handled again
+    done: HashSet<String>,
+    hung: HashSet<String>,
+}
+
+impl Device {
+    fn id(&self) -> &str {
+        self.client.controller_id()
+    }
+
+    async fn feedback(&self, action_id: &str, feedback: Feedback) {
+        if let Err(e) = self
+            .client
+            .send_deployment_feedback(action_id, &feedback)
+            .await
+        {
+            tracing::warn!(
+                "{}: feedback for action {} failed: {}",
+                self.id(),
+                action_id,
+                e
+            );
+            count(&self.report, |report| report.feedback_errors += 1);
+        }
+    }
+
+    async fn run(mut self) {
+        let mut attributes_sent = false;
+        loop {
+            let base = match self.client.poll().await {
+                Ok(base) => base,
+                Err(e) => {
+                    tracing::warn!("{}: poll failed: {}", self.id(), e);
+                    count(&self.report, |report| report.poll_errors += 1);
+                    let interval = self.group.poll_interval.unwrap_or(FALLBACK_POLL_INTERVAL);
+                    if !pause(&mut self.stop, interval).await {
+                        return;
+                    }
+                    continue;
+                }
+            };
+            count(&self.report, |report| report.polls += 1);
+
+            if (!attributes_sent || base.links.config_data.is_some())
+                && !self.group.attributes.is_empty()
+            {
+                let attributes: HashMap<String, String> = self
+                    .group
+                    .attributes
+                    .iter()
+                    .map(|(key, value)| (key.clone(), value.clone()))
+                    .collect();
+                match self
+                    .client
+                    .put_config_data(&attributes, ConfigDataMode::Merge)
+                    .await
+                {
+                    Ok(()) => attributes_sent = true,
+                    Err(e) => tracing::warn!("{}: sending attributes failed: {}", self.id(), e),
+                }
+            }
+
+            if let Some(link) = &base.links.cancel_action {
+                self.handle_cancel(&link.href).await;
+            } else if let Some(link) = &base.links.deployment_base {
+                match self.client.get_deployment_base(&link.href).await {
+                    Ok(deployment) => {
+                        if !self.handle_deployment(deployment).await {
+                            return;
+                        }
+                    }
+                    Err(e) => tracing::warn!("{}: fetching deployment failed: {}", self.id(), e),
+                }
+            }
+
+            let interval = self
+                .group
+                .poll_interval
+                .unwrap_or_else(|| base.sleep().unwrap_or(FALLBACK_POLL_INTERVAL));
+            if !pause(&mut self.stop, interval).await {
+                return;
+            }
+        }
+    }
+
+    async fn handle_cancel(&mut self, href: &str) {
+        let cancel = match self.client.get_cancel_action(href).await {
+            Ok(cancel) => cancel,
+            Err(e) => {
+                tracing::warn!("{}: fetching cancel action failed: {}", self.id(), e);
+                return;
+            }
+        };
+        if self.hung.contains(&cancel.cancel_action.stop_id) {
+            return;
+        }
+        tracing::info!(
+            "{}: canceling action {}",
+            self.id(),
+            cancel.cancel_action.stop_id
+        );
+        let feedback = Feedback::success().with_message("Canceled by the simulator");
+        match self
+            .client
+            .send_cancel_feedback(&cancel.id, &feedback)
+            .await
+        {
+            Ok(()) => {
+                self.done.insert(cancel.cancel_action.stop_id);
+                count(&self.report, |report| report.canceled += 1);
+            }
+            Err(e) => {
+                tracing::warn!("{}: cancel feedback failed: {}", self.id(), e);
+                count(&self.report, |report| report.feedback_errors += 1);
+            }
+        }
+    }
+
+    /// Returns false if the device went offline for good or was stopped.
+    async fn handle_deployment(&mut self, deployment: DeploymentBase) -> bool {
+        let action_id = deployment.id.clone();
+        if self.done.contains(&action_id) || self.hung.contains(&action_id) {
+            return true;
+        }
+        let script = &self.group.script;
+        let behaviour = script[self.handled.min(script.len() - 1)].clone();
+        self.handled += 1;
+        count(&self.report, |report| report.deployments += 1);
+        tracing::info!(
+            "{}: action {} offered, {:?}",
+            self.id(),
+            action_id,
+            behaviour
+        );
+
+        if let Behaviour::Offline { duration } = behaviour {
+            count(&self.report, |report| report.went_offline += 1);
+            return match duration {
+                Some(duration) => pause(&mut self.stop, duration).await,
+                None => false,
+            };
+        }
+
+        self.feedback(
+            &action_id,
+            Feedback::proceeding().with_message("Installing"),
+        )
+        .await;
+        if matches!(behaviour, Behaviour::Hang) {
+            self.hung.insert(action_id);
+            count(&self.report, |report| report.hung += 1);
+            return true;
+        }
+
+        let mut download_error = None;
+        if self.group.download {
+            for artifact in deployment
+                .deployment
+                .chunks
+                .iter()
+                .flat_map(|chunk| &chunk.artifacts)
+            {
+                match self.client.fetch_artifact(artifact).await {
+                    Ok(size) => count(&self.report, |report| report.downloaded_bytes += size),
+                    Err(e) => {
+                        download_error = Some(e.to_string());
+                        break;
+                    }
+                }
+            }
+        }
+        if !pause(&mut self.stop, self.group.install_time).await {
+            return false;
+        }
+
+        let feedback = match (download_error, behaviour) {
+            (Some(error), _) => {
+                count(&self.report, |report| report.failed += 1);
+                Feedback::failure().with_message(format!("Download failed: {}", error))
+            }
+            (None, Behaviour::Fail { message }) => {
+                count(&self.report, |report| report.failed += 1);
+                Feedback::failure().with_message(message)
+            }
+            _ => {
+                count(&self.report, |report| report.succeeded += 1);
+                Feedback::success().with_message("Installed")
+            }
+        };
+        self.feedback(&action_id, feedback).await;
+        self.done.insert(action_id);
+        true
+    }
+}
+
+/// Runs the devices of `scenario` until its duration elapsed or `shutdown`
+/// completes. Host, tenant and TLS settings not given by the scenario come
+/// from `config`.
+pub async fn run(
+    scenario: &Scenario,
+    config: Option<&HawkbitConfig>,
+    shutdown: impl Future<Output = ()>,
+) -> HawkbitResult<SimulationReport> {
+    let host = scenario
+        .host
+        .as_deref()
+        .or(config.map(HawkbitConfig::host))
+        .ok_or_else(|| HawkbitError::new("The scenario sets no host and no profile is loaded"))?;
+    let tenant = scenario
+        .tenant
+        .as_deref()
+        .or(config.and_then(HawkbitConfig::tenant));
+    let default_transport = TransportConfig::default();
+    let transport = config.map_or(&default_transport, HawkbitConfig::transport);
+    let auth = match &scenario.gateway_token {
+        Some(token) => DdiAuth::GatewayToken(
+            token
+                .resolve(&VaultLocation::from_env(None))?
+                .expose()
+                .to_string(),
+        ),
+        None => DdiAuth::None,
+    };
+
+    // One connection pool for all devices, each sends its own credentials
+    let http = DdiClient::http_client(transport)?;
+    let report = SharedReport::default();
+    let (stop_sender, stop) = watch::channel(false);
+    let mut devices = Vec::new();
+    for group in &scenario.devices {
+        let group = Arc::new(group.clone());
+        for controller_id in group.controller_ids() {
+            devices.push(Device {
+                client: DdiClient::with_client(http.clone(), host, tenant, &controller_id, &auth)?,
+                group: group.clone(),
+                report: report.clone(),
+                stop: stop.clone(),
+                handled: 0,
+                done: HashSet::new(),
+                hung: HashSet::new(),
+            });
+        }
+    }
+    count(&report, |report| report.devices = devices.len());
+    tracing::info!("Starting {} simulated devices on {}", devices.len(), host);
+
+    let mut tasks = JoinSet::new();
+    let ramp_up = scenario.ramp_up;
+    let mut start_stop = stop.clone();
+    let start = async {
+        for device in devices {
+            tasks.spawn(device.run());
+            if !pause(&mut start_stop, ramp_up).await {
+                break;
+            }
+        }
+        std::future::pending::<()>().await
+    };
+    let deadline = async {
+        match scenario.duration {
+            Some(duration) => tokio::time::sleep(duration).await,
+            None => std::future::pending().await,
+        }
+    };
+    tokio::select! {
+        _ = start => {}
+        _ = deadline => {}
+        _ = shutdown => {}
+    }
+
+    stop_sender.send_replace(true);
+    while let Some(result) = tasks.join_next().await {
+        if let Err(e) = result {
+            tracing::warn!("Simulated device crashed: {}", e);
+        }
+    }
+    let report = report.lock().expect("report lock poisoned").clone();
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scenario(toml: &str) -> HawkbitResult<Scenario> {
+        let scenario: Scenario =
+            toml::from_str(toml).map_err(|e| HawkbitError::new(e.to_string()))?;
+        scenario.validate()?;
+        Ok(scenario)
+    }
+
+    #[test]
+    fn example_scenario_is_valid() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("simulation.example.toml");
+        let scenario = Scenario::read(&path).unwrap();
+        assert_eq!(scenario.duration, Some(Duration::from_secs(30 * 60)));
+        assert_eq!(scenario.devices[0].controller_ids().len(), 50);
+    }
+
+    #[test]
+    fn controller_ids_are_listed_and_generated() {
+        let scenario = scenario(
+            r#"
+            [[devices]]
+            controller_ids = ["gateway"]
+            prefix = "sim-"
+            count = 3
+            first = 9
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            scenario.devices[0].controller_ids(),
+            ["gateway", "sim-0009", "sim-0010", "sim-0011"]
+        );
+        assert_eq!(scenario.ramp_up, Duration::from_millis(100));
+        assert!(matches!(
+            scenario.devices[0].script[..],
+            [Behaviour::Succeed]
+        ));
+    }
+
+    #[test]
+    fn invalid_scenarios_are_rejected() {
+        for (toml, error) in [
+            (
+                "[[devices]]\ncontroller_ids = [\"a\"]\nscript = []",
+                "Device group 1 has an empty script",
+            ),
+            (
+                "[[devices]]\ncontroller_ids = [\"a\"]\n[[devices]]\nprefix = \"sim-\"",
+                "Device group 2 has no devices",
+            ),
+            (
+                "[[devices]]\ncontroller_ids = [\"a\", \"a\"]",
+                "Controller ID a is used by more than one device",
+            ),
+            (
+                "[[devices]]\nprefix = \"sim-\"\ncount = 2\n[[devices]]\ncontroller_ids = [\"sim-0002\"]",
+                "Controller ID sim-0002 is used by more than one device",
+            ),
+            (
+                "[[devices]]\ncontroller_ids = [\"a\"]\ninstall_time = \"soon\"",
+                "is not a duration",
+            ),
+            (
+                "[[devices]]\ncontroller_ids = [\"a\"]\nscript = [{ action = \"explode\" }]",
+                "unknown variant",
+            ),
+        ] {
+            let err = scenario(toml).unwrap_err().to_string();
+            assert!(err.contains(error), "{:?}: {}", toml, err);
+        }
+    }
+}
@@ -0,0 +1,449 @@
+use chrono::Utc;
+use serde::Serialize;
+use std::io::Write;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::hawkbit::{Action, HawkbitMgmtClient, HawkbitResult};
+
+/// Decides which open update actions of a target get canceled.
+///
+/// Actions are soft-canceled first. hawkBit then keeps them in `canceling`
+/// until the device acknowledges the cancellation; if that does not happen
+/// within `force_after` the cancellation is escalated to a force-cancel.
+///
+/// The default only cancels superseded actions and never forces, so a
+/// target's newest assignment is left alone however long it takes.
+#[derive(Debug, Clone)]
+pub struct StaleActionPolicy {
+    /// Cancel older open actions once a newer update action exists
+    pub cancel_superseded: bool,
+    /// An action without any status event for this long counts as stuck
+    pub stuck_after: Option<Duration>,
+    /// Grace period for a soft-cancel before it is forced; never forced
+    /// without it
+    pub force_after: Option<Duration>,
+    /// Only report what would be done
+    pub dry_run: bool,
+}
+
+impl Default for StaleActionPolicy {
+    fn default() -> Self {
+        Self {
+            cancel_superseded: true,
+            stuck_after: None,
+            force_after: None,
+            dry_run: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum StaleReason {
+    /// A newer update action was assigned to the target
+    Superseded { by_action: i64 },
+    /// No status event was reported for `idle_secs`
+    Stuck { idle_secs: u64 },
+    /// The soft-cancel has not been acknowledged for `canceling_secs`
+    CancelingTooLong { canceling_secs: u64 },
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ActionDecision {
+    pub controller_id: String,
+    pub action_id: i64,
+    pub detail_status: String,
+    #[serde(flatten)]
+    pub reason: StaleReason,
+    pub force: bool,
+    /// Time of the decision, ms since epoch
+    pub decided_at: i64,
+    pub dry_run: bool,
+    pub error: Option<String>,
+}
+
+fn age(now_ms: i64, timestamp_ms: u64) -> Duration {
+    Duration::from_millis((now_ms.max(0) as u64).saturating_sub(timestamp_ms))
+}
+
+fn is_open_update(action: &Action) -> bool {
+    action.action_type == "update" && action.status != "finished"
+}
+
+/// Applies the policy to the open actions of one target and returns what was
+/// touched and why.
+pub async fn process_target(
+    client: &HawkbitMgmtClient,
+    controller_id: &str,
+    policy: &StaleActionPolicy,
+) -> HawkbitResult<Vec<ActionDecision>> {
+    let mut actions: Vec<Action> = client
+        .get_target_actions(controller_id, Some(50), Some("status==\"pending\""))
+        .await?
+        .into_iter()
+        .filter(is_open_update)
+        .collect();
+    actions.sort_by_key(|action| std::cmp::Reverse(action.id));
+
+    let now = Utc::now().timestamp_millis();
+    // The newest open action is the one the target should work on
+    let newest = actions
+        .iter()
+        .find(|action| action.detail_status != "canceling")
+        .map(|action| action.id);
+
+    let mut decisions = Vec::new();
+    for action in &actions {
+        let decision = if action.detail_status == "canceling" {
+            let canceling_for = age(now, action.last_modified_at);
+            let Some(force_after) = policy.force_after else {
+                continue;
+            };
+            if canceling_for < force_after {
+                continue;
+            }
+            Some((
+                StaleReason::CancelingTooLong {
+                    canceling_secs: canceling_for.as_secs(),
+                },
+                true,
+            ))
+        } else if policy.cancel_superseded
+            && Some(action.id) != newest
+            && action.detail_status != "running"
+        {
+            Some((
+                StaleReason::Superseded {
+                    by_action: newest.unwrap_or_default(),
+                },
+                false,
+            ))
+        } else if let Some(stuck_after) = policy.stuck_after {
+            let last_event = client
+                .get_latest_action_status(controller_id, &action.id)
+                .await?
+                .map(|event| event.reported_at)
+                .unwrap_or(action.last_modified_at);
+            let idle = age(now, last_event);
+            (idle >= stuck_after).then_some((
+                StaleReason::Stuck {
+                    idle_secs: idle.as_secs(),
+                },
+                false,
+            ))
+        } else {
+            None
+        };
+
+        let Some((reason, force)) = decision else {
+            continue;
+        };
+
+        let error = if policy.dry_run {
+            None
+        } else {
+            client
+                .cancel_action(controller_id, &action.id, force)
+                .await
+                .err()
+                .map(|e| e.to_string())
+        };
+
+        decisions.push(ActionDecision {
+            controller_id: controller_id.to_string(),
+            action_id: action.id,
+            detail_status: action.detail_status.clone(),
+            reason,
+            force,
+            decided_at: now,
+            dry_run: policy.dry_run,
+            error,
+        });
+    }
+    Ok(decisions)
+}
+
+/// Appends decisions as JSON lines so it can be traced later why an action
+/// was canceled.
+pub fn append_log(path: &Path, decisions: &[ActionDecision]) -> HawkbitResult<()> {
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?;
+    for decision in decisions {
+        writeln!(file, "{}", serde_json::to_string(decision)?)?;
+    }
+    Ok(())
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct StaleActionReport {
+    pub checked: usize,
+    pub decisions: Vec<ActionDecision>,
+    /// Targets whose actions could not be read, with the error
+    pub failed: Vec<(String, String)>,
+}
+
+impl StaleActionReport {
+    /// Number of failed targets and failed cancellations
+    pub fn failures(&self) -> usize {
+        self.failed.len()
+            + self
+                .decisions
+                .iter()
+                .filter(|decision| decision.error.is_some())
+                .count()
+    }
+}
+
+/// Applies the policy to all targets matching `filter`. A target that fails
+/// is recorded and does not stop the others.
+pub async fn process_targets(
+    client: &HawkbitMgmtClient,
+    filter: &str,
+    policy: &StaleActionPolicy,
+) -> HawkbitResult<StaleActionReport> {
+    let targets = client.get_targets(Some(filter)).await?;
+    let mut report = StaleActionReport {
+        checked: targets.len(),
+        ..Default::default()
+    };
+    for target in &targets {
+        match process_target(client, &target.controller_id, policy).await {
+            Ok(decisions) => report.decisions.extend(decisions),
+            Err(e) => report
+                .failed
+                .push((target.controller_id.clone(), e.to_string())),
+        }
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::HawkbitConfig;
+    use axum::Router;
+    use axum::extract::{Request, State};
+    use axum::http::{Method, StatusCode};
+    use axum::response::{IntoResponse, Response};
+    use serde_json::{Value, json};
+    use std::sync::{Arc, Mutex};
+
+    const MINUTE: u64 = 60_000;
+
+    struct Target {
+        actions: Value,
+        /// Age of the latest status event of every action, in minutes
+        last_event: Option<u64>,
+        /// Path and query of every DELETE request
+        cancels: Mutex<Vec<String>>,
+    }
+
+    fn now() -> u64 {
+        Utc::now().timestamp_millis() as u64
+    }
+
+    /// An open update action last modified `age` minutes ago
+    fn action(id: i64, detail_status: &str, age: u64) -> Value {
+        json!({
+            "_links": {},
+            "createdAt": 0,
+            "createdBy": "admin",
+            "detailStatus": detail_status,
+            "forceType": "forced",
+            "id": id,
+            "lastModifiedAt": now() - age * MINUTE,
+            "lastModifiedBy": "admin",
+            "status": "pending",
+            "type": "update",
+        })
+    }
+
+    async fn handle(State(target): State<Arc<Target>>, request: Request) -> Response {
+        let path = request.uri().path();
+        if request.method() == Method::DELETE {
+            let query = request.uri().query().unwrap_or_default();
+            target
+                .cancels
+                .lock()
+                .unwrap()
+                .push(format!("{}?{}", path, query));
+            return StatusCode::NO_CONTENT.into_response();
+        }
+        let content = if path.ends_with("/status") {
+            let events: Vec<Value> = target
+                .last_event
+                .map(|age| json!({ "id": 1, "messages": [], "reportedAt": now() - age * MINUTE, "type": "running" }))
+                .into_iter()
+                .collect();
+            Value::from(events)
+        } else {
+            target.actions.clone()
+        };
+        let size = content.as_array().unwrap().len();
+        axum::Json(json!({ "content": content, "size": size, "total": size })).into_response()
+    }
+
+    async fn serve(
+        actions: Vec<Value>,
+        last_event: Option<u64>,
+    ) -> (HawkbitMgmtClient, Arc<Target>) {
+        let target = Arc::new(Target {
+            actions: Value::from(actions),
+            last_event,
+            cancels: Mutex::new(Vec::new()),
+        });
+        let app = Router::new().fallback(handle).with_state(target.clone());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let host = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        let client = HawkbitMgmtClient::from_config(&HawkbitConfig::for_tests(&host)).unwrap();
+        (client, target)
+    }
+
+    fn policy(stuck_after: Option<u64>, force_after: Option<u64>) -> StaleActionPolicy {
+        let minutes = |minutes: u64| Duration::from_secs(minutes * 60);
+        StaleActionPolicy {
+            stuck_after: stuck_after.map(minutes),
+            force_after: force_after.map(minutes),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn policy_decisions() {
+        let no_superseded = StaleActionPolicy {
+            cancel_superseded: false,
+            ..Default::default()
+        };
+        // Actions, minutes since the last status event, policy, and the
+        // expected (action, reason, force) decisions
+        let cases = [
+            (
+                "older pending action is superseded",
+                vec![action(3, "pending", 1), action(2, "pending", 600)],
+                None,
+                StaleActionPolicy::default(),
+                vec![(2, "superseded", false)],
+            ),
+            (
+                "running older action is left alone",
+                vec![action(3, "pending", 1), action(2, "running", 600)],
+                None,
+                StaleActionPolicy::default(),
+                vec![],
+            ),
+            (
+                "superseding can be turned off",
+                vec![action(3, "pending", 1), action(2, "pending", 600)],
+                None,
+                no_superseded,
+                vec![],
+            ),
+            (
+                "newest action is never stuck by default",
+                vec![action(1, "running", 60 * 24 * 30)],
+                Some(60 * 24 * 30),
+                StaleActionPolicy::default(),
+                vec![],
+            ),
+            (
+                "idle newest action is stuck",
+                vec![action(1, "running", 600)],
+                Some(120),
+                policy(Some(60), None),
+                vec![(1, "stuck", false)],
+            ),
+            (
+                "recent status event keeps the action",
+                vec![action(1, "running", 600)],
+                Some(30),
+                policy(Some(60), None),
+                vec![],
+            ),
+            (
+                "action without events is idle since its last change",
+                vec![action(1, "pending", 600)],
+                None,
+                policy(Some(60), None),
+                vec![(1, "stuck", false)],
+            ),
+            (
+                "canceling action is never forced by default",
+                vec![action(2, "canceling", 600)],
+                None,
+                StaleActionPolicy::default(),
+                vec![],
+            ),
+            (
+                "canceling action is forced after the grace period",
+                vec![action(2, "canceling", 120)],
+                None,
+                policy(None, Some(60)),
+                vec![(2, "canceling_too_long", true)],
+            ),
+            (
+                "canceling action within the grace period is kept",
+                vec![action(2, "canceling", 30)],
+                None,
+                policy(None, Some(60)),
+                vec![],
+            ),
+            (
+                "canceling action does not supersede older ones",
+                vec![action(5, "canceling", 1), action(4, "pending", 600)],
+                None,
+                StaleActionPolicy::default(),
+                vec![],
+            ),
+        ];
+
+        for (name, actions, last_event, policy, expected) in cases {
+            let (client, target) = serve(actions, last_event).await;
+            let decisions = process_target(&client, "dev-1", &policy).await.unwrap();
+            let actual: Vec<(i64, String, bool)> = decisions
+                .iter()
+                .map(|decision| {
+                    let reason = serde_json::to_value(decision).unwrap()["reason"].clone();
+                    (
+                        decision.action_id,
+                        reason.as_str().unwrap().to_string(),
+                        decision.force,
+                    )
+                })
+                .collect();
+            let expected: Vec<(i64, String, bool)> = expected
+                .into_iter()
+                .map(|(id, reason, force)| (id, reason.to_string(), force))
+                .collect();
+            assert_eq!(actual, expected, "{}", name);
+
+            let cancels: Vec<String> = expected
+                .iter()
+                .map(|(id, _, force)| {
+                    format!("/rest/v1/targets/dev-1/actions/{}?force={}", id, force)
+                })
+                .collect();
+            assert_eq!(*target.cancels.lock().unwrap(), cancels, "{}", name);
+        }
+    }
+
+    #[tokio::test]
+    async fn dry_run_cancels_nothing() {
+        let (client, target) = serve(
+            vec![action(3, "pending", 1), action(2, "pending", 600)],
+            None,
+        )
+        .await;
+        let policy = StaleActionPolicy {
+            dry_run: true,
+            ..Default::default()
+        };
+        let decisions = process_target(&client, "dev-1", &policy).await.unwrap();
+        assert_eq!(decisions.len(), 1);
+        assert!(decisions[0].dry_run);
+        assert!(target.cancels.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,100 @@
+use futures::stream::{self, StreamExt};
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::hawkbit::{HawkbitConfig, HawkbitMgmtClient, HawkbitResult, MgmtTarget};
+
+/// Result of an operation on one tenant
+#[derive(Debug, Serialize)]
+pub struct TenantOutcome<T> {
+    /// Profile of the config file the tenant was configured in
+    pub profile: String,
+    pub tenant: Option<String>,
+    pub result: Option<T>,
+    pub error: Option<String>,
+}
+
+/// Runs `operation` against every configured tenant, at most `concurrency`
+/// at a time. A tenant that cannot be configured or reached only fails its
+/// own outcome. Outcomes keep the order of `configs`.
+pub async fn fan_out<T, F>(
+    configs: Vec<(String, HawkbitResult<HawkbitConfig>)>,
+    concurrency: usize,
+    operation: F,
+) -> Vec<TenantOutcome<T>>
+where
+    F: AsyncFn(&str, &HawkbitMgmtClient) -> HawkbitResult<T>,
+{
+    let operation = &operation;
+    stream::iter(configs)
+        .map(|(profile, config)| async move {
+            let tenant = config
+                .as_ref()
+                .ok()
+                .and_then(|config| config.tenant().map(str::to_string));
+            let result = match config.and_then(|config| HawkbitMgmtClient::from_config(&config)) {
+                Ok(client) => operation(&profile, &client).await,
+                Err(e) => Err(e),
+            };
+            let (result, error) = match result {
+                Ok(value) => (Some(value), None),
+                Err(e) => (None, Some(e.to_string())),
+            };
+            TenantOutcome {
+                profile,
+                tenant,
+                result,
+                error,
+            }
+        })
+        .buffered(concurrency.max(1))
+        .collect()
+        .await
+}
+
+/// Number of targets per update status
+#[derive(Debug, Default, Serialize)]
+pub struct StatusReport {
+    pub targets: usize,
+    pub in_sync: usize,
+    pub pending: usize,
+    pub error: usize,
+    pub registered: usize,
+    /// Targets that missed their expected poll
+    pub overdue: usize,
+    pub never_seen: usize,
+}
+
+impl StatusReport {
+    pub fn from_targets(targets: &[MgmtTarget]) -> Self {
+        let mut report = StatusReport {
+            targets: targets.len(),
+            ..Default::default()
+        };
+        for target in targets {
+            match target.update_status.as_deref() {
+                Some("in_sync") => report.in_sync += 1,
+                Some("pending") => report.pending += 1,
+                Some("error") => report.error += 1,
+                Some("registered") => report.registered += 1,
+                _ => {}
+            }
+            if target.last_controller_request_at.is_none() {
+                report.never_seen += 1;
+            }
+            let overdue = target
+                .poll_status
+                .as_ref()
+                .and_then(|status| status.get("overdue"))
+                .and_then(Value::as_bool);
+            if overdue == Some(true) {
+                report.overdue += 1;
+            }
+        }
+        report
+    }
+}
+
+pub async fn status_report(client: &HawkbitMgmtClient) -> HawkbitResult<StatusReport> {
+    Ok(StatusReport::from_targets(&client.get_targets(None).await?))
+}
@@ -0,0 +1,171 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::hawkbit::{HawkbitMgmtClient, HawkbitResult};
+
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum EventSource {
+    Target,
+    Action,
+    ActionStatus,
+    Attributes,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TimelineEvent {
+    /// ms since epoch
+    pub timestamp: u64,
+    pub source: EventSource,
+    pub action_id: Option<i64>,
+    pub summary: String,
+    pub messages: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Timeline {
+    pub controller_id: String,
+    pub update_status: Option<String>,
+    pub attributes: HashMap<String, String>,
+    pub events: Vec<TimelineEvent>,
+}
+
+fn format_timestamp(timestamp: u64) -> String {
+    DateTime::<Utc>::from_timestamp_millis(timestamp as i64)
+        .map(|t| t.format("%Y-%m-%d %H:%M:%S%.3f UTC").to_string())
+        .unwrap_or_else(|| timestamp.to_string())
+}
+
+impl fmt::Display for Timeline {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Timeline of {} (update status: {})",
+            self.controller_id,
+            self.update_status.as_deref().unwrap_or("unknown")
+        )?;
+        for event in &self.events {
+            let action = event
+                .action_id
+                .map(|id| format!(" [action {}]", id))
+                .unwrap_or_default();
+            writeln!(
+                f,
+                "{}{} {}",
+                format_timestamp(event.timestamp),
+                action,
+                event.summary
+            )?;
+            for message in &event.messages {
+                writeln!(f, "        {}", message)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Reconstructs the update history of a target from its actions, their full
+/// status history and the target's own timestamps. hawkBit keeps no history
+/// of controller attributes, so they are included as a snapshot taken now.
+pub async fn build_timeline(
+    client: &HawkbitMgmtClient,
+    controller_id: &str,
+    max_actions: usize,
+) -> HawkbitResult<Timeline> {
+    let target = client.get_target(controller_id).await?;
+    let attributes = client.get_target_attributes(controller_id, None).await?;
+    let mut events = Vec::new();
+
+    if let Some(created_at) = target.created_at {
+        events.push(TimelineEvent {
+            timestamp: created_at,
+            source: EventSource::Target,
+            action_id: None,
+            summary: "Target registered".to_string(),
+            messages: Vec::new(),
+        });
+    }
+    if let Some(installed_at) = target.installed_at {
+        events.push(TimelineEvent {
+            timestamp: installed_at.max(0) as u64,
+            source: EventSource::Target,
+            action_id: None,
+            summary: "Installed distribution set reported".to_string(),
+            messages: Vec::new(),
+        });
+    }
+    if let Some(last_request) = target.last_controller_request_at {
+        events.push(TimelineEvent {
+            timestamp: last_request.max(0) as u64,
+            source: EventSource::Target,
+            action_id: None,
+            summary: "Last poll of the controller".to_string(),
+            messages: Vec::new(),
+        });
+    }
+
+    let controller_id = controller_id.to_string();
+    let actions = client
+        .get_target_actions(&controller_id, Some(max_actions), None)
+        .await?;
+    for action in &actions {
+        let detail = client.get_action_detail(&controller_id, &action.id).await?;
+        let distribution_set = detail
+            .links
+            .distribution_set
+            .as_ref()
+            .and_then(|link| link.name.clone())
+            .unwrap_or_else(|| "unknown distribution set".to_string());
+        let rollout = detail
+            .rollout_name
+            .as_ref()
+            .map(|name| format!(" by rollout {:?}", name))
+            .unwrap_or_default();
+
+        events.push(TimelineEvent {
+            timestamp: detail.created_at,
+            source: EventSource::Action,
+            action_id: Some(action.id),
+            summary: format!(
+                "{} action created for {} ({}){}, created by {}",
+                detail.action_type, distribution_set, detail.force_type, rollout, detail.created_by
+            ),
+            messages: Vec::new(),
+        });
+
+        for status in client.get_action_status(&controller_id, &action.id).await? {
+            events.push(TimelineEvent {
+                timestamp: status.reported_at,
+                source: EventSource::ActionStatus,
+                action_id: Some(action.id),
+                summary: format!("status {}", status.event_type),
+                messages: status.messages,
+            });
+        }
+    }
+
+    let mut snapshot: Vec<String> = attributes
+        .iter()
+        .map(|(key, value)| format!("{}={}", key, value))
+        .collect();
+    snapshot.sort();
+    events.push(TimelineEvent {
+        timestamp: Utc::now().timestamp_millis().max(0) as u64,
+        source: EventSource::Attributes,
+        action_id: None,
+        summary: "Current controller attributes".to_string(),
+        messages: snapshot,
+    });
+
+    // Stable sort keeps the action before its own status events on equal timestamps
+    events.sort_by_key(|event| event.timestamp);
+
+    Ok(Timeline {
+        controller_id,
+        update_status: target.update_status,
+        attributes,
+        events,
+    })
+}
@@ -0,0 +1,507 @@
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::crypto;
+use crate::hawkbit::{HawkbitMgmtClient, HawkbitResult, MgmtTarget};
+use crate::provision::generate_security_token;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RotationStatus {
+    /// New token set, the device has not polled with it yet
+    Pending,
+    /// The device polled after the rotation, so it uses the new token
+    Confirmed,
+    /// The old token was restored
+    Reverted,
+    /// Setting the new token failed; both tokens are kept in case the
+    /// request was applied after all
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RotationEntry {
+    pub controller_id: String,
+    pub old_token: String,
+    pub new_token: String,
+    /// ms since epoch
+    pub rotated_at: i64,
+    pub status: RotationStatus,
+}
+
+/// Rotation state, kept encrypted on disk as it contains the old and new
+/// tokens of every rotated target.
+pub struct RotationStore {
+    path: PathBuf,
+    passphrase: String,
+    pub entries: Vec<RotationEntry>,
+}
+
+impl RotationStore {
+    /// Opens the store, starting empty if the file does not exist yet.
+    pub fn open(path: &Path, passphrase: &str) -> HawkbitResult<Self> {
+        let entries = if path.exists() {
+            serde_json::from_slice(&crypto::read_encrypted(path, passphrase)?)?
+        } else {
+            Vec::new()
+        };
+        Ok(Self {
+            path: path.to_path_buf(),
+            passphrase: passphrase.to_string(),
+            entries,
+        })
+    }
+
+    pub fn save(&self) -> HawkbitResult<()> {
+        crypto::write_encrypted(
+            &self.path,
+            &self.passphrase,
+            &serde_json::to_vec(&self.entries)?,
+        )
+    }
+
+    /// Whether the target has a rotation in progress or completed. Reverted
+    /// and failed rotations leave the target on its old token.
+    fn is_rotated(&self, controller_id: &str) -> bool {
+        self.entries.iter().any(|e| {
+            e.controller_id == controller_id
+                && matches!(
+                    e.status,
+                    RotationStatus::Pending | RotationStatus::Confirmed
+                )
+        })
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct RotationOutcome {
+    pub controller_id: String,
+    pub error: Option<String>,
+}
+
+/// Gives up to `limit` of the targets a new security token. Targets with a
+/// pending or confirmed rotation are skipped, so repeated runs roll out in
+/// stages. The state is saved before each token is changed so the old token
+/// can always be restored.
+pub async fn rotate(
+    client: &HawkbitMgmtClient,
+    store: &mut RotationStore,
+    targets: &[MgmtTarget],
+    limit: usize,
+    dry_run: bool,
+) -> HawkbitResult<Vec<RotationOutcome>> {
+    let selected: Vec<&MgmtTarget> = targets
+        .iter()
+        .filter(|target| !store.is_rotated(&target.controller_id))
+        .take(limit)
+        .collect();
+    let mut outcomes = Vec::new();
+    for target in selected {
+        let controller_id = target.controller_id.clone();
+        // The list endpoint omits the token, only the single target has it
+        let old_token = match client.get_target(&controller_id).await {
+            Ok(MgmtTarget {
+                security_token: Some(token),
+                ..
+            }) => token,
+            Ok(_) => {
+                outcomes.push(RotationOutcome {
+                    controller_id,
+                    error: Some("security token is not readable".to_string()),
+                });
+                continue;
+            }
+            Err(e) => {
+                outcomes.push(RotationOutcome {
+                    controller_id,
+                    error: Some(e.to_string()),
+                });
+                continue;
+            }
+        };
+        if dry_run {
+            outcomes.push(RotationOutcome {
+                controller_id,
+                error: None,
+            });
+            continue;
+        }
+
+        let new_token = generate_security_token();
+        store.entries.push(RotationEntry {
+            controller_id: controller_id.clone(),
+            old_token,
+            new_token: new_token.clone(),
+            rotated_at: Utc::now().timestamp_millis(),
+            status: RotationStatus::Pending,
+        });
+        store.save()?;
+
+        let result = client
+            .modify_target(&controller_id, json!({ "securityToken": new_token }))
+            .await;
+        if let Err(e) = result {
+            if let Some(entry) = store.entries.last_mut() {
+                entry.status = RotationStatus::Failed;
+            }
+            store.save()?;
+            outcomes.push(RotationOutcome {
+                controller_id,
+                error: Some(e.to_string()),
+            });
+            continue;
+        }
+        outcomes.push(RotationOutcome {
+            controller_id,
+            error: None,
+        });
+    }
+    Ok(outcomes)
+}
+
+/// Marks pending rotations as confirmed for devices that polled since.
+/// The old token is invalid after the rotation, so a successful poll proves
+/// the device uses the new one.
+///
+/// hawkBit does not record which credentials a poll used. A device that
+/// polls with a gateway token is confirmed as well, without proving anything
+/// about its target token; rotate only targets that authenticate with their
+/// own token.
+pub async fn refresh_status(
+    client: &HawkbitMgmtClient,
+    store: &mut RotationStore,
+) -> HawkbitResult<()> {
+    for entry in store
+        .entries
+        .iter_mut()
+        .filter(|entry| entry.status == RotationStatus::Pending)
+    {
+        let target = match client.get_target(&entry.controller_id).await {
+            Ok(target) => target,
+            Err(e) => {
+                tracing::warn!("Failed to get target {:?}: {}", entry.controller_id, e);
+                continue;
+            }
+        };
+        if target
+            .last_controller_request_at
+            .is_some_and(|last_seen| last_seen > entry.rotated_at)
+        {
+            entry.status = RotationStatus::Confirmed;
+        }
+    }
+    store.save()
+}
+
+/// Restores the old token of pending rotations older than `grace`, for
+/// devices that never picked up their new token.
+pub async fn revert_stale(
+    client: &HawkbitMgmtClient,
+    store: &mut RotationStore,
+    grace: Duration,
+    dry_run: bool,
+) -> HawkbitResult<Vec<RotationOutcome>> {
+    let cutoff = Utc::now().timestamp_millis() - grace.as_millis() as i64;
+    let mut outcomes = Vec::new();
+    for entry in store
+        .entries
+        .iter_mut()
+        .filter(|entry| entry.status == RotationStatus::Pending && entry.rotated_at <= cutoff)
+    {
+        let mut outcome = RotationOutcome {
+            controller_id: entry.controller_id.clone(),
+            error: None,
+        };
+        if !dry_run {
+            match client
+                .modify_target(
+                    &entry.controller_id,
+                    json!({ "securityToken": entry.old_token }),
+                )
+                .await
+            {
+                Ok(_) => entry.status = RotationStatus::Reverted,
+                Err(e) => outcome.error = Some(e.to_string()),
+            }
+        }
+        outcomes.push(outcome);
+    }
+    if !dry_run {
+        store.save()?;
+    }
+    Ok(outcomes)
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ExportedToken {
+    #[serde(rename = "controllerId")]
+    pub controller_id: String,
+    #[serde(rename = "securityToken")]
+    pub security_token: String,
+}
+
+/// Writes the current token of every rotated, not reverted or failed target
+/// to an encrypted JSON file for the provisioning system.
+pub fn export_tokens(store: &RotationStore, path: &Path, passphrase: &str) -> HawkbitResult<usize> {
+    let mut seen = HashSet::new();
+    let tokens: Vec<ExportedToken> = store
+        .entries
+        .iter()
+        .rev()
+        // Only the latest rotation of a target is current
+        .filter(|entry| seen.insert(entry.controller_id.as_str()))
+        .filter(|entry| {
+            matches!(
+                entry.status,
+                RotationStatus::Pending | RotationStatus::Confirmed
+            )
+        })
+        .map(|entry| ExportedToken {
+            controller_id: entry.controller_id.clone(),
+            security_token: entry.new_token.clone(),
+        })
+        .collect();
+    crypto::write_encrypted(path, passphrase, &serde_json::to_vec_pretty(&tokens)?)?;
+    Ok(tokens.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::HawkbitConfig;
+    use axum::Router;
+    use axum::extract::{Path as UrlPath, State};
+    use axum::http::StatusCode;
+    use axum::routing::get;
+    use serde_json::Value;
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+
+    /// Security token and last poll of every target on the fake server
+    type Targets = Arc<Mutex<HashMap<String, (String, Option<i64>)>>>;
+
+    const BROKEN: &str = "broken";
+
+    async fn get_target(
+        State(targets): State<Targets>,
+        UrlPath(id): UrlPath<String>,
+    ) -> Result<axum::Json<Value>, StatusCode> {
+        let targets = targets.lock().unwrap();
+        let (token, last_seen) = targets.get(&id).ok_or(StatusCode::NOT_FOUND)?;
+        Ok(axum::Json(json!({
+            "_links": {},
+            "controllerId": id,
+            "securityToken": token,
+            "lastControllerRequestAt": last_seen,
+        })))
+    }
+
+    async fn put_target(
+        State(targets): State<Targets>,
+        UrlPath(id): UrlPath<String>,
+        axum::Json(body): axum::Json<Value>,
+    ) -> Result<axum::Json<Value>, StatusCode> {
+        if id == BROKEN {
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+        let mut targets = targets.lock().unwrap();
+        let target = targets.get_mut(&id).ok_or(StatusCode::NOT_FOUND)?;
+        target.0 = body["securityToken"].as_str().unwrap().to_string();
+        Ok(axum::Json(json!({ "controllerId": id })))
+    }
+
+    async fn serve(ids: &[&str]) -> (HawkbitMgmtClient, Targets) {
+        let targets: Targets = Arc::new(Mutex::new(
+            ids.iter()
+                .map(|id| (id.to_string(), (format!("{}-old", id), None)))
+                .collect(),
+        ));
+        let app = Router::new()
+            .route("/rest/v1/targets/{id}", get(get_target).put(put_target))
+            .with_state(targets.clone());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let host = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        let client = HawkbitMgmtClient::from_config(&HawkbitConfig::for_tests(&host)).unwrap();
+        (client, targets)
+    }
+
+    fn listed(ids: &[&str]) -> Vec<MgmtTarget> {
+        ids.iter()
+            .map(|id| serde_json::from_value(json!({ "_links": {}, "controllerId": id })).unwrap())
+            .collect()
+    }
+
+    fn store_path(test: &str) -> PathBuf {
+        std::env::temp_dir().join(format!(
+            "hawkbit-rotation-{}-{}.enc",
+            test,
+            std::process::id()
+        ))
+    }
+
+    fn token(targets: &Targets, id: &str) -> String {
+        targets.lock().unwrap()[id].0.clone()
+    }
+
+    fn entry(controller_id: &str, rotated_at: i64, status: RotationStatus) -> RotationEntry {
+        RotationEntry {
+            controller_id: controller_id.to_string(),
+            old_token: format!("{}-old", controller_id),
+            new_token: format!("{}-new", controller_id),
+            rotated_at,
+            status,
+        }
+    }
+
+    #[tokio::test]
+    async fn rotation_rolls_out_in_stages() {
+        let ids = ["dev-1", "dev-2", "dev-3"];
+        let (client, targets) = serve(&ids).await;
+        let path = store_path("stages");
+        let mut store = RotationStore::open(&path, "pass").unwrap();
+
+        let outcomes = rotate(&client, &mut store, &listed(&ids), 2, true)
+            .await
+            .unwrap();
+        assert_eq!(outcomes.len(), 2);
+        assert!(store.entries.is_empty());
+        assert_eq!(token(&targets, "dev-1"), "dev-1-old");
+        assert!(!path.exists());
+
+        rotate(&client, &mut store, &listed(&ids), 2, false)
+            .await
+            .unwrap();
+        for entry in &store.entries {
+            assert_eq!(entry.status, RotationStatus::Pending);
+            assert_eq!(entry.old_token, format!("{}-old", entry.controller_id));
+            assert_eq!(token(&targets, &entry.controller_id), entry.new_token);
+        }
+
+        // The saved state knows the first stage, so only dev-3 is left
+        let mut store = RotationStore::open(&path, "pass").unwrap();
+        assert_eq!(store.entries.len(), 2);
+        let outcomes = rotate(&client, &mut store, &listed(&ids), 2, false)
+            .await
+            .unwrap();
+        let rotated: Vec<_> = outcomes.iter().map(|o| o.controller_id.as_str()).collect();
+        assert_eq!(rotated, ["dev-3"]);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn failed_rotation_keeps_both_tokens_and_is_retried() {
+        let (client, _targets) = serve(&[BROKEN]).await;
+        let path = store_path("failed");
+        let mut store = RotationStore::open(&path, "pass").unwrap();
+
+        let outcomes = rotate(&client, &mut store, &listed(&[BROKEN]), 10, false)
+            .await
+            .unwrap();
+        assert!(outcomes[0].error.is_some());
+        assert_eq!(store.entries[0].status, RotationStatus::Failed);
+        assert_eq!(store.entries[0].old_token, "broken-old");
+        assert!(!store.is_rotated(BROKEN));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn devices_that_polled_since_the_rotation_are_confirmed() {
+        let (client, targets) = serve(&["polled", "silent", "before"]).await;
+        let path = store_path("confirm");
+        let mut store = RotationStore::open(&path, "pass").unwrap();
+        store.entries = vec![
+            entry("polled", 1_000, RotationStatus::Pending),
+            entry("silent", 1_000, RotationStatus::Pending),
+            entry("before", 1_000, RotationStatus::Pending),
+        ];
+        {
+            let mut targets = targets.lock().unwrap();
+            targets.get_mut("polled").unwrap().1 = Some(1_001);
+            targets.get_mut("before").unwrap().1 = Some(1_000);
+        }
+
+        refresh_status(&client, &mut store).await.unwrap();
+        let statuses: Vec<_> = store.entries.iter().map(|e| e.status).collect();
+        assert_eq!(
+            statuses,
+            [
+                RotationStatus::Confirmed,
+                RotationStatus::Pending,
+                RotationStatus::Pending
+            ]
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn only_stale_pending_rotations_are_reverted() {
+        let (client, targets) = serve(&["stale", "fresh", "confirmed"]).await;
+        let path = store_path("revert");
+        let mut store = RotationStore::open(&path, "pass").unwrap();
+        let now = Utc::now().timestamp_millis();
+        let hour = Duration::from_secs(3600);
+        store.entries = vec![
+            entry("stale", now - 2 * 3_600_000, RotationStatus::Pending),
+            entry("fresh", now, RotationStatus::Pending),
+            entry("confirmed", now - 2 * 3_600_000, RotationStatus::Confirmed),
+        ];
+        for id in ["stale", "fresh", "confirmed"] {
+            targets.lock().unwrap().get_mut(id).unwrap().0 = format!("{}-new", id);
+        }
+
+        let outcomes = revert_stale(&client, &mut store, hour, true).await.unwrap();
+        assert_eq!(outcomes.len(), 1);
+        assert_eq!(store.entries[0].status, RotationStatus::Pending);
+        assert_eq!(token(&targets, "stale"), "stale-new");
+        assert!(!path.exists(), "dry run must not save the state");
+
+        let outcomes = revert_stale(&client, &mut store, hour, false)
+            .await
+            .unwrap();
+        assert_eq!(outcomes[0].controller_id, "stale");
+        assert!(outcomes[0].error.is_none());
+        assert_eq!(token(&targets, "stale"), "stale-old");
+        assert_eq!(token(&targets, "fresh"), "fresh-new");
+        assert_eq!(token(&targets, "confirmed"), "confirmed-new");
+        let saved = RotationStore::open(&path, "pass").unwrap();
+        let statuses: Vec<_> = saved.entries.iter().map(|e| e.status).collect();
+        assert_eq!(
+            statuses,
+            [
+                RotationStatus::Reverted,
+                RotationStatus::Pending,
+                RotationStatus::Confirmed
+            ]
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn export_contains_the_latest_active_token_of_each_target() {
+        let path = store_path("export");
+        let mut store = RotationStore::open(&path, "pass").unwrap();
+        store.entries = vec![
+            entry("dev-1", 1, RotationStatus::Confirmed),
+            entry("dev-2", 1, RotationStatus::Reverted),
+            entry("dev-3", 1, RotationStatus::Failed),
+            entry("dev-4", 1, RotationStatus::Pending),
+            entry("dev-1", 2, RotationStatus::Reverted),
+            entry("dev-2", 2, RotationStatus::Pending),
+        ];
+
+        assert_eq!(export_tokens(&store, &path, "pass").unwrap(), 2);
+        let exported: Vec<ExportedToken> =
+            serde_json::from_slice(&crypto::read_encrypted(&path, "pass").unwrap()).unwrap();
+        let exported: Vec<_> = exported
+            .iter()
+            .map(|t| (t.controller_id.as_str(), t.security_token.as_str()))
+            .collect();
+        assert_eq!(exported, [("dev-2", "dev-2-new"), ("dev-4", "dev-4-new")]);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
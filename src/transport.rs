@@ -0,0 +1,124 @@
+use reqwest::tls::Version;
+use reqwest::{Certificate, Client, Identity, Proxy, header};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::hawkbit::{HawkbitError, HawkbitResult};
+
+/// Connection settings of the HTTP client: TLS, proxy and timeouts.
+#[derive(Debug, Clone, Default)]
+pub struct TransportConfig {
+    /// PEM client certificate for mutual TLS, used with `client_key`
+    pub client_cert: Option<PathBuf>,
+    /// PEM private key of the client certificate in PKCS#8 format
+    pub client_key: Option<PathBuf>,
+    /// PEM bundle of CAs trusted in addition to the system roots
+    pub ca_bundle: Option<PathBuf>,
+    pub min_tls_version: Option<Version>,
+    /// `http://`, `https://` or `socks5://` URL. Without it the standard
+    /// `HTTPS_PROXY`/`ALL_PROXY` variables apply.
+    pub proxy: Option<String>,
+    /// Comma separated hosts that bypass `proxy`
+    pub no_proxy: Option<String>,
+    pub connect_timeout: Option<Duration>,
+    /// Maximum time between two reads of a response
+    pub read_timeout: Option<Duration>,
+    pub user_agent: Option<String>,
+}
+
+pub fn parse_tls_version(version: &str) -> HawkbitResult<Version> {
+    match version
+        .trim()
+        .trim_start_matches("TLS")
+        .trim_start_matches('v')
+    {
+        "1.0" => Ok(Version::TLS_1_0),
+        "1.1" => Ok(Version::TLS_1_1),
+        "1.2" => Ok(Version::TLS_1_2),
+        "1.3" => Ok(Version::TLS_1_3),
+        _ => Err(HawkbitError::new(format!(
+            "Unsupported TLS version {:?}, expected 1.0, 1.1, 1.2 or 1.3",
+            version
+        ))),
+    }
+}
+
+fn read_file(path: &Path, what: &str) -> HawkbitResult<Vec<u8>> {
+    std::fs::read(path).map_err(|e| {
+        HawkbitError::new(format!("Failed to read {} {}: {}", what, path.display(), e))
+    })
+}
+
+fn config_error(what: &str, err: reqwest::Error) -> HawkbitError {
+    HawkbitError::new(format!("Invalid {}: {}", what, err))
+}
+
+pub const DEFAULT_USER_AGENT: &str =
+    concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
+
+/// Builds an HTTP client sending `default_headers` with every request.
+pub fn build_client(
+    config: &TransportConfig,
+    default_headers: header::HeaderMap,
+) -> HawkbitResult<Client> {
+    let mut builder = Client::builder()
+        .default_headers(default_headers)
+        .user_agent(config.user_agent.as_deref().unwrap_or(DEFAULT_USER_AGENT));
+
+    match (&config.client_cert, &config.client_key) {
+        (Some(cert), Some(key)) => {
+            let identity = Identity::from_pkcs8_pem(
+                &read_file(cert, "client certificate")?,
+                &read_file(key, "client key")?,
+            )
+            .map_err(|e| config_error("client certificate or key", e))?;
+            builder = builder.identity(identity);
+        }
+        (None, None) => {}
+        _ => {
+            return Err(HawkbitError::new(
+                "Client certificate and key must be configured together",
+            ));
+        }
+    }
+
+    if let Some(path) = &config.ca_bundle {
+        let certs = Certificate::from_pem_bundle(&read_file(path, "CA bundle")?)
+            .map_err(|e| config_error("CA bundle", e))?;
+        if certs.is_empty() {
+            return Err(HawkbitError::new(format!(
+                "CA bundle {} contains no certificates",
+                path.display()
+            )));
+        }
+        for cert in certs {
+            builder = builder.add_root_certificate(cert);
+        }
+    }
+    if let Some(version) = config.min_tls_version {
+        builder = builder.min_tls_version(version);
+    }
+
+    if let Some(url) = &config.proxy {
+        let proxy = Proxy::all(url)
+            .map_err(|e| config_error("proxy URL", e))?
+            .no_proxy(
+                config
+                    .no_proxy
+                    .as_deref()
+                    .and_then(reqwest::NoProxy::from_string),
+            );
+        builder = builder.proxy(proxy);
+    }
+
+    if let Some(timeout) = config.connect_timeout {
+        builder = builder.connect_timeout(timeout);
+    }
+    if let Some(timeout) = config.read_timeout {
+        builder = builder.read_timeout(timeout);
+    }
+
+    builder
+        .build()
+        .map_err(|e| HawkbitError::new(format!("Failed to build HTTP client: {}", e)))
+}
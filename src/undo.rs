@@ -0,0 +1,272 @@
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+use crate::hawkbit::{
+    Action, AssignmentRequest, DistributionSet, HawkbitMgmtClient, HawkbitResult, MetadataEntry,
+    NewTarget,
+};
+
+/// Number of recent actions kept in a snapshot, as a record only
+const SNAPSHOT_ACTIONS: usize = 50;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DistributionSetRef {
+    pub id: u64,
+    pub name: String,
+    pub version: String,
+}
+
+impl From<DistributionSet> for DistributionSetRef {
+    fn from(set: DistributionSet) -> Self {
+        Self {
+            id: set.id,
+            name: set.name,
+            version: set.version,
+        }
+    }
+}
+
+/// Everything needed to recreate a target after it was deleted
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TargetSnapshot {
+    /// ms since epoch
+    pub captured_at: i64,
+    pub controller_id: String,
+    pub name: Option<String>,
+    pub description: Option<String>,
+    /// Only readable with the READ_TARGET_SECURITY_TOKEN permission
+    pub security_token: Option<String>,
+    pub target_type: Option<i64>,
+    pub target_type_name: Option<String>,
+    /// Tag names, as tag IDs may change if a tag is recreated
+    pub tags: Vec<String>,
+    pub metadata: Vec<MetadataEntry>,
+    pub attributes: HashMap<String, String>,
+    pub assigned_distribution_set: Option<DistributionSetRef>,
+    pub installed_distribution_set: Option<DistributionSetRef>,
+    /// Most recent actions first; not restored
+    #[serde(default)]
+    pub actions: Vec<Action>,
+}
+
+/// Collects the current state of a target.
+pub async fn capture_target(
+    client: &HawkbitMgmtClient,
+    controller_id: &str,
+) -> HawkbitResult<TargetSnapshot> {
+    let target = client.get_target(controller_id).await?;
+    let tags = client.get_tags_of_target(controller_id).await?;
+    let metadata = client.get_target_metadata(controller_id, None).await?;
+    let attributes = client.get_target_attributes(controller_id, None).await?;
+    let assigned = client.get_assigned_distribution_set(controller_id).await?;
+    let installed = client.get_installed_distribution_set(controller_id).await?;
+    let actions = client
+        .get_target_actions(controller_id, Some(SNAPSHOT_ACTIONS), None)
+        .await?;
+
+    if target.security_token.is_none() {
+        tracing::warn!(
+            "Security token of {:?} is not readable, a restored target gets a new one",
+            controller_id
+        );
+    }
+
+    Ok(TargetSnapshot {
+        captured_at: Utc::now().timestamp_millis(),
+        controller_id: target.controller_id,
+        name: target.name,
+        description: target.description,
+        security_token: target.security_token,
+        target_type: target.target_type,
+        target_type_name: target.target_type_name,
+        tags: tags.into_iter().map(|tag| tag.name).collect(),
+        metadata,
+        attributes,
+        assigned_distribution_set: assigned.map(DistributionSetRef::from),
+        installed_distribution_set: installed.map(DistributionSetRef::from),
+        actions,
+    })
+}
+
+/// Append-only JSON lines file of target snapshots
+#[derive(Debug, Clone)]
+pub struct UndoJournal {
+    path: PathBuf,
+}
+
+impl UndoJournal {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Appends a snapshot. Snapshots carry security tokens, so the journal
+    /// is only readable by the current user.
+    pub fn append(&self, snapshot: &TargetSnapshot) -> HawkbitResult<()> {
+        let mut options = std::fs::OpenOptions::new();
+        options.create(true).append(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let mut file = options.open(&self.path)?;
+        // Journals written before were world-readable
+        #[cfg(unix)]
+        file.set_permissions(std::os::unix::fs::PermissionsExt::from_mode(0o600))?;
+        writeln!(file, "{}", serde_json::to_string(snapshot)?)?;
+        file.sync_all()?;
+        Ok(())
+    }
+
+    pub fn entries(&self) -> HawkbitResult<Vec<TargetSnapshot>> {
+        let file = match std::fs::File::open(&self.path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut entries = Vec::new();
+        for line in BufReader::new(file).lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            entries.push(serde_json::from_str(&line)?);
+        }
+        Ok(entries)
+    }
+
+    /// The most recent snapshot of a target
+    pub fn latest(&self, controller_id: &str) -> HawkbitResult<Option<TargetSnapshot>> {
+        Ok(self
+            .entries()?
+            .into_iter()
+            .rev()
+            .find(|snapshot| snapshot.controller_id == controller_id))
+    }
+}
+
+/// Deletes a target after recording its snapshot in the journal. Nothing is
+/// deleted if the snapshot cannot be taken or written.
+pub async fn delete_target(
+    client: &HawkbitMgmtClient,
+    journal: &UndoJournal,
+    controller_id: &str,
+) -> HawkbitResult<TargetSnapshot> {
+    let snapshot = capture_target(client, controller_id).await?;
+    delete_captured(client, journal, &snapshot).await?;
+    Ok(snapshot)
+}
+
+/// Deletes the target of a snapshot taken with `capture_target`, recording
+/// the snapshot in the journal first.
+pub async fn delete_captured(
+    client: &HawkbitMgmtClient,
+    journal: &UndoJournal,
+    snapshot: &TargetSnapshot,
+) -> HawkbitResult<()> {
+    journal.append(snapshot)?;
+    client.delete_target(&snapshot.controller_id).await?;
+    Ok(())
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct RestoreResult {
+    pub controller_id: String,
+    pub same_security_token: bool,
+    pub tags: usize,
+    pub metadata: usize,
+    pub assigned_distribution_set: Option<u64>,
+    /// Parts of the snapshot that could not be restored
+    pub warnings: Vec<String>,
+}
+
+/// Recreates a target from a snapshot and reassigns its last distribution set.
+///
+/// Controller attributes cannot be written through the management API; the
+/// restored target is asked to report them again instead.
+pub async fn restore_target(
+    client: &HawkbitMgmtClient,
+    snapshot: &TargetSnapshot,
+    request: &AssignmentRequest,
+) -> HawkbitResult<RestoreResult> {
+    let controller_id = snapshot.controller_id.as_str();
+    let new_target = NewTarget {
+        controller_id: controller_id.to_string(),
+        name: snapshot
+            .name
+            .clone()
+            .unwrap_or_else(|| controller_id.to_string()),
+        description: snapshot.description.clone(),
+        security_token: snapshot.security_token.clone(),
+        target_type: snapshot.target_type,
+    };
+    client
+        .create_targets(std::slice::from_ref(&new_target))
+        .await?;
+
+    let mut result = RestoreResult {
+        controller_id: controller_id.to_string(),
+        same_security_token: snapshot.security_token.is_some(),
+        ..Default::default()
+    };
+    if snapshot.security_token.is_none() {
+        result
+            .warnings
+            .push("security token was not captured, hawkBit generated a new one".to_string());
+    }
+
+    if !snapshot.metadata.is_empty() {
+        match client
+            .create_target_metadata(controller_id, &snapshot.metadata)
+            .await
+        {
+            Ok(_) => result.metadata = snapshot.metadata.len(),
+            Err(e) => result.warnings.push(format!("metadata: {}", e)),
+        }
+    }
+
+    for name in &snapshot.tags {
+        let assigned = match client.ensure_target_tag(name).await {
+            Ok(tag) => client.assign_target_tag(tag.id, &[controller_id]).await,
+            Err(e) => Err(e),
+        };
+        match assigned {
+            Ok(_) => result.tags += 1,
+            Err(e) => result.warnings.push(format!("tag {:?}: {}", name, e)),
+        }
+    }
+
+    // Prefer the assignment, it is what the target was about to install
+    let set = snapshot
+        .assigned_distribution_set
+        .as_ref()
+        .or(snapshot.installed_distribution_set.as_ref());
+    if let Some(set) = set {
+        match client
+            .assign_distribution(controller_id, &set.id, request)
+            .await
+        {
+            Ok(_) => result.assigned_distribution_set = Some(set.id),
+            Err(e) => result.warnings.push(format!(
+                "distribution set {} {} ({}): {}",
+                set.name, set.version, set.id, e
+            )),
+        }
+    }
+
+    if !snapshot.attributes.is_empty()
+        && let Err(e) = client
+            .modify_target(controller_id, json!({ "requestAttributes": true }))
+            .await
+    {
+        result
+            .warnings
+            .push(format!("requesting attributes: {}", e));
+    }
+    Ok(result)
+}
@@ -0,0 +1,211 @@
+//! OAuth2 client credentials against a local token endpoint, and the retry of
+//! management requests whose token the server rejects.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use axum::extract::{Path, State};
+use axum::http::{HeaderMap, StatusCode, header};
+use axum::routing::{get, post};
+use axum::{Form, Json, Router};
+use hawkbit_data_proxy_rs::auth::{AuthStrategy, OAuth2ClientCredentials};
+use hawkbit_data_proxy_rs::config::HawkbitConfig;
+use hawkbit_data_proxy_rs::hawkbit::HawkbitMgmtClient;
+use serde_json::{Value, json};
+
+// client:secret
+const CLIENT_CREDENTIALS: &str = "Basic Y2xpZW50OnNlY3JldA==";
+
+#[derive(Default)]
+struct Server {
+    /// Number of tokens handed out, the last one is `token-<issued>`
+    issued: AtomicUsize,
+    /// Tokens numbered below this are rejected by the API
+    valid_from: AtomicUsize,
+    api_requests: AtomicUsize,
+}
+
+type Shared = Arc<Server>;
+
+fn issue_token(
+    server: &Server,
+    headers: &HeaderMap,
+    form: &HashMap<String, String>,
+    expires_in: u64,
+) -> Result<Json<Value>, StatusCode> {
+    let authorized = headers
+        .get(header::AUTHORIZATION)
+        .is_some_and(|value| value == CLIENT_CREDENTIALS);
+    if !authorized {
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+    if form.get("grant_type").map(String::as_str) != Some("client_credentials")
+        || form.get("scope").map(String::as_str) != Some("hawkbit")
+    {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let number = server.issued.fetch_add(1, Ordering::SeqCst) + 1;
+    Ok(Json(json!({
+        "access_token": format!("token-{}", number),
+        "token_type": "Bearer",
+        "expires_in": expires_in,
+    })))
+}
+
+async fn long_lived(
+    State(server): State<Shared>,
+    headers: HeaderMap,
+    Form(form): Form<HashMap<String, String>>,
+) -> Result<Json<Value>, StatusCode> {
+    issue_token(&server, &headers, &form, 3600)
+}
+
+/// Lifetime within the refresh margin, so the token is never reused
+async fn short_lived(
+    State(server): State<Shared>,
+    headers: HeaderMap,
+    Form(form): Form<HashMap<String, String>>,
+) -> Result<Json<Value>, StatusCode> {
+    issue_token(&server, &headers, &form, 10)
+}
+
+async fn target(
+    State(server): State<Shared>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+) -> Result<Json<Value>, StatusCode> {
+    server.api_requests.fetch_add(1, Ordering::SeqCst);
+    let number: usize = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer token-"))
+        .and_then(|number| number.parse().ok())
+        .ok_or(StatusCode::UNAUTHORIZED)?;
+    if number < server.valid_from.load(Ordering::SeqCst) {
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+    Ok(Json(json!({ "_links": {}, "controllerId": id })))
+}
+
+async fn serve() -> (String, Shared) {
+    let server = Shared::default();
+    let app = Router::new()
+        .route("/token", post(long_lived))
+        .route("/short-token", post(short_lived))
+        .route("/rest/v1/targets/{id}", get(target))
+        .with_state(server.clone());
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let base = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    (base, server)
+}
+
+fn credentials(token_url: &str) -> OAuth2ClientCredentials {
+    OAuth2ClientCredentials::new(
+        reqwest::Client::new(),
+        token_url,
+        "client",
+        "secret",
+        Some("hawkbit"),
+    )
+}
+
+/// The Authorization header `auth` adds to a request
+async fn authorization(auth: &OAuth2ClientCredentials) -> String {
+    let request = reqwest::Client::new().get("http://127.0.0.1/");
+    let request = auth.authenticate(request).await.unwrap().build().unwrap();
+    request.headers()[header::AUTHORIZATION]
+        .to_str()
+        .unwrap()
+        .to_string()
+}
+
+/// Management client with OAuth2 credentials, configured through a profile
+fn mgmt_client(base: &str, test: &str) -> HawkbitMgmtClient {
+    let path =
+        std::env::temp_dir().join(format!("hawkbit-auth-{}-{}.toml", test, std::process::id()));
+    let config = format!(
+        r#"
+[profiles.test]
+host = "{0}"
+
+[profiles.test.auth]
+type = "oauth2"
+token_url = "{0}/token"
+client_id = "client"
+client_secret = "secret"
+scope = "hawkbit"
+"#,
+        base
+    );
+    std::fs::write(&path, config).unwrap();
+    let (_, config) = HawkbitConfig::load_profiles(Some(&path), &[])
+        .unwrap()
+        .pop()
+        .unwrap();
+    std::fs::remove_file(&path).unwrap();
+    HawkbitMgmtClient::from_config(&config.unwrap()).unwrap()
+}
+
+#[tokio::test]
+async fn token_is_cached_until_it_expires() {
+    let (base, server) = serve().await;
+
+    let auth = credentials(&format!("{}/token", base));
+    assert_eq!(authorization(&auth).await, "Bearer token-1");
+    assert_eq!(authorization(&auth).await, "Bearer token-1");
+    assert_eq!(server.issued.load(Ordering::SeqCst), 1);
+
+    let auth = credentials(&format!("{}/short-token", base));
+    assert_eq!(authorization(&auth).await, "Bearer token-2");
+    assert_eq!(authorization(&auth).await, "Bearer token-3");
+}
+
+#[tokio::test]
+async fn invalidated_token_is_fetched_again() {
+    let (base, _server) = serve().await;
+    let auth = credentials(&format!("{}/token", base));
+    assert_eq!(authorization(&auth).await, "Bearer token-1");
+    assert!(auth.invalidate().await);
+    assert_eq!(authorization(&auth).await, "Bearer token-2");
+}
+
+#[tokio::test]
+async fn rejected_client_credentials_are_reported() {
+    let (base, server) = serve().await;
+    let auth = OAuth2ClientCredentials::new(
+        reqwest::Client::new(),
+        &format!("{}/token", base),
+        "client",
+        "wrong",
+        Some("hawkbit"),
+    );
+    let request = reqwest::Client::new().get("http://127.0.0.1/");
+    let err = auth.authenticate(request).await.unwrap_err();
+    assert!(err.to_string().contains("HTTP 401"), "{}", err);
+    assert_eq!(server.issued.load(Ordering::SeqCst), 0);
+}
+
+#[tokio::test]
+async fn rejected_token_is_refreshed_and_retried_once() {
+    let (base, server) = serve().await;
+    let client = mgmt_client(&base, "retry");
+
+    client.get_target("dev-1").await.unwrap();
+    assert_eq!(server.issued.load(Ordering::SeqCst), 1);
+    assert_eq!(server.api_requests.load(Ordering::SeqCst), 1);
+
+    // The server revokes token-1: one 401, then a retry with token-2
+    server.valid_from.store(2, Ordering::SeqCst);
+    client.get_target("dev-1").await.unwrap();
+    assert_eq!(server.issued.load(Ordering::SeqCst), 2);
+    assert_eq!(server.api_requests.load(Ordering::SeqCst), 3);
+
+    // A fresh token that is rejected as well is not retried again
+    server.valid_from.store(usize::MAX, Ordering::SeqCst);
+    let err = client.get_target("dev-1").await.unwrap_err();
+    assert!(err.to_string().contains("401"), "{}", err);
+    assert_eq!(server.issued.load(Ordering::SeqCst), 3);
+    assert_eq!(server.api_requests.load(Ordering::SeqCst), 5);
+}
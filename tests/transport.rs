@@ -0,0 +1,277 @@
+//! `build_client` against a local TLS listener that only trusts its own CA
+//! and requires a client certificate issued by it.
+
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener};
+use std::path::PathBuf;
+
+use hawkbit_data_proxy_rs::transport::{TransportConfig, build_client};
+use openssl::asn1::Asn1Time;
+use openssl::bn::{BigNum, MsbOption};
+use openssl::ec::{EcGroup, EcKey};
+use openssl::hash::MessageDigest;
+use openssl::nid::Nid;
+use openssl::pkey::{PKey, Private};
+use openssl::ssl::{SslAcceptor, SslMethod, SslVerifyMode};
+use openssl::x509::extension::{
+    BasicConstraints, ExtendedKeyUsage, KeyUsage, SubjectAlternativeName,
+};
+use openssl::x509::{X509, X509NameBuilder};
+use reqwest::header::HeaderMap;
+
+fn generate_key() -> PKey<Private> {
+    let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+    PKey::from_ec_key(EcKey::generate(&group).unwrap()).unwrap()
+}
+
+enum Role {
+    Ca,
+    Server,
+    Client,
+}
+
+/// Issues a certificate for `key`, self-signed if no issuer is given.
+fn issue(
+    common_name: &str,
+    key: &PKey<Private>,
+    role: Role,
+    issuer: Option<(&X509, &PKey<Private>)>,
+) -> X509 {
+    let mut name = X509NameBuilder::new().unwrap();
+    name.append_entry_by_nid(Nid::COMMONNAME, common_name)
+        .unwrap();
+    let name = name.build();
+
+    let mut builder = X509::builder().unwrap();
+    builder.set_version(2).unwrap();
+    let mut serial = BigNum::new().unwrap();
+    serial.rand(64, MsbOption::MAYBE_ZERO, false).unwrap();
+    builder
+        .set_serial_number(&serial.to_asn1_integer().unwrap())
+        .unwrap();
+    builder.set_subject_name(&name).unwrap();
+    builder.set_pubkey(key).unwrap();
+    builder
+        .set_not_before(&Asn1Time::days_from_now(0).unwrap())
+        .unwrap();
+    builder
+        .set_not_after(&Asn1Time::days_from_now(1).unwrap())
+        .unwrap();
+
+    match role {
+        Role::Ca => {
+            builder
+                .append_extension(BasicConstraints::new().critical().ca().build().unwrap())
+                .unwrap();
+            builder
+                .append_extension(KeyUsage::new().key_cert_sign().crl_sign().build().unwrap())
+                .unwrap();
+        }
+        Role::Server | Role::Client => {
+            builder
+                .append_extension(BasicConstraints::new().build().unwrap())
+                .unwrap();
+            builder
+                .append_extension(KeyUsage::new().digital_signature().build().unwrap())
+                .unwrap();
+            let mut usage = ExtendedKeyUsage::new();
+            if matches!(role, Role::Server) {
+                usage.server_auth();
+            } else {
+                usage.client_auth();
+            }
+            builder.append_extension(usage.build().unwrap()).unwrap();
+        }
+    }
+    if matches!(role, Role::Server) {
+        let san = SubjectAlternativeName::new()
+            .dns("localhost")
+            .ip("127.0.0.1")
+            .build(&builder.x509v3_context(issuer.map(|(cert, _)| cert.as_ref()), None))
+            .unwrap();
+        builder.append_extension(san).unwrap();
+    }
+
+    match issuer {
+        Some((cert, issuer_key)) => {
+            builder.set_issuer_name(cert.subject_name()).unwrap();
+            builder.sign(issuer_key, MessageDigest::sha256()).unwrap();
+        }
+        None => {
+            builder.set_issuer_name(&name).unwrap();
+            builder.sign(key, MessageDigest::sha256()).unwrap();
+        }
+    }
+    builder.build()
+}
+
+struct Pki {
+    dir: PathBuf,
+    ca: X509,
+    ca_key: PKey<Private>,
+}
+
+impl Pki {
+    fn new(test: &str) -> Self {
+        let dir =
+            std::env::temp_dir().join(format!("hawkbit-transport-{}-{}", test, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let ca_key = generate_key();
+        let ca = issue("Test CA", &ca_key, Role::Ca, None);
+        Self { dir, ca, ca_key }
+    }
+
+    fn issue(&self, common_name: &str, role: Role) -> (X509, PKey<Private>) {
+        let key = generate_key();
+        let cert = issue(common_name, &key, role, Some((&self.ca, &self.ca_key)));
+        (cert, key)
+    }
+
+    fn write(&self, name: &str, pem: &[u8]) -> PathBuf {
+        let path = self.dir.join(name);
+        std::fs::write(&path, pem).unwrap();
+        path
+    }
+
+    fn ca_bundle(&self) -> PathBuf {
+        self.write("ca.pem", &self.ca.to_pem().unwrap())
+    }
+
+    /// Writes a client certificate and its PKCS#8 key, returning both paths.
+    fn client_identity(&self, common_name: &str) -> (PathBuf, PathBuf) {
+        let (cert, key) = self.issue(common_name, Role::Client);
+        (
+            self.write("client.pem", &cert.to_pem().unwrap()),
+            self.write("client.key", &key.private_key_to_pem_pkcs8().unwrap()),
+        )
+    }
+
+    /// Starts a TLS listener that requires a client certificate issued by
+    /// the CA and answers every request with the certificate's common name.
+    fn serve(&self) -> SocketAddr {
+        let (cert, key) = self.issue("localhost", Role::Server);
+        let mut acceptor = SslAcceptor::mozilla_intermediate_v5(SslMethod::tls()).unwrap();
+        acceptor.set_certificate(&cert).unwrap();
+        acceptor.set_private_key(&key).unwrap();
+        acceptor.cert_store_mut().add_cert(self.ca.clone()).unwrap();
+        acceptor.set_verify(SslVerifyMode::PEER | SslVerifyMode::FAIL_IF_NO_PEER_CERT);
+        let acceptor = acceptor.build();
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(mut stream) = acceptor.accept(stream.unwrap()) else {
+                    continue;
+                };
+                let mut request = Vec::new();
+                let mut buf = [0u8; 1024];
+                while !request.ends_with(b"\r\n\r\n") {
+                    match stream.read(&mut buf) {
+                        Ok(0) | Err(_) => break,
+                        Ok(n) => request.extend_from_slice(&buf[..n]),
+                    }
+                }
+                let peer = stream
+                    .ssl()
+                    .peer_certificate()
+                    .and_then(|cert| {
+                        cert.subject_name()
+                            .entries_by_nid(Nid::COMMONNAME)
+                            .next()
+                            .map(|entry| entry.data().as_utf8().unwrap().to_string())
+                    })
+                    .unwrap_or_default();
+                let _ = write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    peer.len(),
+                    peer
+                );
+                let _ = stream.shutdown();
+            }
+        });
+        addr
+    }
+}
+
+impl Drop for Pki {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+async fn fetch(config: &TransportConfig, addr: SocketAddr) -> reqwest::Result<String> {
+    let client = build_client(config, HeaderMap::new()).unwrap();
+    client
+        .get(format!("https://localhost:{}/", addr.port()))
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await
+}
+
+#[tokio::test]
+async fn ca_bundle_and_client_certificate_are_used() {
+    let pki = Pki::new("mtls");
+    let addr = pki.serve();
+    let (client_cert, client_key) = pki.client_identity("device-1");
+
+    let config = TransportConfig {
+        ca_bundle: Some(pki.ca_bundle()),
+        client_cert: Some(client_cert),
+        client_key: Some(client_key),
+        ..Default::default()
+    };
+    assert_eq!(fetch(&config, addr).await.unwrap(), "device-1");
+}
+
+#[tokio::test]
+async fn server_is_untrusted_without_ca_bundle() {
+    let pki = Pki::new("no-ca");
+    let addr = pki.serve();
+    let (client_cert, client_key) = pki.client_identity("device-1");
+
+    let config = TransportConfig {
+        client_cert: Some(client_cert),
+        client_key: Some(client_key),
+        ..Default::default()
+    };
+    assert!(fetch(&config, addr).await.is_err());
+}
+
+#[tokio::test]
+async fn handshake_fails_without_client_certificate() {
+    let pki = Pki::new("no-client-cert");
+    let addr = pki.serve();
+
+    let config = TransportConfig {
+        ca_bundle: Some(pki.ca_bundle()),
+        ..Default::default()
+    };
+    assert!(fetch(&config, addr).await.is_err());
+}
+
+#[test]
+fn client_certificate_requires_key() {
+    let pki = Pki::new("cert-only");
+    let (client_cert, _) = pki.client_identity("device-1");
+
+    let config = TransportConfig {
+        client_cert: Some(client_cert),
+        ..Default::default()
+    };
+    let err = build_client(&config, HeaderMap::new()).unwrap_err();
+    assert!(err.to_string().contains("configured together"), "{}", err);
+}
+
+#[test]
+fn empty_ca_bundle_is_rejected() {
+    let pki = Pki::new("empty-ca");
+    let config = TransportConfig {
+        ca_bundle: Some(pki.write("empty.pem", b"")),
+        ..Default::default()
+    };
+    assert!(build_client(&config, HeaderMap::new()).is_err());
+}